@@ -0,0 +1,587 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::migrate::{self, DOCUMENT_VERSION, VERSION_KEY};
+
+/// Layout version of the archive itself: which files it holds and how the
+/// manifest describes them. The data in it is versioned separately by
+/// [`DOCUMENT_VERSION`], so older archives go through the usual upgrades.
+const ARCHIVE_FORMAT: u64 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Fields of [`Data`] stored in the archive, each as `<field>.json`. The
+/// undo journal is left out: it only makes sense on the machine it was
+/// recorded on.
+const SECTIONS: &[&str] = &[
+    "bills",
+    "payments",
+    "categories",
+    "rates",
+    "settings",
+    "trash",
+    "history",
+];
+
+/// Files larger than this are refused rather than unpacked, so a damaged or
+/// hostile archive cannot exhaust memory.
+#[cfg(not(test))]
+const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+/// Small enough for the tests to go over it cheaply.
+#[cfg(test)]
+const MAX_FILE_SIZE: u64 = 64 * 1024;
+
+/// Describes an archive, so a restore can tell it is complete and intact
+/// before touching the current data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub format: u64,
+    pub schema_version: u64,
+    /// Version of the app that wrote the archive.
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the file content.
+    pub sha256: String,
+}
+
+/// A verified archive.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub manifest: Manifest,
+    pub data: Data,
+}
+
+/// How a restore treats the current data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreMode {
+    /// Replaces everything, settings included, with the archive.
+    Replace,
+    /// Adds the bills, payments, categories and rates the current data does
+    /// not have yet, matched by id. Settings, trash and history stay as they
+    /// are.
+    Merge,
+}
+
+/// What a restore took from an archive.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub created_at: DateTime<Utc>,
+    pub app_version: String,
+    pub bills: usize,
+    pub payments: usize,
+    pub categories: usize,
+    pub rates: usize,
+    /// Records left out of a merge because they are already there, or
+    /// because the bill they belong to is not.
+    pub skipped: usize,
+    /// Problems [`Data::check_integrity`] finds in the archived data.
+    pub issues: usize,
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn zip_error(e: ZipError) -> AppError {
+    match e {
+        ZipError::Io(e) => AppError::io("Failed to read the archive", e),
+        e => AppError::CorruptData(format!("Archive is not a valid zip file: {e}")),
+    }
+}
+
+fn serialize_error(e: serde_json::Error) -> AppError {
+    AppError::CorruptData(format!("Failed to serialize the archive: {e}"))
+}
+
+impl Data {
+    /// Packs the data into a zip archive with a manifest of checksums. The
+    /// archive is plain JSON even while the vault is in use.
+    pub fn export_archive(&self, now: DateTime<Utc>) -> AppResult<Vec<u8>> {
+        let Value::Object(mut fields) = serde_json::to_value(self).map_err(serialize_error)? else {
+            return Err(AppError::CorruptData(
+                "Failed to serialize the archive".to_string(),
+            ));
+        };
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut files = Vec::new();
+        let mut add = |name: String, bytes: &[u8]| -> AppResult<()> {
+            zip.start_file(name.as_str(), options).map_err(zip_error)?;
+            zip.write_all(bytes)
+                .map_err(|e| AppError::io("Failed to write the archive", e))?;
+            files.push(ManifestFile {
+                sha256: hex_digest(bytes),
+                size: bytes.len() as u64,
+                name,
+            });
+            Ok(())
+        };
+        for section in SECTIONS {
+            let value = fields.remove(*section).unwrap_or(Value::Null);
+            let bytes = serde_json::to_vec_pretty(&value).map_err(serialize_error)?;
+            add(format!("{section}.json"), &bytes)?;
+        }
+        let manifest = Manifest {
+            format: ARCHIVE_FORMAT,
+            schema_version: DOCUMENT_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: now,
+            files,
+        };
+        let bytes = serde_json::to_vec_pretty(&manifest).map_err(serialize_error)?;
+        zip.start_file(MANIFEST_FILE, options).map_err(zip_error)?;
+        zip.write_all(&bytes)
+            .map_err(|e| AppError::io("Failed to write the archive", e))?;
+        Ok(zip.finish().map_err(zip_error)?.into_inner())
+    }
+
+    /// Adds the records of `archive` that are not here yet. Categories are
+    /// also matched by name, and bills keep their category through either
+    /// match; payments are only taken for bills that end up here.
+    pub fn merge_archive(&mut self, archive: &Archive) -> RestoreReport {
+        let mut report = RestoreReport::new(archive);
+        let mut category_ids = HashMap::new();
+        for category in &archive.data.categories {
+            let existing = self
+                .categories
+                .iter()
+                .find(|c| c.id == category.id || c.name.eq_ignore_ascii_case(category.name.trim()));
+            match existing {
+                Some(existing) => {
+                    category_ids.insert(category.id.as_str(), existing.id.clone());
+                    report.skipped += 1;
+                }
+                None => {
+                    category_ids.insert(category.id.as_str(), category.id.clone());
+                    self.categories.push(category.clone());
+                    report.categories += 1;
+                }
+            }
+        }
+
+        let mut ids: HashSet<String> = self
+            .bills
+            .iter()
+            .map(|b| b.id.clone())
+            .chain(self.trash.iter().map(|t| t.id().to_string()))
+            .collect();
+        for bill in &archive.data.bills {
+            if !ids.insert(bill.id.clone()) {
+                report.skipped += 1;
+                continue;
+            }
+            let mut bill = bill.clone();
+            bill.category_id = bill
+                .category_id
+                .take()
+                .and_then(|id| category_ids.get(id.as_str()).cloned());
+            self.bills.push(bill);
+            report.bills += 1;
+        }
+
+        let bill_ids: HashSet<&str> = self.bills.iter().map(|b| b.id.as_str()).collect();
+        let payment_ids: HashSet<&str> = self.payments.iter().map(|p| p.id.as_str()).collect();
+        let payments: Vec<_> = archive
+            .data
+            .payments
+            .iter()
+            .filter(|p| {
+                bill_ids.contains(p.bill_id.as_str()) && !payment_ids.contains(p.id.as_str())
+            })
+            .cloned()
+            .collect();
+        report.skipped += archive.data.payments.len() - payments.len();
+        report.payments = payments.len();
+        self.payments.extend(payments);
+
+        for rate in &archive.data.rates {
+            let known = self
+                .rates
+                .iter()
+                .any(|r| r.date == rate.date && r.base == rate.base && r.quote == rate.quote);
+            if known {
+                report.skipped += 1;
+            } else {
+                self.rates.push(rate.clone());
+                report.rates += 1;
+            }
+        }
+        self.rates.sort_by_key(|r| (r.date, r.base, r.quote));
+        report
+    }
+}
+
+impl RestoreReport {
+    /// An empty report of what a merge takes from `archive`.
+    fn new(archive: &Archive) -> Self {
+        Self {
+            created_at: archive.manifest.created_at,
+            app_version: archive.manifest.app_version.clone(),
+            bills: 0,
+            payments: 0,
+            categories: 0,
+            rates: 0,
+            skipped: 0,
+            issues: archive.data.check_integrity().issues.len(),
+        }
+    }
+}
+
+impl Archive {
+    /// What replacing the current data with the archive restores.
+    pub fn replace_report(&self) -> RestoreReport {
+        RestoreReport {
+            bills: self.data.bills.len(),
+            payments: self.data.payments.len(),
+            categories: self.data.categories.len(),
+            rates: self.data.rates.len(),
+            ..RestoreReport::new(self)
+        }
+    }
+}
+
+/// Reads one file of the archive, refusing anything oversized.
+fn read_file(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> AppResult<Vec<u8>> {
+    let file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => {
+            return Err(AppError::CorruptData(format!(
+                "Archive is incomplete: {name} is missing"
+            )))
+        }
+        Err(e) => return Err(zip_error(e)),
+    };
+    let mut bytes = Vec::new();
+    file.take(MAX_FILE_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| AppError::CorruptData(format!("Archive file {name} is damaged: {e}")))?;
+    if bytes.len() as u64 > MAX_FILE_SIZE {
+        return Err(AppError::CorruptData(format!(
+            "Archive file {name} is too large"
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Opens an archive written by [`Data::export_archive`], checking every
+/// file against the manifest before reading the data in it.
+pub fn read_archive(bytes: &[u8]) -> AppResult<Archive> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes)).map_err(zip_error)?;
+    let manifest: Manifest = serde_json::from_slice(&read_file(&mut zip, MANIFEST_FILE)?)
+        .map_err(|e| AppError::CorruptData(format!("Archive manifest is not valid: {e}")))?;
+    migrate::check_version("Archive", manifest.format, ARCHIVE_FORMAT)?;
+
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+    if let Some(name) = zip
+        .file_names()
+        .find(|name| *name != MANIFEST_FILE && !listed.contains(name))
+    {
+        return Err(AppError::CorruptData(format!(
+            "Archive contains {name}, which its manifest does not list"
+        )));
+    }
+
+    let mut doc = Map::new();
+    for file in &manifest.files {
+        let bytes = read_file(&mut zip, &file.name)?;
+        if bytes.len() as u64 != file.size || hex_digest(&bytes) != file.sha256 {
+            return Err(AppError::CorruptData(format!(
+                "Archive file {} does not match its checksum",
+                file.name
+            )));
+        }
+        let Some(section) = file.name.strip_suffix(".json") else {
+            continue;
+        };
+        if SECTIONS.contains(&section) {
+            let value = serde_json::from_slice(&bytes).map_err(|e| {
+                AppError::CorruptData(format!("Archive file {} is not valid: {e}", file.name))
+            })?;
+            doc.insert(section.to_string(), value);
+        }
+    }
+    if let Some(section) = SECTIONS.iter().find(|s| !doc.contains_key(**s)) {
+        return Err(AppError::CorruptData(format!(
+            "Archive is incomplete: {section}.json is missing"
+        )));
+    }
+    doc.insert(VERSION_KEY.to_string(), manifest.schema_version.into());
+    let bytes = serde_json::to_vec(&doc).map_err(serialize_error)?;
+    let data = migrate::decode_document("Archive", &bytes)?;
+    Ok(Archive { manifest, data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fx::ExchangeRate;
+    use crate::money::Currency;
+    use crate::testing::{add_bill, change, date};
+
+    fn sample() -> Data {
+        let mut data = Data::fresh();
+        let rent = change(&mut data, "Add bill", |d| {
+            add_bill(d, "Rent", 120_000, "2024-01-31")
+        });
+        data.bills[0].category_id = Some(data.categories[0].id.clone());
+        data.set_paid(&rent.id, true, date("2024-01-30"), Utc::now())
+            .unwrap();
+        add_bill(&mut data, "Water", 4_000, "2024-01-15");
+        data.rates.push(ExchangeRate {
+            date: date("2024-01-05"),
+            base: Currency::parse("EUR").unwrap(),
+            quote: Currency::USD,
+            rate: 1.0921,
+        });
+        data
+    }
+
+    /// Rewrites an archive file by file: `edit` returns the new content of
+    /// a file, or `None` to leave it out. `extra` files are added at the end.
+    fn repack(
+        bytes: &[u8],
+        edit: impl Fn(&str, Vec<u8>) -> Option<Vec<u8>>,
+        extra: &[(&str, &[u8])],
+    ) -> Vec<u8> {
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let names: Vec<String> = zip.file_names().map(str::to_string).collect();
+        let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut write = |name: &str, content: &[u8]| {
+            out.start_file(name, options).unwrap();
+            out.write_all(content).unwrap();
+        };
+        for name in names {
+            let mut content = Vec::new();
+            zip.by_name(&name)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            if let Some(content) = edit(&name, content) {
+                write(&name, &content);
+            }
+        }
+        for (name, content) in extra {
+            write(name, content);
+        }
+        out.finish().unwrap().into_inner()
+    }
+
+    fn corrupt_message(bytes: &[u8]) -> String {
+        match read_archive(bytes).unwrap_err() {
+            AppError::CorruptData(message) => message,
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+
+    /// A manifest edited by `f`.
+    fn edit_manifest(content: Vec<u8>, f: impl FnOnce(&mut Manifest)) -> Vec<u8> {
+        let mut manifest: Manifest = serde_json::from_slice(&content).unwrap();
+        f(&mut manifest);
+        serde_json::to_vec(&manifest).unwrap()
+    }
+
+    #[test]
+    fn exported_archives_read_back() {
+        let data = sample();
+        let now = Utc::now();
+        let archive = read_archive(&data.export_archive(now).unwrap()).unwrap();
+        assert_eq!(archive.manifest.format, ARCHIVE_FORMAT);
+        assert_eq!(archive.manifest.created_at, now);
+        assert_eq!(archive.manifest.files.len(), SECTIONS.len());
+
+        // Everything but the undo journal comes back
+        let mut expected = data.clone();
+        expected.journal.clear();
+        assert_eq!(
+            serde_json::to_value(&archive.data).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+        let report = archive.replace_report();
+        assert_eq!((report.bills, report.payments, report.rates), (2, 1, 1));
+        assert_eq!(report.issues, 0);
+    }
+
+    #[test]
+    fn rejects_files_that_do_not_match_their_checksum() {
+        let bytes = sample().export_archive(Utc::now()).unwrap();
+        let tampered = repack(
+            &bytes,
+            |name, content| {
+                Some(match name {
+                    "bills.json" => content.to_ascii_lowercase(),
+                    _ => content,
+                })
+            },
+            &[],
+        );
+        assert_eq!(
+            corrupt_message(&tampered),
+            "Archive file bills.json does not match its checksum"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_sections() {
+        let bytes = sample().export_archive(Utc::now()).unwrap();
+        // Listed in the manifest but not in the zip
+        let dropped = repack(
+            &bytes,
+            |name, content| (name != "rates.json").then_some(content),
+            &[],
+        );
+        assert_eq!(
+            corrupt_message(&dropped),
+            "Archive is incomplete: rates.json is missing"
+        );
+        // Left out of both
+        let unlisted = repack(
+            &bytes,
+            |name, content| match name {
+                "rates.json" => None,
+                MANIFEST_FILE => Some(edit_manifest(content, |m| {
+                    m.files.retain(|f| f.name != "rates.json")
+                })),
+                _ => Some(content),
+            },
+            &[],
+        );
+        assert_eq!(
+            corrupt_message(&unlisted),
+            "Archive is incomplete: rates.json is missing"
+        );
+        let no_manifest = repack(
+            &bytes,
+            |name, content| (name != MANIFEST_FILE).then_some(content),
+            &[],
+        );
+        assert_eq!(
+            corrupt_message(&no_manifest),
+            "Archive is incomplete: manifest.json is missing"
+        );
+    }
+
+    #[test]
+    fn rejects_files_the_manifest_does_not_list() {
+        let bytes = sample().export_archive(Utc::now()).unwrap();
+        let extra = repack(&bytes, |_, content| Some(content), &[("notes.txt", b"hi")]);
+        assert_eq!(
+            corrupt_message(&extra),
+            "Archive contains notes.txt, which its manifest does not list"
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_files() {
+        let bytes = sample().export_archive(Utc::now()).unwrap();
+        let huge = vec![b' '; MAX_FILE_SIZE as usize + 1];
+        let padded = repack(
+            &bytes,
+            |name, mut content| {
+                if name == "history.json" {
+                    content.extend_from_slice(&huge);
+                }
+                Some(content)
+            },
+            &[],
+        );
+        assert_eq!(
+            corrupt_message(&padded),
+            "Archive file history.json is too large"
+        );
+    }
+
+    #[test]
+    fn rejects_newer_formats_and_other_files() {
+        let bytes = sample().export_archive(Utc::now()).unwrap();
+        let newer = repack(
+            &bytes,
+            |name, content| match name {
+                MANIFEST_FILE => Some(edit_manifest(content, |m| m.format = ARCHIVE_FORMAT + 1)),
+                _ => Some(content),
+            },
+            &[],
+        );
+        assert_eq!(read_archive(&newer).unwrap_err().code(), "newer_version");
+        assert_eq!(
+            read_archive(b"not a zip").unwrap_err().code(),
+            "corrupt_data"
+        );
+    }
+
+    #[test]
+    fn merges_only_what_is_missing() {
+        let archived = sample();
+        let archive = read_archive(&archived.export_archive(Utc::now()).unwrap()).unwrap();
+
+        let mut data = Data::default();
+        // Same name as the category of the archived rent, but another id
+        let housing = archived.categories[0].name.to_uppercase();
+        let existing = data.create_category(&housing).unwrap();
+        // The archived water bill is already here
+        data.bills.push(archived.bills[1].clone());
+
+        let report = data.merge_archive(&archive);
+        assert_eq!(
+            (
+                report.categories,
+                report.bills,
+                report.payments,
+                report.rates
+            ),
+            (archived.categories.len() - 1, 1, 1, 1)
+        );
+        // The matched category, the water bill
+        assert_eq!(report.skipped, 2);
+        let rent = data.bill(&archived.bills[0].id).unwrap();
+        assert_eq!(rent.category_id, Some(existing.id));
+        assert_eq!(data.payments.len(), 1);
+
+        // Merging again adds nothing
+        let again = data.merge_archive(&archive);
+        assert_eq!(
+            (again.categories, again.bills, again.payments, again.rates),
+            (0, 0, 0, 0)
+        );
+        assert_eq!(
+            again.skipped,
+            archived.categories.len() + archived.bills.len() + 1 + 1
+        );
+    }
+
+    #[test]
+    fn merge_skips_payments_of_bills_left_out() {
+        let archived = sample();
+        let archive = read_archive(&archived.export_archive(Utc::now()).unwrap()).unwrap();
+        let mut data = Data::fresh();
+        // A trashed bill with the rent's id keeps the archived one out
+        data.bills.push(archived.bills[0].clone());
+        data.delete_bill(&archived.bills[0].id, Utc::now()).unwrap();
+
+        let report = data.merge_archive(&archive);
+        assert_eq!((report.bills, report.payments), (1, 0));
+        assert!(data.bill(&archived.bills[0].id).is_none());
+        assert!(data.payments.is_empty());
+    }
+}
@@ -0,0 +1,648 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::integrity::QuarantinedRecord;
+use crate::migrate;
+use crate::vault::{self, VaultKey};
+
+/// Snapshots kept per kind; older ones are deleted as new ones are taken.
+const KEEP_DAILY: usize = 7;
+const KEEP_WEEKLY: usize = 4;
+const KEEP_BEFORE_RESTORE: usize = 3;
+
+const EXTENSION: &str = "json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackupKind {
+    Daily,
+    Weekly,
+    /// The data as it was just before a backup was restored over it.
+    BeforeRestore,
+}
+
+impl BackupKind {
+    const ALL: [BackupKind; 3] = [
+        BackupKind::Daily,
+        BackupKind::Weekly,
+        BackupKind::BeforeRestore,
+    ];
+
+    fn prefix(self) -> &'static str {
+        match self {
+            BackupKind::Daily => "daily-",
+            BackupKind::Weekly => "weekly-",
+            BackupKind::BeforeRestore => "before-restore-",
+        }
+    }
+
+    fn keep(self) -> usize {
+        match self {
+            BackupKind::Daily => KEEP_DAILY,
+            BackupKind::Weekly => KEEP_WEEKLY,
+            BackupKind::BeforeRestore => KEEP_BEFORE_RESTORE,
+        }
+    }
+
+    fn of(name: &str) -> Option<Self> {
+        let stem = name.strip_suffix(EXTENSION)?.strip_suffix('.')?;
+        Self::ALL.into_iter().find(|kind| {
+            stem.strip_prefix(kind.prefix())
+                .is_some_and(|s| !s.is_empty())
+        })
+    }
+}
+
+/// A snapshot file, as listed in the UI.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    /// File name, which also identifies the backup to restore.
+    pub name: String,
+    pub kind: BackupKind,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Rotating JSON snapshots of the app data in a directory of their own,
+/// encrypted with the vault key while the vault is in use.
+#[derive(Debug)]
+pub struct Backups {
+    dir: PathBuf,
+    /// Day of the last snapshot check, so it only touches the disk once a day.
+    checked: Option<NaiveDate>,
+}
+
+impl Backups {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            checked: None,
+        }
+    }
+
+    /// Backups on disk, newest first.
+    pub fn list(&self) -> AppResult<Vec<Backup>> {
+        let failed = |e| AppError::io(format!("Failed to list {}", self.dir.display()), e);
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(failed(e)),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(failed)?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Some(kind) = BackupKind::of(&name) else {
+                continue;
+            };
+            let meta = entry.metadata().map_err(failed)?;
+            backups.push(Backup {
+                name,
+                kind,
+                created_at: meta.modified().map_err(failed)?.into(),
+                size: meta.len(),
+            });
+        }
+        backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        Ok(backups)
+    }
+
+    /// Takes today's daily snapshot and this week's weekly one if they do
+    /// not exist yet. Called with the data as it was before a change, so the
+    /// snapshots hold the state at the start of the day or week.
+    pub fn snapshot_if_due(
+        &mut self,
+        data: &Data,
+        today: NaiveDate,
+        key: Option<&VaultKey>,
+    ) -> AppResult<()> {
+        if self.checked == Some(today) {
+            return Ok(());
+        }
+        let week = today.iso_week();
+        self.take(
+            data,
+            key,
+            BackupKind::Daily,
+            &today.format("%Y-%m-%d").to_string(),
+            false,
+        )?;
+        self.take(
+            data,
+            key,
+            BackupKind::Weekly,
+            &format!("{:04}-W{:02}", week.year(), week.week()),
+            false,
+        )?;
+        self.checked = Some(today);
+        Ok(())
+    }
+
+    /// Saves the current data before a restore replaces it.
+    pub fn snapshot_before_restore(
+        &self,
+        data: &Data,
+        now: DateTime<Utc>,
+        key: Option<&VaultKey>,
+    ) -> AppResult<()> {
+        self.take(
+            data,
+            key,
+            BackupKind::BeforeRestore,
+            &now.format("%Y%m%dT%H%M%S").to_string(),
+            true,
+        )
+    }
+
+    /// Reads the data from the backup called `name`, with the records of it
+    /// that could not be read.
+    pub fn read(
+        &self,
+        name: &str,
+        key: Option<&VaultKey>,
+    ) -> AppResult<(Data, Vec<QuarantinedRecord>)> {
+        // Only bare names of our own files, never a path elsewhere
+        if BackupKind::of(name).is_none() || Path::new(name).file_name() != Some(name.as_ref()) {
+            return Err(AppError::not_found("Backup", name));
+        }
+        let path = self.dir.join(name);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::not_found("Backup", name))
+            }
+            Err(e) => {
+                return Err(AppError::io(
+                    format!("Failed to read {}", path.display()),
+                    e,
+                ))
+            }
+        };
+        let what = format!("Backup {name}");
+        migrate::salvage_document(&what, &plaintext(&what, &bytes, key)?, Utc::now())
+    }
+
+    /// Re-encrypts every backup from `from` to `to`, either of which is
+    /// `None` for plain JSON. Used when the vault is turned on or off or its
+    /// passphrase changes; file times are kept so the list stays in order.
+    pub fn reseal_all(&self, from: Option<&VaultKey>, to: Option<&VaultKey>) -> AppResult<()> {
+        commit_all(self.stage_reseal(from, to)?)
+    }
+
+    /// Writes every backup re-encrypted from `from` to `to` next to the
+    /// original, leaving the originals in place until the staged files are
+    /// committed.
+    pub fn stage_reseal(
+        &self,
+        from: Option<&VaultKey>,
+        to: Option<&VaultKey>,
+    ) -> AppResult<Vec<Staged>> {
+        let mut staged = Vec::new();
+        for backup in self.list()? {
+            let path = self.dir.join(&backup.name);
+            let bytes = fs::read(&path)
+                .map_err(|e| AppError::io(format!("Failed to read {}", path.display()), e))?;
+            let what = format!("Backup {}", backup.name);
+            let doc = plaintext(&what, &bytes, from)?;
+            let mut file = stage(&path, &seal(&doc, to)?)?;
+            file.modified = Some(SystemTime::from(backup.created_at));
+            staged.push(file);
+        }
+        Ok(staged)
+    }
+
+    /// Writes a snapshot unless one with the same name exists and
+    /// `overwrite` is false, then drops the oldest of its kind.
+    fn take(
+        &self,
+        data: &Data,
+        key: Option<&VaultKey>,
+        kind: BackupKind,
+        label: &str,
+        overwrite: bool,
+    ) -> AppResult<()> {
+        let path = self
+            .dir
+            .join(format!("{}{label}.{EXTENSION}", kind.prefix()));
+        if !overwrite && path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| AppError::io(format!("Failed to create {}", self.dir.display()), e))?;
+        let doc = Zeroizing::new(migrate::encode_document(data)?);
+        write_atomic(&path, &seal(&doc, key)?)?;
+        self.prune(kind)
+    }
+
+    fn prune(&self, kind: BackupKind) -> AppResult<()> {
+        // Labels sort chronologically, so names do too
+        let mut names: Vec<String> = self
+            .list()?
+            .into_iter()
+            .filter(|b| b.kind == kind)
+            .map(|b| b.name)
+            .collect();
+        names.sort();
+        let excess = names.len().saturating_sub(kind.keep());
+        for name in &names[..excess] {
+            let path = self.dir.join(name);
+            fs::remove_file(&path)
+                .map_err(|e| AppError::io(format!("Failed to delete {}", path.display()), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Encrypts a document with `key`, or leaves it as plain JSON without one.
+pub fn seal(doc: &[u8], key: Option<&VaultKey>) -> AppResult<Vec<u8>> {
+    match key {
+        Some(key) => vault::seal(key, doc),
+        None => Ok(doc.to_vec()),
+    }
+}
+
+/// The JSON document in a file written by [`seal`], decrypting it if it is
+/// sealed.
+pub fn plaintext(
+    what: &str,
+    bytes: &[u8],
+    key: Option<&VaultKey>,
+) -> AppResult<Zeroizing<Vec<u8>>> {
+    if !vault::is_sealed(bytes) {
+        return Ok(Zeroizing::new(bytes.to_vec()));
+    }
+    vault::unseal(what, key.ok_or(AppError::Locked)?, bytes)
+}
+
+/// Replaces `path` with `bytes` so that a crash leaves either the old or the
+/// new content, never a mix: the bytes go to a temporary file in the same
+/// directory, which is flushed to disk and then renamed over `path`.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> AppResult<()> {
+    stage(path, bytes)?.commit()
+}
+
+/// New content for a file, written and flushed next to it but not yet in
+/// its place, so several files can be replaced together only once all of
+/// them are written. Dropping it uncommitted removes the temporary file.
+pub struct Staged {
+    tmp: PathBuf,
+    path: PathBuf,
+    /// Modification time to give the file, if not now.
+    modified: Option<SystemTime>,
+}
+
+/// Writes `bytes` to a temporary file beside `path`, for
+/// [`Staged::commit`] to rename over it.
+pub fn stage(path: &Path, bytes: &[u8]) -> AppResult<Staged> {
+    let staged = Staged {
+        tmp: with_suffix(path, ".tmp"),
+        path: path.to_path_buf(),
+        modified: None,
+    };
+    let failed = |e| AppError::io(format!("Failed to write {}", path.display()), e);
+    let mut file = File::create(&staged.tmp).map_err(failed)?;
+    file.write_all(bytes).map_err(failed)?;
+    file.sync_all().map_err(failed)?;
+    Ok(staged)
+}
+
+impl Staged {
+    /// Renames the new content over the file.
+    pub fn commit(self) -> AppResult<()> {
+        let path = &self.path;
+        let failed = |e| AppError::io(format!("Failed to write {}", path.display()), e);
+        if let Some(modified) = self.modified {
+            File::options()
+                .write(true)
+                .open(&self.tmp)
+                .and_then(|f| f.set_modified(modified))
+                .map_err(failed)?;
+        }
+        fs::rename(&self.tmp, path).map_err(failed)?;
+        // Persist the rename itself; directories cannot be opened on Windows,
+        // where the rename is already durable
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            File::open(dir).and_then(|d| d.sync_all()).map_err(failed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts every staged file in place, or none of them: each original is kept
+/// aside as a hard link until all are replaced, and put back if any
+/// replacement fails.
+pub fn commit_all(staged: Vec<Staged>) -> AppResult<()> {
+    let mut replaced = Vec::new();
+    let result = staged.into_iter().try_for_each(|file| {
+        let kept = with_suffix(&file.path, ".old");
+        let _ = fs::remove_file(&kept);
+        fs::hard_link(&file.path, &kept)
+            .or_else(|_| fs::copy(&file.path, &kept).map(drop))
+            .map_err(|e| AppError::io(format!("Failed to copy {}", file.path.display()), e))?;
+        replaced.push((file.path.clone(), kept));
+        file.commit()
+    });
+    for (path, kept) in replaced {
+        if result.is_ok() {
+            let _ = fs::remove_file(kept);
+        } else {
+            let _ = fs::rename(kept, path);
+        }
+    }
+    result
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        // Already gone once committed
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{add_bill, date};
+
+    fn names(backups: &Backups, kind: BackupKind) -> Vec<String> {
+        let mut names: Vec<String> = backups
+            .list()
+            .unwrap()
+            .into_iter()
+            .filter(|b| b.kind == kind)
+            .map(|b| b.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn recognises_only_its_own_file_names() {
+        assert_eq!(
+            BackupKind::of("daily-2024-01-05.json"),
+            Some(BackupKind::Daily)
+        );
+        assert_eq!(
+            BackupKind::of("weekly-2024-W01.json"),
+            Some(BackupKind::Weekly)
+        );
+        assert_eq!(
+            BackupKind::of("before-restore-20240105T120000.json"),
+            Some(BackupKind::BeforeRestore)
+        );
+        for name in [
+            "daily-.json",
+            "daily-2024-01-05.json.tmp",
+            "notes.json",
+            "daily-x",
+        ] {
+            assert_eq!(BackupKind::of(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn keeps_seven_daily_and_four_weekly_snapshots() {
+        let dir = TempDir::new().unwrap();
+        let mut backups = Backups::new(dir.path().join("backups"));
+        assert!(backups.list().unwrap().is_empty());
+        let data = Data::default();
+        // Jan 1 2024 is the Monday starting ISO week 1
+        let mut day = date("2024-01-01");
+        while day <= date("2024-01-30") {
+            backups.snapshot_if_due(&data, day, None).unwrap();
+            day = day.succ_opt().unwrap();
+        }
+        let daily: Vec<String> = (24..=30)
+            .map(|d| format!("daily-2024-01-{d}.json"))
+            .collect();
+        assert_eq!(names(&backups, BackupKind::Daily), daily);
+        assert_eq!(
+            names(&backups, BackupKind::Weekly),
+            [
+                "weekly-2024-W02.json",
+                "weekly-2024-W03.json",
+                "weekly-2024-W04.json",
+                "weekly-2024-W05.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_hold_the_first_state_of_the_day() {
+        let dir = TempDir::new().unwrap();
+        let mut backups = Backups::new(dir.path());
+        let mut data = Data::default();
+        backups
+            .snapshot_if_due(&data, date("2024-01-05"), None)
+            .unwrap();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        backups
+            .snapshot_if_due(&data, date("2024-01-05"), None)
+            .unwrap();
+        // A restart forgets the last check but still keeps the morning copy
+        let mut restarted = Backups::new(dir.path());
+        restarted
+            .snapshot_if_due(&data, date("2024-01-05"), None)
+            .unwrap();
+        assert!(restarted
+            .read("daily-2024-01-05.json", None)
+            .unwrap()
+            .0
+            .bills
+            .is_empty());
+        assert!(restarted
+            .read("weekly-2024-W01.json", None)
+            .unwrap()
+            .0
+            .bills
+            .is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_three_copies_before_restores() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path());
+        let mut data = Data::default();
+        for minute in 0..5 {
+            add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+            let now = format!("2024-01-05T12:0{minute}:00Z").parse().unwrap();
+            backups.snapshot_before_restore(&data, now, None).unwrap();
+        }
+        let kept = names(&backups, BackupKind::BeforeRestore);
+        assert_eq!(
+            kept,
+            [
+                "before-restore-20240105T120200.json",
+                "before-restore-20240105T120300.json",
+                "before-restore-20240105T120400.json"
+            ]
+        );
+        assert_eq!(backups.read(&kept[2], None).unwrap().0.bills.len(), 5);
+    }
+
+    #[test]
+    fn reads_only_backups_inside_the_directory() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path().join("backups"));
+        fs::write(dir.path().join("daily-2024-01-05.json"), "{}").unwrap();
+        for name in [
+            "daily-2024-01-06.json",
+            "../daily-2024-01-05.json",
+            "bills.json",
+        ] {
+            let err = backups.read(name, None).unwrap_err();
+            assert_eq!(err.code(), "not_found", "{name}");
+        }
+        fs::create_dir(dir.path().join("backups")).unwrap();
+        fs::write(dir.path().join("backups/daily-2024-01-06.json"), "nope").unwrap();
+        let err = backups.read("daily-2024-01-06.json", None).unwrap_err();
+        assert_eq!(err.code(), "corrupt_data");
+    }
+
+    #[test]
+    fn unreadable_records_are_returned_apart() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path());
+        let mut data = Data::default();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let mut doc: serde_json::Value =
+            serde_json::from_slice(&migrate::encode_document(&data).unwrap()).unwrap();
+        doc["bills"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({ "id": "b2", "name": 42 }));
+        fs::write(
+            dir.path().join("daily-2024-01-05.json"),
+            serde_json::to_vec(&doc).unwrap(),
+        )
+        .unwrap();
+
+        let (read, quarantined) = backups.read("daily-2024-01-05.json", None).unwrap();
+        assert_eq!(read.bills, data.bills);
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].collection, "bills");
+        assert_eq!(quarantined[0].record["id"], "b2");
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_whole_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.json");
+        fs::write(&path, "old content that is longer").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.path().join("data.json.tmp").exists());
+
+        let missing = dir.path().join("no-such-dir/data.json");
+        assert_eq!(
+            write_atomic(&missing, b"new").unwrap_err().code(),
+            "storage"
+        );
+    }
+
+    #[test]
+    fn staged_files_replace_the_original_only_when_committed() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.json");
+        let tmp = dir.path().join("data.json.tmp");
+        fs::write(&path, "old").unwrap();
+
+        let staged = stage(&path, b"new").unwrap();
+        assert_eq!(fs::read(&tmp).unwrap(), b"new");
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        drop(staged);
+        assert!(!tmp.exists());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+
+        stage(&path, b"new").unwrap().commit().unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn resealing_keeps_the_backup_times() {
+        let dir = TempDir::new().unwrap();
+        let mut backups = Backups::new(dir.path());
+        let mut data = Data::default();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        backups
+            .snapshot_if_due(&data, date("2024-01-05"), None)
+            .unwrap();
+        let before = backups.list().unwrap();
+        let key = VaultKey::create("correct horse").unwrap();
+
+        let staged = backups.stage_reseal(None, Some(&key)).unwrap();
+        assert_eq!(staged.len(), before.len());
+        // Uncommitted, the backups are still plain JSON
+        assert_eq!(
+            backups.read("daily-2024-01-05.json", None).unwrap().0.bills,
+            data.bills
+        );
+        drop(staged);
+        assert_eq!(backups.list().unwrap().len(), before.len());
+
+        backups.reseal_all(None, Some(&key)).unwrap();
+        assert!(backups.read("daily-2024-01-05.json", None).is_err());
+        let (read, _) = backups.read("daily-2024-01-05.json", Some(&key)).unwrap();
+        assert_eq!(read.bills, data.bills);
+        let after = backups.list().unwrap();
+        let times = |list: &[Backup]| list.iter().map(|b| b.created_at).collect::<Vec<_>>();
+        assert_eq!(times(&after), times(&before));
+    }
+
+    #[test]
+    fn staged_files_are_committed_together_or_not_at_all() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a.json", "b.json"] {
+            fs::write(path(name), "old").unwrap();
+        }
+        let left = || {
+            let mut names: Vec<String> = fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // A directory in place of the last file stops the commit midway
+        fs::create_dir(path("c.json")).unwrap();
+        let staged = ["a.json", "b.json", "c.json"]
+            .map(|name| stage(&path(name), b"new").unwrap())
+            .into();
+        assert_eq!(commit_all(staged).unwrap_err().code(), "storage");
+        assert_eq!(fs::read(path("a.json")).unwrap(), b"old");
+        assert_eq!(fs::read(path("b.json")).unwrap(), b"old");
+        assert_eq!(left(), ["a.json", "b.json", "c.json"]);
+
+        fs::remove_dir(path("c.json")).unwrap();
+        let staged = ["a.json", "b.json"]
+            .map(|name| stage(&path(name), b"new").unwrap())
+            .into();
+        commit_all(staged).unwrap();
+        assert_eq!(fs::read(path("a.json")).unwrap(), b"new");
+        assert_eq!(fs::read(path("b.json")).unwrap(), b"new");
+        assert_eq!(left(), ["a.json", "b.json"]);
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::Bill;
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::Money;
+use crate::payment::{Payment, PaymentInput};
+
+/// Days a payment may be made before or after the due date it is for.
+const DEFAULT_WINDOW_DAYS: u32 = 7;
+
+/// Relative difference from the expected amount still taken for the same
+/// bill, e.g. a utility bill that varies from month to month.
+const AMOUNT_TOLERANCE: f64 = 0.10;
+
+/// Payee similarity at which a transaction is matched even though its
+/// amount is further off than [`AMOUNT_TOLERANCE`].
+const STRONG_PAYEE: f64 = 0.8;
+
+/// Lowest score proposed as a match.
+const MIN_SCORE: f64 = 0.5;
+
+/// Words banks add around payee names that say nothing about the payee.
+const NOISE_WORDS: &[&str] = &[
+    "ach",
+    "autopay",
+    "bill",
+    "card",
+    "debit",
+    "direct",
+    "dd",
+    "payment",
+    "pmt",
+    "pos",
+    "purchase",
+    "recurring",
+    "sepa",
+    "transfer",
+    "www",
+    "com",
+];
+
+/// One line of a bank statement. Debits have a negative amount.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankTransaction {
+    /// Identifier from the bank, unique within the statement's account.
+    pub id: String,
+    pub date: NaiveDate,
+    pub amount: Money,
+    pub payee: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MatchOptions {
+    /// Days a payment may be made before or after the due date.
+    pub window_days: u32,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            window_days: DEFAULT_WINDOW_DAYS,
+        }
+    }
+}
+
+/// A debit that looks like the payment of an unpaid occurrence.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentProposal {
+    pub transaction: BankTransaction,
+    pub bill_id: String,
+    pub bill_name: String,
+    pub occurrence_date: NaiveDate,
+    /// Outstanding balance of the occurrence.
+    pub balance: Money,
+    /// How confident the match is, from 0 to 1.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchReport {
+    pub proposals: Vec<PaymentProposal>,
+    /// Debits that match no unpaid occurrence.
+    pub unmatched: Vec<BankTransaction>,
+    /// Transactions already recorded as payments by an earlier import.
+    pub already_recorded: usize,
+    /// Credits, which are never bill payments.
+    pub credits: usize,
+}
+
+/// A proposal the user confirmed, possibly with another amount.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankMatch {
+    pub transaction_id: String,
+    /// Payee on the statement, remembered for the bill.
+    #[serde(default)]
+    pub payee: String,
+    pub bill_id: String,
+    pub occurrence_date: NaiveDate,
+    pub paid_date: NaiveDate,
+    pub amount: Money,
+}
+
+/// Lowercase words of a payee, without digits-only words and bank noise.
+fn payee_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| !w.is_empty() && !w.chars().all(|c| c.is_ascii_digit()))
+        .filter(|w| !NOISE_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn bigrams(text: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// A payee as remembered for learned matches, e.g. "netflix" for
+/// "NETFLIX.COM 866-579"; empty when nothing distinctive is left.
+pub fn payee_key(payee: &str) -> String {
+    payee_words(payee).join(" ")
+}
+
+/// How alike two payee names are, from 0 to 1: the Dice coefficient of
+/// their letter pairs, or 1 when every word of one appears in the other,
+/// as in "NETFLIX.COM 866-579" and "Netflix".
+pub fn payee_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (payee_words(a), payee_words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (short, long) = if a.len() <= b.len() {
+        (&a, &b)
+    } else {
+        (&b, &a)
+    };
+    if short.iter().all(|w| long.contains(w)) {
+        return 1.0;
+    }
+    let x = bigrams(&a.concat());
+    let mut y = bigrams(&b.concat());
+    if x.is_empty() || y.is_empty() {
+        return 0.0;
+    }
+    let total = x.len() + y.len();
+    let mut shared = 0;
+    for pair in x {
+        if let Some(at) = y.iter().position(|p| *p == pair) {
+            y.swap_remove(at);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / total as f64
+}
+
+/// How close `paid` is to `expected`, from 0 to 1; 0 beyond the tolerance.
+fn amount_score(paid: Money, expected: Money) -> f64 {
+    if paid.currency != expected.currency || !expected.is_positive() {
+        return 0.0;
+    }
+    let Some(diff) = paid.minor.checked_sub(expected.minor) else {
+        return 0.0;
+    };
+    let diff = diff.unsigned_abs() as f64 / expected.minor as f64;
+    if diff == 0.0 {
+        1.0
+    } else if diff <= AMOUNT_TOLERANCE {
+        1.0 - diff / AMOUNT_TOLERANCE / 2.0
+    } else {
+        0.0
+    }
+}
+
+impl Data {
+    /// How likely a transaction is to be a payment of `bill` judging by its
+    /// payee and memo, from 0 to 1. A payee confirmed for the bill before
+    /// counts as certain.
+    pub fn payee_score(&self, transaction: &BankTransaction, bill: &Bill) -> f64 {
+        let learned = self
+            .settings
+            .payee_bills
+            .get(&payee_key(&transaction.payee));
+        if learned == Some(&bill.id) {
+            return 1.0;
+        }
+        let memo = transaction.memo.as_deref().unwrap_or_default();
+        payee_similarity(&transaction.payee, &bill.name).max(payee_similarity(memo, &bill.name))
+    }
+
+    /// Payments already recorded for bank transactions.
+    pub fn recorded_transactions(&self) -> HashSet<&str> {
+        self.payments
+            .iter()
+            .filter_map(|p| p.transaction_id.as_deref())
+            .collect()
+    }
+
+    /// Pairs debits with unpaid occurrences due within the date window,
+    /// scoring each pair by amount, date and payee and keeping the best
+    /// ones; a transaction or occurrence is proposed at most once.
+    pub fn match_transactions(
+        &self,
+        transactions: &[BankTransaction],
+        options: &MatchOptions,
+    ) -> AppResult<MatchReport> {
+        let recorded = self.recorded_transactions();
+        let window = Days::new(u64::from(options.window_days));
+        let mut report = MatchReport {
+            proposals: Vec::new(),
+            unmatched: Vec::new(),
+            already_recorded: 0,
+            credits: 0,
+        };
+        let mut debits = Vec::new();
+        for transaction in transactions {
+            if recorded.contains(transaction.id.as_str()) {
+                report.already_recorded += 1;
+            } else if transaction.amount.is_positive() || transaction.amount.minor == 0 {
+                report.credits += 1;
+            } else {
+                debits.push(transaction);
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for (t, transaction) in debits.iter().enumerate() {
+            // An amount too large to negate matches nothing
+            let Some(paid) = transaction.amount.minor.checked_neg() else {
+                continue;
+            };
+            let paid = Money::new(paid, transaction.amount.currency);
+            let (Some(from), Some(until)) = (
+                transaction.date.checked_sub_days(window),
+                transaction.date.checked_add_days(window),
+            ) else {
+                continue;
+            };
+            for bill in &self.bills {
+                if bill.amount.currency != paid.currency {
+                    continue;
+                }
+                let payee = self.payee_score(transaction, bill);
+                // Earlier occurrences were settled when the bill rolled over;
+                // an overdue current one may be paid any time late
+                let start = from.max(bill.due_date);
+                let late = (!bill.paid && bill.due_date < from).then_some(bill.due_date);
+                let dates = bill
+                    .occurrences()
+                    .skip_while(|d| *d < start)
+                    .take_while(|d| *d <= until);
+                for date in late.into_iter().chain(dates) {
+                    let balance = self.balance(bill, date)?;
+                    if !balance.is_positive() {
+                        continue;
+                    }
+                    let amount = amount_score(paid, balance).max(amount_score(paid, bill.amount));
+                    if amount == 0.0 && payee < STRONG_PAYEE {
+                        continue;
+                    }
+                    let days = (transaction.date - date).num_days().unsigned_abs() as f64;
+                    let timing = (1.0 - days / (f64::from(options.window_days) + 1.0)).max(0.0);
+                    let score = 0.45 * amount + 0.2 * timing + 0.35 * payee;
+                    if score >= MIN_SCORE {
+                        candidates.push((score, t, bill, date, balance));
+                    }
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut used_transactions = HashSet::new();
+        let mut used_occurrences = HashSet::new();
+        for (score, t, bill, date, balance) in candidates {
+            if used_transactions.contains(&t) || used_occurrences.contains(&(&bill.id, date)) {
+                continue;
+            }
+            used_transactions.insert(t);
+            used_occurrences.insert((&bill.id, date));
+            report.proposals.push(PaymentProposal {
+                transaction: debits[t].clone(),
+                bill_id: bill.id.clone(),
+                bill_name: bill.name.clone(),
+                occurrence_date: date,
+                balance,
+                score: (score * 100.0).round() / 100.0,
+            });
+        }
+        report.proposals.sort_by_key(|p| p.transaction.date);
+        report.unmatched = debits
+            .iter()
+            .enumerate()
+            .filter(|(t, _)| !used_transactions.contains(t))
+            .map(|(_, transaction)| (*transaction).clone())
+            .collect();
+        Ok(report)
+    }
+
+    /// Records the confirmed matches as payments, in date order so a bill
+    /// rolls over occurrence by occurrence, and remembers each payee for its
+    /// bill so later statements match it outright.
+    pub fn record_bank_payments(
+        &mut self,
+        mut matches: Vec<BankMatch>,
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> AppResult<Vec<Payment>> {
+        matches.sort_by_key(|m| (m.occurrence_date, m.paid_date));
+        let mut payments = Vec::new();
+        for m in matches {
+            if self
+                .recorded_transactions()
+                .contains(m.transaction_id.as_str())
+            {
+                return Err(AppError::Conflict(format!(
+                    "Transaction {} is already recorded as a payment",
+                    m.transaction_id
+                )));
+            }
+            let key = payee_key(&m.payee);
+            if !key.is_empty() {
+                self.settings.payee_bills.insert(key, m.bill_id.clone());
+            }
+            let input = PaymentInput {
+                bill_id: m.bill_id,
+                occurrence_date: Some(m.occurrence_date),
+                paid_date: Some(m.paid_date),
+                amount: Some(m.amount),
+                method: None,
+                confirmation: None,
+                transaction_id: Some(m.transaction_id),
+            };
+            payments.push(self.record_payment(input, today, now)?);
+        }
+        Ok(payments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bill::Bill;
+    use crate::money::Currency;
+    use crate::testing::{add_bill, date as day, usd};
+
+    fn debit(id: &str, date: &str, minor: i64, payee: &str) -> BankTransaction {
+        BankTransaction {
+            id: id.to_string(),
+            date: day(date),
+            amount: usd(-minor),
+            payee: payee.to_string(),
+            memo: None,
+        }
+    }
+
+    fn confirm(t: &BankTransaction, bill: &Bill, occurrence: &str) -> BankMatch {
+        BankMatch {
+            transaction_id: t.id.clone(),
+            payee: t.payee.clone(),
+            bill_id: bill.id.clone(),
+            occurrence_date: day(occurrence),
+            paid_date: t.date,
+            amount: usd(-t.amount.minor),
+        }
+    }
+
+    #[test]
+    fn compares_payees() {
+        assert_eq!(payee_similarity("NETFLIX.COM 866-579", "Netflix"), 1.0);
+        assert_eq!(payee_similarity("SEPA DD City Water", "city water"), 1.0);
+        assert_eq!(payee_similarity("Payment 123", "Rent"), 0.0);
+        assert_eq!(payee_similarity("", "Rent"), 0.0);
+        let close = payee_similarity("Comcast Cable", "Comcastcable Inc");
+        assert!(close > 0.8 && close < 1.0, "{close}");
+        assert!(payee_similarity("Spotify", "Electric Co") < 0.2);
+        assert_eq!(payee_key("POS DEBIT NETFLIX.COM 866-579"), "netflix");
+        assert_eq!(payee_key("ACH 1234"), "");
+    }
+
+    #[test]
+    fn scores_amounts_within_tolerance() {
+        assert_eq!(amount_score(usd(10000), usd(10000)), 1.0);
+        assert_eq!(amount_score(usd(10500), usd(10000)), 0.75);
+        assert_eq!(amount_score(usd(9000), usd(10000)), 0.5);
+        assert_eq!(amount_score(usd(8999), usd(10000)), 0.0);
+        assert_eq!(amount_score(usd(10000), usd(0)), 0.0);
+        let eur = Money::new(10000, Currency::parse("EUR").unwrap());
+        assert_eq!(amount_score(eur, usd(10000)), 0.0);
+        // Too far apart to subtract
+        assert_eq!(amount_score(usd(i64::MIN), usd(10000)), 0.0);
+    }
+
+    #[test]
+    fn proposes_the_best_match_once() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent", 120000, "2024-01-31");
+        let water = add_bill(&mut data, "City Water", 4500, "2024-02-03");
+        let transactions = [
+            debit("1", "2024-01-31", 120000, "Landlord Ltd"),
+            debit("2", "2024-02-01", 120000, "Landlord Ltd"),
+            debit("3", "2024-02-03", 4700, "SEPA DD CITY WATER"),
+            debit("4", "2024-02-02", 999, "Coffee"),
+            BankTransaction {
+                amount: usd(5000),
+                ..debit("5", "2024-02-02", 0, "Refund")
+            },
+        ];
+        let report = data
+            .match_transactions(&transactions, &MatchOptions::default())
+            .unwrap();
+        let proposals: Vec<_> = report
+            .proposals
+            .iter()
+            .map(|p| (p.transaction.id.as_str(), p.bill_id.as_str(), p.score))
+            .collect();
+        // Weighted amount, timing and payee: 0.45 * 0.78 + 0.2 * 1 + 0.35 * 1
+        assert_eq!(
+            proposals,
+            [("1", rent.id.as_str(), 0.65), ("3", water.id.as_str(), 0.9)]
+        );
+        let unmatched: Vec<_> = report.unmatched.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(unmatched, ["2", "4"]);
+        assert_eq!(report.credits, 1);
+    }
+
+    #[test]
+    fn strong_payee_matches_other_amounts() {
+        let mut data = Data::fresh();
+        let power = add_bill(&mut data, "Power", 8000, "2024-01-20");
+        let report = data
+            .match_transactions(
+                &[debit("1", "2024-01-20", 16000, "POWER")],
+                &MatchOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(report.proposals[0].bill_id, power.id);
+        assert_eq!(report.proposals[0].score, 0.55);
+
+        // Outside the window it is not a match
+        let options = MatchOptions { window_days: 2 };
+        let report = data
+            .match_transactions(&[debit("2", "2024-01-10", 8000, "Other")], &options)
+            .unwrap();
+        assert!(report.proposals.is_empty());
+    }
+
+    #[test]
+    fn skips_amounts_that_cannot_be_negated() {
+        let mut data = Data::fresh();
+        add_bill(&mut data, "Power", 8000, "2024-01-20");
+        let huge = BankTransaction {
+            amount: usd(i64::MIN),
+            ..debit("1", "2024-01-20", 0, "Power")
+        };
+        let report = data
+            .match_transactions(&[huge], &MatchOptions::default())
+            .unwrap();
+        assert!(report.proposals.is_empty());
+        assert_eq!(report.unmatched.len(), 1);
+    }
+
+    #[test]
+    fn records_matches_and_learns_payees() {
+        let mut data = Data::fresh();
+        let phone = add_bill(&mut data, "Phone", 5000, "2024-01-10");
+        let january = debit("1", "2024-01-09", 5000, "VZW WEBPAY 8001");
+        let february = debit("2", "2024-02-10", 5000, "VZW WEBPAY 8002");
+        // Given out of order, recorded occurrence by occurrence
+        let matches = vec![
+            confirm(&february, &phone, "2024-02-10"),
+            confirm(&january, &phone, "2024-01-10"),
+        ];
+        let payments = data
+            .record_bank_payments(matches, day("2024-02-10"), Utc::now())
+            .unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(data.bill(&phone.id).unwrap().due_date, day("2024-03-10"));
+        assert_eq!(data.settings.payee_bills["vzw webpay"], phone.id);
+
+        let march = debit("3", "2024-03-11", 5100, "VZW WEBPAY 8003");
+        let report = data
+            .match_transactions(&[january.clone(), march], &MatchOptions::default())
+            .unwrap();
+        assert_eq!(report.already_recorded, 1);
+        assert_eq!(report.proposals[0].score, 0.93);
+
+        let err = data
+            .record_bank_payments(
+                vec![confirm(&january, &phone, "2024-03-10")],
+                day("2024-03-10"),
+                Utc::now(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::category::normalize_tags;
+use crate::error::{AppError, AppResult};
+use crate::money::Money;
+use crate::recurrence::{Recurrence, RecurrenceLimits};
+
+/// A bill as stored and shown in the list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bill {
+    pub id: String,
+    pub name: String,
+    pub amount: Money,
+    pub due_date: NaiveDate,
+    pub recurrence: Recurrence,
+    /// First due date of the recurring series. Absent in data written before
+    /// it existed, in which case the current due date starts the series.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "RecurrenceLimits::is_empty")]
+    pub limits: RecurrenceLimits,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    pub paid: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Bill {
+    pub fn series_start(&self) -> NaiveDate {
+        self.start_date.unwrap_or(self.due_date)
+    }
+
+    /// Every due date of the series, with end conditions and pauses applied.
+    pub fn occurrences(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+        self.limits
+            .apply(self.recurrence.occurrences(self.series_start()))
+    }
+
+    /// First due date after `date`, if the series continues past it.
+    pub fn next_due_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        self.occurrences().find(|d| *d > date)
+    }
+
+    /// Whether the series has an occurrence due on `date`.
+    pub fn is_occurrence(&self, date: NaiveDate) -> bool {
+        self.occurrences()
+            .take_while(|d| *d <= date)
+            .any(|d| d == date)
+    }
+
+    /// The next `n` due dates, starting with the current one.
+    pub fn next_occurrences(&self, n: usize) -> Vec<NaiveDate> {
+        self.occurrences()
+            .skip_while(|d| *d < self.due_date)
+            .take(n)
+            .collect()
+    }
+}
+
+/// A bill with the payment state of its current occurrence, as listed in the
+/// UI.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillView {
+    #[serde(flatten)]
+    pub bill: Bill,
+    /// Paid so far towards the current occurrence.
+    pub paid_amount: Money,
+    /// Still owed for the current occurrence.
+    pub balance: Money,
+}
+
+/// Narrows the bill list. Empty fields match every bill.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillFilter {
+    /// Only bills in this category; an empty string selects uncategorized
+    /// bills.
+    #[serde(default)]
+    pub category_id: Option<String>,
+    /// Only bills carrying all of these tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Case-insensitive text to look for in the name or notes.
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl BillFilter {
+    pub fn matches(&self, bill: &Bill) -> bool {
+        let category_ok = match self.category_id.as_deref() {
+            None => true,
+            Some("") => bill.category_id.is_none(),
+            Some(id) => bill.category_id.as_deref() == Some(id),
+        };
+        let tags_ok = normalize_tags(self.tags.clone())
+            .iter()
+            .all(|t| bill.tags.contains(t));
+        let text_ok = match self.text.as_deref().map(str::trim) {
+            None | Some("") => true,
+            Some(text) => {
+                let text = text.to_lowercase();
+                bill.name.to_lowercase().contains(&text)
+                    || bill
+                        .notes
+                        .as_deref()
+                        .is_some_and(|n| n.to_lowercase().contains(&text))
+            }
+        };
+        category_ok && tags_ok && text_ok
+    }
+}
+
+/// Fields submitted by the bill form. A missing `id` creates a new bill.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillInput {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    pub amount: Money,
+    pub due_date: String,
+    pub recurrence: Recurrence,
+    #[serde(default)]
+    pub limits: RecurrenceLimits,
+    #[serde(default)]
+    pub category_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// `updatedAt` of the bill the form was filled from; edits against an
+    /// older version are rejected instead of silently overwriting.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl BillInput {
+    /// Checks the form rules and returns the parsed due date.
+    pub fn validate(&self) -> AppResult<NaiveDate> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::validation("Bill name is required"));
+        }
+        if !self.amount.is_positive() {
+            return Err(AppError::validation("Amount must be greater than 0"));
+        }
+        self.recurrence.validate()?;
+        if self.due_date.trim().is_empty() {
+            return Err(AppError::validation("Due date is required"));
+        }
+        let due_date = NaiveDate::parse_from_str(self.due_date.trim(), "%Y-%m-%d")
+            .map_err(|_| AppError::validation("Invalid due date format"))?;
+        self.limits.validate(due_date)?;
+        Ok(due_date)
+    }
+
+    /// Trimmed notes, with blank input treated as no notes.
+    pub fn clean_notes(&self) -> Option<String> {
+        self.notes
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::testing::{add_bill, bill_input, date, usd};
+
+    #[test]
+    fn validate_accepts_a_complete_form() {
+        let input = bill_input(" Rent ", 120_000, " 2024-01-31 ");
+        assert_eq!(input.validate().unwrap(), date("2024-01-31"));
+    }
+
+    #[test]
+    fn validate_rejects_bad_fields() {
+        let input = || bill_input("Rent", 120_000, "2024-01-31");
+        let cases = [
+            (
+                BillInput {
+                    name: "  ".to_string(),
+                    ..input()
+                },
+                "Bill name is required",
+            ),
+            (
+                BillInput {
+                    amount: usd(0),
+                    ..input()
+                },
+                "Amount must be greater than 0",
+            ),
+            (
+                BillInput {
+                    amount: usd(-1),
+                    ..input()
+                },
+                "Amount must be greater than 0",
+            ),
+            (
+                BillInput {
+                    recurrence: Recurrence::EveryNDays { days: 0 },
+                    ..input()
+                },
+                "Interval must be at least 1 day",
+            ),
+            (
+                BillInput {
+                    limits: RecurrenceLimits {
+                        end_date: Some(date("2023-12-31")),
+                        ..Default::default()
+                    },
+                    ..input()
+                },
+                "End date must not be before the due date",
+            ),
+            (
+                BillInput {
+                    due_date: String::new(),
+                    ..input()
+                },
+                "Due date is required",
+            ),
+            (
+                BillInput {
+                    due_date: "31/01/2024".to_string(),
+                    ..input()
+                },
+                "Invalid due date format",
+            ),
+            (
+                BillInput {
+                    due_date: "2023-02-29".to_string(),
+                    ..input()
+                },
+                "Invalid due date format",
+            ),
+        ];
+        for (case, message) in cases {
+            let err = case.validate().unwrap_err();
+            assert_eq!(
+                (err.code(), err.to_string()),
+                ("validation", message.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn clean_notes_drops_blank_notes() {
+        let notes = |n: Option<&str>| {
+            BillInput {
+                notes: n.map(str::to_string),
+                ..bill_input("Rent", 120_000, "2024-01-31")
+            }
+            .clean_notes()
+        };
+        assert_eq!(notes(None), None);
+        assert_eq!(notes(Some("  \n ")), None);
+        assert_eq!(
+            notes(Some(" gate code 12 ")).as_deref(),
+            Some("gate code 12")
+        );
+    }
+
+    #[test]
+    fn next_occurrences_start_at_the_current_due_date() {
+        let mut data = Data::default();
+        let mut bill = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        bill.due_date = date("2024-03-31");
+        assert_eq!(
+            bill.next_occurrences(3),
+            [date("2024-03-31"), date("2024-04-30"), date("2024-05-31")]
+        );
+        // Bills saved before series existed start from their due date
+        bill.start_date = None;
+        assert_eq!(bill.series_start(), date("2024-03-31"));
+    }
+
+    #[test]
+    fn filters_by_category_tags_and_text() {
+        let mut data = Data::default();
+        let mut power = add_bill(&mut data, "Electric", 6000, "2024-01-10");
+        power.category_id = Some("utilities".to_string());
+        power.tags = vec!["home".to_string(), "energy".to_string()];
+        power.notes = Some("Smart meter".to_string());
+        let mut gym = add_bill(&mut data, "Gym", 3000, "2024-01-01");
+        gym.tags = vec!["health".to_string()];
+
+        let matches = |filter: BillFilter| {
+            [&power, &gym]
+                .into_iter()
+                .filter(|b| filter.matches(b))
+                .map(|b| b.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matches(BillFilter::default()), ["Electric", "Gym"]);
+        let category = |id: &str| BillFilter {
+            category_id: Some(id.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(matches(category("utilities")), ["Electric"]);
+        assert_eq!(matches(category("")), ["Gym"]);
+        let tags = |tags: &[&str]| BillFilter {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(matches(tags(&[" HOME", "energy"])), ["Electric"]);
+        assert!(matches(tags(&["home", "health"])).is_empty());
+        let text = |text: &str| BillFilter {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(matches(text("METER")), ["Electric"]);
+        assert_eq!(matches(text(" gym ")), ["Gym"]);
+        assert_eq!(matches(text("  ")), ["Electric", "Gym"]);
+    }
+}
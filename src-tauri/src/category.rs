@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bill::Bill;
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+
+/// A named group of bills such as "Utilities" or "Insurance".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+}
+
+/// Categories a first launch starts with.
+const DEFAULT_CATEGORIES: &[&str] = &[
+    "Utilities",
+    "Housing",
+    "Insurance",
+    "Subscriptions",
+    "Loans",
+    "Other",
+];
+
+impl Category {
+    fn new(name: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        DEFAULT_CATEGORIES
+            .iter()
+            .map(|name| Self::new(name))
+            .collect()
+    }
+}
+
+/// Trims and lowercases tags, dropping blanks and duplicates.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+impl Data {
+    pub fn category(&self, id: &str) -> AppResult<&Category> {
+        self.categories
+            .iter()
+            .find(|c| c.id == id)
+            .ok_or_else(|| AppError::not_found("Category", id))
+    }
+
+    /// Checks a category name and that no other category already uses it.
+    fn category_name(&self, name: &str, except_id: Option<&str>) -> AppResult<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::validation("Category name is required"));
+        }
+        let taken = self
+            .categories
+            .iter()
+            .any(|c| Some(c.id.as_str()) != except_id && c.name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(AppError::Conflict(format!(
+                "A category named \"{name}\" already exists"
+            )));
+        }
+        Ok(name.to_string())
+    }
+
+    pub fn create_category(&mut self, name: &str) -> AppResult<Category> {
+        let category = Category::new(&self.category_name(name, None)?);
+        self.categories.push(category.clone());
+        Ok(category)
+    }
+
+    /// Id of the category called `name`, ignoring case, which is created if
+    /// there is none; for imports, which name categories rather than pick
+    /// them. The names of created categories are added to `created`.
+    pub fn category_named(&mut self, name: &str, created: &mut Vec<String>) -> AppResult<String> {
+        let existing = self
+            .categories
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name.trim()));
+        match existing {
+            Some(category) => Ok(category.id.clone()),
+            None => {
+                let category = self.create_category(name)?;
+                created.push(category.name);
+                Ok(category.id)
+            }
+        }
+    }
+
+    pub fn rename_category(&mut self, id: &str, name: &str) -> AppResult<Category> {
+        let name = self.category_name(name, Some(id))?;
+        let category = self
+            .categories
+            .iter_mut()
+            .find(|c| c.id == id)
+            .ok_or_else(|| AppError::not_found("Category", id))?;
+        category.name = name;
+        Ok(category.clone())
+    }
+
+    /// Bills including trashed ones, which keep their category in step so
+    /// they restore into the right one.
+    fn bills_and_trash_mut(&mut self) -> impl Iterator<Item = &mut Bill> {
+        self.bills
+            .iter_mut()
+            .chain(self.trash.iter_mut().map(|t| &mut t.bill))
+    }
+
+    /// Moves every bill of `from_id` into `into_id`, then removes `from_id`.
+    pub fn merge_categories(&mut self, from_id: &str, into_id: &str) -> AppResult<Category> {
+        if from_id == into_id {
+            return Err(AppError::validation("Cannot merge a category into itself"));
+        }
+        self.category(from_id)?;
+        let into = self.category(into_id)?.clone();
+        for bill in self.bills_and_trash_mut() {
+            if bill.category_id.as_deref() == Some(from_id) {
+                bill.category_id = Some(into_id.to_string());
+            }
+        }
+        self.categories.retain(|c| c.id != from_id);
+        Ok(into)
+    }
+
+    /// Removes a category; its bills become uncategorized.
+    pub fn delete_category(&mut self, id: &str) -> AppResult<Category> {
+        let category = self.category(id)?.clone();
+        for bill in self.bills_and_trash_mut() {
+            if bill.category_id.as_deref() == Some(id) {
+                bill.category_id = None;
+            }
+        }
+        self.categories.retain(|c| c.id != id);
+        Ok(category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::bill::BillInput;
+    use crate::testing::bill_input;
+
+    fn names(data: &Data) -> Vec<&str> {
+        data.categories.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn tags_are_trimmed_lowercased_and_unique() {
+        let tags = ["Home", " car ", "", "home", "  "]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(normalize_tags(tags), ["car", "home"]);
+    }
+
+    #[test]
+    fn names_must_be_present_and_unique() {
+        let mut data = Data::fresh();
+        assert_eq!(names(&data), DEFAULT_CATEGORIES);
+        let pets = data.create_category("  Pets ").unwrap();
+        assert_eq!(pets.name, "Pets");
+
+        let err = data.create_category(" ").unwrap_err();
+        assert_eq!(err.to_string(), "Category name is required");
+        let err = data.create_category("utilities").unwrap_err();
+        assert_eq!(
+            (err.code(), err.to_string()),
+            (
+                "conflict",
+                "A category named \"utilities\" already exists".to_string()
+            )
+        );
+        // Renaming may change the case of the category's own name
+        let renamed = data.rename_category(&pets.id, "PETS").unwrap();
+        assert_eq!(renamed.name, "PETS");
+        assert!(data.rename_category(&pets.id, "Loans").is_err());
+        assert_eq!(
+            data.rename_category("gone", "Gone").unwrap_err().code(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn merging_and_deleting_move_bills() {
+        let mut data = Data::fresh();
+        let power = data.create_category("Power").unwrap();
+        let utilities = data.categories[0].clone();
+        let bill = data
+            .upsert_bill(
+                BillInput {
+                    category_id: Some(power.id.clone()),
+                    ..bill_input("Electric", 6000, "2024-01-10")
+                },
+                Utc::now(),
+            )
+            .unwrap();
+        assert_eq!(bill.category_id.as_deref(), Some(power.id.as_str()));
+
+        assert!(data.merge_categories(&power.id, &power.id).is_err());
+        let into = data.merge_categories(&power.id, &utilities.id).unwrap();
+        assert_eq!(into, utilities);
+        assert!(data.category(&power.id).is_err());
+        assert_eq!(
+            data.bills[0].category_id.as_deref(),
+            Some(utilities.id.as_str())
+        );
+
+        data.delete_category(&utilities.id).unwrap();
+        assert_eq!(data.bills[0].category_id, None);
+        assert!(!names(&data).contains(&"Utilities"));
+    }
+
+    #[test]
+    fn bills_need_an_existing_category() {
+        let mut data = Data::fresh();
+        let input = BillInput {
+            category_id: Some("gone".to_string()),
+            ..bill_input("Electric", 6000, "2024-01-10")
+        };
+        let err = data.upsert_bill(input, Utc::now()).unwrap_err();
+        assert_eq!(err.to_string(), "Category gone not found");
+        // An empty id from the form's "none" option means uncategorized
+        let input = BillInput {
+            category_id: Some(String::new()),
+            ..bill_input("Electric", 6000, "2024-01-10")
+        };
+        assert_eq!(
+            data.upsert_bill(input, Utc::now()).unwrap().category_id,
+            None
+        );
+    }
+}
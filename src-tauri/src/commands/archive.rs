@@ -0,0 +1,40 @@
+use chrono::Utc;
+use tauri::State;
+
+use crate::archive::{self, RestoreMode, RestoreReport};
+use crate::error::AppResult;
+use crate::history::ChangeSource;
+use crate::state::AppState;
+
+/// The data as a zip archive, for a full backup.
+#[tauri::command]
+pub fn export_archive(state: State<'_, AppState>) -> AppResult<Vec<u8>> {
+    state.read(|data| data.export_archive(Utc::now()))
+}
+
+/// Restores an archive from [`export_archive`] once every file in it has
+/// been verified. A dry run reports the same without changing anything.
+#[tauri::command]
+pub fn import_archive(
+    state: State<'_, AppState>,
+    archive: Vec<u8>,
+    mode: RestoreMode,
+    dry_run: bool,
+) -> AppResult<RestoreReport> {
+    let archive = archive::read_archive(&archive)?;
+    match mode {
+        RestoreMode::Replace => {
+            let report = archive.replace_report();
+            if !dry_run {
+                state.restore_data(archive.data, Utc::now())?;
+            }
+            Ok(report)
+        }
+        RestoreMode::Merge if dry_run => {
+            state.read(|data| Ok(data.clone().merge_archive(&archive)))
+        }
+        RestoreMode::Merge => state.write_as(ChangeSource::Import, "Merge archive", |data| {
+            Ok(data.merge_archive(&archive))
+        }),
+    }
+}
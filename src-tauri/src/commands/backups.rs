@@ -0,0 +1,16 @@
+use chrono::Utc;
+use tauri::State;
+
+use crate::backup::Backup;
+use crate::error::AppResult;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn list_backups(state: State<'_, AppState>) -> AppResult<Vec<Backup>> {
+    state.list_backups()
+}
+
+#[tauri::command]
+pub fn restore_backup(state: State<'_, AppState>, name: String) -> AppResult<()> {
+    state.restore_backup(&name, Utc::now())
+}
@@ -0,0 +1,32 @@
+use chrono::{Local, Utc};
+use tauri::State;
+
+use crate::bank::{BankMatch, MatchOptions, MatchReport};
+use crate::error::AppResult;
+use crate::history::ChangeSource;
+use crate::ofx;
+use crate::payment::Payment;
+use crate::state::AppState;
+
+/// Reads an OFX or QFX statement and proposes payments for the debits that
+/// match unpaid bills. Nothing is recorded until the proposals are confirmed.
+#[tauri::command]
+pub fn import_ofx(
+    state: State<'_, AppState>,
+    ofx: String,
+    options: MatchOptions,
+) -> AppResult<MatchReport> {
+    let transactions = ofx::parse_ofx(&ofx)?;
+    state.read(|data| data.match_transactions(&transactions, &options))
+}
+
+/// Records confirmed matches as payments, remembering their transactions.
+#[tauri::command]
+pub fn record_bank_payments(
+    state: State<'_, AppState>,
+    matches: Vec<BankMatch>,
+) -> AppResult<Vec<Payment>> {
+    state.write_as(ChangeSource::Import, "Record bank payments", |data| {
+        data.record_bank_payments(matches, Local::now().date_naive(), Utc::now())
+    })
+}
@@ -0,0 +1,69 @@
+use chrono::{Local, NaiveDate, Utc};
+use tauri::State;
+
+use crate::bill::{Bill, BillFilter, BillInput, BillView};
+use crate::error::{AppError, AppResult};
+use crate::history::HistoryEntry;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn list_bills(
+    state: State<'_, AppState>,
+    filter: Option<BillFilter>,
+) -> AppResult<Vec<BillView>> {
+    state.read(|data| data.bill_views(&filter.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub fn get_bill(state: State<'_, AppState>, id: String) -> AppResult<Bill> {
+    state.read(|data| {
+        data.bill(&id)
+            .cloned()
+            .ok_or_else(|| AppError::not_found("Bill", &id))
+    })
+}
+
+#[tauri::command]
+pub fn upsert_bill(state: State<'_, AppState>, input: BillInput) -> AppResult<Bill> {
+    state.write("Save bill", |data| data.upsert_bill(input, Utc::now()))
+}
+
+#[tauri::command]
+pub fn delete_bill(state: State<'_, AppState>, id: String) -> AppResult<Bill> {
+    state.write("Delete bill", |data| data.delete_bill(&id, Utc::now()))
+}
+
+#[tauri::command]
+pub fn set_paid(state: State<'_, AppState>, id: String, paid: bool) -> AppResult<Bill> {
+    let label = if paid { "Mark paid" } else { "Mark unpaid" };
+    state.write(label, |data| {
+        data.set_paid(&id, paid, Local::now().date_naive(), Utc::now())
+    })
+}
+
+/// Changes recorded for a bill, newest first.
+#[tauri::command]
+pub fn bill_history(state: State<'_, AppState>, id: String) -> AppResult<Vec<HistoryEntry>> {
+    state.read(|data| data.bill_history(&id))
+}
+
+/// Longest schedule a single `next_occurrences` call will compute.
+const MAX_OCCURRENCES: usize = 1000;
+
+#[tauri::command]
+pub fn next_occurrences(
+    state: State<'_, AppState>,
+    id: String,
+    n: usize,
+) -> AppResult<Vec<NaiveDate>> {
+    if n > MAX_OCCURRENCES {
+        return Err(AppError::validation(format!(
+            "At most {MAX_OCCURRENCES} occurrences can be requested"
+        )));
+    }
+    state.read(|data| {
+        data.bill(&id)
+            .map(|bill| bill.next_occurrences(n))
+            .ok_or_else(|| AppError::not_found("Bill", &id))
+    })
+}
@@ -0,0 +1,41 @@
+use tauri::State;
+
+use crate::category::Category;
+use crate::error::AppResult;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn list_categories(state: State<'_, AppState>) -> AppResult<Vec<Category>> {
+    state.read(|data| Ok(data.categories.clone()))
+}
+
+#[tauri::command]
+pub fn create_category(state: State<'_, AppState>, name: String) -> AppResult<Category> {
+    state.write("Create category", |data| data.create_category(&name))
+}
+
+#[tauri::command]
+pub fn rename_category(
+    state: State<'_, AppState>,
+    id: String,
+    name: String,
+) -> AppResult<Category> {
+    state.write("Rename category", |data| data.rename_category(&id, &name))
+}
+
+/// Moves all bills of `from_id` into `into_id` and deletes `from_id`.
+#[tauri::command]
+pub fn merge_categories(
+    state: State<'_, AppState>,
+    from_id: String,
+    into_id: String,
+) -> AppResult<Category> {
+    state.write("Merge categories", |data| {
+        data.merge_categories(&from_id, &into_id)
+    })
+}
+
+#[tauri::command]
+pub fn delete_category(state: State<'_, AppState>, id: String) -> AppResult<Category> {
+    state.write("Delete category", |data| data.delete_category(&id))
+}
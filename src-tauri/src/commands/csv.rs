@@ -0,0 +1,40 @@
+use chrono::Utc;
+use tauri::State;
+
+use crate::csv::{self, CsvImport, CsvImportReport, CsvPreview};
+use crate::error::AppResult;
+use crate::history::ChangeSource;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn export_bills_csv(state: State<'_, AppState>) -> AppResult<String> {
+    state.read(|data| Ok(data.export_bills_csv()))
+}
+
+#[tauri::command]
+pub fn export_payments_csv(state: State<'_, AppState>) -> AppResult<String> {
+    state.read(|data| Ok(data.export_payments_csv()))
+}
+
+/// Headers, first rows and the guessed column mapping of a bill CSV.
+#[tauri::command]
+pub fn preview_bills_csv(csv: String) -> AppResult<CsvPreview> {
+    csv::preview_bills(&csv)
+}
+
+/// Imports the valid rows of a bill CSV and reports the others. A dry run
+/// reports the same without changing anything.
+#[tauri::command]
+pub fn import_bills_csv(
+    state: State<'_, AppState>,
+    csv: String,
+    options: CsvImport,
+    dry_run: bool,
+) -> AppResult<CsvImportReport> {
+    if dry_run {
+        return state.read(|data| data.clone().import_bills_csv(&csv, &options, Utc::now()));
+    }
+    state.write_as(ChangeSource::Import, "Import bills", |data| {
+        data.import_bills_csv(&csv, &options, Utc::now())
+    })
+}
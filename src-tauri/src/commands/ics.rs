@@ -0,0 +1,33 @@
+use chrono::{Local, Utc};
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::history::ChangeSource;
+use crate::ics::{IcsImport, IcsImportReport};
+use crate::state::AppState;
+
+/// The bills as an iCalendar file to subscribe to or import into a
+/// calendar app.
+#[tauri::command]
+pub fn export_ics(state: State<'_, AppState>) -> AppResult<String> {
+    state.read(|data| Ok(data.export_ics(Local::now().date_naive(), Utc::now())))
+}
+
+/// Creates bills from the events of an iCalendar file and reports the
+/// events it skipped. A dry run previews the same without changing
+/// anything.
+#[tauri::command]
+pub fn import_ics(
+    state: State<'_, AppState>,
+    ics: String,
+    options: IcsImport,
+    dry_run: bool,
+) -> AppResult<IcsImportReport> {
+    let today = Local::now().date_naive();
+    if dry_run {
+        return state.read(|data| data.clone().import_ics(&ics, &options, today, Utc::now()));
+    }
+    state.write_as(ChangeSource::Import, "Import calendar", |data| {
+        data.import_ics(&ics, &options, today, Utc::now())
+    })
+}
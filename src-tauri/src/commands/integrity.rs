@@ -0,0 +1,20 @@
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::integrity::{IntegrityReport, RepairRequest};
+use crate::state::AppState;
+
+/// Validates every record and lists what is wrong, changing nothing.
+#[tauri::command]
+pub fn check_integrity(state: State<'_, AppState>) -> AppResult<IntegrityReport> {
+    state.read(|data| Ok(data.check_integrity()))
+}
+
+/// Applies repairs picked from the latest report; returns a fresh report.
+#[tauri::command]
+pub fn repair_integrity(
+    state: State<'_, AppState>,
+    repairs: Vec<RepairRequest>,
+) -> AppResult<IntegrityReport> {
+    state.repair_integrity(&repairs)
+}
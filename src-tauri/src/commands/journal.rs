@@ -0,0 +1,22 @@
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::journal::JournalStatus;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn journal_status(state: State<'_, AppState>) -> AppResult<JournalStatus> {
+    state.journal_status()
+}
+
+/// Reverts the latest change; returns its label.
+#[tauri::command]
+pub fn undo(state: State<'_, AppState>) -> AppResult<String> {
+    state.undo()
+}
+
+/// Re-applies the latest undone change; returns its label.
+#[tauri::command]
+pub fn redo(state: State<'_, AppState>) -> AppResult<String> {
+    state.redo()
+}
@@ -0,0 +1,24 @@
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::ledger::{LedgerAccounts, LedgerExport};
+use crate::settings::Settings;
+use crate::state::AppState;
+
+/// Recorded payments as a ledger, hledger or beancount journal.
+#[tauri::command]
+pub fn export_ledger(state: State<'_, AppState>, options: LedgerExport) -> AppResult<String> {
+    state.read(|data| data.export_ledger(&options))
+}
+
+/// Accounts payments are booked to, by category and payment method.
+#[tauri::command]
+pub fn set_ledger_accounts(
+    state: State<'_, AppState>,
+    accounts: LedgerAccounts,
+) -> AppResult<Settings> {
+    state.write("Change ledger accounts", |data| {
+        data.settings.ledger_accounts = accounts.clean(data)?;
+        Ok(data.settings.clone())
+    })
+}
@@ -0,0 +1,19 @@
+pub mod archive;
+pub mod backups;
+pub mod bank;
+pub mod bills;
+pub mod categories;
+pub mod csv;
+pub mod ics;
+pub mod integrity;
+pub mod journal;
+pub mod ledger;
+pub mod payments;
+pub mod profiles;
+pub mod rates;
+pub mod reconcile;
+pub mod reports;
+pub mod settings;
+pub mod startup;
+pub mod trash;
+pub mod vault;
@@ -0,0 +1,24 @@
+use chrono::{Local, Utc};
+use tauri::State;
+
+use crate::error::{AppError, AppResult};
+use crate::payment::{Payment, PaymentInput};
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn record_payment(state: State<'_, AppState>, input: PaymentInput) -> AppResult<Payment> {
+    state.write("Record payment", |data| {
+        data.record_payment(input, Local::now().date_naive(), Utc::now())
+    })
+}
+
+/// Payment history of a bill, most recent first.
+#[tauri::command]
+pub fn list_payments(state: State<'_, AppState>, bill_id: String) -> AppResult<Vec<Payment>> {
+    state.read(|data| {
+        if data.bill(&bill_id).is_none() {
+            return Err(AppError::not_found("Bill", &bill_id));
+        }
+        Ok(data.payments_for(&bill_id))
+    })
+}
@@ -0,0 +1,32 @@
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::profile::{Profile, ProfileList};
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn list_profiles(state: State<'_, AppState>) -> ProfileList {
+    state.list_profiles()
+}
+
+#[tauri::command]
+pub fn create_profile(state: State<'_, AppState>, name: String) -> AppResult<Profile> {
+    state.create_profile(&name)
+}
+
+#[tauri::command]
+pub fn rename_profile(state: State<'_, AppState>, id: String, name: String) -> AppResult<Profile> {
+    state.rename_profile(&id, &name)
+}
+
+/// Deletes a profile other than the open one, with all of its data.
+#[tauri::command]
+pub fn delete_profile(state: State<'_, AppState>, id: String) -> AppResult<Profile> {
+    state.delete_profile(&id)
+}
+
+/// Opens the profile `id`; the UI reloads everything afterwards.
+#[tauri::command]
+pub fn switch_profile(state: State<'_, AppState>, id: String) -> AppResult<ProfileList> {
+    state.switch_profile(&id)
+}
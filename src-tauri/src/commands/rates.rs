@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::fx::{self, ExchangeRate};
+use crate::money::MINOR_DIGITS;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn list_rates(state: State<'_, AppState>) -> AppResult<Vec<ExchangeRate>> {
+    state.read(|data| Ok(data.rates.clone()))
+}
+
+/// Adds or replaces a single manually entered rate.
+#[tauri::command]
+pub fn set_rate(state: State<'_, AppState>, rate: ExchangeRate) -> AppResult<()> {
+    state.write("Set exchange rate", |data| {
+        data.import_rates(vec![rate]).map(drop)
+    })
+}
+
+/// Imports the contents of an ECB-style rate CSV; returns the number of
+/// rates stored.
+#[tauri::command]
+pub fn import_rates(state: State<'_, AppState>, csv: String) -> AppResult<usize> {
+    let rates = fx::parse_ecb_csv(&csv)?;
+    state.write("Import exchange rates", |data| data.import_rates(rates))
+}
+
+/// Decimal places of the currencies that have other than two, for the UI
+/// to format and parse amounts exactly as they are stored.
+#[tauri::command]
+pub fn currency_digits() -> BTreeMap<&'static str, u32> {
+    MINOR_DIGITS.iter().copied().collect()
+}
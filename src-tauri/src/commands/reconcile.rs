@@ -0,0 +1,53 @@
+use chrono::Local;
+use tauri::State;
+
+use crate::bank::MatchOptions;
+use crate::error::AppResult;
+use crate::reconcile::{self, BankCsvPreview, BankProfile, Reconciliation};
+use crate::settings::Settings;
+use crate::state::AppState;
+
+/// The first rows of a bank CSV, with its columns guessed for a new profile.
+#[tauri::command]
+pub fn preview_bank_csv(csv: String) -> AppResult<BankCsvPreview> {
+    reconcile::preview_bank_csv(&csv)
+}
+
+/// Saves a bank profile, replacing the one of the same name.
+#[tauri::command]
+pub fn save_bank_profile(state: State<'_, AppState>, profile: BankProfile) -> AppResult<Settings> {
+    state.write("Save bank profile", |data| {
+        data.save_bank_profile(profile)?;
+        Ok(data.settings.clone())
+    })
+}
+
+#[tauri::command]
+pub fn delete_bank_profile(state: State<'_, AppState>, name: String) -> AppResult<Settings> {
+    state.write("Delete bank profile", |data| {
+        data.delete_bank_profile(&name)?;
+        Ok(data.settings.clone())
+    })
+}
+
+/// Reads a bank CSV with a saved profile and proposes payments for it.
+/// Nothing is recorded until the proposals are confirmed.
+#[tauri::command]
+pub fn reconcile_bank_csv(
+    state: State<'_, AppState>,
+    csv: String,
+    profile: String,
+    options: MatchOptions,
+) -> AppResult<Reconciliation> {
+    let today = Local::now().date_naive();
+    state.read(|data| data.reconcile_bank_csv(&csv, &profile, &options, today))
+}
+
+/// Stops matching a learned payee to its bill.
+#[tauri::command]
+pub fn forget_payee(state: State<'_, AppState>, payee: String) -> AppResult<Settings> {
+    state.write("Forget bank payee", |data| {
+        data.forget_payee(&payee)?;
+        Ok(data.settings.clone())
+    })
+}
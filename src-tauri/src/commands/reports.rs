@@ -0,0 +1,21 @@
+use chrono::{Local, NaiveDate};
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::fx::{ConvertedTotal, ForecastMonth};
+use crate::state::AppState;
+
+/// Outstanding balance of the given bills (all when `ids` is omitted) in the
+/// reporting currency.
+#[tauri::command]
+pub fn bill_totals(
+    state: State<'_, AppState>,
+    ids: Option<Vec<String>>,
+) -> AppResult<ConvertedTotal> {
+    state.read(|data| data.outstanding_total(ids.as_deref(), Local::now().date_naive()))
+}
+
+#[tauri::command]
+pub fn forecast(state: State<'_, AppState>, until: NaiveDate) -> AppResult<Vec<ForecastMonth>> {
+    state.read(|data| data.forecast(until, Local::now().date_naive()))
+}
@@ -0,0 +1,48 @@
+use tauri::State;
+
+use crate::error::AppResult;
+use crate::money::Currency;
+use crate::settings::Settings;
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn get_settings(state: State<'_, AppState>) -> AppResult<Settings> {
+    state.read(|data| Ok(data.settings.clone()))
+}
+
+#[tauri::command]
+pub fn set_reporting_currency(
+    state: State<'_, AppState>,
+    currency: Currency,
+) -> AppResult<Settings> {
+    state.write("Change reporting currency", |data| {
+        data.settings.reporting_currency = currency;
+        Ok(data.settings.clone())
+    })
+}
+
+#[tauri::command]
+pub fn set_auto_lock_minutes(state: State<'_, AppState>, minutes: u32) -> AppResult<Settings> {
+    state.write("Change auto-lock", |data| {
+        data.settings.auto_lock_minutes = minutes;
+        Ok(data.settings.clone())
+    })
+}
+
+/// Days before a due date that reminders, including calendar alarms, go off.
+#[tauri::command]
+pub fn set_reminder_days(state: State<'_, AppState>, days: u32) -> AppResult<Settings> {
+    state.write("Change reminders", |data| {
+        data.settings.reminder_days = days;
+        Ok(data.settings.clone())
+    })
+}
+
+/// Days deleted bills are kept in the trash; 0 keeps them until purged.
+#[tauri::command]
+pub fn set_trash_retention_days(state: State<'_, AppState>, days: u32) -> AppResult<Settings> {
+    state.write("Change trash retention", |data| {
+        data.settings.trash_retention_days = days;
+        Ok(data.settings.clone())
+    })
+}
@@ -0,0 +1,11 @@
+use tauri::State;
+
+use crate::state::StartupError;
+
+/// Why the data could not be opened when the app started, if it could not.
+/// Every other command fails until the app is restarted with the cause
+/// fixed.
+#[tauri::command]
+pub fn startup_error(error: State<'_, Option<StartupError>>) -> Option<StartupError> {
+    error.inner().clone()
+}
@@ -0,0 +1,24 @@
+use tauri::State;
+
+use crate::bill::Bill;
+use crate::error::AppResult;
+use crate::state::AppState;
+use crate::trash::TrashedBill;
+
+/// Deleted bills, most recently deleted first.
+#[tauri::command]
+pub fn list_trash(state: State<'_, AppState>) -> AppResult<Vec<TrashedBill>> {
+    state.read(|data| Ok(data.list_trash()))
+}
+
+#[tauri::command]
+pub fn restore_bill(state: State<'_, AppState>, id: String) -> AppResult<Bill> {
+    state.write("Restore bill", |data| data.restore_bill(&id))
+}
+
+/// Permanently deletes the trashed bill `id`, or everything in the trash;
+/// returns the number of bills purged. This cannot be undone.
+#[tauri::command]
+pub fn purge_trash(state: State<'_, AppState>, id: Option<String>) -> AppResult<usize> {
+    state.purge_trash(id.as_deref())
+}
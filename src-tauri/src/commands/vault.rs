@@ -0,0 +1,45 @@
+use tauri::State;
+use zeroize::Zeroizing;
+
+use crate::error::AppResult;
+use crate::state::AppState;
+use crate::vault::VaultStatus;
+
+#[tauri::command]
+pub fn vault_status(state: State<'_, AppState>) -> VaultStatus {
+    state.vault_status()
+}
+
+#[tauri::command]
+pub fn unlock_vault(state: State<'_, AppState>, passphrase: String) -> AppResult<()> {
+    let passphrase = Zeroizing::new(passphrase);
+    state.unlock_vault(&passphrase)
+}
+
+#[tauri::command]
+pub fn lock_vault(state: State<'_, AppState>) -> AppResult<()> {
+    state.lock_vault()
+}
+
+#[tauri::command]
+pub fn enable_vault(state: State<'_, AppState>, passphrase: String) -> AppResult<()> {
+    let passphrase = Zeroizing::new(passphrase);
+    state.enable_vault(&passphrase)
+}
+
+#[tauri::command]
+pub fn disable_vault(state: State<'_, AppState>, passphrase: String) -> AppResult<()> {
+    let passphrase = Zeroizing::new(passphrase);
+    state.disable_vault(&passphrase)
+}
+
+#[tauri::command]
+pub fn change_passphrase(
+    state: State<'_, AppState>,
+    old_passphrase: String,
+    new_passphrase: String,
+) -> AppResult<()> {
+    let old_passphrase = Zeroizing::new(old_passphrase);
+    let new_passphrase = Zeroizing::new(new_passphrase);
+    state.change_passphrase(&old_passphrase, &new_passphrase)
+}
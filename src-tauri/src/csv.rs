@@ -0,0 +1,916 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, BillInput};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::{Currency, Money};
+use crate::recurrence::{Recurrence, RecurrenceLimits};
+
+/// Rows shown in an import preview.
+pub const PREVIEW_ROWS: usize = 5;
+
+/// One line of a CSV file, split into cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Line the record starts on, counting from 1.
+    pub line: usize,
+    pub cells: Vec<String>,
+}
+
+/// Splits CSV text into records. Quoted cells may hold delimiters, doubled
+/// quotes and line breaks. The delimiter is whichever of `,`, `;` and tab is
+/// most common in the first line, so spreadsheet exports that use `;` read
+/// too. Blank lines are skipped.
+pub fn read_records(text: &str) -> AppResult<Vec<Record>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let first = text.lines().next().unwrap_or_default();
+    let delimiter = [',', ';', '\t']
+        .into_iter()
+        .max_by_key(|d| first.matches(*d).count())
+        .unwrap_or(',');
+
+    let mut records = Vec::new();
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let (mut line, mut start) = (1, 1);
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    cell.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if cell.trim().is_empty() => {
+                cell.clear();
+                quoted = true;
+            }
+            '\n' if quoted => {
+                line += 1;
+                cell.push(c);
+            }
+            '\r' if !quoted => {}
+            '\n' => {
+                cells.push(std::mem::take(&mut cell));
+                push_record(&mut records, start, std::mem::take(&mut cells));
+                line += 1;
+                start = line;
+            }
+            c if c == delimiter && !quoted => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    if quoted {
+        return Err(AppError::validation(format!(
+            "Line {start}: a quoted value is never closed"
+        )));
+    }
+    cells.push(cell);
+    push_record(&mut records, start, cells);
+    Ok(records)
+}
+
+fn push_record(records: &mut Vec<Record>, line: usize, cells: Vec<String>) {
+    if cells.iter().any(|c| !c.trim().is_empty()) {
+        records.push(Record { line, cells });
+    }
+}
+
+/// Appends one CSV line, quoting the cells that need it.
+pub fn write_row<S: AsRef<str>>(out: &mut String, cells: &[S]) {
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let cell = cell.as_ref();
+        if cell.contains([',', '"', '\n', '\r']) || cell.trim() != cell {
+            out.push('"');
+            out.push_str(&cell.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(cell);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// Date layouts recognized in imported files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DateFormat {
+    Iso,
+    YearMonthDay,
+    DayMonthYear,
+    MonthDayYear,
+    DayMonthYearDots,
+    DayMonthYearDashes,
+}
+
+impl DateFormat {
+    const ALL: [DateFormat; 6] = [
+        DateFormat::Iso,
+        DateFormat::YearMonthDay,
+        DateFormat::DayMonthYear,
+        DateFormat::MonthDayYear,
+        DateFormat::DayMonthYearDots,
+        DateFormat::DayMonthYearDashes,
+    ];
+
+    fn pattern(self) -> &'static str {
+        match self {
+            DateFormat::Iso => "%Y-%m-%d",
+            DateFormat::YearMonthDay => "%Y/%m/%d",
+            DateFormat::DayMonthYear => "%d/%m/%Y",
+            DateFormat::MonthDayYear => "%m/%d/%Y",
+            DateFormat::DayMonthYearDots => "%d.%m.%Y",
+            DateFormat::DayMonthYearDashes => "%d-%m-%Y",
+        }
+    }
+
+    /// How a date looks in this format, for messages.
+    pub fn example(self) -> &'static str {
+        match self {
+            DateFormat::Iso => "2024-01-31",
+            DateFormat::YearMonthDay => "2024/01/31",
+            DateFormat::DayMonthYear => "31/01/2024",
+            DateFormat::MonthDayYear => "01/31/2024",
+            DateFormat::DayMonthYearDots => "31.01.2024",
+            DateFormat::DayMonthYearDashes => "31-01-2024",
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(text.trim(), self.pattern()).ok()
+    }
+
+    /// Formats that read every non-blank value, most likely first. Both
+    /// day-first and month-first remain when no day is above 12.
+    pub fn detect<'a>(values: impl Iterator<Item = &'a str> + Clone) -> Vec<DateFormat> {
+        let mut values = values.filter(|v| !v.trim().is_empty()).peekable();
+        if values.peek().is_none() {
+            return Vec::new();
+        }
+        Self::ALL
+            .into_iter()
+            .filter(|f| values.clone().all(|v| f.parse(v).is_some()))
+            .collect()
+    }
+}
+
+/// Bill fields a CSV column can be imported into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BillColumn {
+    Name,
+    Amount,
+    Currency,
+    DueDate,
+    Recurrence,
+    Category,
+    Tags,
+    Notes,
+    /// Last day an occurrence may fall on.
+    EndDate,
+    /// Number of occurrences in the series.
+    MaxOccurrences,
+}
+
+impl BillColumn {
+    const ALL: [BillColumn; 10] = [
+        BillColumn::Name,
+        BillColumn::Amount,
+        BillColumn::Currency,
+        BillColumn::DueDate,
+        BillColumn::Recurrence,
+        BillColumn::Category,
+        BillColumn::Tags,
+        BillColumn::Notes,
+        BillColumn::EndDate,
+        BillColumn::MaxOccurrences,
+    ];
+
+    /// Header used on export, which import recognizes too.
+    fn header(self) -> &'static str {
+        match self {
+            BillColumn::Name => "Name",
+            BillColumn::Amount => "Amount",
+            BillColumn::Currency => "Currency",
+            BillColumn::DueDate => "Due date",
+            BillColumn::Recurrence => "Recurrence",
+            BillColumn::Category => "Category",
+            BillColumn::Tags => "Tags",
+            BillColumn::Notes => "Notes",
+            BillColumn::EndDate => "Ends on",
+            BillColumn::MaxOccurrences => "Occurrences",
+        }
+    }
+
+    /// Other headers commonly used for the field, normalized.
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            BillColumn::Name => &["bill", "payee", "title", "description"],
+            BillColumn::Amount => &["amountdue", "price", "cost", "total"],
+            BillColumn::Currency => &["ccy"],
+            BillColumn::DueDate => &["due", "duedate", "date", "nextdue"],
+            BillColumn::Recurrence => &["recurs", "repeat", "frequency", "interval"],
+            BillColumn::Category => &["group", "type"],
+            BillColumn::Tags => &["labels"],
+            BillColumn::Notes => &["note", "memo", "comment", "comments"],
+            BillColumn::EndDate => &["enddate", "ends", "until"],
+            BillColumn::MaxOccurrences => &["count", "installments"],
+        }
+    }
+
+    fn matches_header(self, header: &str) -> bool {
+        let header = normalize(header);
+        normalize(self.header()) == header || self.aliases().contains(&header.as_str())
+    }
+}
+
+/// Lowercases and drops everything but letters and digits, so `Due Date`,
+/// `due_date` and `due-date` compare equal.
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Which column of the file holds each field.
+pub type ColumnMapping = BTreeMap<BillColumn, usize>;
+
+/// What an import would read from a file, for the user to confirm the
+/// column mapping and date format.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvPreview {
+    pub headers: Vec<String>,
+    /// The first few rows after the header.
+    pub rows: Vec<Vec<String>>,
+    pub row_count: usize,
+    /// Columns matched to fields by their header.
+    pub mapping: ColumnMapping,
+    /// Formats that read every due date in the file, most likely first.
+    pub date_formats: Vec<DateFormat>,
+}
+
+/// How to import a file, as confirmed in the preview.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImport {
+    pub mapping: ColumnMapping,
+    /// Detected from the file when absent, which fails if the dates fit
+    /// more than one format.
+    #[serde(default)]
+    pub date_format: Option<DateFormat>,
+    /// Currency of rows without one; defaults to the reporting currency.
+    #[serde(default)]
+    pub currency: Option<Currency>,
+}
+
+/// A row that could not be imported and why.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    pub line: usize,
+    /// The field at fault, if the problem is with a single one.
+    pub column: Option<BillColumn>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportReport {
+    /// Bills created, or that would be created by a dry run.
+    pub bills: Vec<Bill>,
+    /// Categories created for names that did not exist yet.
+    pub new_categories: Vec<String>,
+    /// Rows left out; the others are imported regardless.
+    pub errors: Vec<RowError>,
+}
+
+/// Reads a file's header and first rows, and guesses the column mapping
+/// and date format.
+pub fn preview_bills(text: &str) -> AppResult<CsvPreview> {
+    let mut records = read_records(text)?.into_iter();
+    let headers = records
+        .next()
+        .ok_or_else(|| AppError::validation("The file is empty"))?
+        .cells;
+    let rows: Vec<Vec<String>> = records.map(|r| r.cells).collect();
+    let mut mapping = ColumnMapping::new();
+    for field in BillColumn::ALL {
+        let unused = |i: &usize| !mapping.values().any(|m| m == i);
+        if let Some(i) =
+            (0..headers.len()).find(|i| unused(i) && field.matches_header(&headers[*i]))
+        {
+            mapping.insert(field, i);
+        }
+    }
+    let date_formats = match mapping.get(&BillColumn::DueDate) {
+        Some(&col) => {
+            DateFormat::detect(rows.iter().filter_map(|r| r.get(col)).map(String::as_str))
+        }
+        None => Vec::new(),
+    };
+    Ok(CsvPreview {
+        row_count: rows.len(),
+        rows: rows.into_iter().take(PREVIEW_ROWS).collect(),
+        headers,
+        mapping,
+        date_formats,
+    })
+}
+
+/// Reads a recurrence as exported (`monthly`, or JSON for rules with
+/// parameters) or as commonly written (`Every 2 weeks`, `one-time`).
+fn parse_recurrence(text: &str) -> AppResult<Recurrence> {
+    let text = text.trim();
+    if text.starts_with('{') {
+        let recurrence: Recurrence = serde_json::from_str(text)
+            .map_err(|_| AppError::validation(format!("\"{text}\" is not a recurrence")))?;
+        recurrence.validate()?;
+        return Ok(recurrence);
+    }
+    Ok(match normalize(text).as_str() {
+        "" | "none" | "once" | "onetime" | "never" => Recurrence::None,
+        "daily" | "everyday" => Recurrence::Daily,
+        "weekly" | "everyweek" => Recurrence::Weekly,
+        "biweekly" | "fortnightly" | "every2weeks" => Recurrence::BiWeekly,
+        "monthly" | "everymonth" => Recurrence::Monthly,
+        "quarterly" | "every3months" => Recurrence::Quarterly,
+        "semiannual" | "semiannually" | "every6months" => Recurrence::SemiAnnual,
+        "yearly" | "annual" | "annually" | "everyyear" => Recurrence::Yearly,
+        "lastdayofmonth" => Recurrence::LastDayOfMonth,
+        "lastbusinessday" => Recurrence::LastBusinessDay,
+        _ => {
+            return Err(AppError::validation(format!(
+                "\"{text}\" is not a recurrence"
+            )))
+        }
+    })
+}
+
+/// Writes a recurrence the way [`parse_recurrence`] reads it.
+fn recurrence_text(recurrence: Recurrence) -> String {
+    match serde_json::to_value(recurrence) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// A row's fields, before they are checked against the bill rules.
+struct RowFields {
+    input: BillInput,
+    category: Option<String>,
+}
+
+impl Data {
+    /// All bills as CSV, one row each, with the category by name.
+    pub fn export_bills_csv(&self) -> String {
+        let mut out = String::new();
+        let mut headers: Vec<&str> = BillColumn::ALL.iter().map(|c| c.header()).collect();
+        headers.extend(["Paid", "Id"]);
+        write_row(&mut out, &headers);
+        for bill in &self.bills {
+            let category = bill
+                .category_id
+                .as_deref()
+                .and_then(|id| self.category(id).ok())
+                .map(|c| c.name.as_str());
+            write_row(
+                &mut out,
+                &[
+                    bill.name.as_str(),
+                    &bill.amount.decimal(),
+                    bill.amount.currency.code(),
+                    &bill.due_date.to_string(),
+                    &recurrence_text(bill.recurrence),
+                    category.unwrap_or_default(),
+                    &bill.tags.join(", "),
+                    bill.notes.as_deref().unwrap_or_default(),
+                    &bill
+                        .limits
+                        .end_date
+                        .map(|d| d.to_string())
+                        .unwrap_or_default(),
+                    &bill
+                        .limits
+                        .max_occurrences
+                        .map(|n| n.to_string())
+                        .unwrap_or_default(),
+                    if bill.paid { "yes" } else { "no" },
+                    bill.id.as_str(),
+                ],
+            );
+        }
+        out
+    }
+
+    /// All payments as CSV, oldest first, with the bill they are for.
+    pub fn export_payments_csv(&self) -> String {
+        let mut out = String::new();
+        write_row(
+            &mut out,
+            &[
+                "Paid on",
+                "Bill",
+                "Due date",
+                "Amount",
+                "Currency",
+                "Method",
+                "Confirmation",
+                "Bill id",
+                "Id",
+            ],
+        );
+        let mut payments: Vec<_> = self.payments.iter().collect();
+        payments.sort_by_key(|p| (p.paid_date, p.created_at));
+        for payment in payments {
+            let bill = self.bill(&payment.bill_id).map(|b| b.name.as_str());
+            write_row(
+                &mut out,
+                &[
+                    payment.paid_date.to_string().as_str(),
+                    bill.unwrap_or_default(),
+                    &payment.occurrence_date.to_string(),
+                    &payment.amount.decimal(),
+                    payment.amount.currency.code(),
+                    payment.method.as_deref().unwrap_or_default(),
+                    payment.confirmation.as_deref().unwrap_or_default(),
+                    payment.bill_id.as_str(),
+                    payment.id.as_str(),
+                ],
+            );
+        }
+        out
+    }
+
+    /// Creates a bill from every valid row of `text` and reports the rows
+    /// that are not. Categories are matched by name and created if missing.
+    /// Run it on a copy of the data for a dry run.
+    pub fn import_bills_csv(
+        &mut self,
+        text: &str,
+        options: &CsvImport,
+        now: DateTime<Utc>,
+    ) -> AppResult<CsvImportReport> {
+        for required in [BillColumn::Name, BillColumn::Amount, BillColumn::DueDate] {
+            if !options.mapping.contains_key(&required) {
+                return Err(AppError::validation(format!(
+                    "Choose the column that holds the {}",
+                    required.header().to_lowercase()
+                )));
+            }
+        }
+        let mut records = read_records(text)?.into_iter();
+        let headers = records.next().map_or(0, |r| r.cells.len());
+        if let Some((field, _)) = options.mapping.iter().find(|(_, &col)| col >= headers) {
+            return Err(AppError::validation(format!(
+                "The file has no column {} for the {}",
+                options.mapping[field] + 1,
+                field.header().to_lowercase()
+            )));
+        }
+        let rows: Vec<Record> = records.collect();
+        let date_format = match options.date_format {
+            Some(format) => format,
+            None => {
+                let col = options.mapping[&BillColumn::DueDate];
+                let values = rows
+                    .iter()
+                    .filter_map(|r| r.cells.get(col))
+                    .map(String::as_str);
+                match DateFormat::detect(values)[..] {
+                    [format] => format,
+                    [] => {
+                        return Err(AppError::validation(
+                            "Could not recognize the date format; choose one",
+                        ))
+                    }
+                    // Day-first and month-first both fit, and guessing
+                    // wrong would move every due date
+                    ref formats => {
+                        let examples: Vec<&str> = formats.iter().map(|f| f.example()).collect();
+                        return Err(AppError::validation(format!(
+                            "The dates could be read as {}; choose the date format",
+                            examples.join(" or ")
+                        )));
+                    }
+                }
+            }
+        };
+        let currency = options.currency.unwrap_or(self.settings.reporting_currency);
+
+        let mut report = CsvImportReport {
+            bills: Vec::new(),
+            new_categories: Vec::new(),
+            errors: Vec::new(),
+        };
+        for row in &rows {
+            let line = row.line;
+            let fields = match row_fields(row, &options.mapping, date_format, currency) {
+                Ok(fields) => fields,
+                Err(errors) => {
+                    report.errors.extend(errors);
+                    continue;
+                }
+            };
+            let imported = fields
+                .input
+                .validate()
+                .and_then(|_| self.import_row(fields, &mut report.new_categories, now));
+            match imported {
+                Ok(bill) => report.bills.push(bill),
+                Err(e) => report.errors.push(RowError {
+                    line,
+                    column: None,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    fn import_row(
+        &mut self,
+        mut fields: RowFields,
+        new_categories: &mut Vec<String>,
+        now: DateTime<Utc>,
+    ) -> AppResult<Bill> {
+        if let Some(name) = fields.category {
+            fields.input.category_id = Some(self.category_named(&name, new_categories)?);
+        }
+        self.upsert_bill(fields.input, now)
+    }
+}
+
+/// The value of a parsed field, or `None` after adding its error.
+fn check<T>(
+    errors: &mut Vec<RowError>,
+    line: usize,
+    column: BillColumn,
+    result: AppResult<T>,
+) -> Option<T> {
+    result
+        .map_err(|e| {
+            errors.push(RowError {
+                line,
+                column: Some(column),
+                message: e.to_string(),
+            })
+        })
+        .ok()
+}
+
+/// Reads the mapped cells of a row, reporting every field that does not
+/// parse.
+fn row_fields(
+    row: &Record,
+    mapping: &ColumnMapping,
+    date_format: DateFormat,
+    currency: Currency,
+) -> Result<RowFields, Vec<RowError>> {
+    let cell = |field| {
+        mapping
+            .get(&field)
+            .and_then(|&col| row.cells.get(col))
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+    };
+    let mut errors = Vec::new();
+    let line = row.line;
+
+    let currency = match cell(BillColumn::Currency) {
+        Some(code) => check(
+            &mut errors,
+            line,
+            BillColumn::Currency,
+            Currency::parse(code),
+        ),
+        None => Some(currency),
+    };
+    let amount = match (cell(BillColumn::Amount), currency) {
+        (Some(text), Some(currency)) => check(
+            &mut errors,
+            line,
+            BillColumn::Amount,
+            Money::parse(text, currency),
+        ),
+        (None, _) => check(
+            &mut errors,
+            line,
+            BillColumn::Amount,
+            Err(AppError::validation("Amount is required")),
+        ),
+        (Some(_), None) => None,
+    };
+    let parse_date = |text: &str| {
+        date_format.parse(text).ok_or_else(|| {
+            AppError::validation(format!(
+                "\"{text}\" is not a date like {}",
+                date_format.example()
+            ))
+        })
+    };
+    let due_date = match cell(BillColumn::DueDate).map(parse_date) {
+        Some(result) => check(&mut errors, line, BillColumn::DueDate, result),
+        None => check(
+            &mut errors,
+            line,
+            BillColumn::DueDate,
+            Err(AppError::validation("Due date is required")),
+        ),
+    };
+    let recurrence = check(
+        &mut errors,
+        line,
+        BillColumn::Recurrence,
+        parse_recurrence(cell(BillColumn::Recurrence).unwrap_or_default()),
+    );
+
+    let end_date = cell(BillColumn::EndDate)
+        .map(|text| check(&mut errors, line, BillColumn::EndDate, parse_date(text)));
+    let max_occurrences = cell(BillColumn::MaxOccurrences).map(|text| {
+        let count = text.parse().map_err(|_| {
+            AppError::validation(format!("\"{text}\" is not a number of occurrences"))
+        });
+        check(&mut errors, line, BillColumn::MaxOccurrences, count)
+    });
+
+    let (Some(amount), Some(due_date), Some(recurrence)) = (amount, due_date, recurrence) else {
+        return Err(errors);
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let limits = RecurrenceLimits {
+        end_date: end_date.flatten(),
+        max_occurrences: max_occurrences.flatten(),
+        pauses: Vec::new(),
+    };
+    let tags = cell(BillColumn::Tags)
+        .map(|t| t.split([',', ';']).map(str::to_string).collect())
+        .unwrap_or_default();
+    Ok(RowFields {
+        input: BillInput {
+            id: None,
+            name: cell(BillColumn::Name).unwrap_or_default().to_string(),
+            amount,
+            due_date: due_date.format("%Y-%m-%d").to_string(),
+            recurrence,
+            limits,
+            category_id: None,
+            tags,
+            notes: cell(BillColumn::Notes).map(str::to_string),
+            updated_at: None,
+        },
+        category: cell(BillColumn::Category).map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{bill_input, date};
+
+    fn cells(records: &[Record]) -> Vec<Vec<&str>> {
+        records
+            .iter()
+            .map(|r| r.cells.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    fn import(data: &mut Data, text: &str) -> CsvImportReport {
+        let options = CsvImport {
+            mapping: preview_bills(text).unwrap().mapping,
+            date_format: None,
+            currency: None,
+        };
+        data.import_bills_csv(text, &options, Utc::now()).unwrap()
+    }
+
+    #[test]
+    fn detects_the_delimiter() {
+        for text in ["a,b;c\n1,2;3", "a;b,c\n1;2,3", "a\tb,c\n1\t2,3"] {
+            let records = read_records(text).unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].cells.len(), 2, "{text:?}");
+        }
+        assert_eq!(cells(&read_records("a;b\n1,5;2").unwrap())[1], ["1,5", "2"]);
+    }
+
+    #[test]
+    fn reads_quoted_cells() {
+        let text = "\u{feff}Name,Notes\r\n\"Rent, flat\",\"Say \"\"hi\"\"\nthen pay\"\r\n\r\n  ,\nWater, \"x\" \n";
+        let records = read_records(text).unwrap();
+        assert_eq!(
+            cells(&records),
+            [
+                vec!["Name", "Notes"],
+                vec!["Rent, flat", "Say \"hi\"\nthen pay"],
+                vec!["Water", "x "],
+            ]
+        );
+        let lines: Vec<usize> = records.iter().map(|r| r.line).collect();
+        assert_eq!(lines, [1, 2, 6]);
+        let err = read_records("a\n\"open").unwrap_err();
+        assert_eq!(err.to_string(), "Line 2: a quoted value is never closed");
+    }
+
+    #[test]
+    fn written_rows_read_back() {
+        let row = ["plain", "a,b", "say \"hi\"", "two\nlines", " padded", ""];
+        let mut out = String::new();
+        write_row(&mut out, &row);
+        write_row(&mut out, &["end"]);
+        assert_eq!(
+            cells(&read_records(&out).unwrap()),
+            [row.to_vec(), vec!["end"]]
+        );
+    }
+
+    #[test]
+    fn detects_date_formats() {
+        use DateFormat::*;
+        let detect = |values: &[&str]| DateFormat::detect(values.iter().copied());
+        assert_eq!(detect(&["2024-01-31", " ", "2024-02-01"]), [Iso]);
+        assert_eq!(detect(&["2024/1/31"]), [YearMonthDay]);
+        assert_eq!(detect(&["31/01/2024", "01/02/2024"]), [DayMonthYear]);
+        assert_eq!(detect(&["01/31/2024", "02/01/2024"]), [MonthDayYear]);
+        assert_eq!(detect(&["01/02/2024"]), [DayMonthYear, MonthDayYear]);
+        assert_eq!(detect(&["31.01.2024"]), [DayMonthYearDots]);
+        assert_eq!(detect(&["31-01-2024"]), [DayMonthYearDashes]);
+        assert_eq!(detect(&["2024-01-31", "31/01/2024"]), []);
+        assert_eq!(detect(&["", " "]), []);
+        assert_eq!(DayMonthYear.parse("29/02/2023"), None);
+    }
+
+    #[test]
+    fn maps_columns_by_header() {
+        let text = "Payee;Amount Due;due_date;Memo;Until;Total\nRent;1200;31/01/2024;;;\n";
+        let preview = preview_bills(text).unwrap();
+        let expected = [
+            (BillColumn::Name, 0),
+            (BillColumn::Amount, 1),
+            (BillColumn::DueDate, 2),
+            (BillColumn::Notes, 3),
+            (BillColumn::EndDate, 4),
+        ];
+        assert_eq!(preview.mapping, ColumnMapping::from(expected));
+        assert_eq!(preview.row_count, 1);
+        assert_eq!(preview.date_formats, [DateFormat::DayMonthYear]);
+        assert!(preview_bills("\n\n").is_err());
+    }
+
+    #[test]
+    fn reads_recurrences() {
+        assert_eq!(parse_recurrence("").unwrap(), Recurrence::None);
+        assert_eq!(
+            parse_recurrence("Every 2 weeks").unwrap(),
+            Recurrence::BiWeekly
+        );
+        assert_eq!(parse_recurrence("one-time").unwrap(), Recurrence::None);
+        assert!(parse_recurrence("sometimes").is_err());
+        assert!(parse_recurrence("{\"everyNDays\":{\"days\":0}}").is_err());
+        for recurrence in [
+            Recurrence::LastBusinessDay,
+            Recurrence::EveryNDays { days: 10 },
+            Recurrence::NthWeekday {
+                nth: -1,
+                weekday: chrono::Weekday::Fri,
+            },
+        ] {
+            assert_eq!(
+                parse_recurrence(&recurrence_text(recurrence)).unwrap(),
+                recurrence
+            );
+        }
+    }
+
+    #[test]
+    fn export_imports_back() {
+        let mut data = Data::fresh();
+        let until = RecurrenceLimits {
+            end_date: Some(date("2024-12-31")),
+            ..Default::default()
+        };
+        let count = RecurrenceLimits {
+            max_occurrences: Some(12),
+            ..Default::default()
+        };
+        for (name, limits) in [("Rent", until), ("Loan", count)] {
+            let mut input = bill_input(name, 123_456, "2024-01-31");
+            input.limits = limits;
+            input.amount.currency = Currency::parse("EUR").unwrap();
+            input.tags = vec!["home".to_string(), "fixed".to_string()];
+            input.notes = Some("Flat 3, \"upstairs\"".to_string());
+            data.upsert_bill(input, Utc::now()).unwrap();
+        }
+
+        let mut copy = Data::fresh();
+        let report = import(&mut copy, &data.export_bills_csv());
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        for (imported, bill) in report.bills.iter().zip(&data.bills) {
+            assert_eq!(imported.name, bill.name);
+            assert_eq!(imported.amount, bill.amount);
+            assert_eq!(imported.due_date, bill.due_date);
+            assert_eq!(imported.recurrence, bill.recurrence);
+            assert_eq!(imported.limits, bill.limits);
+            assert_eq!(imported.tags, bill.tags);
+            assert_eq!(imported.notes, bill.notes);
+        }
+        assert_eq!(report.bills.len(), 2);
+    }
+
+    #[test]
+    fn reports_every_bad_field_of_a_row() {
+        let text = "Name,Amount,Due date,Recurrence,Ends on,Occurrences\n\
+                    Rent,abc,2024-01-31,sometimes,soon,many\n\
+                    Water,25,2024-01-31,,,\n";
+        let mut data = Data::fresh();
+        let report = import(&mut data, text);
+        assert_eq!(report.bills.len(), 1);
+        let columns: Vec<_> = report.errors.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(
+            columns,
+            [
+                (2, Some(BillColumn::Amount)),
+                (2, Some(BillColumn::Recurrence)),
+                (2, Some(BillColumn::EndDate)),
+                (2, Some(BillColumn::MaxOccurrences)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_amounts_with_stray_characters() {
+        let text = "Name,Amount,Due date\n\
+                    Rent,1e5,2024-01-31\n\
+                    Water,12/03,2024-01-31\n\
+                    Power,abc12def,2024-01-31\n\
+                    Phone,$ 1.234,2024-01-31\n";
+        let mut data = Data::fresh();
+        let report = import(&mut data, text);
+        let amounts: Vec<Money> = report.bills.iter().map(|b| b.amount).collect();
+        assert_eq!(amounts, [Money::new(123_400, Currency::USD)]);
+        let errors: Vec<_> = report
+            .errors
+            .iter()
+            .map(|e| (e.line, e.column, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, Some(BillColumn::Amount), "\"1e5\" is not an amount"),
+                (3, Some(BillColumn::Amount), "\"12/03\" is not an amount"),
+                (4, Some(BillColumn::Amount), "\"abc12def\" is not an amount"),
+            ]
+        );
+    }
+
+    #[test]
+    fn asks_for_the_format_of_ambiguous_dates() {
+        let text = "Name,Amount,Due date\nRent,1200,01/02/2024\nWater,25,05/03/2024\n";
+        let mut options = CsvImport {
+            mapping: preview_bills(text).unwrap().mapping,
+            date_format: None,
+            currency: None,
+        };
+        let mut data = Data::fresh();
+        let err = data
+            .import_bills_csv(text, &options, Utc::now())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The dates could be read as 31/01/2024 or 01/31/2024; choose the date format"
+        );
+        assert!(data.bills.is_empty());
+
+        options.date_format = Some(DateFormat::MonthDayYear);
+        let report = data.import_bills_csv(text, &options, Utc::now()).unwrap();
+        assert_eq!(report.bills[0].due_date, date("2024-01-02"));
+    }
+
+    #[test]
+    fn requires_the_key_columns() {
+        let mut data = Data::fresh();
+        let options = CsvImport {
+            mapping: ColumnMapping::from([(BillColumn::Name, 0), (BillColumn::Amount, 1)]),
+            date_format: None,
+            currency: None,
+        };
+        let err = data
+            .import_bills_csv("Name,Amount\nRent,5\n", &options, Utc::now())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Choose the column that holds the due date");
+    }
+}
@@ -0,0 +1,333 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, BillInput};
+use crate::category::{normalize_tags, Category};
+use crate::error::{AppError, AppResult};
+use crate::fx::ExchangeRate;
+use crate::history::HistoryEntry;
+use crate::journal::Operation;
+use crate::payment::{Payment, PaymentInput};
+use crate::settings::Settings;
+use crate::trash::TrashedBill;
+
+/// Everything the app persists. Serializes in the shape `bills.json` used
+/// before the database, which is how that file is imported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Data {
+    #[serde(default)]
+    pub bills: Vec<Bill>,
+    #[serde(default)]
+    pub payments: Vec<Payment>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub rates: Vec<ExchangeRate>,
+    #[serde(default)]
+    pub settings: Settings,
+    /// Deleted bills awaiting restore or purge.
+    #[serde(default)]
+    pub trash: Vec<TrashedBill>,
+    /// Field changes of bills, oldest first.
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// Undo history, oldest first.
+    #[serde(default)]
+    pub journal: Vec<Operation>,
+}
+
+impl Data {
+    /// Data for a first launch, with the built-in categories.
+    pub fn fresh() -> Self {
+        Self {
+            categories: Category::defaults(),
+            ..Self::default()
+        }
+    }
+
+    pub fn bill(&self, id: &str) -> Option<&Bill> {
+        self.bills.iter().find(|b| b.id == id)
+    }
+
+    pub fn bill_index(&self, id: &str) -> AppResult<usize> {
+        self.bills
+            .iter()
+            .position(|b| b.id == id)
+            .ok_or_else(|| AppError::not_found("Bill", id))
+    }
+
+    /// Creates a bill, or replaces the editable fields of an existing one.
+    /// `paid` and `createdAt` are preserved on edit.
+    pub fn upsert_bill(&mut self, input: BillInput, now: DateTime<Utc>) -> AppResult<Bill> {
+        let due_date = input.validate()?;
+        let notes = input.clean_notes();
+        let name = input.name.trim().to_string();
+        let category_id = input.category_id.filter(|id| !id.is_empty());
+        if let Some(id) = &category_id {
+            self.category(id)?;
+        }
+        let tags = normalize_tags(input.tags);
+
+        let existing = match input.id.as_deref().filter(|id| !id.is_empty()) {
+            Some(id) => Some(self.bill_index(id)?),
+            None => None,
+        };
+
+        match existing {
+            Some(idx) => {
+                let bill = &mut self.bills[idx];
+                if input.updated_at.is_some_and(|seen| seen != bill.updated_at) {
+                    return Err(AppError::Conflict(format!(
+                        "\"{}\" was changed elsewhere; reload before saving",
+                        bill.name
+                    )));
+                }
+                // Changing the schedule starts a new series from the new date
+                if bill.due_date != due_date || bill.recurrence != input.recurrence {
+                    bill.start_date = Some(due_date);
+                }
+                bill.name = name;
+                bill.amount = input.amount;
+                bill.due_date = due_date;
+                bill.recurrence = input.recurrence;
+                bill.limits = input.limits;
+                bill.category_id = category_id;
+                bill.tags = tags;
+                bill.notes = notes;
+                bill.updated_at = now;
+                Ok(bill.clone())
+            }
+            None => {
+                let bill = Bill {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    name,
+                    amount: input.amount,
+                    due_date,
+                    recurrence: input.recurrence,
+                    start_date: Some(due_date),
+                    limits: input.limits,
+                    category_id,
+                    tags,
+                    notes,
+                    paid: false,
+                    created_at: now,
+                    updated_at: now,
+                };
+                self.bills.push(bill.clone());
+                Ok(bill)
+            }
+        }
+    }
+
+    /// Marks a bill paid or unpaid from the list toggle. Marking it paid
+    /// records a payment of the outstanding balance of the current
+    /// occurrence; marking it unpaid drops the payments recorded for it.
+    pub fn set_paid(
+        &mut self,
+        id: &str,
+        paid: bool,
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> AppResult<Bill> {
+        let idx = self.bill_index(id)?;
+        let bill = &self.bills[idx];
+        if paid == bill.paid {
+            return Ok(bill.clone());
+        }
+        if paid && self.is_settled(bill, bill.due_date)? {
+            // Earlier partial payments already cover a since-lowered amount
+            self.settle_current(id, now)?;
+        } else if paid {
+            let input = PaymentInput {
+                bill_id: id.to_string(),
+                occurrence_date: None,
+                paid_date: None,
+                amount: None,
+                method: None,
+                confirmation: None,
+                transaction_id: None,
+            };
+            self.record_payment(input, today, now)?;
+        } else {
+            let due_date = bill.due_date;
+            self.payments
+                .retain(|p| !(p.bill_id == id && p.occurrence_date == due_date));
+            let bill = &mut self.bills[idx];
+            bill.paid = false;
+            bill.updated_at = now;
+        }
+        Ok(self.bills[idx].clone())
+    }
+
+    /// Closes the bill's current occurrence. A recurring bill rolls over to
+    /// the next cycle that is not already paid in full, which starts out
+    /// unpaid; once the series has ended the bill simply stays paid.
+    pub fn settle_current(&mut self, id: &str, now: DateTime<Utc>) -> AppResult<()> {
+        let idx = self.bill_index(id)?;
+        let mut due_date = self.bills[idx].due_date;
+        let mut paid = true;
+        while let Some(next) = self.bills[idx].next_due_after(due_date) {
+            due_date = next;
+            if !self.is_settled(&self.bills[idx], next)? {
+                paid = false;
+                break;
+            }
+        }
+        let bill = &mut self.bills[idx];
+        bill.due_date = due_date;
+        bill.paid = paid;
+        bill.updated_at = now;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recurrence::{Recurrence, RecurrenceLimits};
+    use crate::testing::{add_bill, bill_input, date};
+
+    #[test]
+    fn edits_keep_paid_and_created_at() {
+        let mut data = Data::default();
+        let bill = add_bill(&mut data, " Rent ", 120_000, "2024-01-31");
+        assert_eq!(bill.name, "Rent");
+        assert!(!bill.paid);
+        data.bills[0].paid = true;
+
+        let edit = BillInput {
+            id: Some(bill.id.clone()),
+            notes: Some(" flat 3 ".to_string()),
+            ..bill_input("Rent", 125_000, "2024-02-29")
+        };
+        let edited = data.upsert_bill(edit, Utc::now()).unwrap();
+        assert_eq!(data.bills.len(), 1);
+        assert_eq!(
+            (edited.amount.minor, edited.due_date),
+            (125_000, date("2024-02-29"))
+        );
+        assert_eq!(edited.notes.as_deref(), Some("flat 3"));
+        assert!(edited.paid);
+        assert_eq!(edited.created_at, bill.created_at);
+    }
+
+    #[test]
+    fn unknown_ids_are_rejected() {
+        let mut data = Data::default();
+        let edit = BillInput {
+            id: Some("gone".to_string()),
+            ..bill_input("Rent", 120_000, "2024-01-31")
+        };
+        let err = data.upsert_bill(edit, Utc::now()).unwrap_err();
+        assert_eq!(
+            (err.code(), err.to_string()),
+            ("not_found", "Bill gone not found".to_string())
+        );
+        assert!(data.delete_bill("gone", Utc::now()).is_err());
+        assert!(data
+            .set_paid("gone", true, date("2024-01-05"), Utc::now())
+            .is_err());
+    }
+
+    #[test]
+    fn stale_edits_conflict() {
+        let mut data = Data::default();
+        let bill = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let edit = |seen| BillInput {
+            id: Some(bill.id.clone()),
+            updated_at: Some(seen),
+            ..bill_input("Rent", 125_000, "2024-01-31")
+        };
+        let later = bill.updated_at + chrono::Duration::seconds(1);
+        let saved = data.upsert_bill(edit(bill.updated_at), later).unwrap();
+        // The form still holds the version from before that save
+        let err = data.upsert_bill(edit(bill.updated_at), later).unwrap_err();
+        assert_eq!(err.code(), "conflict");
+        assert_eq!(data.bills[0], saved);
+    }
+
+    #[test]
+    fn paying_rolls_recurring_bills_over() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let paid = data
+            .set_paid(&rent.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        assert_eq!((paid.due_date, paid.paid), (date("2024-02-29"), false));
+        // The series keeps its day of month instead of drifting to the 29th
+        let paid = data
+            .set_paid(&rent.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        assert_eq!(paid.due_date, date("2024-03-31"));
+
+        let once = data
+            .upsert_bill(
+                BillInput {
+                    recurrence: Recurrence::None,
+                    ..bill_input("Repair", 8000, "2024-01-10")
+                },
+                Utc::now(),
+            )
+            .unwrap();
+        let paid = data
+            .set_paid(&once.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        assert_eq!((paid.due_date, paid.paid), (date("2024-01-10"), true));
+        assert!(
+            !data
+                .set_paid(&once.id, false, date("2024-01-05"), Utc::now())
+                .unwrap()
+                .paid
+        );
+
+        assert_eq!(data.delete_bill(&rent.id, Utc::now()).unwrap().id, rent.id);
+        assert_eq!(data.bills.len(), 1);
+    }
+
+    #[test]
+    fn schedule_edits_restart_the_series() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        data.set_paid(&rent.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        let rename = BillInput {
+            id: Some(rent.id.clone()),
+            ..bill_input("Flat rent", 120_000, "2024-02-29")
+        };
+        let renamed = data.upsert_bill(rename, Utc::now()).unwrap();
+        assert_eq!(renamed.series_start(), date("2024-01-31"));
+
+        let moved = BillInput {
+            id: Some(rent.id.clone()),
+            ..bill_input("Flat rent", 120_000, "2024-02-15")
+        };
+        let moved = data.upsert_bill(moved, Utc::now()).unwrap();
+        assert_eq!(moved.series_start(), date("2024-02-15"));
+        assert_eq!(
+            moved.next_occurrences(2),
+            [date("2024-02-15"), date("2024-03-15")]
+        );
+    }
+
+    #[test]
+    fn finished_series_stay_paid() {
+        let mut data = Data::default();
+        let input = BillInput {
+            limits: RecurrenceLimits {
+                max_occurrences: Some(2),
+                ..Default::default()
+            },
+            ..bill_input("Loan", 30_000, "2024-01-15")
+        };
+        let loan = data.upsert_bill(input, Utc::now()).unwrap();
+        let paid = data
+            .set_paid(&loan.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        assert_eq!((paid.due_date, paid.paid), (date("2024-02-15"), false));
+        let paid = data
+            .set_paid(&loan.id, true, date("2024-01-05"), Utc::now())
+            .unwrap();
+        assert_eq!((paid.due_date, paid.paid), (date("2024-02-15"), true));
+    }
+}
@@ -0,0 +1,139 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Error returned by every command.
+///
+/// Serialized to the frontend as `{ code, message }` so callers can branch on
+/// `code` while still showing `message` to the user.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// Input rejected by a business rule.
+    #[error("{0}")]
+    Validation(String),
+    #[error("{entity} {id} not found")]
+    NotFound { entity: &'static str, id: String },
+    /// Reading or writing app data failed.
+    #[error("{context}: {source}")]
+    Storage {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The database rejected a query.
+    #[error("{context}: {source}")]
+    Database {
+        context: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// Stored data exists but cannot be understood.
+    #[error("{0}")]
+    CorruptData(String),
+    /// Data was written by a newer version of the app and may use a format
+    /// this one does not understand.
+    #[error(
+        "{what} was saved by a newer version of AutoBillChecker (format {found}, this \
+         version reads up to {supported}); update the app to open it"
+    )]
+    NewerVersion {
+        what: String,
+        found: u64,
+        supported: u64,
+    },
+    /// The change is based on stale data or clashes with an existing record.
+    #[error("{0}")]
+    Conflict(String),
+    /// The data is encrypted and no passphrase has been entered yet.
+    #[error("The bills are locked; unlock them with your passphrase")]
+    Locked,
+    /// The OS refused access to app data.
+    #[error("{0}")]
+    Permission(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn validation(msg: impl Into<String>) -> Self {
+        Self::Validation(msg.into())
+    }
+
+    pub fn not_found(entity: &'static str, id: impl Into<String>) -> Self {
+        Self::NotFound {
+            entity,
+            id: id.into(),
+        }
+    }
+
+    /// Wraps an I/O error, singling out permission problems so the UI can
+    /// tell the user to fix access rather than retry.
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        let context = context.into();
+        if source.kind() == std::io::ErrorKind::PermissionDenied {
+            Self::Permission(format!("{context}: {source}"))
+        } else {
+            Self::Storage { context, source }
+        }
+    }
+
+    /// Wraps a SQLite error. A damaged database file is reported as corrupt
+    /// data rather than a failed query.
+    pub fn db(context: impl Into<String>, source: rusqlite::Error) -> Self {
+        let context = context.into();
+        match source.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase) => {
+                Self::CorruptData(format!("{context}: {source}"))
+            }
+            Some(rusqlite::ErrorCode::PermissionDenied | rusqlite::ErrorCode::ReadOnly) => {
+                Self::Permission(format!("{context}: {source}"))
+            }
+            _ => Self::Database { context, source },
+        }
+    }
+
+    /// Stable machine-readable identifier for the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation(_) => "validation",
+            Self::NotFound { .. } => "not_found",
+            Self::Storage { .. } | Self::Database { .. } => "storage",
+            Self::CorruptData(_) => "corrupt_data",
+            Self::NewerVersion { .. } => "newer_version",
+            Self::Conflict(_) => "conflict",
+            Self::Locked => "locked",
+            Self::Permission(_) => "permission",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AppError", 2)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_and_message() {
+        let json = serde_json::to_value(AppError::not_found("Bill", "b1")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "code": "not_found", "message": "Bill b1 not found" })
+        );
+    }
+
+    #[test]
+    fn io_errors_single_out_permissions() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let err = AppError::io("Failed to write bills.json", denied);
+        assert_eq!(err.code(), "permission");
+        assert!(err.to_string().starts_with("Failed to write bills.json: "));
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(AppError::io("Failed to read", missing).code(), "storage");
+    }
+}
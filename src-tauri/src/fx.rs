@@ -0,0 +1,455 @@
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::{Currency, Money};
+
+/// One unit of `base` is worth `rate` units of `quote` on `date`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub base: Currency,
+    pub quote: Currency,
+    pub rate: f64,
+}
+
+impl ExchangeRate {
+    pub fn validate(&self) -> AppResult<()> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(AppError::validation("Exchange rate must be greater than 0"));
+        }
+        if self.base == self.quote {
+            return Err(AppError::validation(
+                "Exchange rate needs two different currencies",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Base currency of the ECB reference rate files.
+const ECB_BASE: &str = "EUR";
+
+/// Parses an ECB reference rate CSV: a `Date` column followed by one column
+/// per currency, each holding how much of it one euro buys. Both the
+/// historical (`2024-01-05`) and daily (`05 January 2024`) date styles are
+/// accepted, and `N/A` cells are skipped.
+pub fn parse_ecb_csv(text: &str) -> AppResult<Vec<ExchangeRate>> {
+    let base = Currency::parse(ECB_BASE)?;
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| AppError::validation("Rate file is empty"))?;
+    let mut columns = header.split(',').map(str::trim);
+    if !columns
+        .next()
+        .is_some_and(|c| c.eq_ignore_ascii_case("date"))
+    {
+        return Err(AppError::validation(
+            "Rate file must start with a Date column",
+        ));
+    }
+    let quotes = columns
+        .map(|c| (!c.is_empty()).then(|| Currency::parse(c)).transpose())
+        .collect::<AppResult<Vec<_>>>()?;
+
+    let mut rates = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let line_no = n + 2;
+        let mut cells = line.split(',').map(str::trim);
+        let date_text = cells.next().unwrap_or_default();
+        let date = NaiveDate::parse_from_str(date_text, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(date_text, "%d %B %Y"))
+            .map_err(|_| {
+                AppError::validation(format!("Line {line_no}: invalid date \"{date_text}\""))
+            })?;
+        for (quote, cell) in quotes.iter().zip(cells) {
+            let Some(quote) = quote else { continue };
+            if cell.is_empty() || cell.eq_ignore_ascii_case("N/A") {
+                continue;
+            }
+            let rate = ExchangeRate {
+                date,
+                base,
+                quote: *quote,
+                rate: cell.parse().map_err(|_| {
+                    AppError::validation(format!("Line {line_no}: invalid {quote} rate \"{cell}\""))
+                })?,
+            };
+            rate.validate()
+                .map_err(|e| AppError::validation(format!("Line {line_no}: {e}")))?;
+            rates.push(rate);
+        }
+    }
+    Ok(rates)
+}
+
+/// A sum converted into the reporting currency.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertedTotal {
+    pub total: Money,
+    /// Date of the oldest exchange rate used, if anything was converted.
+    pub rate_date: Option<NaiveDate>,
+    /// Currencies left out of `total` because no rate is known for them.
+    pub missing_rates: BTreeSet<Currency>,
+}
+
+impl ConvertedTotal {
+    fn new(currency: Currency) -> Self {
+        Self {
+            total: Money::zero(currency),
+            rate_date: None,
+            missing_rates: BTreeSet::new(),
+        }
+    }
+}
+
+/// Amount due in one calendar month.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastMonth {
+    /// `YYYY-MM`
+    pub month: String,
+    #[serde(flatten)]
+    pub due: ConvertedTotal,
+}
+
+impl Data {
+    /// Adds rates, replacing any already stored for the same day and pair.
+    /// Returns how many rates were stored.
+    pub fn import_rates(&mut self, rates: Vec<ExchangeRate>) -> AppResult<usize> {
+        for rate in &rates {
+            rate.validate()?;
+        }
+        let count = rates.len();
+        for rate in rates {
+            self.rates
+                .retain(|r| !(r.date == rate.date && r.base == rate.base && r.quote == rate.quote));
+            self.rates.push(rate);
+        }
+        self.rates.sort_by_key(|r| (r.date, r.base, r.quote));
+        Ok(count)
+    }
+
+    /// Latest stored rate for exactly `base` -> `quote` on or before `on`,
+    /// also using the inverse pair.
+    fn direct_rate(
+        &self,
+        base: Currency,
+        quote: Currency,
+        on: NaiveDate,
+    ) -> Option<(f64, NaiveDate)> {
+        self.rates
+            .iter()
+            .filter(|r| r.date <= on)
+            .filter_map(|r| {
+                if r.base == base && r.quote == quote {
+                    Some((r.rate, r.date))
+                } else if r.base == quote && r.quote == base {
+                    Some((1.0 / r.rate, r.date))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(_, date)| *date)
+    }
+
+    /// Rate from `from` to `to` as of `on`, crossing through a shared base
+    /// currency (e.g. USD -> EUR -> GBP) when there is no direct rate.
+    /// Returns the rate and the date of the oldest quote it relies on.
+    pub fn rate_between(
+        &self,
+        from: Currency,
+        to: Currency,
+        on: NaiveDate,
+    ) -> Option<(f64, NaiveDate)> {
+        if let Some(direct) = self.direct_rate(from, to, on) {
+            return Some(direct);
+        }
+        let bases: BTreeSet<Currency> = self.rates.iter().map(|r| r.base).collect();
+        bases.into_iter().find_map(|base| {
+            let (to_from, d1) = self.direct_rate(base, from, on)?;
+            let (to_to, d2) = self.direct_rate(base, to, on)?;
+            Some((to_to / to_from, d1.min(d2)))
+        })
+    }
+
+    /// Converts `amount` into `to` using the rates known on `on`, rounding to
+    /// the nearest minor unit. The date is that of the rate used, or `None`
+    /// when no conversion was needed.
+    pub fn convert(
+        &self,
+        amount: Money,
+        to: Currency,
+        on: NaiveDate,
+    ) -> AppResult<(Money, Option<NaiveDate>)> {
+        if amount.currency == to {
+            return Ok((amount, None));
+        }
+        let (rate, date) = self.rate_between(amount.currency, to, on).ok_or_else(|| {
+            AppError::not_found("Exchange rate", format!("{}/{to}", amount.currency))
+        })?;
+        let major = amount.minor as f64 / 10f64.powi(amount.currency.minor_digits() as i32);
+        let converted = Money::from_f64(major * rate, to)
+            .ok_or_else(|| AppError::validation("Converted amount is too large"))?;
+        Ok((converted, Some(date)))
+    }
+
+    fn add_converted(
+        &self,
+        total: &mut ConvertedTotal,
+        amount: Money,
+        on: NaiveDate,
+    ) -> AppResult<()> {
+        match self.convert(amount, total.total.currency, on) {
+            Ok((converted, rate_date)) => {
+                total.total = total.total.checked_add(converted)?;
+                if let Some(date) = rate_date {
+                    total.rate_date = Some(total.rate_date.map_or(date, |d| d.min(date)));
+                }
+            }
+            Err(AppError::NotFound { .. }) => {
+                total.missing_rates.insert(amount.currency);
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Outstanding balance of the current occurrence of the given bills (all
+    /// bills when `ids` is `None`), in the reporting currency.
+    pub fn outstanding_total(
+        &self,
+        ids: Option<&[String]>,
+        today: NaiveDate,
+    ) -> AppResult<ConvertedTotal> {
+        let mut total = ConvertedTotal::new(self.settings.reporting_currency);
+        for bill in &self.bills {
+            if bill.paid || ids.is_some_and(|ids| !ids.contains(&bill.id)) {
+                continue;
+            }
+            let balance = self.balance(bill, bill.due_date)?;
+            self.add_converted(&mut total, balance, today)?;
+        }
+        Ok(total)
+    }
+
+    /// Amount due per month from the current occurrences up to `until`, net
+    /// of payments already made, in the reporting currency. Conversions use
+    /// the latest rates as of `today`.
+    pub fn forecast(&self, until: NaiveDate, today: NaiveDate) -> AppResult<Vec<ForecastMonth>> {
+        let mut months: Vec<ForecastMonth> = Vec::new();
+        for bill in self.bills.iter().filter(|b| !b.paid) {
+            let dates = bill
+                .occurrences()
+                .skip_while(|d| *d < bill.due_date)
+                .take_while(|d| *d <= until);
+            for date in dates {
+                let month = format!("{:04}-{:02}", date.year(), date.month());
+                let idx = match months.iter().position(|m| m.month == month) {
+                    Some(idx) => idx,
+                    None => {
+                        months.push(ForecastMonth {
+                            month,
+                            due: ConvertedTotal::new(self.settings.reporting_currency),
+                        });
+                        months.len() - 1
+                    }
+                };
+                let balance = self.balance(bill, date)?;
+                self.add_converted(&mut months[idx].due, balance, today)?;
+            }
+        }
+        months.sort_by(|a, b| a.month.cmp(&b.month));
+        Ok(months)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_bill, date, usd};
+
+    /// GBP is missing from the latest row and JPY from the one before.
+    const ECB: &str = "Date, USD, JPY, GBP,\n\
+                       2024-01-05, 1.0921, 158.2, N/A,\n\
+                       \n\
+                       04 January 2024, 1.0944, N/A, 0.8620,\n";
+
+    fn currency(code: &str) -> Currency {
+        Currency::parse(code).unwrap()
+    }
+
+    fn ecb_data() -> Data {
+        let mut data = Data::default();
+        data.import_rates(parse_ecb_csv(ECB).unwrap()).unwrap();
+        data
+    }
+
+    #[test]
+    fn parses_ecb_files() {
+        let rates = parse_ecb_csv(ECB).unwrap();
+        let parsed: Vec<_> = rates
+            .iter()
+            .map(|r| (r.date, r.base.code(), r.quote.code(), r.rate))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (date("2024-01-05"), "EUR", "USD", 1.0921),
+                (date("2024-01-05"), "EUR", "JPY", 158.2),
+                (date("2024-01-04"), "EUR", "USD", 1.0944),
+                (date("2024-01-04"), "EUR", "GBP", 0.862),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_ecb_files() {
+        let cases = [
+            ("", "Rate file is empty"),
+            ("Day, USD\n", "Rate file must start with a Date column"),
+            ("Date, US\n", "\"US\" is not a three-letter currency code"),
+            (
+                "Date, USD\n2024-01-05, 1.09\n5/1/2024, 1.09\n",
+                "Line 3: invalid date \"5/1/2024\"",
+            ),
+            (
+                "Date, USD\n2024-01-05, n/a1\n",
+                "Line 2: invalid USD rate \"n/a1\"",
+            ),
+            (
+                "Date, USD\n2024-01-05, 0\n",
+                "Line 2: Exchange rate must be greater than 0",
+            ),
+            (
+                "Date, EUR\n2024-01-05, 1\n",
+                "Line 2: Exchange rate needs two different currencies",
+            ),
+        ];
+        for (text, message) in cases {
+            let err = parse_ecb_csv(text).unwrap_err();
+            assert_eq!(err.to_string(), message, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn import_replaces_rates_for_the_same_day() {
+        let mut data = ecb_data();
+        let update = ExchangeRate {
+            date: date("2024-01-05"),
+            base: currency("EUR"),
+            quote: Currency::USD,
+            rate: 1.1,
+        };
+        assert_eq!(data.import_rates(vec![update.clone()]).unwrap(), 1);
+        assert_eq!(data.rates.len(), 4);
+        assert!(data.rates.contains(&update));
+
+        let bad = ExchangeRate {
+            rate: -1.0,
+            ..update
+        };
+        assert!(data.import_rates(vec![bad]).is_err());
+        assert_eq!(data.rates.len(), 4);
+    }
+
+    #[test]
+    fn rates_use_the_latest_quote_on_or_before_the_day() {
+        let data = ecb_data();
+        let eur = currency("EUR");
+        assert_eq!(
+            data.rate_between(eur, Currency::USD, date("2024-01-10")),
+            Some((1.0921, date("2024-01-05")))
+        );
+        assert_eq!(
+            data.rate_between(eur, Currency::USD, date("2024-01-04")),
+            Some((1.0944, date("2024-01-04")))
+        );
+        assert_eq!(
+            data.rate_between(eur, Currency::USD, date("2024-01-03")),
+            None
+        );
+        // The inverse pair is used too
+        let (rate, _) = data
+            .rate_between(currency("GBP"), eur, date("2024-01-10"))
+            .unwrap();
+        assert!((rate - 1.0 / 0.862).abs() < 1e-12);
+    }
+
+    #[test]
+    fn converts_through_the_shared_base() {
+        let data = ecb_data();
+        let gbp = currency("GBP");
+        // The GBP quote is a day older than the USD one, and that is the
+        // date reported for the conversion
+        let (converted, rate_date) = data.convert(usd(10_000), gbp, date("2024-01-10")).unwrap();
+        assert_eq!(converted, Money::new(7893, gbp));
+        assert_eq!(rate_date, Some(date("2024-01-04")));
+
+        let jpy = currency("JPY");
+        let (converted, _) = data.convert(usd(1234), jpy, date("2024-01-10")).unwrap();
+        assert_eq!(converted, Money::new(1788, jpy));
+
+        assert_eq!(
+            data.convert(usd(1234), Currency::USD, date("2024-01-10"))
+                .unwrap(),
+            (usd(1234), None)
+        );
+        let err = data
+            .convert(usd(1234), currency("CHF"), date("2024-01-10"))
+            .unwrap_err();
+        assert_eq!(err.code(), "not_found");
+        // JPY has no quote as old as Jan 4
+        assert!(data.convert(usd(1234), jpy, date("2024-01-04")).is_err());
+    }
+
+    #[test]
+    fn totals_list_currencies_without_rates() {
+        let mut data = ecb_data();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let flat = add_bill(&mut data, "Flat", 1, "2024-02-15");
+        let fee = add_bill(&mut data, "Fee", 1, "2024-01-20");
+        let paid = add_bill(&mut data, "Paid", 5000, "2024-01-20");
+        for bill in &mut data.bills {
+            if bill.id == flat.id {
+                bill.amount = Money::new(50_000, currency("GBP"));
+            } else if bill.id == fee.id {
+                bill.amount = Money::new(1000, currency("CHF"));
+            } else if bill.id == paid.id {
+                bill.paid = true;
+            }
+        }
+
+        let total = data.outstanding_total(None, date("2024-01-10")).unwrap();
+        assert_eq!(total.total, usd(120_000 + 63_347));
+        assert_eq!(total.rate_date, Some(date("2024-01-04")));
+        assert_eq!(
+            total.missing_rates.into_iter().collect::<Vec<_>>(),
+            [currency("CHF")]
+        );
+
+        let only_rent = [data.bills[0].id.clone()];
+        let total = data
+            .outstanding_total(Some(&only_rent), date("2024-01-10"))
+            .unwrap();
+        assert_eq!((total.total, total.rate_date), (usd(120_000), None));
+
+        let months = data
+            .forecast(date("2024-02-29"), date("2024-01-10"))
+            .unwrap();
+        let months: Vec<_> = months
+            .iter()
+            .map(|m| (m.month.as_str(), m.due.total.minor))
+            .collect();
+        assert_eq!(
+            months,
+            [("2024-01", 120_000), ("2024-02", 120_000 + 63_347)]
+        );
+    }
+}
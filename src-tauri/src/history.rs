@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::bill::Bill;
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+
+/// Bill fields left out of the history because every change touches them.
+const IGNORED_FIELDS: &[&str] = &["updatedAt"];
+
+/// What made a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeSource {
+    /// Edited in the app.
+    Ui,
+    /// Brought in from a file or another app.
+    Import,
+    /// Applied automatically by a rule.
+    Rule,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryEvent {
+    Created,
+    Updated,
+    /// Moved to the trash, or removed by undoing its creation.
+    Deleted,
+    /// Brought back from the trash.
+    Restored,
+}
+
+/// One field of a bill before and after a change, as serialized; `None`
+/// when the field was not set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Something that happened to a bill.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// Increases with every entry recorded.
+    pub seq: u64,
+    pub bill_id: String,
+    pub at: DateTime<Utc>,
+    pub source: ChangeSource,
+    pub event: HistoryEvent,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FieldChange>,
+}
+
+fn bill_fields(bill: &Bill) -> Map<String, Value> {
+    match serde_json::to_value(bill) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Fields that differ between two versions of a bill, in field order.
+fn field_changes(old: &Bill, new: &Bill) -> Vec<FieldChange> {
+    let old = bill_fields(old);
+    let mut new = bill_fields(new);
+    let mut changes = Vec::new();
+    for (field, value) in &old {
+        let after = new.remove(field);
+        if Some(value) != after.as_ref() {
+            changes.push(FieldChange {
+                field: field.clone(),
+                old: Some(value.clone()),
+                new: after,
+            });
+        }
+    }
+    changes.extend(new.into_iter().map(|(field, value)| FieldChange {
+        field,
+        old: None,
+        new: Some(value),
+    }));
+    changes.retain(|c| !IGNORED_FIELDS.contains(&c.field.as_str()));
+    changes
+}
+
+impl Data {
+    /// Records how each bill changed from `before`. History of bills that
+    /// no longer exist anywhere, not even in the trash, is dropped with them.
+    pub fn record_history(&mut self, before: &Data, source: ChangeSource, now: DateTime<Utc>) {
+        let old: HashMap<&str, &Bill> = before.bills.iter().map(|b| (b.id.as_str(), b)).collect();
+        let new: HashMap<&str, &Bill> = self.bills.iter().map(|b| (b.id.as_str(), b)).collect();
+        let was_trashed = |id: &str| before.trash.iter().any(|t| t.id() == id);
+
+        let mut events = Vec::new();
+        for bill in &before.bills {
+            if !new.contains_key(bill.id.as_str()) {
+                events.push((bill.id.clone(), HistoryEvent::Deleted, Vec::new()));
+            }
+        }
+        for bill in &self.bills {
+            match old.get(bill.id.as_str()) {
+                None if was_trashed(&bill.id) => {
+                    events.push((bill.id.clone(), HistoryEvent::Restored, Vec::new()))
+                }
+                None => events.push((bill.id.clone(), HistoryEvent::Created, Vec::new())),
+                Some(prev) => {
+                    let changes = field_changes(prev, bill);
+                    if !changes.is_empty() {
+                        events.push((bill.id.clone(), HistoryEvent::Updated, changes));
+                    }
+                }
+            }
+        }
+
+        for (bill_id, event, changes) in events {
+            let seq = self.history.last().map_or(1, |e| e.seq + 1);
+            self.history.push(HistoryEntry {
+                seq,
+                bill_id,
+                at: now,
+                source,
+                event,
+                changes,
+            });
+        }
+
+        self.prune_history();
+    }
+
+    /// Drops the history of bills that are neither listed nor in the trash.
+    pub fn prune_history(&mut self) {
+        let known: HashSet<&str> = self
+            .bills
+            .iter()
+            .map(|b| b.id.as_str())
+            .chain(self.trash.iter().map(|t| t.id()))
+            .collect();
+        self.history.retain(|e| known.contains(e.bill_id.as_str()));
+    }
+
+    /// Everything recorded for a bill, including a trashed one, newest
+    /// first.
+    pub fn bill_history(&self, id: &str) -> AppResult<Vec<HistoryEntry>> {
+        if self.bill(id).is_none() && !self.trash.iter().any(|t| t.id() == id) {
+            return Err(AppError::not_found("Bill", id));
+        }
+        Ok(self
+            .history
+            .iter()
+            .rev()
+            .filter(|e| e.bill_id == id)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_bill, bill_input, usd};
+
+    /// Applies `f` and records it in the history, as a command does.
+    fn edit<T>(data: &mut Data, f: impl FnOnce(&mut Data) -> T) -> T {
+        let before = data.clone();
+        let out = f(data);
+        data.record_history(&before, ChangeSource::Ui, Utc::now());
+        out
+    }
+
+    fn events(data: &Data, id: &str) -> Vec<HistoryEvent> {
+        let history = data.bill_history(id).unwrap();
+        history.iter().map(|e| e.event).collect()
+    }
+
+    #[test]
+    fn records_the_life_of_a_bill() {
+        let mut data = Data::fresh();
+        let rent = edit(&mut data, |d| add_bill(d, "Rent", 120_000, "2024-01-31"));
+        edit(&mut data, |d| d.delete_bill(&rent.id, Utc::now()).unwrap());
+        edit(&mut data, |d| d.restore_bill(&rent.id).unwrap());
+        edit(&mut data, |d| d.bills[0].tags.push("home".to_string()));
+
+        use HistoryEvent::*;
+        assert_eq!(
+            events(&data, &rent.id),
+            [Updated, Restored, Deleted, Created]
+        );
+        let seqs: Vec<u64> = data.history.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [1, 2, 3, 4]);
+        assert!(data.bill_history("unknown").is_err());
+    }
+
+    #[test]
+    fn updates_list_each_changed_field() {
+        let mut data = Data::fresh();
+        let rent = edit(&mut data, |d| add_bill(d, "Rent", 120_000, "2024-01-31"));
+        edit(&mut data, |d| {
+            let mut input = bill_input("Rent", 125_000, "2024-01-31");
+            input.id = Some(rent.id.clone());
+            input.notes = Some("Landlord".to_string());
+            d.upsert_bill(input, Utc::now()).unwrap()
+        });
+
+        let entry = &data.bill_history(&rent.id).unwrap()[0];
+        assert_eq!(entry.source, ChangeSource::Ui);
+        let amount = serde_json::to_value(usd(125_000)).unwrap();
+        assert_eq!(
+            entry.changes,
+            [
+                FieldChange {
+                    field: "amount".to_string(),
+                    old: Some(serde_json::to_value(usd(120_000)).unwrap()),
+                    new: Some(amount),
+                },
+                FieldChange {
+                    field: "notes".to_string(),
+                    old: None,
+                    new: Some(Value::from("Landlord")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_bills_record_nothing() {
+        let mut data = Data::fresh();
+        edit(&mut data, |d| add_bill(d, "Rent", 120_000, "2024-01-31"));
+        edit(&mut data, |d| d.bills[0].updated_at = Utc::now());
+        assert_eq!(data.history.len(), 1);
+    }
+
+    #[test]
+    fn history_goes_with_purged_bills() {
+        let mut data = Data::fresh();
+        let rent = edit(&mut data, |d| add_bill(d, "Rent", 120_000, "2024-01-31"));
+        let water = edit(&mut data, |d| add_bill(d, "Water", 4_000, "2024-01-15"));
+        let long_ago = Utc::now() - chrono::Duration::days(365);
+        edit(&mut data, |d| d.delete_bill(&rent.id, long_ago).unwrap());
+        assert_eq!(data.history.len(), 3);
+
+        data.purge_expired_trash(Utc::now());
+        assert!(data.bill_history(&rent.id).is_err());
+        assert!(data.history.iter().all(|e| e.bill_id == water.id));
+    }
+}
@@ -0,0 +1,1077 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, BillInput};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::{Currency, Money};
+use crate::recurrence::{Pause, Recurrence, RecurrenceLimits};
+
+const PRODUCT_ID: &str = "-//AutoBillChecker//Bills//EN";
+/// Appended to bill ids to make event UIDs globally unique, while keeping
+/// them the same on every export so calendars update existing events.
+const UID_DOMAIN: &str = "autobillchecker";
+/// Lines longer than this many bytes are folded, as RFC 5545 requires.
+const MAX_LINE: usize = 75;
+/// Hour of the day alarms go off, as due dates have no time.
+const REMINDER_HOUR: i64 = 9;
+
+/// Escapes a TEXT value.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Appends a content line, folded into continuation lines that start with
+/// a space. Folds never split a UTF-8 character.
+fn push_line(out: &mut String, line: &str) {
+    let mut rest = line;
+    let mut limit = MAX_LINE;
+    while rest.len() > limit {
+        let mut at = limit;
+        while !rest.is_char_boundary(at) {
+            at -= 1;
+        }
+        out.push_str(&rest[..at]);
+        out.push_str("\r\n ");
+        rest = &rest[at..];
+        // The leading space counts towards the next line
+        limit = MAX_LINE - 1;
+    }
+    out.push_str(rest);
+    out.push_str("\r\n");
+}
+
+fn date(d: NaiveDate) -> String {
+    d.format("%Y%m%d").to_string()
+}
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn weekday(w: Weekday) -> &'static str {
+    match w {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// Month days for a series due on `day` that falls back to the last day of
+/// shorter months, as the app does: with `BYSETPOS=-1`, the last of
+/// 28..=day that exists.
+fn clamped_days(day: u32) -> String {
+    let days: Vec<String> = (28..=day).map(|d| d.to_string()).collect();
+    days.join(",")
+}
+
+fn clamped_month_day(day: u32) -> String {
+    format!("BYMONTHDAY={};BYSETPOS=-1", clamped_days(day))
+}
+
+/// The RRULE parts for `recurrence` starting on `start`, without end
+/// conditions; `None` for one-time bills.
+fn rule(recurrence: Recurrence, start: NaiveDate) -> Option<String> {
+    let every_months = |n: u32| {
+        let interval = if n > 1 {
+            format!(";INTERVAL={n}")
+        } else {
+            String::new()
+        };
+        if start.day() > 28 {
+            format!("FREQ=MONTHLY{interval};{}", clamped_month_day(start.day()))
+        } else {
+            format!("FREQ=MONTHLY{interval}")
+        }
+    };
+    Some(match recurrence {
+        Recurrence::None => return None,
+        Recurrence::Daily => "FREQ=DAILY".to_string(),
+        Recurrence::Weekly => "FREQ=WEEKLY".to_string(),
+        Recurrence::BiWeekly => "FREQ=WEEKLY;INTERVAL=2".to_string(),
+        Recurrence::EveryNDays { days } => format!("FREQ=DAILY;INTERVAL={days}"),
+        Recurrence::Monthly => every_months(1),
+        Recurrence::Quarterly => every_months(3),
+        Recurrence::SemiAnnual => every_months(6),
+        Recurrence::Yearly if start.month() == 2 && start.day() == 29 => {
+            format!("FREQ=YEARLY;BYMONTH=2;{}", clamped_month_day(29))
+        }
+        Recurrence::Yearly => "FREQ=YEARLY".to_string(),
+        Recurrence::NthWeekday { nth, weekday: w } => {
+            format!("FREQ=MONTHLY;BYDAY={nth}{}", weekday(w))
+        }
+        Recurrence::LastDayOfMonth => "FREQ=MONTHLY;BYMONTHDAY=-1".to_string(),
+        Recurrence::LastBusinessDay => "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1".to_string(),
+    })
+}
+
+/// Alarm offset from the start of an all-day event on the due date.
+fn trigger(days_before: u32) -> String {
+    let hours = REMINDER_HOUR - 24 * i64::from(days_before);
+    if hours < 0 {
+        format!("-PT{}H", -hours)
+    } else {
+        format!("PT{hours}H")
+    }
+}
+
+impl Data {
+    /// An iCalendar file with one all-day event per bill, repeating like
+    /// the bill and with an alarm `reminder_days` before each due date.
+    /// Bills whose series has ended before `today`, with nothing left to
+    /// pay, are left out.
+    pub fn export_ics(&self, today: NaiveDate, now: DateTime<Utc>) -> String {
+        let mut out = String::new();
+        for line in [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            &format!("PRODID:{PRODUCT_ID}"),
+            "CALSCALE:GREGORIAN",
+            "METHOD:PUBLISH",
+        ] {
+            push_line(&mut out, line);
+        }
+        for bill in &self.bills {
+            let upcoming = bill.occurrences().any(|d| d >= today);
+            if upcoming || !bill.paid {
+                self.push_event(&mut out, bill, now);
+            }
+        }
+        push_line(&mut out, "END:VCALENDAR");
+        out
+    }
+
+    fn push_event(&self, out: &mut String, bill: &Bill, now: DateTime<Utc>) {
+        let raw = || bill.recurrence.occurrences(bill.series_start());
+        let Some(first) = raw().next() else { return };
+        if bill.occurrences().next().is_none() {
+            return;
+        }
+        let start = if bill.recurrence == Recurrence::None {
+            bill.due_date
+        } else {
+            first
+        };
+        let category = bill
+            .category_id
+            .as_deref()
+            .and_then(|id| self.category(id).ok())
+            .map(|c| c.name.as_str());
+
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@{UID_DOMAIN}", bill.id),
+            format!("DTSTAMP:{}", timestamp(now)),
+            format!("LAST-MODIFIED:{}", timestamp(bill.updated_at)),
+            format!("DTSTART;VALUE=DATE:{}", date(start)),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                date(start.checked_add_days(Days::new(1)).unwrap_or(start))
+            ),
+            format!(
+                "SUMMARY:{}",
+                escape(&format!("{} ({})", bill.name, bill.amount))
+            ),
+            "TRANSP:TRANSPARENT".to_string(),
+        ];
+        if let Some(notes) = &bill.notes {
+            lines.push(format!("DESCRIPTION:{}", escape(notes)));
+        }
+        if let Some(category) = category {
+            lines.push(format!("CATEGORIES:{}", escape(category)));
+        }
+        if let Some(rule) = rule(bill.recurrence, bill.series_start()) {
+            // An occurrence limit becomes the date of the last occurrence, as
+            // paused occurrences do not count towards it but would in COUNT
+            let until = match bill.limits.max_occurrences {
+                Some(_) => bill.occurrences().last(),
+                None => bill.limits.end_date,
+            };
+            let end = until.map(|d| format!(";UNTIL={}", date(d)));
+            lines.push(format!("RRULE:{rule}{}", end.unwrap_or_default()));
+            let last_pause = bill.limits.pauses.iter().map(|p| p.until).max();
+            let paused: Vec<String> = raw()
+                .take_while(|d| last_pause.is_some_and(|p| *d <= p))
+                .take_while(|d| until.is_none_or(|u| *d <= u))
+                .filter(|d| bill.limits.is_paused(*d))
+                .map(date)
+                .collect();
+            if !paused.is_empty() {
+                lines.push(format!("EXDATE;VALUE=DATE:{}", paused.join(",")));
+            }
+        }
+        lines.extend([
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            format!("DESCRIPTION:{}", escape(&format!("{} is due", bill.name))),
+            format!("TRIGGER:{}", trigger(self.settings.reminder_days)),
+            "END:VALARM".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+        for line in lines {
+            push_line(out, &line);
+        }
+    }
+}
+
+/// Currency codes recognized next to an amount in event text. Any three
+/// capitals would also match words like "TAX".
+const KNOWN_CODES: &[&str] = &[
+    "USD", "EUR", "GBP", "JPY", "CHF", "CAD", "AUD", "NZD", "SEK", "NOK", "DKK", "PLN", "CZK",
+    "HUF", "INR", "CNY", "MXN", "BRL", "ZAR",
+];
+
+/// Joins folded lines and splits each into a property name, without its
+/// parameters, and value.
+fn properties(text: &str) -> Vec<(String, String)> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+        .iter()
+        .filter_map(|line| {
+            // The value starts at the first colon outside a quoted parameter
+            let mut quoted = false;
+            let colon = line.char_indices().find_map(|(i, c)| {
+                match c {
+                    '"' => quoted = !quoted,
+                    ':' if !quoted => return Some(i),
+                    _ => {}
+                }
+                None
+            })?;
+            let name = line[..colon].split(';').next()?.trim().to_ascii_uppercase();
+            Some((name, line[colon + 1..].trim().to_string()))
+        })
+        .collect()
+}
+
+/// Reverses [`escape`].
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// The date of a DATE or DATE-TIME value. Times and time zones are
+/// dropped, as bills are due on a day.
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    Some(match code {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// Maps an RRULE onto a bill recurrence and its end conditions. Rules the
+/// bill model has no equivalent for, such as several days a week, are
+/// rejected rather than approximated.
+fn parse_rule(rule: &str, start: NaiveDate) -> AppResult<(Recurrence, RecurrenceLimits)> {
+    let parts: BTreeMap<String, String> = rule
+        .split(';')
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim().to_ascii_uppercase()))
+        .collect();
+    let unsupported = || AppError::validation(format!("Bills cannot repeat like \"{rule}\""));
+    let get = |key: &str| parts.get(key).map(String::as_str);
+    // Parts beyond these narrow the rule in ways bills cannot follow
+    let only = |allowed: &[&str]| {
+        parts.keys().all(|k| {
+            ["FREQ", "INTERVAL", "UNTIL", "COUNT", "WKST"].contains(&k.as_str())
+                || allowed.contains(&k.as_str())
+        })
+    };
+    let interval: u32 = match get("INTERVAL") {
+        Some(n) => n.parse().map_err(|_| unsupported())?,
+        None => 1,
+    };
+    let by_day = get("BYDAY");
+    let by_set_pos = get("BYSETPOS");
+    // A month day is fine if it restates DTSTART, or clamps it like the
+    // export does
+    let same_day = match get("BYMONTHDAY") {
+        None => by_set_pos.is_none(),
+        Some(days) if by_set_pos == Some("-1") => days == clamped_days(start.day()),
+        Some(day) => by_set_pos.is_none() && day == start.day().to_string(),
+    };
+    let same_weekday = by_day.is_none_or(|d| d == weekday(start.weekday()));
+    let same_month = get("BYMONTH").is_none_or(|m| m == start.month().to_string());
+
+    let recurrence = match (get("FREQ").ok_or_else(unsupported)?, interval) {
+        ("DAILY", 1) if only(&[]) => Recurrence::Daily,
+        ("DAILY", days) if only(&[]) => Recurrence::EveryNDays { days },
+        ("WEEKLY", weeks) if only(&["BYDAY"]) && same_weekday => match weeks {
+            1 => Recurrence::Weekly,
+            2 => Recurrence::BiWeekly,
+            _ => Recurrence::EveryNDays {
+                days: weeks.checked_mul(7).ok_or_else(unsupported)?,
+            },
+        },
+        ("MONTHLY", 1) if get("BYMONTHDAY") == Some("-1") && only(&["BYMONTHDAY"]) => {
+            Recurrence::LastDayOfMonth
+        }
+        ("MONTHLY", 1) if by_day == Some("MO,TU,WE,TH,FR") && by_set_pos == Some("-1") => {
+            Recurrence::LastBusinessDay
+        }
+        ("MONTHLY", 1) if by_day.is_some() && only(&["BYDAY", "BYSETPOS"]) => {
+            // Either BYDAY=2TU, or BYDAY=TU;BYSETPOS=2
+            let by_day = by_day.unwrap_or_default();
+            let (nth, code) = match by_set_pos {
+                Some(pos) => (pos, by_day),
+                None => by_day.split_at(by_day.len().saturating_sub(2)),
+            };
+            let recurrence = Recurrence::NthWeekday {
+                nth: nth
+                    .trim_start_matches('+')
+                    .parse()
+                    .map_err(|_| unsupported())?,
+                weekday: parse_weekday(code).ok_or_else(unsupported)?,
+            };
+            recurrence.validate().map_err(|_| unsupported())?;
+            recurrence
+        }
+        ("MONTHLY", months) if same_day && only(&["BYMONTHDAY", "BYSETPOS"]) => match months {
+            1 => Recurrence::Monthly,
+            3 => Recurrence::Quarterly,
+            6 => Recurrence::SemiAnnual,
+            12 => Recurrence::Yearly,
+            _ => return Err(unsupported()),
+        },
+        ("YEARLY", 1) if same_day && same_month && only(&["BYMONTH", "BYMONTHDAY", "BYSETPOS"]) => {
+            Recurrence::Yearly
+        }
+        _ => return Err(unsupported()),
+    };
+    let limits = RecurrenceLimits {
+        end_date: get("UNTIL")
+            .map(|u| parse_date(u).ok_or_else(unsupported))
+            .transpose()?,
+        max_occurrences: get("COUNT")
+            .map(|c| c.parse().map_err(|_| unsupported()))
+            .transpose()?,
+        pauses: Vec::new(),
+    };
+    Ok((recurrence, limits))
+}
+
+fn symbol_currency(c: char) -> Option<&'static str> {
+    match c {
+        '$' => Some("USD"),
+        '€' => Some("EUR"),
+        '£' => Some("GBP"),
+        '¥' => Some("JPY"),
+        _ => None,
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+/// A currency written just before the end of `before`, with the byte
+/// offset it starts at.
+fn currency_before(before: &str) -> Option<(&str, usize)> {
+    let before = before.trim_end();
+    let last = before.chars().next_back()?;
+    if let Some(code) = symbol_currency(last) {
+        return Some((code, before.len() - last.len_utf8()));
+    }
+    let at = before.len().checked_sub(3)?;
+    let code = before.get(at..)?;
+    (KNOWN_CODES.contains(&code) && !before[..at].ends_with(is_word)).then_some((code, at))
+}
+
+/// A currency written at the start of `after`, with the byte length up to
+/// its end.
+fn currency_after(after: &str) -> Option<(&str, usize)> {
+    let gap = after.len() - after.trim_start().len();
+    let rest = &after[gap..];
+    let first = rest.chars().next()?;
+    if let Some(code) = symbol_currency(first) {
+        return Some((code, gap + first.len_utf8()));
+    }
+    let code = rest.get(..3)?;
+    (KNOWN_CODES.contains(&code) && !rest[3..].starts_with(is_word)).then_some((code, gap + 3))
+}
+
+/// Runs of digits and separators that start a word, with their offsets.
+fn numbers(text: &str) -> Vec<(usize, &str)> {
+    let mut numbers = Vec::new();
+    let mut rest_at = 0;
+    for (i, c) in text.char_indices() {
+        if i < rest_at || !c.is_ascii_digit() || text[..i].ends_with(is_word) {
+            continue;
+        }
+        let len = text[i..]
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(text.len() - i);
+        numbers.push((i, text[i..i + len].trim_end_matches(['.', ','])));
+        rest_at = i + len;
+    }
+    numbers
+}
+
+/// Finds an amount in event text: a number next to a currency symbol or
+/// code, or failing that the first number with two decimals. Returns it
+/// with the byte range it was written in, to take it out of the name.
+fn find_amount(text: &str, currency: Currency) -> Option<(Money, Range<usize>)> {
+    let mut fallback = None;
+    for (start, number) in numbers(text) {
+        let end = start + number.len();
+        let marked = currency_before(&text[..start])
+            .map(|(code, at)| (code, at..end))
+            .or_else(|| currency_after(&text[end..]).map(|(code, len)| (code, start..end + len)));
+        if let Some((code, span)) = marked {
+            let currency = Currency::parse(code).ok()?;
+            if let Ok(money) = Money::parse(number, currency) {
+                return Some((money, span));
+            }
+        } else if fallback.is_none()
+            && number
+                .rfind(['.', ','])
+                .is_some_and(|at| at + 3 == number.len())
+        {
+            fallback = Money::parse(number, currency).ok().map(|m| (m, start..end));
+        }
+    }
+    fallback
+}
+
+/// `summary` without the amount at `span`, and without the brackets or
+/// dashes that set it apart, e.g. "Rent" from "Rent (1200.00 USD)".
+fn name_without(summary: &str, span: Range<usize>) -> String {
+    let name = format!("{}{}", &summary[..span.start], &summary[span.end..]);
+    name.replace("()", "")
+        .replace("[]", "")
+        .trim_matches(|c: char| c.is_whitespace() || "-–:,".contains(c))
+        .to_string()
+}
+
+/// How to import a calendar file, as confirmed in the preview.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IcsImport {
+    /// Currency of amounts written without one; defaults to the reporting
+    /// currency.
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    /// Amounts by event UID, for events that do not mention one.
+    #[serde(default)]
+    pub amounts: BTreeMap<String, Money>,
+    /// UIDs of events to leave out.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// An event that was not imported and why.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedEvent {
+    pub uid: String,
+    pub summary: String,
+    pub message: String,
+    /// The event would import given an amount.
+    pub needs_amount: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IcsImportReport {
+    /// Bills created, or that would be created by a dry run.
+    pub bills: Vec<Bill>,
+    /// UID of the event each bill came from, in the same order.
+    pub uids: Vec<String>,
+    /// Categories created for names that did not exist yet.
+    pub new_categories: Vec<String>,
+    pub skipped: Vec<SkippedEvent>,
+}
+
+/// The properties of a VEVENT that an import uses.
+#[derive(Default)]
+struct Event {
+    uid: String,
+    summary: String,
+    description: Option<String>,
+    start: Option<NaiveDate>,
+    rule: Option<String>,
+    exdates: Vec<NaiveDate>,
+    category: Option<String>,
+    /// Cancelled, or a changed copy of one occurrence of another event.
+    ignored: bool,
+}
+
+/// The VEVENTs of a calendar file. Alarms and other components inside an
+/// event are skipped.
+fn events(text: &str) -> AppResult<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut current: Option<Event> = None;
+    let mut depth = 0;
+    let mut calendar = false;
+    for (name, value) in properties(text) {
+        match (name.as_str(), value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VCALENDAR") => calendar = true,
+            ("BEGIN", "VEVENT") if current.is_none() => current = Some(Event::default()),
+            ("BEGIN", _) if current.is_some() => depth += 1,
+            ("END", "VEVENT") if depth == 0 => events.extend(current.take()),
+            ("END", _) if current.is_some() => depth -= 1,
+            _ => {}
+        }
+        let Some(event) = current.as_mut().filter(|_| depth == 0) else {
+            continue;
+        };
+        match name.as_str() {
+            "UID" => event.uid = value,
+            "SUMMARY" => event.summary = unescape(&value),
+            "DESCRIPTION" => event.description = Some(unescape(&value)),
+            "DTSTART" => event.start = parse_date(&value),
+            "RRULE" => event.rule = Some(value),
+            "EXDATE" => event
+                .exdates
+                .extend(value.split(',').filter_map(parse_date)),
+            "CATEGORIES" => {
+                let first = unescape(value.split(',').next().unwrap_or_default());
+                event.category = Some(first.trim().to_string()).filter(|c| !c.is_empty());
+            }
+            "STATUS" => event.ignored |= value.eq_ignore_ascii_case("CANCELLED"),
+            "RECURRENCE-ID" => event.ignored = true,
+            _ => {}
+        }
+    }
+    if !calendar {
+        return Err(AppError::validation("This is not an iCalendar file"));
+    }
+    Ok(events)
+}
+
+/// The first date, recurrence and end conditions of an event.
+fn schedule(event: &Event) -> AppResult<(NaiveDate, Recurrence, RecurrenceLimits)> {
+    let start = event
+        .start
+        .ok_or_else(|| AppError::validation("The event has no start date"))?;
+    let (recurrence, mut limits) = match &event.rule {
+        Some(rule) => parse_rule(rule, start)?,
+        None => (Recurrence::None, RecurrenceLimits::default()),
+    };
+    // Excluded dates become one-day pauses. Those still count towards
+    // COUNT, but pauses do not count towards the occurrence limit
+    let excluded: Vec<NaiveDate> = event
+        .exdates
+        .iter()
+        .copied()
+        .filter(|d| {
+            recurrence
+                .occurrences(start)
+                .take_while(|o| o <= d)
+                .any(|o| o == *d)
+        })
+        .collect();
+    if let Some(max) = &mut limits.max_occurrences {
+        let excluded = u32::try_from(excluded.len()).unwrap_or(u32::MAX);
+        *max = max.saturating_sub(excluded).max(1);
+    }
+    limits.pauses = excluded
+        .into_iter()
+        .map(|d| Pause { from: d, until: d })
+        .collect();
+    Ok((start, recurrence, limits))
+}
+
+impl Data {
+    /// Creates a bill from every event of a calendar file that can be one,
+    /// and reports the others. The amount is taken from the summary or
+    /// description, and the due date is the first occurrence from `today`
+    /// on. Run it on a copy of the data for a dry run.
+    pub fn import_ics(
+        &mut self,
+        text: &str,
+        options: &IcsImport,
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> AppResult<IcsImportReport> {
+        let currency = options.currency.unwrap_or(self.settings.reporting_currency);
+        let mut report = IcsImportReport {
+            bills: Vec::new(),
+            uids: Vec::new(),
+            new_categories: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for event in events(text)? {
+            if event.ignored || options.exclude.contains(&event.uid) {
+                continue;
+            }
+            let found = find_amount(&event.summary, currency)
+                .map(|(money, span)| (money, name_without(&event.summary, span)))
+                .or_else(|| {
+                    let description = event.description.as_deref()?;
+                    let (money, _) = find_amount(description, currency)?;
+                    Some((money, event.summary.trim().to_string()))
+                });
+            let (amount, name) = match found {
+                Some((money, name)) => (Some(money), name),
+                None => (None, event.summary.trim().to_string()),
+            };
+            let amount = options.amounts.get(&event.uid).copied().or(amount);
+            let scheduled = schedule(&event);
+            // Only worth asking for an amount if nothing else is wrong
+            let needs_amount = amount.is_none() && scheduled.is_ok();
+            let imported = scheduled.and_then(|(start, recurrence, limits)| {
+                let amount =
+                    amount.ok_or_else(|| AppError::validation("No amount found in the event"))?;
+                let input = BillInput {
+                    id: None,
+                    name,
+                    amount,
+                    due_date: start.format("%Y-%m-%d").to_string(),
+                    recurrence,
+                    limits,
+                    category_id: None,
+                    tags: Vec::new(),
+                    notes: event.description.clone(),
+                    updated_at: None,
+                };
+                self.import_event(&event, input, &mut report.new_categories, today, now)
+            });
+            match imported {
+                Ok(bill) => {
+                    report.bills.push(bill);
+                    report.uids.push(event.uid);
+                }
+                Err(e) => report.skipped.push(SkippedEvent {
+                    needs_amount,
+                    uid: event.uid,
+                    summary: event.summary,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    fn import_event(
+        &mut self,
+        event: &Event,
+        mut input: BillInput,
+        new_categories: &mut Vec<String>,
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> AppResult<Bill> {
+        let start = input.validate()?;
+        // Events exported by this app carry the bill id in their UID
+        let own = event.uid.strip_suffix(&format!("@{UID_DOMAIN}"));
+        let duplicate = self.bills.iter().find(|b| {
+            Some(b.id.as_str()) == own
+                || (b.name.eq_ignore_ascii_case(input.name.trim())
+                    && b.amount == input.amount
+                    && b.recurrence == input.recurrence)
+        });
+        if let Some(bill) = duplicate {
+            return Err(AppError::Conflict(format!(
+                "Already in the app as \"{}\"",
+                bill.name
+            )));
+        }
+
+        let due = input
+            .limits
+            .apply(input.recurrence.occurrences(start))
+            .find(|d| *d >= today)
+            .ok_or_else(|| AppError::validation("All of its dates are in the past"))?;
+        if let Some(category) = &event.category {
+            input.category_id = Some(self.category_named(category, new_categories)?);
+        }
+        let bill = self.upsert_bill(input, now)?;
+        // The series keeps its start, so it repeats from the same day
+        let idx = self.bill_index(&bill.id)?;
+        self.bills[idx].due_date = due;
+        Ok(self.bills[idx].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recurrence::{Pause, RecurrenceLimits};
+    use crate::testing::{add_bill, bill_input, date as day, usd};
+
+    fn at(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    fn calendar(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{events}END:VCALENDAR\r\n")
+    }
+
+    fn import(data: &mut Data, events: &str) -> IcsImportReport {
+        data.import_ics(
+            &calendar(events),
+            &IcsImport::default(),
+            day("2024-01-01"),
+            Utc::now(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn exports_a_bill_as_a_repeating_event() {
+        let mut data = Data::fresh();
+        let mut input = bill_input("Loan, car", 25_000, "2024-01-31");
+        input.notes = Some("Bank; ref 42".to_string());
+        input.limits = RecurrenceLimits {
+            max_occurrences: Some(6),
+            pauses: vec![Pause {
+                from: day("2024-03-01"),
+                until: day("2024-03-31"),
+            }],
+            ..Default::default()
+        };
+        data.upsert_bill(input, Utc::now()).unwrap();
+        data.bills[0].id = "b1".to_string();
+        data.bills[0].updated_at = at("2024-01-02T10:00:00Z");
+        data.settings.reminder_days = 2;
+
+        let expected = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "PRODID:-//AutoBillChecker//Bills//EN",
+            "CALSCALE:GREGORIAN",
+            "METHOD:PUBLISH",
+            "BEGIN:VEVENT",
+            "UID:b1@autobillchecker",
+            "DTSTAMP:20240105T120000Z",
+            "LAST-MODIFIED:20240102T100000Z",
+            "DTSTART;VALUE=DATE:20240131",
+            "DTEND;VALUE=DATE:20240201",
+            r"SUMMARY:Loan\, car (250.00 USD)",
+            "TRANSP:TRANSPARENT",
+            r"DESCRIPTION:Bank\; ref 42",
+            // Six occurrences, not counting the paused one in March
+            "RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1;UNTIL=20240731",
+            "EXDATE;VALUE=DATE:20240331",
+            "BEGIN:VALARM",
+            "ACTION:DISPLAY",
+            r"DESCRIPTION:Loan\, car is due",
+            "TRIGGER:-PT39H",
+            "END:VALARM",
+            "END:VEVENT",
+            "END:VCALENDAR",
+            "",
+        ];
+        assert_eq!(
+            data.export_ics(day("2024-01-05"), at("2024-01-05T12:00:00Z")),
+            expected.join("\r\n")
+        );
+    }
+
+    #[test]
+    fn one_time_bills_do_not_repeat() {
+        let mut data = Data::fresh();
+        let mut input = bill_input("Dentist", 8_000, "2024-03-01");
+        input.recurrence = Recurrence::None;
+        data.upsert_bill(input, Utc::now()).unwrap();
+        let text = data.export_ics(day("2024-01-01"), Utc::now());
+        assert!(text.contains("DTSTART;VALUE=DATE:20240301\r\n"));
+        assert!(!text.contains("RRULE"));
+        assert!(text.contains("TRIGGER:PT9H\r\n"));
+    }
+
+    #[test]
+    fn uses_the_category_name() {
+        let mut data = Data::fresh();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        data.bills[0].category_id = Some(data.categories[0].id.clone());
+        let name = escape(&data.categories[0].name);
+        assert!(data
+            .export_ics(day("2024-01-01"), Utc::now())
+            .contains(&format!("\r\nCATEGORIES:{name}\r\n")));
+    }
+
+    #[test]
+    fn month_end_rules_clamp() {
+        assert_eq!(
+            rule(Recurrence::Monthly, day("2024-01-30")).unwrap(),
+            "FREQ=MONTHLY;BYMONTHDAY=28,29,30;BYSETPOS=-1"
+        );
+        assert_eq!(
+            rule(Recurrence::Quarterly, day("2024-01-28")).unwrap(),
+            "FREQ=MONTHLY;INTERVAL=3"
+        );
+        assert_eq!(
+            rule(Recurrence::Yearly, day("2024-02-29")).unwrap(),
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=28,29;BYSETPOS=-1"
+        );
+        assert_eq!(
+            rule(
+                Recurrence::NthWeekday {
+                    nth: -1,
+                    weekday: Weekday::Fri
+                },
+                day("2024-01-26")
+            )
+            .unwrap(),
+            "FREQ=MONTHLY;BYDAY=-1FR"
+        );
+        assert_eq!(rule(Recurrence::None, day("2024-01-01")), None);
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let mut out = String::new();
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        push_line(&mut out, &line);
+        assert!(out.split("\r\n").all(|l| l.len() <= MAX_LINE));
+        let props = properties(&out);
+        assert_eq!(props, [("SUMMARY".to_string(), "é".repeat(60))]);
+    }
+
+    #[test]
+    fn escapes_text() {
+        let text = "Rent; flat 3, \\ upstairs\nthanks";
+        assert_eq!(escape(text), r"Rent\; flat 3\, \\ upstairs\nthanks");
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(trigger(0), "PT9H");
+        assert_eq!(trigger(2), "-PT39H");
+    }
+
+    #[test]
+    fn exported_rules_parse_back() {
+        let recurrences = [
+            Recurrence::Daily,
+            Recurrence::Weekly,
+            Recurrence::BiWeekly,
+            Recurrence::EveryNDays { days: 10 },
+            Recurrence::Monthly,
+            Recurrence::Quarterly,
+            Recurrence::SemiAnnual,
+            Recurrence::Yearly,
+            Recurrence::NthWeekday {
+                nth: 2,
+                weekday: Weekday::Tue,
+            },
+            Recurrence::NthWeekday {
+                nth: -1,
+                weekday: Weekday::Fri,
+            },
+            Recurrence::LastDayOfMonth,
+            Recurrence::LastBusinessDay,
+        ];
+        for start in ["2024-01-09", "2024-01-31", "2024-02-29"] {
+            let start = day(start);
+            for recurrence in recurrences {
+                let rule = rule(recurrence, start).unwrap();
+                let (parsed, limits) = parse_rule(&rule, start).unwrap();
+                assert_eq!(parsed, recurrence, "{rule} from {start}");
+                assert!(limits.is_empty());
+            }
+        }
+        assert_eq!(rule(Recurrence::None, day("2024-01-01")), None);
+    }
+
+    #[test]
+    fn parses_common_rules() {
+        let start = day("2024-01-09");
+        let parse = |rule: &str| parse_rule(rule, start).map(|(r, _)| r);
+        let second_tuesday = Recurrence::NthWeekday {
+            nth: 2,
+            weekday: Weekday::Tue,
+        };
+        assert_eq!(parse("freq=monthly;byday=+2tu").unwrap(), second_tuesday);
+        assert_eq!(
+            parse("FREQ=MONTHLY;BYDAY=TU;BYSETPOS=2").unwrap(),
+            second_tuesday
+        );
+        assert_eq!(
+            parse("FREQ=WEEKLY;INTERVAL=3;WKST=MO").unwrap(),
+            Recurrence::EveryNDays { days: 21 }
+        );
+        assert_eq!(
+            parse("FREQ=MONTHLY;INTERVAL=12").unwrap(),
+            Recurrence::Yearly
+        );
+        assert_eq!(
+            parse("FREQ=MONTHLY;BYMONTHDAY=9").unwrap(),
+            Recurrence::Monthly
+        );
+        assert_eq!(parse("FREQ=YEARLY;BYMONTH=1").unwrap(), Recurrence::Yearly);
+
+        let (_, limits) = parse_rule("FREQ=WEEKLY;UNTIL=20241231T235959Z", start).unwrap();
+        assert_eq!(limits.end_date, Some(day("2024-12-31")));
+        let (_, limits) = parse_rule("FREQ=WEEKLY;COUNT=12", start).unwrap();
+        assert_eq!(limits.max_occurrences, Some(12));
+    }
+
+    #[test]
+    fn rejects_rules_bills_cannot_follow() {
+        let start = day("2024-01-09");
+        for rule in [
+            "",
+            "FREQ=HOURLY",
+            "FREQ=WEEKLY;BYDAY=MO,WE",
+            "FREQ=WEEKLY;BYDAY=FR",
+            "FREQ=MONTHLY;INTERVAL=2",
+            "FREQ=MONTHLY;BYMONTHDAY=15",
+            "FREQ=MONTHLY;BYDAY=5TU",
+            "FREQ=MONTHLY;INTERVAL=x",
+            "FREQ=YEARLY;BYMONTH=3",
+            "FREQ=DAILY;BYHOUR=9",
+            "FREQ=DAILY;COUNT=many",
+            "FREQ=WEEKLY;INTERVAL=1000000000",
+        ] {
+            let err = parse_rule(rule, start).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Bills cannot repeat like \"{rule}\"")
+            );
+        }
+    }
+
+    #[test]
+    fn finds_amounts_in_text() {
+        fn found(text: &str) -> Option<(Money, &str)> {
+            find_amount(text, Currency::USD).map(|(m, s)| (m, &text[s]))
+        }
+        assert_eq!(
+            found("Rent (1,200.00 USD)"),
+            Some((usd(120000), "1,200.00 USD"))
+        );
+        assert_eq!(found("Gym $25"), Some((usd(2500), "$25")));
+        assert_eq!(found("Flat 3 - 45.50"), Some((usd(4550), "45.50")));
+        assert_eq!(found("Flat 3"), None);
+        // Malformed numbers are skipped rather than read as something else
+        assert_eq!(found("Fee $1,2,3"), None);
+        assert_eq!(found("Call 555.12.34 - 45.50"), Some((usd(4550), "45.50")));
+        assert_eq!(found("TAX2024 form"), None);
+        assert_eq!(name_without("Rent (1,200.00 USD)", 6..18), "Rent");
+    }
+
+    #[test]
+    fn imports_events() {
+        let mut data = Data::fresh();
+        let events = "BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Rent ($1200)\r\nDTSTART;VALUE=DATE:20231231\r\n\
+                      RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1\r\nEXDATE:20240229\r\n\
+                      CATEGORIES:Housing,Home\r\nBEGIN:VALARM\r\nSUMMARY:ignored\r\nEND:VALARM\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:b\r\nSUMMARY:Dentist\r\nDTSTART:20240301\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:c\r\nSUMMARY:Old ($5)\r\nDTSTART:20230301\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:d\r\nSUMMARY:Gone ($5)\r\nDTSTART:20240301\r\nSTATUS:CANCELLED\r\nEND:VEVENT\r\n";
+        let report = import(&mut data, events);
+        assert_eq!(report.uids, ["a"]);
+        let rent = &report.bills[0];
+        assert_eq!((rent.name.as_str(), rent.amount), ("Rent", usd(120000)));
+        assert_eq!(rent.recurrence, Recurrence::Monthly);
+        assert_eq!(rent.series_start(), day("2023-12-31"));
+        assert_eq!(rent.due_date, day("2024-01-31"));
+        assert!(rent.limits.is_paused(day("2024-02-29")));
+        assert!(rent.category_id.is_some());
+
+        let skipped: Vec<_> = report
+            .skipped
+            .iter()
+            .map(|s| (s.uid.as_str(), s.needs_amount))
+            .collect();
+        assert_eq!(skipped, [("b", true), ("c", false)]);
+
+        // Importing again finds the bill already there
+        let again = import(&mut data, events);
+        assert!(again.bills.is_empty());
+        assert_eq!(again.skipped[0].message, "Already in the app as \"Rent\"");
+
+        assert!(data
+            .import_ics(
+                "BEGIN:VEVENT\r\nEND:VEVENT\r\n",
+                &IcsImport::default(),
+                day("2024-01-01"),
+                Utc::now()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn exported_calendar_imports_back() {
+        let mut data = Data::fresh();
+        let mut input = bill_input("Loan, car", 25_000, "2024-01-31");
+        input.limits.max_occurrences = Some(6);
+        input.notes = Some("Bank; ref 42".to_string());
+        let bill = data.upsert_bill(input, Utc::now()).unwrap();
+        let text = data.export_ics(day("2024-01-01"), Utc::now());
+        assert!(text
+            .contains("RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1;UNTIL=20240630\r\n"));
+
+        let mut copy = Data::fresh();
+        let report = copy
+            .import_ics(&text, &IcsImport::default(), day("2024-01-01"), Utc::now())
+            .unwrap();
+        let imported = &report.bills[0];
+        assert_eq!(imported.name, bill.name);
+        assert_eq!(imported.amount, bill.amount);
+        assert_eq!(imported.notes, bill.notes);
+        assert_eq!(
+            imported.occurrences().collect::<Vec<_>>(),
+            bill.occurrences().collect::<Vec<_>>()
+        );
+
+        // The bill's own UID marks it as a duplicate
+        data.bills[0].name = "Renamed".to_string();
+        let again = data
+            .import_ics(&text, &IcsImport::default(), day("2024-01-01"), Utc::now())
+            .unwrap();
+        assert!(again.bills.is_empty());
+    }
+
+    #[test]
+    fn leaves_finished_bills_out() {
+        let mut data = Data::fresh();
+        let mut input = bill_input("Phone", 3_000, "2024-01-15");
+        input.limits.end_date = Some(day("2024-03-15"));
+        data.upsert_bill(input, Utc::now()).unwrap();
+        let exported = |data: &Data| {
+            data.export_ics(day("2024-06-01"), Utc::now())
+                .contains("BEGIN:VEVENT")
+        };
+        // Ended but unpaid bills stay, so they are not forgotten
+        assert!(exported(&data));
+        data.bills[0].paid = true;
+        assert!(!exported(&data));
+    }
+}
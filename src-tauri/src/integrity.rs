@@ -0,0 +1,772 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use zeroize::Zeroizing;
+
+use crate::backup::{self, write_atomic, Staged};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::vault::VaultKey;
+
+/// Dates outside these years are taken for typos or corruption.
+const YEARS: std::ops::RangeInclusive<i32> = 1900..=2200;
+
+/// What is wrong with a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Problem {
+    MissingId,
+    DuplicateId,
+    BlankName,
+    InvalidAmount,
+    InvalidSchedule,
+    InvalidDate,
+    /// A payment for a bill that does not exist.
+    DanglingPayment,
+    CurrencyMismatch,
+    MissingCategory,
+}
+
+/// A fix the user can pick for an issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Repair {
+    /// Gives the record a new id of its own.
+    NewId,
+    /// Makes a bill uncategorized.
+    ClearCategory,
+    /// Restarts a bill's series at its current due date.
+    ResetStartDate,
+    /// Moves the record out of the data into the quarantine file.
+    Quarantine,
+}
+
+/// One problem found by [`Data::check_integrity`]. Records are identified
+/// by collection and position, as ids may be missing or repeated.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+    /// `bills`, `payments`, `categories` or `trash`.
+    pub collection: String,
+    pub index: usize,
+    pub record_id: String,
+    pub problem: Problem,
+    pub message: String,
+    /// Fixes that apply, the least destructive first.
+    pub repairs: Vec<Repair>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    /// Number of records checked.
+    pub records: usize,
+    pub issues: Vec<Issue>,
+}
+
+/// A repair picked from an [`Issue`] of the latest report.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairRequest {
+    pub collection: String,
+    pub index: usize,
+    pub record_id: String,
+    pub repair: Repair,
+}
+
+/// A record set aside because it could not be used, kept so it can still
+/// be recovered by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedRecord {
+    pub collection: String,
+    pub reason: String,
+    pub quarantined_at: DateTime<Utc>,
+    pub record: Value,
+}
+
+/// Side file collecting quarantined records, encrypted with the vault key
+/// while the vault is in use.
+#[derive(Debug)]
+pub struct Quarantine {
+    path: PathBuf,
+}
+
+impl Quarantine {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn read(&self, key: Option<&VaultKey>) -> AppResult<Vec<QuarantinedRecord>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(AppError::io(
+                    format!("Failed to read {}", self.path.display()),
+                    e,
+                ))
+            }
+        };
+        let what = self.path.display().to_string();
+        serde_json::from_slice(&backup::plaintext(&what, &bytes, key)?)
+            .map_err(|e| AppError::CorruptData(format!("{what} is not valid: {e}")))
+    }
+
+    /// Adds `records` to the file.
+    pub fn append(&self, records: &[QuarantinedRecord], key: Option<&VaultKey>) -> AppResult<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut all = self.read(key)?;
+        all.extend_from_slice(records);
+        self.write(&all, key)
+    }
+
+    /// Re-encrypts the file from `from` to `to`, like the backups.
+    pub fn reseal(&self, from: Option<&VaultKey>, to: Option<&VaultKey>) -> AppResult<()> {
+        match self.stage_reseal(from, to)? {
+            Some(staged) => staged.commit(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the file re-encrypted from `from` to `to` next to it, if there
+    /// is one, without replacing it yet.
+    pub fn stage_reseal(
+        &self,
+        from: Option<&VaultKey>,
+        to: Option<&VaultKey>,
+    ) -> AppResult<Option<Staged>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let doc = Self::encode(&self.read(from)?)?;
+        backup::stage(&self.path, &backup::seal(&doc, to)?).map(Some)
+    }
+
+    fn write(&self, records: &[QuarantinedRecord], key: Option<&VaultKey>) -> AppResult<()> {
+        let doc = Self::encode(records)?;
+        write_atomic(&self.path, &backup::seal(&doc, key)?)
+    }
+
+    fn encode(records: &[QuarantinedRecord]) -> AppResult<Zeroizing<Vec<u8>>> {
+        serde_json::to_vec_pretty(records)
+            .map(Zeroizing::new)
+            .map_err(|e| AppError::CorruptData(format!("Failed to serialize records: {e}")))
+    }
+}
+
+/// Whether `value`, as the only entry of `field`, deserializes as part of
+/// [`Data`].
+fn probe(field: &str, value: &Value, list: bool) -> Result<(), String> {
+    let value = if list {
+        Value::Array(vec![value.clone()])
+    } else {
+        value.clone()
+    };
+    let mut doc = Map::new();
+    doc.insert(field.to_string(), value);
+    serde_json::from_value::<Data>(Value::Object(doc))
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Reads an upgraded JSON document record by record: records that do not
+/// deserialize, and later ones repeating an id, are returned for quarantine
+/// instead of failing the whole document.
+pub fn salvage(
+    what: &str,
+    mut doc: Value,
+    now: DateTime<Utc>,
+) -> AppResult<(Data, Vec<QuarantinedRecord>)> {
+    let Some(fields) = doc.as_object_mut() else {
+        return Err(AppError::CorruptData(format!(
+            "{what} is not a JSON object"
+        )));
+    };
+    let mut quarantined = Vec::new();
+    let mut set_aside = |collection: &str, reason: String, record: Value| {
+        quarantined.push(QuarantinedRecord {
+            collection: collection.to_string(),
+            reason,
+            quarantined_at: now,
+            record,
+        })
+    };
+    let mut invalid_fields = Vec::new();
+    for (field, value) in fields.iter_mut() {
+        let Value::Array(records) = value else {
+            if let Err(reason) = probe(field, value, false) {
+                invalid_fields.push(field.clone());
+                set_aside(field, reason, value.take());
+            }
+            continue;
+        };
+        let mut ids = HashSet::new();
+        for record in std::mem::take(records) {
+            let reason = match probe(field, &record, true) {
+                Err(reason) => Some(reason),
+                Ok(()) => match record.get("id").and_then(Value::as_str) {
+                    Some(id) if !ids.insert(id.to_string()) => Some(format!("Duplicate id {id}")),
+                    _ => None,
+                },
+            };
+            match reason {
+                Some(reason) => set_aside(field, reason, record),
+                None => records.push(record),
+            }
+        }
+    }
+    for field in invalid_fields {
+        fields.remove(&field);
+    }
+    let data = serde_json::from_value(doc)
+        .map_err(|e| AppError::CorruptData(format!("{what} is not valid: {e}")))?;
+    Ok((data, quarantined))
+}
+
+fn sane(date: NaiveDate) -> bool {
+    YEARS.contains(&date.year())
+}
+
+/// Collects issues of one collection.
+struct Checker<'a> {
+    collection: &'static str,
+    issues: &'a mut Vec<Issue>,
+    ids: HashSet<String>,
+}
+
+impl Checker<'_> {
+    fn report(
+        &mut self,
+        index: usize,
+        record_id: &str,
+        problem: Problem,
+        message: String,
+        repairs: &[Repair],
+    ) {
+        self.issues.push(Issue {
+            collection: self.collection.to_string(),
+            index,
+            record_id: record_id.to_string(),
+            problem,
+            message,
+            repairs: repairs.to_vec(),
+        });
+    }
+
+    /// Reports a blank id or one already seen.
+    fn check_id(&mut self, index: usize, id: &str, what: &str) {
+        if id.trim().is_empty() {
+            let message = format!("{what} has no id");
+            self.report(
+                index,
+                id,
+                Problem::MissingId,
+                message,
+                &[Repair::NewId, Repair::Quarantine],
+            );
+        } else if !self.ids.insert(id.to_string()) {
+            let message = format!("{what} repeats the id {id}");
+            self.report(
+                index,
+                id,
+                Problem::DuplicateId,
+                message,
+                &[Repair::NewId, Repair::Quarantine],
+            );
+        }
+    }
+}
+
+impl Data {
+    /// Validates every record and reports what is wrong with it, without
+    /// changing anything.
+    pub fn check_integrity(&self) -> IntegrityReport {
+        let mut issues = Vec::new();
+
+        let mut check = Checker {
+            collection: "categories",
+            issues: &mut issues,
+            ids: HashSet::new(),
+        };
+        for (i, category) in self.categories.iter().enumerate() {
+            let what = format!("Category \"{}\"", category.name);
+            check.check_id(i, &category.id, &what);
+            if category.name.trim().is_empty() {
+                let message = format!("Category {} has no name", category.id);
+                check.report(
+                    i,
+                    &category.id,
+                    Problem::BlankName,
+                    message,
+                    &[Repair::Quarantine],
+                );
+            }
+        }
+        let categories = check.ids;
+
+        let mut check = Checker {
+            collection: "bills",
+            issues: &mut issues,
+            ids: HashSet::new(),
+        };
+        for (i, bill) in self.bills.iter().enumerate() {
+            let what = format!("Bill \"{}\"", bill.name);
+            check.check_id(i, &bill.id, &what);
+            let mut report = |problem, message: String, repairs: &[Repair]| {
+                check.report(i, &bill.id, problem, message, repairs)
+            };
+            if bill.name.trim().is_empty() {
+                let message = format!("Bill {} has no name", bill.id);
+                report(Problem::BlankName, message, &[Repair::Quarantine]);
+            }
+            if !bill.amount.is_positive() {
+                let message = format!("{what} has an amount of {}", bill.amount.minor);
+                report(Problem::InvalidAmount, message, &[Repair::Quarantine]);
+            }
+            if let Err(e) = bill
+                .recurrence
+                .validate()
+                .and_then(|()| bill.limits.validate(bill.series_start()))
+            {
+                let message = format!("{what} has an invalid schedule: {e}");
+                report(Problem::InvalidSchedule, message, &[Repair::Quarantine]);
+            }
+            if !sane(bill.due_date) || !sane(bill.series_start()) {
+                let message = format!("{what} is due on {}", bill.due_date);
+                report(Problem::InvalidDate, message, &[Repair::Quarantine]);
+            } else if !bill.is_occurrence(bill.due_date) {
+                let message = format!(
+                    "{what} is due on {}, which is not in its schedule",
+                    bill.due_date
+                );
+                report(
+                    Problem::InvalidDate,
+                    message,
+                    &[Repair::ResetStartDate, Repair::Quarantine],
+                );
+            }
+            if let Some(id) = bill
+                .category_id
+                .as_deref()
+                .filter(|id| !categories.contains(*id))
+            {
+                let message = format!("{what} is in category {id}, which does not exist");
+                report(Problem::MissingCategory, message, &[Repair::ClearCategory]);
+            }
+        }
+        let bills = check.ids;
+
+        // Trashed bills share the id space, as restoring them relists them
+        let mut check = Checker {
+            collection: "trash",
+            issues: &mut issues,
+            ids: bills,
+        };
+        for (i, trashed) in self.trash.iter().enumerate() {
+            check.check_id(
+                i,
+                trashed.id(),
+                &format!("Deleted bill \"{}\"", trashed.bill.name),
+            );
+        }
+
+        let mut check = Checker {
+            collection: "payments",
+            issues: &mut issues,
+            ids: HashSet::new(),
+        };
+        for (i, payment) in self.payments.iter().enumerate() {
+            let what = format!("Payment {}", payment.id);
+            check.check_id(i, &payment.id, &what);
+            let mut report = |problem, message: String| {
+                check.report(i, &payment.id, problem, message, &[Repair::Quarantine])
+            };
+            match self.bill(&payment.bill_id) {
+                None => report(
+                    Problem::DanglingPayment,
+                    format!(
+                        "{what} is for bill {}, which does not exist",
+                        payment.bill_id
+                    ),
+                ),
+                Some(bill) if bill.amount.currency != payment.amount.currency => report(
+                    Problem::CurrencyMismatch,
+                    format!(
+                        "{what} is in {} but \"{}\" is billed in {}",
+                        payment.amount.currency, bill.name, bill.amount.currency
+                    ),
+                ),
+                Some(_) => {}
+            }
+            if !payment.amount.is_positive() {
+                report(
+                    Problem::InvalidAmount,
+                    format!("{what} has an amount of {}", payment.amount.minor),
+                );
+            }
+            if !sane(payment.paid_date) || !sane(payment.occurrence_date) {
+                report(
+                    Problem::InvalidDate,
+                    format!("{what} was paid on {}", payment.paid_date),
+                );
+            }
+        }
+
+        IntegrityReport {
+            records: self.bills.len()
+                + self.payments.len()
+                + self.categories.len()
+                + self.trash.len(),
+            issues,
+        }
+    }
+
+    /// Applies repairs picked from the latest report and returns the
+    /// records taken out for quarantine. Fails without changing anything if
+    /// a repair no longer matches an issue, e.g. because the data changed
+    /// since the check.
+    pub fn repair(
+        &mut self,
+        requests: &[RepairRequest],
+        now: DateTime<Utc>,
+    ) -> AppResult<Vec<QuarantinedRecord>> {
+        let issues = self.check_integrity().issues;
+        let same_record = |a: &RepairRequest, b: &RepairRequest| {
+            a.collection == b.collection && a.index == b.index
+        };
+        if requests.iter().any(|a| {
+            a.repair == Repair::NewId
+                && requests
+                    .iter()
+                    .any(|b| b.repair == Repair::Quarantine && same_record(a, b))
+        }) {
+            return Err(AppError::validation(
+                "A record cannot both get a new id and be quarantined",
+            ));
+        }
+        // Back to front, so earlier positions stay valid, and bills last as
+        // they take their payments along; each record is taken out once
+        let mut removals = BTreeMap::new();
+        for request in requests {
+            let issue = issues
+                .iter()
+                .find(|i| {
+                    i.collection == request.collection
+                        && i.index == request.index
+                        && i.record_id == request.record_id
+                        && i.repairs.contains(&request.repair)
+                })
+                .ok_or_else(|| {
+                    AppError::Conflict(
+                        "The data changed since it was checked; check it again".to_string(),
+                    )
+                })?;
+            let new_id = || uuid::Uuid::new_v4().to_string();
+            match (request.repair, issue.collection.as_str()) {
+                (Repair::Quarantine, _) => {
+                    let key = (
+                        issue.collection == "bills",
+                        issue.collection.clone(),
+                        Reverse(issue.index),
+                    );
+                    removals.entry(key).or_insert_with(|| issue.message.clone());
+                }
+                (Repair::NewId, "bills") => self.bills[issue.index].id = new_id(),
+                (Repair::NewId, "payments") => self.payments[issue.index].id = new_id(),
+                (Repair::NewId, "categories") => self.categories[issue.index].id = new_id(),
+                (Repair::NewId, "trash") => self.trash[issue.index].bill.id = new_id(),
+                (Repair::ClearCategory, "bills") => self.bills[issue.index].category_id = None,
+                (Repair::ResetStartDate, "bills") => {
+                    let bill = &mut self.bills[issue.index];
+                    bill.start_date = Some(bill.due_date);
+                }
+                _ => {}
+            }
+        }
+
+        let mut quarantined = Vec::new();
+        for ((_, collection, Reverse(index)), reason) in removals {
+            let record = match collection.as_str() {
+                "bills" => self.take_bill(index),
+                "payments" => serde_json::to_value(self.payments.remove(index)),
+                "categories" => self.take_category(index),
+                "trash" => serde_json::to_value(self.trash.remove(index)),
+                _ => continue,
+            }
+            .map_err(|e| AppError::CorruptData(format!("Failed to serialize record: {e}")))?;
+            quarantined.push(QuarantinedRecord {
+                collection,
+                reason,
+                quarantined_at: now,
+                record,
+            });
+        }
+        Ok(quarantined)
+    }
+
+    /// Removes a bill together with its payments, unless another bill
+    /// shares its id and so claims them too.
+    fn take_bill(&mut self, index: usize) -> serde_json::Result<Value> {
+        let bill = self.bills.remove(index);
+        let mut record = serde_json::to_value(&bill)?;
+        if self.bill(&bill.id).is_none() {
+            let (payments, kept) = std::mem::take(&mut self.payments)
+                .into_iter()
+                .partition::<Vec<_>, _>(|p| p.bill_id == bill.id);
+            self.payments = kept;
+            if let Value::Object(fields) = &mut record {
+                fields.insert("payments".to_string(), serde_json::to_value(payments)?);
+            }
+        }
+        Ok(record)
+    }
+
+    /// Removes a category, leaving its bills uncategorized unless another
+    /// category shares its id.
+    fn take_category(&mut self, index: usize) -> serde_json::Result<Value> {
+        let category = self.categories.remove(index);
+        if self.category(&category.id).is_err() {
+            let bills = self
+                .bills
+                .iter_mut()
+                .chain(self.trash.iter_mut().map(|t| &mut t.bill));
+            for bill in bills.filter(|b| b.category_id.as_ref() == Some(&category.id)) {
+                bill.category_id = None;
+            }
+        }
+        serde_json::to_value(category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::bill::Bill;
+    use crate::payment::PaymentInput;
+    use crate::testing::{date, usd};
+
+    fn add_bill(data: &mut Data, name: &str) -> Bill {
+        crate::testing::add_bill(data, name, 10000, "2024-01-01")
+    }
+
+    fn pay(data: &mut Data, bill: &Bill, minor: i64) {
+        let input = PaymentInput {
+            bill_id: bill.id.clone(),
+            occurrence_date: None,
+            paid_date: None,
+            amount: Some(usd(minor)),
+            method: None,
+            confirmation: None,
+            transaction_id: None,
+        };
+        data.record_payment(input, date("2024-01-01"), Utc::now())
+            .unwrap();
+    }
+
+    fn request(collection: &str, index: usize, record_id: &str, repair: Repair) -> RepairRequest {
+        RepairRequest {
+            collection: collection.to_string(),
+            index,
+            record_id: record_id.to_string(),
+            repair,
+        }
+    }
+
+    fn problems(data: &Data) -> Vec<(String, usize, Problem)> {
+        data.check_integrity()
+            .issues
+            .into_iter()
+            .map(|i| (i.collection, i.index, i.problem))
+            .collect()
+    }
+
+    /// Data with a repeated payment and a repeated trash entry.
+    fn with_duplicates() -> Data {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent");
+        pay(&mut data, &rent, 2500);
+        let water = add_bill(&mut data, "Water");
+        data.delete_bill(&water.id, Utc::now()).unwrap();
+        data.payments.push(data.payments[0].clone());
+        data.trash.push(data.trash[0].clone());
+        data
+    }
+
+    #[test]
+    fn clean_data_has_no_issues() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent");
+        pay(&mut data, &rent, 2500);
+        let report = data.check_integrity();
+        assert!(report.issues.is_empty());
+        assert_eq!(report.records, 1 + 1 + data.categories.len());
+    }
+
+    #[test]
+    fn finds_broken_records() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent");
+        add_bill(&mut data, "Water");
+        pay(&mut data, &rent, 2500);
+        data.bills[0].category_id = Some("gone".to_string());
+        data.bills[1].id = rent.id.clone();
+        data.bills[1].name = " ".to_string();
+        data.payments[0].bill_id = "gone".to_string();
+        data.payments[0].paid_date = date("1024-01-01");
+        assert_eq!(
+            problems(&data),
+            [
+                ("bills".to_string(), 0, Problem::MissingCategory),
+                ("bills".to_string(), 1, Problem::DuplicateId),
+                ("bills".to_string(), 1, Problem::BlankName),
+                ("payments".to_string(), 0, Problem::DanglingPayment),
+                ("payments".to_string(), 0, Problem::InvalidDate),
+            ]
+        );
+    }
+
+    #[test]
+    fn trash_shares_ids_with_bills() {
+        let mut data = with_duplicates();
+        data.payments.pop();
+        data.trash.pop();
+        data.trash[0].bill.id = data.bills[0].id.clone();
+        assert_eq!(
+            problems(&data),
+            [("trash".to_string(), 0, Problem::DuplicateId)]
+        );
+    }
+
+    #[test]
+    fn quarantines_each_record_once() {
+        let mut data = with_duplicates();
+        let payment = data.payments[1].id.clone();
+        let trashed = data.trash[1].id().to_string();
+        let requests = [
+            request("payments", 1, &payment, Repair::Quarantine),
+            request("trash", 1, &trashed, Repair::Quarantine),
+            request("payments", 1, &payment, Repair::Quarantine),
+        ];
+        let quarantined = data.repair(&requests, Utc::now()).unwrap();
+        let collections: Vec<&str> = quarantined.iter().map(|q| q.collection.as_str()).collect();
+        assert_eq!(collections, ["payments", "trash"]);
+        assert_eq!(data.payments.len(), 1);
+        assert_eq!(data.trash.len(), 1);
+        assert!(data.check_integrity().issues.is_empty());
+    }
+
+    #[test]
+    fn new_id_keeps_both_records() {
+        let mut data = with_duplicates();
+        let payment = data.payments[1].id.clone();
+        data.repair(
+            &[request("payments", 1, &payment, Repair::NewId)],
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(data.payments.len(), 2);
+        assert_ne!(data.payments[0].id, data.payments[1].id);
+    }
+
+    #[test]
+    fn rejects_new_id_and_quarantine_for_one_record() {
+        let mut data = with_duplicates();
+        let before = data.clone();
+        let payment = data.payments[1].id.clone();
+        let requests = [
+            request("payments", 1, &payment, Repair::NewId),
+            request("payments", 1, &payment, Repair::Quarantine),
+        ];
+        let err = data.repair(&requests, Utc::now()).unwrap_err();
+        assert_eq!(err.code(), "validation");
+        assert_eq!(data.payments, before.payments);
+    }
+
+    #[test]
+    fn rejects_stale_requests() {
+        let mut data = with_duplicates();
+        let err = data
+            .repair(
+                &[request("payments", 0, "x", Repair::Quarantine)],
+                Utc::now(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+        // A repair the issue does not offer is stale too
+        let payment = data.payments[1].id.clone();
+        let err = data
+            .repair(
+                &[request("payments", 1, &payment, Repair::ClearCategory)],
+                Utc::now(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+    }
+
+    #[test]
+    fn quarantined_bill_takes_its_payments() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent");
+        pay(&mut data, &rent, 2500);
+        data.bills[0].amount = usd(0);
+        let quarantined = data
+            .repair(
+                &[request("bills", 0, &rent.id, Repair::Quarantine)],
+                Utc::now(),
+            )
+            .unwrap();
+        assert!(data.bills.is_empty() && data.payments.is_empty());
+        assert_eq!(
+            quarantined[0].record["payments"].as_array().unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn resets_start_date_off_schedule() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent");
+        data.bills[0].start_date = Some(date("2024-01-01"));
+        data.bills[0].due_date = date("2024-01-15");
+        assert_eq!(
+            problems(&data),
+            [("bills".to_string(), 0, Problem::InvalidDate)]
+        );
+        data.repair(
+            &[request("bills", 0, &rent.id, Repair::ResetStartDate)],
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(data.bills[0].start_date, Some(date("2024-01-15")));
+        assert!(data.check_integrity().issues.is_empty());
+    }
+
+    #[test]
+    fn salvage_sets_aside_bad_and_repeated_records() {
+        let data = with_duplicates();
+        let mut doc = serde_json::to_value(&data).unwrap();
+        doc["categories"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"name": 5}));
+        doc["settings"] = json!("not settings");
+        let (salvaged, quarantined) = salvage("Data", doc, Utc::now()).unwrap();
+        let mut collections: Vec<&str> =
+            quarantined.iter().map(|q| q.collection.as_str()).collect();
+        collections.sort();
+        assert_eq!(collections, ["categories", "payments", "settings"]);
+        assert_eq!(salvaged.payments.len(), 1);
+        assert_eq!(salvaged.settings, Default::default());
+        assert_eq!(salvaged.categories, data.categories);
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+
+/// Operations kept for undo; older ones are dropped.
+const MAX_OPERATIONS: usize = 200;
+
+/// Top-level fields of [`Data`] that undo leaves alone: the journal itself
+/// and the bill history, which records undos as changes of their own.
+const UNTRACKED: &[&str] = &["journal", "history"];
+
+/// One change made by a command, which can be undone and redone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    /// Increases with every operation recorded.
+    pub seq: u64,
+    /// What the command did, e.g. "Delete bill", for the undo button.
+    pub label: String,
+    pub at: DateTime<Utc>,
+    pub changes: Vec<Change>,
+    /// Undone operations stay at the end of the journal until redone or
+    /// replaced by a new operation.
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// A record as it was before and after an operation. Records are matched by
+/// their `id`, or by their whole value when they have none; fields that are
+/// not lists are one record of their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// Field of [`Data`] holding the record, e.g. `bills`.
+    pub field: String,
+    /// `None` when the change replaces the whole field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Position of the record in its list, to put it back in place.
+    #[serde(default)]
+    pub index: usize,
+    /// `None` when the record was created.
+    pub before: Option<Value>,
+    /// `None` when the record was removed.
+    pub after: Option<Value>,
+}
+
+/// Undo and redo labels for the UI.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalStatus {
+    pub undo: Option<String>,
+    pub redo: Option<String>,
+}
+
+fn to_fields(data: &Data) -> AppResult<Map<String, Value>> {
+    match serde_json::to_value(data) {
+        Ok(Value::Object(mut fields)) => {
+            fields.retain(|k, _| !UNTRACKED.contains(&k.as_str()));
+            Ok(fields)
+        }
+        Ok(_) => Ok(Map::new()),
+        Err(e) => Err(AppError::CorruptData(format!(
+            "Failed to serialize bills: {e}"
+        ))),
+    }
+}
+
+fn record_key(record: &Value) -> String {
+    match record.get("id").and_then(Value::as_str) {
+        Some(id) => id.to_string(),
+        None => record.to_string(),
+    }
+}
+
+fn position(list: &[Value], key: &str) -> Option<usize> {
+    list.iter().position(|r| record_key(r) == key)
+}
+
+/// Whether any string in `value` is one of `ids`, as a record's own id or a
+/// reference to one.
+fn mentions(value: &Value, ids: &HashSet<String>) -> bool {
+    match value {
+        Value::String(s) => ids.contains(s),
+        Value::Array(items) => items.iter().any(|v| mentions(v, ids)),
+        Value::Object(fields) => fields.values().any(|v| mentions(v, ids)),
+        _ => false,
+    }
+}
+
+/// Changes that turn `before` into `after`, field by field.
+fn diff(before: &Data, after: &Data) -> AppResult<Vec<Change>> {
+    let old = to_fields(before)?;
+    let mut changes = Vec::new();
+    for (field, new) in to_fields(after)? {
+        let prev = old.get(&field).cloned().unwrap_or(Value::Null);
+        if prev == new {
+            continue;
+        }
+        let (Value::Array(prev), Value::Array(new)) = (&prev, &new) else {
+            changes.push(Change {
+                field,
+                key: None,
+                index: 0,
+                before: Some(prev),
+                after: Some(new),
+            });
+            continue;
+        };
+        for (index, record) in prev.iter().enumerate() {
+            let key = record_key(record);
+            if position(new, &key).is_none() {
+                changes.push(Change {
+                    field: field.clone(),
+                    key: Some(key),
+                    index,
+                    before: Some(record.clone()),
+                    after: None,
+                });
+            }
+        }
+        for (index, record) in new.iter().enumerate() {
+            let key = record_key(record);
+            let earlier = position(prev, &key).map(|i| &prev[i]);
+            if earlier != Some(record) {
+                changes.push(Change {
+                    field: field.clone(),
+                    key: Some(key),
+                    index,
+                    before: earlier.cloned(),
+                    after: Some(record.clone()),
+                });
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Puts each changed record into its `before` state (`undo`) or its `after`
+/// state.
+fn apply(data: &mut Data, changes: &[Change], undo: bool) -> AppResult<()> {
+    let journal = std::mem::take(&mut data.journal);
+    let history = std::mem::take(&mut data.history);
+    let mut fields = to_fields(data)?;
+    let target = |change: &Change| {
+        if undo {
+            change.before.clone()
+        } else {
+            change.after.clone()
+        }
+    };
+    // Removals first, so records put back by position land where they were
+    let ordered = changes
+        .iter()
+        .filter(|c| target(c).is_none())
+        .chain(changes.iter().filter(|c| target(c).is_some()));
+    for change in ordered {
+        let target = target(change);
+        let Some(key) = &change.key else {
+            let value = target.unwrap_or(Value::Null);
+            fields.insert(change.field.clone(), value);
+            continue;
+        };
+        let list = fields
+            .entry(change.field.clone())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        let Value::Array(list) = list else {
+            return Err(AppError::CorruptData(format!(
+                "Cannot undo: {} is not a list",
+                change.field
+            )));
+        };
+        match (position(list, key), target) {
+            (Some(i), Some(value)) => list[i] = value,
+            (Some(i), None) => {
+                list.remove(i);
+            }
+            (None, Some(value)) => list.insert(change.index.min(list.len()), value),
+            (None, None) => {}
+        }
+    }
+    let mut restored: Data = serde_json::from_value(Value::Object(fields))
+        .map_err(|e| AppError::CorruptData(format!("Cannot undo: {e}")))?;
+    restored.journal = journal;
+    restored.history = history;
+    *data = restored;
+    Ok(())
+}
+
+impl Data {
+    /// Records the difference from `before` as an undoable operation,
+    /// discarding anything that was undone and not redone.
+    pub fn record_operation(
+        &mut self,
+        before: &Data,
+        label: &str,
+        now: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let changes = diff(before, self)?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.journal.retain(|op| !op.undone);
+        let seq = self.journal.last().map_or(1, |op| op.seq + 1);
+        self.journal.push(Operation {
+            seq,
+            label: label.to_string(),
+            at: now,
+            changes,
+            undone: false,
+        });
+        let excess = self.journal.len().saturating_sub(MAX_OPERATIONS);
+        self.journal.drain(..excess);
+        Ok(())
+    }
+
+    /// Drops the latest operation holding a record that mentions any of
+    /// `ids`, with every operation before it, which could not be undone past
+    /// it. Used when records are purged, so undo cannot bring them back.
+    pub fn forget_in_journal(&mut self, ids: &HashSet<String>) {
+        let holds = |op: &Operation| {
+            op.changes
+                .iter()
+                .any(|c| c.before.iter().chain(&c.after).any(|v| mentions(v, ids)))
+        };
+        if let Some(last) = self.journal.iter().rposition(holds) {
+            self.journal.drain(..=last);
+        }
+    }
+
+    pub fn journal_status(&self) -> JournalStatus {
+        JournalStatus {
+            undo: self
+                .journal
+                .iter()
+                .rfind(|op| !op.undone)
+                .map(|op| op.label.clone()),
+            redo: self
+                .journal
+                .iter()
+                .find(|op| op.undone)
+                .map(|op| op.label.clone()),
+        }
+    }
+
+    /// Reverts the latest operation that is not undone yet and returns its
+    /// label.
+    pub fn undo(&mut self) -> AppResult<String> {
+        let idx = self
+            .journal
+            .iter()
+            .rposition(|op| !op.undone)
+            .ok_or_else(|| AppError::validation("Nothing to undo"))?;
+        let op = self.journal[idx].clone();
+        apply(self, &op.changes, true)?;
+        self.journal[idx].undone = true;
+        Ok(op.label)
+    }
+
+    /// Re-applies the earliest undone operation and returns its label.
+    pub fn redo(&mut self) -> AppResult<String> {
+        let idx = self
+            .journal
+            .iter()
+            .position(|op| op.undone)
+            .ok_or_else(|| AppError::validation("Nothing to redo"))?;
+        let op = self.journal[idx].clone();
+        apply(self, &op.changes, false)?;
+        self.journal[idx].undone = false;
+        Ok(op.label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bill::Bill;
+    use crate::testing::{add_bill, change};
+
+    fn names(data: &Data) -> Vec<&str> {
+        data.bills.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    fn add(data: &mut Data, name: &str) -> Bill {
+        change(data, "Add bill", |d| add_bill(d, name, 1000, "2024-01-01"))
+    }
+
+    #[test]
+    fn undo_and_redo_restore_each_state() {
+        let mut data = Data::fresh();
+        add(&mut data, "Rent");
+        let water = add(&mut data, "Water");
+        add(&mut data, "Power");
+        let after_adds = data.clone();
+        change(&mut data, "Rename bill", |d| {
+            d.bills[1].name = "Water & sewer".to_string();
+        });
+
+        assert_eq!(data.undo().unwrap(), "Rename bill");
+        assert_eq!(names(&data), ["Rent", "Water", "Power"]);
+        assert_eq!(data.bills, after_adds.bills);
+
+        // A removed record goes back to its position
+        change(&mut data, "Delete bill", |d| d.bills.remove(1));
+        assert_eq!(names(&data), ["Rent", "Power"]);
+        assert_eq!(data.undo().unwrap(), "Delete bill");
+        assert_eq!(names(&data), ["Rent", "Water", "Power"]);
+        assert_eq!(data.bills[1], water);
+
+        assert_eq!(data.redo().unwrap(), "Delete bill");
+        assert_eq!(names(&data), ["Rent", "Power"]);
+        assert!(data.redo().is_err());
+    }
+
+    #[test]
+    fn new_change_discards_undone_operations() {
+        let mut data = Data::fresh();
+        add(&mut data, "Rent");
+        add(&mut data, "Water");
+        data.undo().unwrap();
+        assert_eq!(data.journal_status().redo.as_deref(), Some("Add bill"));
+        add(&mut data, "Power");
+        assert_eq!(data.journal_status().redo, None);
+        assert_eq!(names(&data), ["Rent", "Power"]);
+        data.undo().unwrap();
+        data.undo().unwrap();
+        assert!(data.bills.is_empty());
+        assert!(data.undo().is_err());
+    }
+
+    #[test]
+    fn unchanged_data_records_nothing() {
+        let mut data = Data::fresh();
+        change(&mut data, "Nothing", |_| ());
+        assert!(data.journal.is_empty());
+        assert_eq!(data.journal_status().undo, None);
+    }
+
+    #[test]
+    fn whole_fields_are_replaced() {
+        let mut data = Data::fresh();
+        change(&mut data, "Change settings", |d| {
+            d.settings.auto_lock_minutes = 3
+        });
+        data.undo().unwrap();
+        assert_eq!(data.settings.auto_lock_minutes, 15);
+        data.redo().unwrap();
+        assert_eq!(data.settings.auto_lock_minutes, 3);
+    }
+
+    #[test]
+    fn journal_keeps_the_latest_operations() {
+        let mut data = Data::fresh();
+        for i in 0..MAX_OPERATIONS + 5 {
+            change(&mut data, &format!("Change {i}"), |d| {
+                d.settings.auto_lock_minutes = i as u32 + 100
+            });
+        }
+        assert_eq!(data.journal.len(), MAX_OPERATIONS);
+        assert_eq!(data.journal[0].label, "Change 5");
+        assert!(data.journal.windows(2).all(|w| w[0].seq < w[1].seq));
+    }
+
+    #[test]
+    fn forgetting_drops_operations_up_to_the_last_mention() {
+        let mut data = Data::fresh();
+        let rent = add(&mut data, "Rent");
+        add(&mut data, "Water");
+        change(&mut data, "Change settings", |d| {
+            d.settings.auto_lock_minutes = 3
+        });
+        let ids = [rent.id].into_iter().collect();
+        data.forget_in_journal(&ids);
+        let labels: Vec<&str> = data.journal.iter().map(|op| op.label.as_str()).collect();
+        assert_eq!(labels, ["Add bill", "Change settings"]);
+        data.forget_in_journal(&["unknown".to_string()].into_iter().collect());
+        assert_eq!(data.journal.len(), 2);
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::Money;
+use crate::payment::Payment;
+
+/// Top-level accounts beancount accepts.
+const BEANCOUNT_ROOTS: &[&str] = &["Assets", "Liabilities", "Equity", "Income", "Expenses"];
+
+/// Plain-text accounting formats payments can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerFormat {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+/// Accounts payments are booked to, stored with the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LedgerAccounts {
+    /// Expense account of uncategorized bills. Categories without an account
+    /// of their own get a subaccount of it named after them.
+    pub expense: String,
+    /// Account payments come out of when their method has none of its own.
+    pub payment: String,
+    /// Expense accounts by category id.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub categories: BTreeMap<String, String>,
+    /// Funding accounts by payment method, e.g. "Card"; matched ignoring
+    /// case.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub methods: BTreeMap<String, String>,
+}
+
+impl Default for LedgerAccounts {
+    fn default() -> Self {
+        Self {
+            expense: "Expenses:Bills".to_string(),
+            payment: "Assets:Checking".to_string(),
+            categories: BTreeMap::new(),
+            methods: BTreeMap::new(),
+        }
+    }
+}
+
+/// Which payments to export; both dates are inclusive and refer to the day
+/// a payment was made.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerExport {
+    pub format: LedgerFormat,
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    #[serde(default)]
+    pub to: Option<NaiveDate>,
+}
+
+/// Checks an account name the way ledger and hledger read it: colon-separated
+/// parts, and no tabs or double spaces, which would end the name early.
+fn check_account(name: &str) -> AppResult<()> {
+    if name.is_empty() {
+        return Err(AppError::validation("Account name must not be empty"));
+    }
+    if name.split(':').any(|part| part.trim().is_empty()) {
+        return Err(AppError::validation(format!(
+            "Account \"{name}\" has an empty part"
+        )));
+    }
+    if name.contains("  ") || name.contains([';', '\t', '\n', '\r']) {
+        return Err(AppError::validation(format!(
+            "Account \"{name}\" must not contain ';', tabs, line breaks or double spaces"
+        )));
+    }
+    Ok(())
+}
+
+/// Makes text usable as one part of an account name.
+fn account_part(name: &str) -> String {
+    name.replace(':', "-")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Rewrites an account name into beancount's stricter form, where each part
+/// starts with a capital letter or digit and holds only letters, digits and
+/// dashes, e.g. "Expenses:Phone & internet" -> "Expenses:Phone-internet".
+fn beancount_account(name: &str) -> AppResult<String> {
+    let mut parts = Vec::new();
+    for part in name.split(':') {
+        let cleaned: String = part
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let mut chars = cleaned.chars();
+        let Some(first) = chars.next() else {
+            return Err(AppError::validation(format!(
+                "Account \"{name}\" cannot be written for beancount"
+            )));
+        };
+        parts.push(first.to_uppercase().chain(chars).collect::<String>());
+    }
+    if !BEANCOUNT_ROOTS.contains(&parts[0].as_str()) {
+        return Err(AppError::validation(format!(
+            "Account \"{name}\" must start with one of {} for beancount",
+            BEANCOUNT_ROOTS.join(", ")
+        )));
+    }
+    Ok(parts.join(":"))
+}
+
+/// A string literal for beancount.
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Text on a single line, for payees and comments.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl LedgerAccounts {
+    /// Trims every account, drops the ones left blank so their category or
+    /// method falls back to the defaults, and checks the rest.
+    pub fn clean(mut self, data: &Data) -> AppResult<Self> {
+        self.expense = self.expense.trim().to_string();
+        self.payment = self.payment.trim().to_string();
+        check_account(&self.expense)?;
+        check_account(&self.payment)?;
+        for map in [&mut self.categories, &mut self.methods] {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, account)| (key.trim().to_string(), account.trim().to_string()))
+                .filter(|(key, account)| !key.is_empty() && !account.is_empty())
+                .collect();
+            for account in map.values() {
+                check_account(account)?;
+            }
+        }
+        for id in self.categories.keys() {
+            data.category(id)?;
+        }
+        Ok(self)
+    }
+
+    fn expense_account(&self, data: &Data, category_id: Option<&str>) -> String {
+        let Some(id) = category_id else {
+            return self.expense.clone();
+        };
+        if let Some(account) = self.categories.get(id) {
+            return account.clone();
+        }
+        match data.category(id) {
+            Ok(category) => format!("{}:{}", self.expense, account_part(&category.name)),
+            Err(_) => self.expense.clone(),
+        }
+    }
+
+    fn payment_account(&self, method: Option<&str>) -> String {
+        method
+            .map(str::trim)
+            .and_then(|method| {
+                self.methods
+                    .iter()
+                    .find(|(m, _)| m.eq_ignore_ascii_case(method))
+            })
+            .map_or_else(|| self.payment.clone(), |(_, account)| account.clone())
+    }
+}
+
+/// A payment ready to be written, with its accounts resolved.
+struct Entry<'a> {
+    payment: &'a Payment,
+    payee: String,
+    expense: String,
+    funding: String,
+}
+
+impl Data {
+    /// Recorded payments as a plain-text accounting journal, each moving its
+    /// amount from the funding account to the expense account of its bill.
+    pub fn export_ledger(&self, options: &LedgerExport) -> AppResult<String> {
+        let accounts = &self.settings.ledger_accounts;
+        let mut payments: Vec<&Payment> = self
+            .payments
+            .iter()
+            .filter(|p| options.from.is_none_or(|from| p.paid_date >= from))
+            .filter(|p| options.to.is_none_or(|to| p.paid_date <= to))
+            .collect();
+        payments.sort_by_key(|p| (p.paid_date, p.created_at));
+
+        let mut entries = Vec::new();
+        for payment in payments {
+            let bill = self.bill(&payment.bill_id);
+            let mut expense =
+                accounts.expense_account(self, bill.and_then(|b| b.category_id.as_deref()));
+            let mut funding = accounts.payment_account(payment.method.as_deref());
+            if options.format == LedgerFormat::Beancount {
+                expense = beancount_account(&expense)?;
+                funding = beancount_account(&funding)?;
+            }
+            entries.push(Entry {
+                payment,
+                payee: one_line(bill.map_or(payment.bill_id.as_str(), |b| b.name.as_str())),
+                expense,
+                funding,
+            });
+        }
+
+        let mut out = String::from("; Payments exported from AutoBillChecker\n\n");
+        let used: BTreeSet<&str> = entries
+            .iter()
+            .flat_map(|e| [e.expense.as_str(), e.funding.as_str()])
+            .collect();
+        match (options.format, entries.first()) {
+            (LedgerFormat::Beancount, Some(first)) => {
+                // Accounts must be open on the day of their first posting
+                for account in &used {
+                    let _ = writeln!(out, "{} open {account}", first.payment.paid_date);
+                }
+            }
+            (LedgerFormat::Beancount, None) => {}
+            _ => {
+                for account in &used {
+                    let _ = writeln!(out, "account {account}");
+                }
+            }
+        }
+        for entry in &entries {
+            out.push('\n');
+            match options.format {
+                LedgerFormat::Ledger | LedgerFormat::Hledger => {
+                    push_ledger_entry(&mut out, entry, options.format)
+                }
+                LedgerFormat::Beancount => push_beancount_entry(&mut out, entry),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// One transaction for ledger or hledger. Both read `key: value` comments
+/// as tags; hledger additionally splits `payee | note` descriptions.
+fn push_ledger_entry(out: &mut String, entry: &Entry, format: LedgerFormat) {
+    let payment = entry.payment;
+    let code = payment
+        .confirmation
+        .as_deref()
+        .map(|c| format!(" ({})", one_line(c).replace(')', "")))
+        .unwrap_or_default();
+    let _ = write!(out, "{} *{code} {}", payment.paid_date, entry.payee);
+    if format == LedgerFormat::Hledger {
+        let _ = write!(out, " | bill due {}", payment.occurrence_date);
+    }
+    out.push('\n');
+    let _ = writeln!(out, "    ; occurrence: {}", payment.occurrence_date);
+    if let Some(method) = &payment.method {
+        let _ = writeln!(out, "    ; method: {}", one_line(method));
+    }
+    let _ = writeln!(out, "    {}  {}", entry.expense, payment.amount);
+    let _ = writeln!(out, "    {}", entry.funding);
+}
+
+/// One beancount transaction; both postings carry their amount, as beancount
+/// only infers one when the other is unambiguous.
+fn push_beancount_entry(out: &mut String, entry: &Entry) {
+    let payment = entry.payment;
+    let negated = Money::new(-payment.amount.minor, payment.amount.currency);
+    let _ = writeln!(
+        out,
+        "{} * {} {}",
+        payment.paid_date,
+        quoted(&entry.payee),
+        quoted(&format!("Bill due {}", payment.occurrence_date))
+    );
+    let _ = writeln!(out, "  occurrence: {}", payment.occurrence_date);
+    if let Some(method) = &payment.method {
+        let _ = writeln!(out, "  method: {}", quoted(&one_line(method)));
+    }
+    if let Some(confirmation) = &payment.confirmation {
+        let _ = writeln!(out, "  confirmation: {}", quoted(&one_line(confirmation)));
+    }
+    let _ = writeln!(out, "  {}  {}", entry.expense, payment.amount);
+    let _ = writeln!(out, "  {}  {}", entry.funding, negated);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::payment::PaymentInput;
+    use crate::testing::{add_bill, date};
+
+    /// Rent paid from the default account, and a categorized phone bill
+    /// paid by card.
+    fn sample() -> Data {
+        let mut data = Data::default();
+        let phone_category = data.create_category("Phone & internet").unwrap();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let phone = add_bill(&mut data, "Phone", 4_550, "2024-01-15");
+        data.bills[1].category_id = Some(phone_category.id);
+        data.settings
+            .ledger_accounts
+            .methods
+            .insert("Card".to_string(), "Liabilities:Visa".to_string());
+        for (bill, paid, method, confirmation) in [
+            (&rent, "2024-01-30", None, None),
+            (&phone, "2024-01-14", Some("card"), Some("X-42")),
+        ] {
+            let input = PaymentInput {
+                bill_id: bill.id.clone(),
+                occurrence_date: None,
+                paid_date: Some(date(paid)),
+                amount: None,
+                method: method.map(str::to_string),
+                confirmation: confirmation.map(str::to_string),
+                transaction_id: None,
+            };
+            data.record_payment(input, date(paid), Utc::now()).unwrap();
+        }
+        data
+    }
+
+    fn export(data: &Data, format: LedgerFormat) -> String {
+        let options = LedgerExport {
+            format,
+            from: None,
+            to: None,
+        };
+        data.export_ledger(&options).unwrap()
+    }
+
+    #[test]
+    fn exports_ledger() {
+        let expected = "\
+; Payments exported from AutoBillChecker
+
+account Assets:Checking
+account Expenses:Bills
+account Expenses:Bills:Phone & internet
+account Liabilities:Visa
+
+2024-01-14 * (X-42) Phone
+    ; occurrence: 2024-01-15
+    ; method: card
+    Expenses:Bills:Phone & internet  45.50 USD
+    Liabilities:Visa
+
+2024-01-30 * Rent
+    ; occurrence: 2024-01-31
+    Expenses:Bills  1200.00 USD
+    Assets:Checking
+";
+        assert_eq!(export(&sample(), LedgerFormat::Ledger), expected);
+    }
+
+    #[test]
+    fn exports_hledger() {
+        let expected = "\
+; Payments exported from AutoBillChecker
+
+account Assets:Checking
+account Expenses:Bills
+account Expenses:Bills:Phone & internet
+account Liabilities:Visa
+
+2024-01-14 * (X-42) Phone | bill due 2024-01-15
+    ; occurrence: 2024-01-15
+    ; method: card
+    Expenses:Bills:Phone & internet  45.50 USD
+    Liabilities:Visa
+
+2024-01-30 * Rent | bill due 2024-01-31
+    ; occurrence: 2024-01-31
+    Expenses:Bills  1200.00 USD
+    Assets:Checking
+";
+        assert_eq!(export(&sample(), LedgerFormat::Hledger), expected);
+    }
+
+    #[test]
+    fn exports_beancount() {
+        let expected = "\
+; Payments exported from AutoBillChecker
+
+2024-01-14 open Assets:Checking
+2024-01-14 open Expenses:Bills
+2024-01-14 open Expenses:Bills:Phone-internet
+2024-01-14 open Liabilities:Visa
+
+2024-01-14 * \"Phone\" \"Bill due 2024-01-15\"
+  occurrence: 2024-01-15
+  method: \"card\"
+  confirmation: \"X-42\"
+  Expenses:Bills:Phone-internet  45.50 USD
+  Liabilities:Visa  -45.50 USD
+
+2024-01-30 * \"Rent\" \"Bill due 2024-01-31\"
+  occurrence: 2024-01-31
+  Expenses:Bills  1200.00 USD
+  Assets:Checking  -1200.00 USD
+";
+        assert_eq!(export(&sample(), LedgerFormat::Beancount), expected);
+    }
+
+    #[test]
+    fn exports_only_the_chosen_dates() {
+        let data = sample();
+        let options = LedgerExport {
+            format: LedgerFormat::Ledger,
+            from: Some(date("2024-01-15")),
+            to: Some(date("2024-01-30")),
+        };
+        let out = data.export_ledger(&options).unwrap();
+        assert!(out.contains("* Rent\n"));
+        assert!(!out.contains("Phone"));
+
+        let none = LedgerExport {
+            format: LedgerFormat::Beancount,
+            from: Some(date("2025-01-01")),
+            to: None,
+        };
+        assert_eq!(
+            data.export_ledger(&none).unwrap(),
+            "; Payments exported from AutoBillChecker\n\n"
+        );
+    }
+
+    #[test]
+    fn rewrites_accounts_for_beancount() {
+        assert_eq!(
+            beancount_account("Expenses:phone & internet").unwrap(),
+            "Expenses:Phone-internet"
+        );
+        assert_eq!(
+            beancount_account("Liabilities:Visa card 2").unwrap(),
+            "Liabilities:Visa-card-2"
+        );
+        assert_eq!(
+            beancount_account("assets:Checking").unwrap(),
+            "Assets:Checking"
+        );
+        for name in ["Expenses:&", "Bills:Rent", "Expenses::Rent"] {
+            assert!(beancount_account(name).is_err(), "{name}");
+        }
+        // An account beancount cannot take fails the export
+        let mut data = sample();
+        data.settings.ledger_accounts.payment = "Bank".to_string();
+        let options = LedgerExport {
+            format: LedgerFormat::Beancount,
+            from: None,
+            to: None,
+        };
+        assert_eq!(
+            data.export_ledger(&options).unwrap_err().to_string(),
+            "Account \"Bank\" must start with one of Assets, Liabilities, Equity, Income, \
+             Expenses for beancount"
+        );
+    }
+
+    #[test]
+    fn rejects_accounts_ledger_cannot_read() {
+        for name in [
+            "",
+            "Expenses:",
+            ":Bills",
+            "Expenses: :Bills",
+            "Expenses:Bills  Rent",
+            "Expenses;Bills",
+            "Expenses:\tBills",
+            "Expenses:\nBills",
+        ] {
+            assert_eq!(
+                check_account(name).unwrap_err().code(),
+                "validation",
+                "{name:?}"
+            );
+        }
+        check_account("Expenses:Bills:Phone & internet").unwrap();
+    }
+
+    #[test]
+    fn cleans_the_accounts_before_saving() {
+        let data = sample();
+        let mut categories = BTreeMap::new();
+        categories.insert(
+            data.categories[0].id.clone(),
+            " Expenses:Phone ".to_string(),
+        );
+        let mut methods = BTreeMap::new();
+        methods.insert(" Cash ".to_string(), " Assets:Wallet".to_string());
+        methods.insert("Card".to_string(), "  ".to_string());
+        let accounts = LedgerAccounts {
+            expense: " Expenses:Bills ".to_string(),
+            payment: "Assets:Checking".to_string(),
+            categories,
+            methods,
+        };
+        let cleaned = accounts.clone().clean(&data).unwrap();
+        assert_eq!(cleaned.expense, "Expenses:Bills");
+        assert_eq!(cleaned.categories[&data.categories[0].id], "Expenses:Phone");
+        assert_eq!(
+            cleaned.methods.into_iter().collect::<Vec<_>>(),
+            [("Cash".to_string(), "Assets:Wallet".to_string())]
+        );
+
+        let mut unknown = accounts.clone();
+        unknown
+            .categories
+            .insert("gone".to_string(), "Expenses:X".to_string());
+        assert_eq!(unknown.clean(&data).unwrap_err().code(), "not_found");
+        let mut invalid = accounts;
+        invalid.payment = "Assets;Checking".to_string();
+        assert_eq!(invalid.clean(&data).unwrap_err().code(), "validation");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_accounts() {
+        let mut data = sample();
+        let category = data.categories[0].id.clone();
+        let accounts = &mut data.settings.ledger_accounts;
+        accounts
+            .categories
+            .insert(category.clone(), "Expenses:Telecom".to_string());
+        let accounts = data.settings.ledger_accounts.clone();
+
+        assert_eq!(accounts.expense_account(&data, None), "Expenses:Bills");
+        assert_eq!(
+            accounts.expense_account(&data, Some(&category)),
+            "Expenses:Telecom"
+        );
+        assert_eq!(
+            accounts.expense_account(&data, Some("gone")),
+            "Expenses:Bills"
+        );
+        data.settings.ledger_accounts.categories.clear();
+        data.categories[0].name = "Phone: mobile  plan".to_string();
+        assert_eq!(
+            data.settings
+                .ledger_accounts
+                .expense_account(&data, Some(&category)),
+            "Expenses:Bills:Phone- mobile plan"
+        );
+
+        assert_eq!(accounts.payment_account(Some(" CARD ")), "Liabilities:Visa");
+        assert_eq!(accounts.payment_account(Some("cash")), "Assets:Checking");
+        assert_eq!(accounts.payment_account(None), "Assets:Checking");
+    }
+}
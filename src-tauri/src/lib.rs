@@ -1,8 +1,43 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
+mod archive;
+mod backup;
+mod bank;
+mod bill;
+mod category;
+mod commands;
+mod csv;
+mod data;
+mod error;
+mod fx;
+mod history;
+mod ics;
+mod integrity;
+mod journal;
+mod ledger;
+mod migrate;
+mod money;
+mod ofx;
+mod payment;
+mod profile;
+mod reconcile;
+mod recurrence;
+mod settings;
+mod state;
+mod store;
+#[cfg(test)]
+mod testing;
+mod trash;
+mod vault;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use tauri::Manager;
+
+use crate::state::{AppState, StartupError};
+
+/// How often an idle encrypted vault is checked for auto-lock and expired
+/// trash is purged.
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(30);
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -13,7 +48,99 @@ pub fn run() {
         .plugin(tauri_plugin_store::Builder::default().build())
         // System notifications (for due bill alerts)
         .plugin(tauri_plugin_notification::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .setup(|app| {
+            let dir = app.path().app_data_dir()?;
+            // Creates or upgrades the database of the open profile before
+            // anything reads it. Data saved by a newer version or damaged
+            // beyond salvage leaves the app running without it, for the UI
+            // to explain instead of closing at once.
+            let state = match AppState::open(dir) {
+                Ok(state) => state,
+                Err(err) => {
+                    eprintln!("Failed to open the bills: {err}");
+                    app.manage(Some(StartupError::from(err)));
+                    return Ok(());
+                }
+            };
+            app.manage(None::<StartupError>);
+            app.manage(state);
+
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(HOUSEKEEPING_INTERVAL);
+                let state = handle.state::<AppState>();
+                state.lock_if_idle();
+                // A failed purge is simply retried next time
+                let _ = state.purge_expired_trash(Utc::now());
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::archive::export_archive,
+            commands::archive::import_archive,
+            commands::backups::list_backups,
+            commands::bank::import_ofx,
+            commands::bank::record_bank_payments,
+            commands::backups::restore_backup,
+            commands::bills::list_bills,
+            commands::bills::get_bill,
+            commands::bills::upsert_bill,
+            commands::bills::delete_bill,
+            commands::bills::set_paid,
+            commands::bills::next_occurrences,
+            commands::bills::bill_history,
+            commands::categories::list_categories,
+            commands::categories::create_category,
+            commands::categories::rename_category,
+            commands::categories::merge_categories,
+            commands::categories::delete_category,
+            commands::csv::export_bills_csv,
+            commands::csv::export_payments_csv,
+            commands::csv::preview_bills_csv,
+            commands::csv::import_bills_csv,
+            commands::ics::export_ics,
+            commands::ics::import_ics,
+            commands::integrity::check_integrity,
+            commands::integrity::repair_integrity,
+            commands::journal::journal_status,
+            commands::journal::undo,
+            commands::journal::redo,
+            commands::ledger::export_ledger,
+            commands::ledger::set_ledger_accounts,
+            commands::payments::record_payment,
+            commands::payments::list_payments,
+            commands::profiles::list_profiles,
+            commands::profiles::create_profile,
+            commands::profiles::rename_profile,
+            commands::profiles::delete_profile,
+            commands::profiles::switch_profile,
+            commands::rates::list_rates,
+            commands::rates::set_rate,
+            commands::rates::import_rates,
+            commands::rates::currency_digits,
+            commands::reconcile::preview_bank_csv,
+            commands::reconcile::save_bank_profile,
+            commands::reconcile::delete_bank_profile,
+            commands::reconcile::reconcile_bank_csv,
+            commands::reconcile::forget_payee,
+            commands::settings::get_settings,
+            commands::settings::set_reporting_currency,
+            commands::settings::set_auto_lock_minutes,
+            commands::settings::set_trash_retention_days,
+            commands::settings::set_reminder_days,
+            commands::startup::startup_error,
+            commands::trash::list_trash,
+            commands::trash::restore_bill,
+            commands::trash::purge_trash,
+            commands::reports::bill_totals,
+            commands::reports::forecast,
+            commands::vault::vault_status,
+            commands::vault::unlock_vault,
+            commands::vault::lock_vault,
+            commands::vault::enable_vault,
+            commands::vault::disable_vault,
+            commands::vault::change_passphrase,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,211 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::integrity::{self, QuarantinedRecord};
+use crate::money::{Currency, Money};
+
+/// Currency of amounts saved before bills carried one; the UI always
+/// displayed them as US dollars.
+pub const LEGACY_CURRENCY: Currency = Currency::USD;
+
+/// Format version of JSON documents written by this build. Documents from
+/// before versioning have no `schemaVersion` and count as version 0.
+pub const DOCUMENT_VERSION: u64 = 1;
+
+pub const VERSION_KEY: &str = "schemaVersion";
+
+/// Upgrade steps for JSON documents; entry `n` takes a document from version
+/// `n` to `n + 1`. Only append to this list.
+const DOCUMENT_STEPS: &[fn(&mut Value) -> AppResult<()>] = &[float_amounts_to_money];
+
+/// Fails when `found` is a format version newer than `supported`.
+pub fn check_version(what: &str, found: u64, supported: u64) -> AppResult<()> {
+    if found > supported {
+        return Err(AppError::NewerVersion {
+            what: what.to_string(),
+            found,
+            supported,
+        });
+    }
+    Ok(())
+}
+
+/// Brings a raw JSON document up to [`DOCUMENT_VERSION`] by running the
+/// steps it has not been through yet, and stamps the new version on it.
+/// `what` names the document in errors.
+fn upgrade_document(what: &str, doc: &mut Value) -> AppResult<()> {
+    let Some(fields) = doc.as_object() else {
+        return Err(AppError::CorruptData(format!(
+            "{what} is not a JSON object"
+        )));
+    };
+    let version = match fields.get(VERSION_KEY) {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| AppError::CorruptData(format!("{what} has an invalid {VERSION_KEY}")))?,
+    };
+    check_version(what, version, DOCUMENT_VERSION)?;
+    for step in DOCUMENT_STEPS.iter().skip(version as usize) {
+        step(doc)?;
+    }
+    if let Some(fields) = doc.as_object_mut() {
+        fields.insert(VERSION_KEY.to_string(), DOCUMENT_VERSION.into());
+    }
+    Ok(())
+}
+
+/// Parses a JSON document of the app data and upgrades it to
+/// [`DOCUMENT_VERSION`], leaving it as raw JSON.
+pub fn upgrade_raw(what: &str, bytes: &[u8]) -> AppResult<Value> {
+    let mut doc = serde_json::from_slice(bytes)
+        .map_err(|e| AppError::CorruptData(format!("{what} is not valid: {e}")))?;
+    upgrade_document(what, &mut doc)?;
+    Ok(doc)
+}
+
+/// Reads a JSON document of the app data, such as `bills.json` or a backup,
+/// upgrading older formats on the way. `what` names the document in errors.
+pub fn decode_document(what: &str, bytes: &[u8]) -> AppResult<Data> {
+    serde_json::from_value(upgrade_raw(what, bytes)?)
+        .map_err(|e| AppError::CorruptData(format!("{what} is not valid: {e}")))
+}
+
+/// Like [`decode_document`], but records that cannot be read are returned
+/// for quarantine instead of failing the whole document.
+pub fn salvage_document(
+    what: &str,
+    bytes: &[u8],
+    now: DateTime<Utc>,
+) -> AppResult<(Data, Vec<QuarantinedRecord>)> {
+    integrity::salvage(what, upgrade_raw(what, bytes)?, now)
+}
+
+/// Serializes the app data as a JSON document stamped with
+/// [`DOCUMENT_VERSION`].
+pub fn encode_document(data: &Data) -> AppResult<Vec<u8>> {
+    let serialize =
+        |e: serde_json::Error| AppError::CorruptData(format!("Failed to serialize bills: {e}"));
+    let mut doc = serde_json::to_value(data).map_err(serialize)?;
+    if let Some(fields) = doc.as_object_mut() {
+        fields.insert(VERSION_KEY.to_string(), DOCUMENT_VERSION.into());
+    }
+    serde_json::to_vec_pretty(&doc).map_err(serialize)
+}
+
+/// Version 0 -> 1: rewrites plain-number `amount` fields of bills and
+/// payments as money objects. Leaves already-converted amounts alone, as
+/// unversioned files were also written after money objects were introduced.
+fn float_amounts_to_money(doc: &mut Value) -> AppResult<()> {
+    for key in ["bills", "payments"] {
+        let Some(records) = doc.get_mut(key).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for record in records {
+            let Some(amount) = record.get_mut("amount") else {
+                continue;
+            };
+            if let Some(float) = amount.as_f64() {
+                let money = Money::from_f64(float, LEGACY_CURRENCY).ok_or_else(|| {
+                    AppError::CorruptData(format!("Amount {float} in {key} is out of range"))
+                })?;
+                *amount = serde_json::to_value(money)
+                    .map_err(|e| AppError::CorruptData(e.to_string()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn plain_amounts_become_dollars() {
+        let money = json!({"minor": 4999, "currency": "EUR"});
+        let mut doc = json!({
+            "bills": [{"amount": 49.99}, {"amount": money}],
+            "payments": [{"amount": 0.1}, {"note": "no amount"}],
+        });
+        float_amounts_to_money(&mut doc).unwrap();
+        assert_eq!(
+            doc,
+            json!({
+                "bills": [
+                    {"amount": {"minor": 4999, "currency": "USD"}},
+                    {"amount": money},
+                ],
+                "payments": [
+                    {"amount": {"minor": 10, "currency": "USD"}},
+                    {"note": "no amount"},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_amounts_are_corrupt() {
+        let mut doc = json!({"bills": [{"amount": 1e300}]});
+        let err = float_amounts_to_money(&mut doc).unwrap_err();
+        assert_eq!(err.code(), "corrupt_data");
+    }
+
+    #[test]
+    fn upgrades_stamp_the_current_version() {
+        let mut doc = json!({"bills": [{"amount": 12.5}]});
+        upgrade_document("bills.json", &mut doc).unwrap();
+        assert_eq!(
+            doc,
+            json!({
+                "bills": [{"amount": {"minor": 1250, "currency": "USD"}}],
+                "schemaVersion": DOCUMENT_VERSION,
+            })
+        );
+        // Steps already applied are not run again
+        let mut current = json!({"bills": [{"amount": 12.5}], "schemaVersion": 1});
+        upgrade_document("bills.json", &mut current).unwrap();
+        assert_eq!(current["bills"][0]["amount"], 12.5);
+    }
+
+    #[test]
+    fn newer_or_unversionable_documents_are_refused() {
+        let mut newer = json!({"schemaVersion": DOCUMENT_VERSION + 1});
+        let err = upgrade_document("backup.json", &mut newer).unwrap_err();
+        assert_eq!(err.code(), "newer_version");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "backup.json was saved by a newer version of AutoBillChecker (format {}, \
+                 this version reads up to {DOCUMENT_VERSION}); update the app to open it",
+                DOCUMENT_VERSION + 1
+            )
+        );
+        for mut doc in [json!([]), json!({"schemaVersion": "1"})] {
+            let err = upgrade_document("backup.json", &mut doc).unwrap_err();
+            assert_eq!(err.code(), "corrupt_data", "{doc}");
+        }
+        assert!(check_version("bills.db", 3, 3).is_ok());
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let mut data = Data::default();
+        crate::testing::add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let bytes = encode_document(&data).unwrap();
+        let doc: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(doc[VERSION_KEY], DOCUMENT_VERSION);
+        let decoded = decode_document("backup", &bytes).unwrap();
+        assert_eq!(decoded.bills, data.bills);
+
+        let err = decode_document("backup", b"{\"bills\": 3}").unwrap_err();
+        assert!(
+            err.to_string().starts_with("backup is not valid: "),
+            "{err}"
+        );
+    }
+}
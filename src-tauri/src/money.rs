@@ -0,0 +1,500 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+
+/// Currencies whose minor unit is not a hundredth, with their number of
+/// decimal places. The UI formats amounts with these too, as browsers
+/// disagree with ISO 4217 on some, such as IQD.
+pub const MINOR_DIGITS: &[(&str, u32)] = &[
+    ("BIF", 0),
+    ("CLP", 0),
+    ("DJF", 0),
+    ("GNF", 0),
+    ("ISK", 0),
+    ("JPY", 0),
+    ("KMF", 0),
+    ("KRW", 0),
+    ("PYG", 0),
+    ("RWF", 0),
+    ("UGX", 0),
+    ("VND", 0),
+    ("VUV", 0),
+    ("XAF", 0),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("BHD", 3),
+    ("IQD", 3),
+    ("JOD", 3),
+    ("KWD", 3),
+    ("LYD", 3),
+    ("OMR", 3),
+    ("TND", 3),
+];
+
+/// ISO 4217 currency code, e.g. `USD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+
+    /// Accepts any three ASCII letters, case-insensitively.
+    pub fn parse(code: &str) -> AppResult<Self> {
+        match code.trim().as_bytes() {
+            &[a, b, c] if [a, b, c].iter().all(u8::is_ascii_alphabetic) => Ok(Self([
+                a.to_ascii_uppercase(),
+                b.to_ascii_uppercase(),
+                c.to_ascii_uppercase(),
+            ])),
+            _ => Err(AppError::validation(format!(
+                "\"{code}\" is not a three-letter currency code"
+            ))),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        // Only ever built from ASCII letters in `parse`
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+
+    /// Number of decimal places in the currency's minor unit.
+    pub fn minor_digits(self) -> u32 {
+        MINOR_DIGITS
+            .iter()
+            .find(|(code, _)| *code == self.code())
+            .map_or(2, |&(_, digits)| digits)
+    }
+
+    fn scale(self) -> i64 {
+        10_i64.pow(self.minor_digits())
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = AppError;
+
+    fn try_from(code: String) -> AppResult<Self> {
+        Self::parse(&code)
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.code().to_string()
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Currency symbols accepted around an amount. Which currency a symbol
+/// stands for is not checked, as `$` alone is used by a dozen.
+const SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹', '₩', '₽', '₺', '₪', '₫', '₱', '฿'];
+
+/// Spaces that group thousands, including the non-breaking ones
+/// spreadsheets write.
+const SPACES: &[char] = &[' ', '\u{a0}', '\u{202f}'];
+
+fn is_separator(c: char) -> bool {
+    c == '.' || c == ',' || SPACES.contains(&c)
+}
+
+/// A sign at either end of `text`, with the rest.
+fn strip_sign(text: &str) -> Option<(bool, &str)> {
+    let signs = ['-', '\u{2212}', '+'];
+    let (sign, rest) = match text.strip_prefix(signs) {
+        Some(rest) => (text.chars().next()?, rest),
+        None => (text.chars().next_back()?, text.strip_suffix(signs)?),
+    };
+    Some((sign != '+', rest.trim()))
+}
+
+/// A currency symbol at the start of `text`, with up to two capitals
+/// before it as in `US$`, and the rest.
+fn strip_symbol_prefix(text: &str) -> Option<&str> {
+    let letters = text.len()
+        - text
+            .trim_start_matches(|c: char| c.is_ascii_uppercase())
+            .len();
+    (letters <= 2).then(|| text[letters..].strip_prefix(SYMBOLS))?
+}
+
+/// A currency symbol at the end of `text`, as in `12,50 €`, and the rest.
+fn strip_symbol_suffix(text: &str) -> Option<&str> {
+    let rest = text.strip_suffix(SYMBOLS)?;
+    let letters = rest.len()
+        - rest
+            .trim_end_matches(|c: char| c.is_ascii_uppercase())
+            .len();
+    (letters <= 2).then(|| &rest[..rest.len() - letters])
+}
+
+/// A currency at either end of `text`, the code of `currency` or a
+/// symbol, with the rest.
+fn strip_currency(text: &str, currency: Currency) -> Option<&str> {
+    let code = |at: usize| {
+        text.get(at..at + 3)
+            .is_some_and(|t| t.eq_ignore_ascii_case(currency.code()))
+    };
+    let rest = if code(0) {
+        &text[3..]
+    } else if text.len() >= 3 && code(text.len() - 3) {
+        &text[..text.len() - 3]
+    } else {
+        strip_symbol_prefix(text).or_else(|| strip_symbol_suffix(text))?
+    };
+    Some(rest.trim())
+}
+
+/// The sign and the number of an amount, without the brackets, sign and
+/// currency around it; `None` if more than one of either is given.
+fn strip_affixes(text: &str, currency: Currency) -> Option<(bool, &str)> {
+    let mut rest = text.trim();
+    let mut negative = false;
+    let mut signed = false;
+    if let Some(inner) = rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+        (negative, signed, rest) = (true, true, inner.trim());
+    }
+    let mut marked = false;
+    loop {
+        if let Some((minus, after)) = strip_sign(rest).filter(|_| !signed) {
+            (negative, signed, rest) = (minus, true, after);
+        } else if let Some(after) = strip_currency(rest, currency).filter(|_| !marked) {
+            (marked, rest) = (true, after);
+        } else {
+            return Some((negative, rest));
+        }
+    }
+}
+
+/// Whether the whole part of an amount is plain digits, or groups of three
+/// after a first group of one to three, split by one kind of separator
+/// other than the decimal one.
+fn well_grouped(whole: &str, decimal: Option<char>) -> bool {
+    let kind = |c: char| if SPACES.contains(&c) { ' ' } else { c };
+    let mut separators = whole.chars().filter(|c| !c.is_ascii_digit()).map(kind);
+    let Some(first) = separators.next() else {
+        return true;
+    };
+    if separators.any(|c| c != first) || decimal == Some(first) {
+        return false;
+    }
+    let mut groups = whole.split(|c: char| !c.is_ascii_digit());
+    let head = groups.next().unwrap_or_default();
+    (1..=3).contains(&head.len()) && !head.starts_with('0') && groups.all(|g| g.len() == 3)
+}
+
+/// An exact amount of money, held as an integer count of the currency's
+/// minor unit (cents for USD, yen for JPY, fils for BHD).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    pub minor: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(minor: i64, currency: Currency) -> Self {
+        Self { minor, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    /// Converts a floating-point major-unit amount, rounding to the nearest
+    /// minor unit. Only meant for reading data saved as plain numbers.
+    pub fn from_f64(amount: f64, currency: Currency) -> Option<Self> {
+        let minor = (amount * currency.scale() as f64).round();
+        (minor.is_finite() && minor.abs() < i64::MAX as f64)
+            .then(|| Self::new(minor as i64, currency))
+    }
+
+    /// Parses an amount in major units as other apps write it, e.g.
+    /// `1,234.50`, `1.234,50`, `1 234,50`, `$49.99`, `49.99 USD` or
+    /// `(12.00)` for a negative amount. A lone separator followed by exactly
+    /// three digits groups thousands, unless the currency has three decimal
+    /// places. Anything besides one sign, one currency symbol or code and
+    /// well-formed digit groups is rejected, so `1e5` or `12/03` never read
+    /// as some other amount.
+    pub fn parse(text: &str, currency: Currency) -> AppResult<Self> {
+        let invalid = || AppError::validation(format!("\"{}\" is not an amount", text.trim()));
+        let (negative, number) = strip_affixes(text, currency).ok_or_else(invalid)?;
+        if !number
+            .chars()
+            .all(|c| c.is_ascii_digit() || is_separator(c))
+            || !number.bytes().any(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let decimal = match (number.rfind('.'), number.rfind(',')) {
+            (Some(dot), Some(comma)) => Some(dot.max(comma)),
+            (Some(at), None) | (None, Some(at)) => {
+                let sep = number.as_bytes()[at] as char;
+                let lone = number.matches(sep).count() == 1;
+                let grouped = number.len() - at - 1 == 3 && currency.minor_digits() != 3;
+                (lone && !grouped).then_some(at)
+            }
+            (None, None) => None,
+        };
+        let (whole, fraction) = match decimal {
+            Some(at) => (&number[..at], &number[at + 1..]),
+            None => (number, ""),
+        };
+        let separator = decimal.map(|at| number.as_bytes()[at] as char);
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) || !well_grouped(whole, separator) {
+            return Err(invalid());
+        }
+        let whole: String = whole.chars().filter(char::is_ascii_digit).collect();
+        let digits = currency.minor_digits() as usize;
+        if fraction.len() > digits {
+            return Err(AppError::validation(format!(
+                "{currency} amounts have at most {digits} decimal places"
+            )));
+        }
+        let too_large = || AppError::validation("Amount is too large");
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| too_large())?
+        };
+        let fraction: i64 = format!("{fraction:0<digits$}").parse().unwrap_or(0);
+        let minor = whole
+            .checked_mul(currency.scale())
+            .and_then(|m| m.checked_add(fraction))
+            .ok_or_else(too_large)?;
+        Ok(Self::new(if negative { -minor } else { minor }, currency))
+    }
+
+    /// The amount as a plain decimal without the currency, e.g. `49.99`.
+    pub fn decimal(self) -> String {
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let digits = self.currency.minor_digits() as usize;
+        let scale = self.currency.scale().unsigned_abs();
+        let abs = self.minor.unsigned_abs();
+        if digits == 0 {
+            format!("{sign}{abs}")
+        } else {
+            format!("{sign}{}.{:0digits$}", abs / scale, abs % scale)
+        }
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.minor > 0
+    }
+
+    fn same_currency(self, other: Money) -> AppResult<()> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(AppError::validation(format!(
+                "Cannot combine {} and {} amounts",
+                self.currency, other.currency
+            )))
+        }
+    }
+
+    pub fn checked_add(self, other: Money) -> AppResult<Money> {
+        self.same_currency(other)?;
+        self.minor
+            .checked_add(other.minor)
+            .map(|minor| Money::new(minor, self.currency))
+            .ok_or_else(|| AppError::validation("Amount is too large"))
+    }
+
+    pub fn checked_sub(self, other: Money) -> AppResult<Money> {
+        self.same_currency(other)?;
+        self.minor
+            .checked_sub(other.minor)
+            .map(|minor| Money::new(minor, self.currency))
+            .ok_or_else(|| AppError::validation("Amount is too large"))
+    }
+
+    /// Adds up `amounts`, all of which must be in `currency`.
+    pub fn sum(currency: Currency, amounts: impl IntoIterator<Item = Money>) -> AppResult<Money> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), Money::checked_add)
+    }
+}
+
+/// Formats as a plain decimal with the currency code, e.g. `49.99 USD`.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.decimal(), self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(code: &str) -> Currency {
+        Currency::parse(code).unwrap()
+    }
+
+    fn parse_usd(text: &str) -> i64 {
+        Money::parse(text, Currency::USD).unwrap().minor
+    }
+
+    #[test]
+    fn currency_codes() {
+        assert_eq!(currency(" eur ").code(), "EUR");
+        assert!(Currency::parse("EU").is_err());
+        assert!(Currency::parse("EURO").is_err());
+        assert!(Currency::parse("E1R").is_err());
+        assert_eq!(serde_json::to_string(&currency("gbp")).unwrap(), "\"GBP\"");
+        assert!(serde_json::from_str::<Currency>("\"12\"").is_err());
+        assert_eq!(currency("JPY").minor_digits(), 0);
+        assert_eq!(currency("KWD").minor_digits(), 3);
+        assert_eq!(currency("IQD").minor_digits(), 3);
+        assert_eq!(Currency::USD.minor_digits(), 2);
+    }
+
+    #[test]
+    fn displays_in_major_units() {
+        let cases = [
+            (4999, Currency::USD, "49.99 USD"),
+            (-5, Currency::USD, "-0.05 USD"),
+            (0, Currency::USD, "0.00 USD"),
+            (1500, currency("JPY"), "1500 JPY"),
+            (-1234, currency("BHD"), "-1.234 BHD"),
+            (i64::MIN, Currency::USD, "-92233720368547758.08 USD"),
+        ];
+        for (minor, currency, text) in cases {
+            assert_eq!(Money::new(minor, currency).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn parse_common_formats() {
+        assert_eq!(parse_usd("49.99"), 4999);
+        assert_eq!(parse_usd("$49.99"), 4999);
+        assert_eq!(parse_usd("  49 "), 4900);
+        assert_eq!(parse_usd("49.9"), 4990);
+        assert_eq!(parse_usd(".5"), 50);
+        assert_eq!(parse_usd("1,234.50"), 123_450);
+        assert_eq!(parse_usd("1.234,50"), 123_450);
+        assert_eq!(parse_usd("1 234,50"), 123_450);
+        assert_eq!(parse_usd("1,5"), 150);
+        assert_eq!(parse_usd("1.234.567"), 123_456_700);
+        assert_eq!(parse_usd("1\u{a0}234,50"), 123_450);
+        assert_eq!(parse_usd("49.99 USD"), 4999);
+        assert_eq!(parse_usd("usd 49.99"), 4999);
+        assert_eq!(parse_usd("US$49.99"), 4999);
+        assert_eq!(parse_usd("12,50 €"), 1250);
+        assert_eq!(parse_usd("+5"), 500);
+    }
+
+    #[test]
+    fn parse_lone_separator_before_three_digits_groups_thousands() {
+        assert_eq!(parse_usd("1,234"), 123_400);
+        assert_eq!(parse_usd("1.234"), 123_400);
+        // Unless the currency has three decimal places
+        let kwd = Money::parse("1.234", currency("KWD")).unwrap();
+        assert_eq!(kwd.minor, 1234);
+    }
+
+    #[test]
+    fn parse_negative_amounts() {
+        assert_eq!(parse_usd("-12.00"), -1200);
+        assert_eq!(parse_usd("12.00-"), -1200);
+        assert_eq!(parse_usd("(12.00)"), -1200);
+        assert_eq!(parse_usd("-$0.01"), -1);
+        assert_eq!(parse_usd("$-0.01"), -1);
+        assert_eq!(parse_usd("($12.00)"), -1200);
+        assert_eq!(parse_usd("\u{2212}12.00"), -1200);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_amounts() {
+        for text in [
+            "",
+            "abc",
+            "-",
+            "1.2,3.4",
+            "1.9999",
+            "1e5",
+            "12/03",
+            "abc12def",
+            "12 EUR",
+            "$$12",
+            "--1",
+            "-12-",
+            "-(12)",
+            "(12",
+            "1,2,3",
+            "12,34,567",
+            "1 234.567",
+            "12 34",
+            "0,125",
+        ] {
+            assert!(Money::parse(text, Currency::USD).is_err(), "{text}");
+        }
+        assert!(Money::parse("1.5", currency("JPY")).is_err());
+        assert!(Money::parse("99999999999999999999", Currency::USD).is_err());
+        assert!(Money::parse("92233720368547758.07", Currency::USD).is_ok());
+        assert!(Money::parse("92233720368547758.08", Currency::USD).is_err());
+    }
+
+    #[test]
+    fn decimal_round_trips_through_parse() {
+        let cases = [
+            (4999, Currency::USD, "49.99"),
+            (-5, Currency::USD, "-0.05"),
+            (0, Currency::USD, "0.00"),
+            (1500, currency("JPY"), "1500"),
+            (-1234, currency("BHD"), "-1.234"),
+            (i64::MAX, Currency::USD, "92233720368547758.07"),
+        ];
+        for (minor, currency, text) in cases {
+            let money = Money::new(minor, currency);
+            assert_eq!(money.decimal(), text);
+            assert_eq!(Money::parse(&money.decimal(), currency).unwrap(), money);
+        }
+        assert_eq!(
+            Money::new(i64::MIN, Currency::USD).decimal(),
+            "-92233720368547758.08"
+        );
+        assert_eq!(Money::new(4999, Currency::USD).to_string(), "49.99 USD");
+    }
+
+    #[test]
+    fn from_f64_rounds_to_the_minor_unit() {
+        assert_eq!(Money::from_f64(0.1 + 0.2, Currency::USD).unwrap().minor, 30);
+        assert_eq!(Money::from_f64(19.995, currency("JPY")).unwrap().minor, 20);
+        assert!(Money::from_f64(f64::NAN, Currency::USD).is_none());
+        assert!(Money::from_f64(1e300, Currency::USD).is_none());
+    }
+
+    #[test]
+    fn arithmetic_checks_currency_and_overflow() {
+        let a = Money::new(1000, Currency::USD);
+        assert_eq!(a.checked_add(a).unwrap().minor, 2000);
+        assert_eq!(
+            a.checked_sub(Money::new(250, Currency::USD)).unwrap().minor,
+            750
+        );
+        assert!(a.checked_add(Money::new(1, currency("EUR"))).is_err());
+        assert!(Money::new(i64::MAX, Currency::USD).checked_add(a).is_err());
+        assert!(a.checked_sub(Money::new(i64::MIN, Currency::USD)).is_err());
+        assert_eq!(
+            Money::new(-1, Currency::USD)
+                .checked_sub(Money::new(i64::MAX, Currency::USD))
+                .unwrap()
+                .minor,
+            i64::MIN
+        );
+        assert_eq!(Money::sum(Currency::USD, [a, a, a]).unwrap().minor, 3000);
+        assert_eq!(
+            Money::sum(Currency::USD, []).unwrap(),
+            Money::zero(Currency::USD)
+        );
+        assert!(Money::sum(Currency::USD, [Money::new(1, currency("EUR"))]).is_err());
+    }
+}
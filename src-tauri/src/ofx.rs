@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::bank::BankTransaction;
+use crate::error::{AppError, AppResult};
+use crate::money::{Currency, Money};
+
+/// Statement currency when the file does not name one.
+const DEFAULT_CURRENCY: Currency = Currency::USD;
+
+/// The text of an element, with the entities OFX uses decoded.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// The day of an OFX date such as `20240105`, `20240105120000.000` or
+/// `20240105120000[-5:EST]`; the time and zone are ignored.
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text.get(..8)?, "%Y%m%d").ok()
+}
+
+/// An OFX amount such as `-49.99` or `+1200`. The decimal point is always
+/// `.` (or `,`, which the spec also allows) and never groups thousands, so
+/// `-49.990` is 49.99 rather than the lenient reading of 49,990. Digits
+/// beyond the currency's minor unit are rounded half away from zero.
+fn parse_amount(text: &str, currency: Currency) -> Option<Money> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = digits.split_once(['.', ',']).unwrap_or((digits, ""));
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() && fraction.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+    let scale = currency.minor_digits() as usize;
+    let (kept, dropped) = fraction.split_at(fraction.len().min(scale));
+    let mut minor: i128 = 0;
+    for b in whole.bytes().chain(kept.bytes()) {
+        minor = minor.checked_mul(10)?.checked_add(i128::from(b - b'0'))?;
+    }
+    minor = minor.checked_mul(10_i128.checked_pow((scale - kept.len()) as u32)?)?;
+    if dropped.bytes().next().is_some_and(|b| b >= b'5') {
+        minor += 1;
+    }
+    let minor = i64::try_from(if negative { -minor } else { minor }).ok()?;
+    Some(Money::new(minor, currency))
+}
+
+/// Elements in document order as `(tag, text)`, where closing tags start
+/// with `/` and aggregates have empty text. Handles both the SGML form of
+/// OFX 1.x, whose leaf elements are not closed, and the XML of OFX 2.x.
+fn elements(text: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[..close].trim();
+        rest = &rest[close + 1..];
+        let end = rest.find('<').unwrap_or(rest.len());
+        let value = rest[..end].trim();
+        // Processing instructions, comments and the like carry no data
+        if tag.starts_with(['?', '!']) || tag.is_empty() {
+            continue;
+        }
+        let name = tag.split_whitespace().next().unwrap_or(tag);
+        out.push((name.trim_end_matches('/').to_uppercase(), unescape(value)));
+    }
+    out
+}
+
+/// Builds a transaction from the fields of one `STMTTRN` aggregate. Ids are
+/// prefixed with the account, as banks only keep them unique per account.
+fn transaction(
+    fields: &HashMap<String, String>,
+    account: &str,
+    currency: Currency,
+) -> AppResult<BankTransaction> {
+    let field = |name: &str| fields.get(name);
+    let id = field("FITID")
+        .ok_or_else(|| AppError::validation("A transaction in the statement has no FITID"))?;
+    let date = field("DTPOSTED")
+        .and_then(|d| parse_date(d))
+        .ok_or_else(|| {
+            AppError::validation(format!("Transaction {id} has no valid posting date"))
+        })?;
+    let amount = field("TRNAMT")
+        .ok_or_else(|| AppError::validation(format!("Transaction {id} has no amount")))?;
+    let amount = parse_amount(amount, currency).ok_or_else(|| {
+        AppError::validation(format!(
+            "Transaction {id} has an invalid amount \"{amount}\""
+        ))
+    })?;
+    // Some banks leave NAME out and put the payee in MEMO
+    let memo = field("MEMO").cloned();
+    let (payee, memo) = match field("NAME") {
+        Some(name) => (name.clone(), memo),
+        None => (memo.unwrap_or_default(), None),
+    };
+    Ok(BankTransaction {
+        id: if account.is_empty() {
+            id.clone()
+        } else {
+            format!("{account}:{id}")
+        },
+        date,
+        amount,
+        payee,
+        memo,
+    })
+}
+
+/// Reads the transactions of every statement in an OFX or QFX file.
+pub fn parse_ofx(text: &str) -> AppResult<Vec<BankTransaction>> {
+    let elements = elements(text);
+    if !elements.iter().any(|(tag, _)| tag == "OFX") {
+        return Err(AppError::validation("Not an OFX or QFX file"));
+    }
+    let mut transactions = Vec::new();
+    let mut account = String::new();
+    let mut currency = DEFAULT_CURRENCY;
+    let mut current: Option<HashMap<String, String>> = None;
+    for (tag, value) in elements {
+        match tag.as_str() {
+            "STMTTRN" => current = Some(HashMap::new()),
+            "/STMTTRN" => {
+                if let Some(fields) = current.take() {
+                    transactions.push(transaction(&fields, &account, currency)?);
+                }
+            }
+            "ACCTID" if current.is_none() => account = value,
+            "CURDEF" if current.is_none() => currency = Currency::parse(&value)?,
+            _ => {
+                if let Some(fields) = current.as_mut().filter(|_| !value.is_empty()) {
+                    // The first NAME wins, over one nested in a PAYEE aggregate
+                    fields.entry(tag).or_insert(value);
+                }
+            }
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{date, usd};
+
+    const SGML: &str =
+        "OFXHEADER:100\r\nDATA:OFXSGML\r\n\r\n<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS>\
+        <CURDEF>EUR<BANKACCTFROM><ACCTID>123<ACCTTYPE>CHECKING</BANKACCTFROM><BANKTRANLIST>\
+        <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240105120000[-5:EST]<TRNAMT>-49,99<FITID>A1\
+        <NAME>AT&amp;T<MEMO>Phone</STMTTRN>\
+        <STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240106<TRNAMT>+1200<FITID>A2\
+        <MEMO>Salary</STMTTRN>\
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+
+    const XML: &str = "<?xml version=\"1.0\"?><?OFX OFXHEADER=\"200\"?><OFX><!-- note -->\
+        <STMTTRN><DTPOSTED>20240105</DTPOSTED><TRNAMT>-87.130</TRNAMT><FITID>X</FITID>\
+        <PAYEE><NAME>Nested</NAME></PAYEE><NAME>Power Co</NAME></STMTTRN><STMTTRN/></OFX>";
+
+    #[test]
+    fn parses_strict_amounts() {
+        let parse = |text| parse_amount(text, Currency::USD);
+        assert_eq!(parse("-49.990"), Some(usd(-4999)));
+        assert_eq!(parse("+1200"), Some(usd(120000)));
+        assert_eq!(parse("1,5"), Some(usd(150)));
+        assert_eq!(parse(" .25 "), Some(usd(25)));
+        assert_eq!(parse("7."), Some(usd(700)));
+        assert_eq!(parse("0.005"), Some(usd(1)));
+        assert_eq!(parse("-0.004"), Some(usd(0)));
+        assert_eq!(parse("-1.995"), Some(usd(-200)));
+        let yen = Currency::parse("JPY").unwrap();
+        assert_eq!(parse_amount("1200.5", yen), Some(Money::new(1201, yen)));
+        for text in [
+            "", "-", ".", "1,200.00", "1.2.3", "1 200", "12a", "--1", "+-1", "1e3",
+        ] {
+            assert_eq!(parse(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_out_of_range() {
+        assert_eq!(
+            parse_amount("92233720368547758.07", Currency::USD),
+            Some(usd(i64::MAX))
+        );
+        assert_eq!(parse_amount("92233720368547758.08", Currency::USD), None);
+        assert_eq!(parse_amount(&"9".repeat(50), Currency::USD), None);
+    }
+
+    #[test]
+    fn reads_dates_and_entities() {
+        assert_eq!(parse_date("20240105"), Some(date("2024-01-05")));
+        assert_eq!(
+            parse_date("20240105120000.000[-5:EST]"),
+            Some(date("2024-01-05"))
+        );
+        assert_eq!(parse_date("2024010"), None);
+        assert_eq!(parse_date("20241305"), None);
+        assert_eq!(
+            unescape("A&amp;lt;B &lt;&gt;&quot;&apos;&nbsp;"),
+            "A&lt;B <>\"' "
+        );
+    }
+
+    #[test]
+    fn parses_sgml_statements() {
+        let transactions = parse_ofx(SGML).unwrap();
+        let eur = |minor| Money::new(minor, Currency::parse("EUR").unwrap());
+        assert_eq!(
+            transactions,
+            [
+                BankTransaction {
+                    id: "123:A1".to_string(),
+                    date: date("2024-01-05"),
+                    amount: eur(-4999),
+                    payee: "AT&T".to_string(),
+                    memo: Some("Phone".to_string()),
+                },
+                BankTransaction {
+                    id: "123:A2".to_string(),
+                    date: date("2024-01-06"),
+                    amount: eur(120000),
+                    payee: "Salary".to_string(),
+                    memo: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_xml_statements() {
+        let transactions = parse_ofx(XML).unwrap();
+        assert_eq!(transactions.len(), 1);
+        let t = &transactions[0];
+        assert_eq!((t.id.as_str(), t.amount), ("X", usd(-8713)));
+        assert_eq!(t.payee, "Nested");
+    }
+
+    #[test]
+    fn rejects_broken_statements() {
+        assert_eq!(
+            parse_ofx("<html></html>").unwrap_err().to_string(),
+            "Not an OFX or QFX file"
+        );
+        let statement = |fields: &str| format!("<OFX><STMTTRN>{fields}</STMTTRN></OFX>");
+        for (fields, message) in [
+            (
+                "<DTPOSTED>20240105<TRNAMT>1",
+                "A transaction in the statement has no FITID",
+            ),
+            (
+                "<FITID>A<TRNAMT>1",
+                "Transaction A has no valid posting date",
+            ),
+            ("<FITID>A<DTPOSTED>20240105", "Transaction A has no amount"),
+            (
+                "<FITID>A<DTPOSTED>20240105<TRNAMT>-1,200.00",
+                "Transaction A has an invalid amount \"-1,200.00\"",
+            ),
+        ] {
+            let err = parse_ofx(&statement(fields)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+}
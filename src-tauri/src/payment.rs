@@ -0,0 +1,452 @@
+use std::cmp::Reverse;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, BillFilter, BillView};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::Money;
+
+/// A recorded payment towards one occurrence of a bill.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payment {
+    pub id: String,
+    pub bill_id: String,
+    /// Due date of the occurrence this payment is for.
+    pub occurrence_date: NaiveDate,
+    pub paid_date: NaiveDate,
+    pub amount: Money,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<String>,
+    /// Bank transaction the payment was matched to, so importing the same
+    /// statement again does not match it twice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Fields submitted when recording a payment. Omitted values default to the
+/// bill's current due date, today, and the bill's amount.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentInput {
+    pub bill_id: String,
+    #[serde(default)]
+    pub occurrence_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub paid_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub amount: Option<Money>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub confirmation: Option<String>,
+    #[serde(default)]
+    pub transaction_id: Option<String>,
+}
+
+fn clean(s: Option<String>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+impl Data {
+    /// Payments for a bill, most recent first.
+    pub fn payments_for(&self, bill_id: &str) -> Vec<Payment> {
+        let mut payments: Vec<Payment> = self
+            .payments
+            .iter()
+            .filter(|p| p.bill_id == bill_id)
+            .cloned()
+            .collect();
+        payments.sort_by_key(|p| Reverse((p.paid_date, p.created_at)));
+        payments
+    }
+
+    /// Total paid towards one occurrence of a bill, in the bill's currency.
+    pub fn paid_towards(&self, bill: &Bill, occurrence_date: NaiveDate) -> AppResult<Money> {
+        Money::sum(
+            bill.amount.currency,
+            self.payments
+                .iter()
+                .filter(|p| p.bill_id == bill.id && p.occurrence_date == occurrence_date)
+                .map(|p| p.amount),
+        )
+    }
+
+    /// Amount still owed for one occurrence; never negative.
+    pub fn balance(&self, bill: &Bill, occurrence_date: NaiveDate) -> AppResult<Money> {
+        let owed = bill
+            .amount
+            .checked_sub(self.paid_towards(bill, occurrence_date)?)?;
+        Ok(if owed.is_positive() {
+            owed
+        } else {
+            Money::zero(owed.currency)
+        })
+    }
+
+    pub fn is_settled(&self, bill: &Bill, occurrence_date: NaiveDate) -> AppResult<bool> {
+        Ok(!self.balance(bill, occurrence_date)?.is_positive())
+    }
+
+    /// Bills matching `filter`, with the payment state of their current
+    /// occurrence.
+    pub fn bill_views(&self, filter: &BillFilter) -> AppResult<Vec<BillView>> {
+        self.bills
+            .iter()
+            .filter(|bill| filter.matches(bill))
+            .map(|bill| {
+                let paid_amount = self.paid_towards(bill, bill.due_date)?;
+                let balance = if bill.paid {
+                    Money::zero(bill.amount.currency)
+                } else {
+                    self.balance(bill, bill.due_date)?
+                };
+                Ok(BillView {
+                    bill: bill.clone(),
+                    paid_amount,
+                    balance,
+                })
+            })
+            .collect()
+    }
+
+    /// Records a payment, which may cover only part of an occurrence. Once
+    /// the bill's current occurrence is paid in full it is settled, rolling a
+    /// recurring bill over to its next cycle.
+    pub fn record_payment(
+        &mut self,
+        input: PaymentInput,
+        today: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> AppResult<Payment> {
+        let bill = self
+            .bill(&input.bill_id)
+            .ok_or_else(|| AppError::not_found("Bill", &input.bill_id))?;
+        let occurrence_date = input.occurrence_date.unwrap_or(bill.due_date);
+        if !bill.is_occurrence(occurrence_date) {
+            return Err(AppError::validation(format!(
+                "\"{}\" is not due on {occurrence_date}",
+                bill.name
+            )));
+        }
+        let amount = match input.amount {
+            Some(amount) => amount,
+            None if self.is_settled(bill, occurrence_date)? => {
+                return Err(AppError::validation(format!(
+                    "\"{}\" due {occurrence_date} is already paid in full",
+                    bill.name
+                )));
+            }
+            None => self.balance(bill, occurrence_date)?,
+        };
+        if !amount.is_positive() {
+            return Err(AppError::validation("Amount must be greater than 0"));
+        }
+        if amount.currency != bill.amount.currency {
+            return Err(AppError::validation(format!(
+                "\"{}\" is billed in {}, not {}",
+                bill.name, bill.amount.currency, amount.currency
+            )));
+        }
+        let is_current = occurrence_date == bill.due_date && !bill.paid;
+
+        let payment = Payment {
+            id: uuid::Uuid::new_v4().to_string(),
+            bill_id: input.bill_id,
+            occurrence_date,
+            paid_date: input.paid_date.unwrap_or(today),
+            amount,
+            method: clean(input.method),
+            confirmation: clean(input.confirmation),
+            transaction_id: clean(input.transaction_id),
+            created_at: now,
+        };
+        self.payments.push(payment.clone());
+        if is_current {
+            let bill = self
+                .bill(&payment.bill_id)
+                .ok_or_else(|| AppError::not_found("Bill", &payment.bill_id))?;
+            if self.is_settled(bill, occurrence_date)? {
+                self.settle_current(&payment.bill_id, now)?;
+            }
+        }
+        Ok(payment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+    use crate::testing::{add_bill, date, usd};
+
+    fn input(bill_id: &str) -> PaymentInput {
+        PaymentInput {
+            bill_id: bill_id.to_string(),
+            occurrence_date: None,
+            paid_date: None,
+            amount: None,
+            method: None,
+            confirmation: None,
+            transaction_id: None,
+        }
+    }
+
+    #[test]
+    fn defaults_settle_the_current_occurrence() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let payment = data
+            .record_payment(
+                PaymentInput {
+                    method: Some(" card ".to_string()),
+                    confirmation: Some("  ".to_string()),
+                    ..input(&rent.id)
+                },
+                date("2024-01-30"),
+                Utc::now(),
+            )
+            .unwrap();
+        assert_eq!(
+            (payment.occurrence_date, payment.paid_date, payment.amount),
+            (date("2024-01-31"), date("2024-01-30"), usd(120_000))
+        );
+        assert_eq!(payment.method.as_deref(), Some("card"));
+        assert_eq!(payment.confirmation, None);
+        let bill = data.bill(&rent.id).unwrap();
+        assert_eq!((bill.due_date, bill.paid), (date("2024-02-29"), false));
+    }
+
+    #[test]
+    fn past_occurrences_do_not_move_the_bill() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        data.bills[0].due_date = date("2024-03-31");
+        let late = PaymentInput {
+            occurrence_date: Some(date("2024-02-29")),
+            ..input(&rent.id)
+        };
+        data.record_payment(late, date("2024-03-02"), Utc::now())
+            .unwrap();
+        assert_eq!(data.bills[0].due_date, date("2024-03-31"));
+        assert_eq!(data.payments.len(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_payments() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let cases = [
+            (
+                PaymentInput {
+                    occurrence_date: Some(date("2024-02-15")),
+                    ..input(&rent.id)
+                },
+                "\"Rent\" is not due on 2024-02-15",
+            ),
+            (
+                PaymentInput {
+                    amount: Some(usd(0)),
+                    ..input(&rent.id)
+                },
+                "Amount must be greater than 0",
+            ),
+            (
+                PaymentInput {
+                    amount: Some(usd(-100)),
+                    ..input(&rent.id)
+                },
+                "Amount must be greater than 0",
+            ),
+            (
+                PaymentInput {
+                    amount: Some(Money::new(100, Currency::parse("EUR").unwrap())),
+                    ..input(&rent.id)
+                },
+                "\"Rent\" is billed in USD, not EUR",
+            ),
+        ];
+        for (case, message) in cases {
+            let err = data
+                .record_payment(case, date("2024-01-30"), Utc::now())
+                .unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+        let err = data
+            .record_payment(input("gone"), date("2024-01-30"), Utc::now())
+            .unwrap_err();
+        assert_eq!(err.code(), "not_found");
+        assert!(data.payments.is_empty());
+    }
+
+    #[test]
+    fn history_is_per_bill_and_most_recent_first() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let water = add_bill(&mut data, "Water", 4000, "2024-01-10");
+        for paid in ["2024-01-30", "2024-02-27"] {
+            data.record_payment(input(&rent.id), date(paid), Utc::now())
+                .unwrap();
+        }
+        data.record_payment(input(&water.id), date("2024-01-09"), Utc::now())
+            .unwrap();
+        let history: Vec<_> = data
+            .payments_for(&rent.id)
+            .into_iter()
+            .map(|p| (p.paid_date, p.occurrence_date))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (date("2024-02-27"), date("2024-02-29")),
+                (date("2024-01-30"), date("2024-01-31"))
+            ]
+        );
+
+        data.delete_bill(&rent.id, Utc::now()).unwrap();
+        assert_eq!(data.payments.len(), 1);
+        assert_eq!(data.payments[0].bill_id, water.id);
+    }
+
+    #[test]
+    fn unticking_paid_drops_the_payment() {
+        let mut data = Data::default();
+        let fee = add_bill(&mut data, "Fee", 2500, "2024-01-10");
+        data.bills[0].recurrence = crate::recurrence::Recurrence::None;
+        let paid = data
+            .set_paid(&fee.id, true, date("2024-01-09"), Utc::now())
+            .unwrap();
+        assert!(paid.paid);
+        assert_eq!(data.payments_for(&fee.id).len(), 1);
+        let unpaid = data
+            .set_paid(&fee.id, false, date("2024-01-09"), Utc::now())
+            .unwrap();
+        assert!(!unpaid.paid);
+        assert!(data.payments.is_empty());
+    }
+
+    fn pay(data: &mut Data, bill_id: &str, amount: i64) -> Payment {
+        let input = PaymentInput {
+            amount: Some(usd(amount)),
+            ..input(bill_id)
+        };
+        data.record_payment(input, date("2024-01-20"), Utc::now())
+            .unwrap()
+    }
+
+    fn view(data: &Data) -> (NaiveDate, bool, i64, i64) {
+        let view = &data.bill_views(&BillFilter::default()).unwrap()[0];
+        (
+            view.bill.due_date,
+            view.bill.paid,
+            view.paid_amount.minor,
+            view.balance.minor,
+        )
+    }
+
+    #[test]
+    fn partial_payments_add_up_to_the_occurrence() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        pay(&mut data, &rent.id, 50_000);
+        pay(&mut data, &rent.id, 40_010);
+        assert_eq!(view(&data), (date("2024-01-31"), false, 90_010, 29_990));
+        assert_eq!(
+            data.balance(&data.bills[0], date("2024-01-31")).unwrap(),
+            usd(29_990)
+        );
+
+        // The default amount is whatever is still owed
+        let rest = data
+            .record_payment(input(&rent.id), date("2024-01-30"), Utc::now())
+            .unwrap();
+        assert_eq!(rest.amount, usd(29_990));
+        assert_eq!(
+            data.paid_towards(&data.bills[0], date("2024-01-31"))
+                .unwrap(),
+            usd(120_000)
+        );
+        assert_eq!(view(&data), (date("2024-02-29"), false, 0, 120_000));
+    }
+
+    #[test]
+    fn overpayment_settles_without_negative_balance() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        pay(&mut data, &rent.id, 150_000);
+        assert_eq!(
+            data.paid_towards(&data.bills[0], date("2024-01-31"))
+                .unwrap(),
+            usd(150_000)
+        );
+        assert_eq!(
+            data.balance(&data.bills[0], date("2024-01-31")).unwrap(),
+            usd(0)
+        );
+        assert_eq!(data.bills[0].due_date, date("2024-02-29"));
+
+        let again = PaymentInput {
+            occurrence_date: Some(date("2024-01-31")),
+            ..input(&rent.id)
+        };
+        let err = data
+            .record_payment(again, date("2024-02-01"), Utc::now())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"Rent\" due 2024-01-31 is already paid in full"
+        );
+    }
+
+    #[test]
+    fn settling_skips_cycles_paid_in_advance() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let ahead = PaymentInput {
+            occurrence_date: Some(date("2024-02-29")),
+            ..input(&rent.id)
+        };
+        data.record_payment(ahead, date("2024-01-20"), Utc::now())
+            .unwrap();
+        assert_eq!(view(&data), (date("2024-01-31"), false, 0, 120_000));
+        pay(&mut data, &rent.id, 120_000);
+        assert_eq!(view(&data), (date("2024-03-31"), false, 0, 120_000));
+    }
+
+    #[test]
+    fn unticking_paid_undoes_partial_payments() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        pay(&mut data, &rent.id, 20_000);
+        // Ticking pays the remaining balance rather than the full amount
+        data.set_paid(&rent.id, true, date("2024-01-30"), Utc::now())
+            .unwrap();
+        let amounts: Vec<i64> = data.payments.iter().map(|p| p.amount.minor).collect();
+        assert_eq!(amounts, [20_000, 100_000]);
+
+        data.bills[0].due_date = date("2024-01-31");
+        data.bills[0].paid = true;
+        data.set_paid(&rent.id, false, date("2024-01-30"), Utc::now())
+            .unwrap();
+        assert!(data.payments.is_empty());
+        assert_eq!(view(&data), (date("2024-01-31"), false, 0, 120_000));
+    }
+
+    #[test]
+    fn ticking_a_covered_occurrence_records_nothing() {
+        let mut data = Data::default();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        pay(&mut data, &rent.id, 60_000);
+        data.bills[0].amount = usd(50_000);
+        assert_eq!(view(&data), (date("2024-01-31"), false, 60_000, 0));
+        data.set_paid(&rent.id, true, date("2024-01-30"), Utc::now())
+            .unwrap();
+        assert_eq!(data.payments.len(), 1);
+        assert_eq!(data.bills[0].due_date, date("2024-02-29"));
+    }
+}
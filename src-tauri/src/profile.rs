@@ -0,0 +1,325 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::backup::write_atomic;
+use crate::error::{AppError, AppResult};
+use crate::store::DataFiles;
+
+/// Lists the profiles and which one is open, in the app data directory.
+const REGISTRY_FILE: &str = "profiles.json";
+/// Holds one directory of data files per profile.
+const PROFILE_DIR: &str = "profiles";
+/// The profile that adopts the data of a single-profile install. Its id is
+/// fixed so that a move interrupted by a crash resumes into the same place.
+const FIRST_PROFILE_ID: &str = "personal";
+const FIRST_PROFILE_NAME: &str = "Personal";
+
+/// A separate set of bills, payments and settings, e.g. personal bills and
+/// those of a rental property.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileList {
+    /// Id of the open profile.
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+/// The profiles of this install, kept in `profiles.json` so the open one
+/// is remembered across restarts.
+#[derive(Debug)]
+pub struct Profiles {
+    root: PathBuf,
+    list: ProfileList,
+}
+
+impl Profiles {
+    /// Reads the profiles under `root`. The first run creates one profile
+    /// and moves the data of an install from before profiles into it.
+    pub fn open(root: impl Into<PathBuf>, now: DateTime<Utc>) -> AppResult<Self> {
+        let root = root.into();
+        let path = root.join(REGISTRY_FILE);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let list: ProfileList = serde_json::from_slice(&bytes).map_err(|e| {
+                    AppError::CorruptData(format!("{} is not valid: {e}", path.display()))
+                })?;
+                if !list.profiles.iter().any(|p| p.id == list.active) {
+                    return Err(AppError::CorruptData(format!(
+                        "{} names no open profile",
+                        path.display()
+                    )));
+                }
+                Ok(Self { root, list })
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let profile = Profile {
+                    id: FIRST_PROFILE_ID.to_string(),
+                    name: FIRST_PROFILE_NAME.to_string(),
+                    created_at: now,
+                };
+                let profiles = Self {
+                    list: ProfileList {
+                        active: profile.id.clone(),
+                        profiles: vec![profile],
+                    },
+                    root,
+                };
+                // Moved before the registry exists, so an interrupted move
+                // is simply finished on the next start
+                DataFiles::new(&profiles.root).move_into(&profiles.active_files())?;
+                profiles.save()?;
+                Ok(profiles)
+            }
+            Err(e) => Err(AppError::io(
+                format!("Failed to read {}", path.display()),
+                e,
+            )),
+        }
+    }
+
+    pub fn list(&self) -> &ProfileList {
+        &self.list
+    }
+
+    fn dir(&self, id: &str) -> PathBuf {
+        self.root.join(PROFILE_DIR).join(id)
+    }
+
+    /// Data files of the profile `id`.
+    pub fn files(&self, id: &str) -> AppResult<DataFiles> {
+        self.profile(id)?;
+        Ok(DataFiles::new(self.dir(id)))
+    }
+
+    pub fn active_files(&self) -> DataFiles {
+        DataFiles::new(self.dir(&self.list.active))
+    }
+
+    fn profile(&self, id: &str) -> AppResult<&Profile> {
+        self.list
+            .profiles
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| AppError::not_found("Profile", id))
+    }
+
+    /// Checks a profile name and that no other profile already uses it.
+    fn name(&self, name: &str, except_id: Option<&str>) -> AppResult<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::validation("Profile name is required"));
+        }
+        let taken = self
+            .list
+            .profiles
+            .iter()
+            .any(|p| Some(p.id.as_str()) != except_id && p.name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(AppError::Conflict(format!(
+                "A profile named \"{name}\" already exists"
+            )));
+        }
+        Ok(name.to_string())
+    }
+
+    /// Adds an empty profile; its data is created when it is first opened.
+    pub fn create(&mut self, name: &str, now: DateTime<Utc>) -> AppResult<Profile> {
+        let profile = Profile::new(&self.name(name, None)?, now);
+        self.list.profiles.push(profile.clone());
+        self.save_or_revert(|list| {
+            list.profiles.pop();
+        })?;
+        Ok(profile)
+    }
+
+    pub fn rename(&mut self, id: &str, name: &str) -> AppResult<Profile> {
+        let name = self.name(name, Some(id))?;
+        let idx = self.index(id)?;
+        let old = std::mem::replace(&mut self.list.profiles[idx].name, name);
+        self.save_or_revert(|list| list.profiles[idx].name = old)?;
+        Ok(self.list.profiles[idx].clone())
+    }
+
+    /// Remembers `id` as the open profile.
+    pub fn set_active(&mut self, id: &str) -> AppResult<()> {
+        self.profile(id)?;
+        let old = std::mem::replace(&mut self.list.active, id.to_string());
+        self.save_or_revert(|list| list.active = old)
+    }
+
+    /// Removes a profile that is not open, with all of its data.
+    pub fn delete(&mut self, id: &str) -> AppResult<Profile> {
+        let idx = self.index(id)?;
+        if id == self.list.active {
+            return Err(AppError::validation(
+                "Switch to another profile before deleting this one",
+            ));
+        }
+        let profile = self.list.profiles.remove(idx);
+        self.save_or_revert(|list| list.profiles.insert(idx, profile.clone()))?;
+        remove_dir(&self.dir(id))?;
+        Ok(profile)
+    }
+
+    fn index(&self, id: &str) -> AppResult<usize> {
+        self.list
+            .profiles
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or_else(|| AppError::not_found("Profile", id))
+    }
+
+    fn save(&self) -> AppResult<()> {
+        let bytes = serde_json::to_vec_pretty(&self.list)
+            .map_err(|e| AppError::CorruptData(format!("Failed to serialize profiles: {e}")))?;
+        fs::create_dir_all(&self.root)
+            .map_err(|e| AppError::io(format!("Failed to create {}", self.root.display()), e))?;
+        write_atomic(&self.root.join(REGISTRY_FILE), &bytes)
+    }
+
+    /// Saves, undoing the in-memory change with `revert` if that fails.
+    fn save_or_revert(&mut self, revert: impl FnOnce(&mut ProfileList)) -> AppResult<()> {
+        self.save().inspect_err(|_| revert(&mut self.list))
+    }
+}
+
+impl Profile {
+    fn new(name: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            created_at: now,
+        }
+    }
+}
+
+fn remove_dir(path: &Path) -> AppResult<()> {
+    match fs::remove_dir_all(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::io(
+            format!("Failed to delete {}", path.display()),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn open(dir: &TempDir) -> Profiles {
+        Profiles::open(dir.path(), Utc::now()).unwrap()
+    }
+
+    #[test]
+    fn first_run_moves_the_data_into_a_profile() {
+        let dir = TempDir::new().unwrap();
+        for name in [
+            "bills.db",
+            "bills.db-wal",
+            "bills.json.imported",
+            "notes.txt",
+        ] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        fs::create_dir(dir.path().join("backups")).unwrap();
+
+        let profiles = open(&dir);
+        assert_eq!(profiles.list().active, FIRST_PROFILE_ID);
+        let moved = profiles.active_files().database();
+        assert_eq!(fs::read_to_string(moved).unwrap(), "bills.db");
+        let personal = dir.path().join(PROFILE_DIR).join(FIRST_PROFILE_ID);
+        assert!(personal.join("backups").is_dir());
+        assert!(personal.join("bills.db-wal").exists());
+        assert!(dir.path().join("notes.txt").exists());
+        assert!(!dir.path().join("bills.db").exists());
+
+        // Later starts read the registry instead
+        fs::write(dir.path().join("bills.db"), "stray").unwrap();
+        let reopened = open(&dir);
+        assert_eq!(reopened.list().profiles, profiles.list().profiles);
+        assert!(dir.path().join("bills.db").exists());
+    }
+
+    #[test]
+    fn names_are_required_and_unique() {
+        let dir = TempDir::new().unwrap();
+        let mut profiles = open(&dir);
+        let rental = profiles.create(" Rental ", Utc::now()).unwrap();
+        assert_eq!(rental.name, "Rental");
+        assert_eq!(
+            profiles.create("rental", Utc::now()).unwrap_err().code(),
+            "conflict"
+        );
+        assert_eq!(
+            profiles.create("  ", Utc::now()).unwrap_err().code(),
+            "validation"
+        );
+        assert_eq!(
+            profiles.rename(&rental.id, "RENTAL").unwrap().name,
+            "RENTAL"
+        );
+        assert_eq!(
+            profiles.rename(&rental.id, "personal").unwrap_err().code(),
+            "conflict"
+        );
+        let names: Vec<String> = open(&dir)
+            .list()
+            .profiles
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        assert_eq!(names, ["Personal", "RENTAL"]);
+    }
+
+    #[test]
+    fn deletes_only_profiles_that_are_not_open() {
+        let dir = TempDir::new().unwrap();
+        let mut profiles = open(&dir);
+        let rental = profiles.create("Rental", Utc::now()).unwrap();
+        let files = profiles.files(&rental.id).unwrap();
+        fs::create_dir_all(files.backups()).unwrap();
+
+        assert_eq!(
+            profiles.delete(FIRST_PROFILE_ID).unwrap_err().code(),
+            "validation"
+        );
+        profiles.delete(&rental.id).unwrap();
+        assert!(!files.backups().exists());
+        assert_eq!(profiles.files(&rental.id).unwrap_err().code(), "not_found");
+        assert_eq!(
+            profiles.set_active(&rental.id).unwrap_err().code(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn remembers_the_open_profile() {
+        let dir = TempDir::new().unwrap();
+        let mut profiles = open(&dir);
+        let rental = profiles.create("Rental", Utc::now()).unwrap();
+        profiles.set_active(&rental.id).unwrap();
+        assert_eq!(open(&dir).list().active, rental.id);
+
+        let registry = dir.path().join(REGISTRY_FILE);
+        let doc = fs::read_to_string(&registry).unwrap();
+        let active = format!("\"active\": \"{}\"", rental.id);
+        assert!(doc.contains(&active));
+        fs::write(&registry, doc.replace(&active, r#""active": "gone""#)).unwrap();
+        let err = Profiles::open(dir.path(), Utc::now()).unwrap_err();
+        assert_eq!(err.code(), "corrupt_data");
+    }
+}
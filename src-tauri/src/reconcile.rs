@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::bank::{payee_key, BankTransaction, MatchOptions, MatchReport};
+use crate::csv::{normalize, read_records, DateFormat, RowError, PREVIEW_ROWS};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::money::{Currency, Money};
+
+/// Payee similarity at which an overdue bill is reported as possibly paid
+/// by a transaction that did not match it.
+const WEAK_PAYEE: f64 = 0.4;
+
+/// Fields a column of a bank CSV can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BankColumn {
+    Date,
+    Payee,
+    Memo,
+    /// Signed amount, negative for money going out unless the profile says
+    /// otherwise.
+    Amount,
+    /// Money going out, in a column of its own.
+    Debit,
+    /// Money coming in, in a column of its own.
+    Credit,
+    /// The bank's reference for the row.
+    Id,
+}
+
+impl BankColumn {
+    const ALL: [BankColumn; 7] = [
+        BankColumn::Date,
+        BankColumn::Payee,
+        BankColumn::Memo,
+        BankColumn::Amount,
+        BankColumn::Debit,
+        BankColumn::Credit,
+        BankColumn::Id,
+    ];
+
+    /// Headers banks commonly use for the field, normalized.
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            BankColumn::Date => &[
+                "date",
+                "postingdate",
+                "posteddate",
+                "transactiondate",
+                "bookingdate",
+                "valuedate",
+            ],
+            BankColumn::Payee => &[
+                "payee",
+                "description",
+                "name",
+                "merchant",
+                "counterparty",
+                "details",
+                "narrative",
+            ],
+            BankColumn::Memo => &["memo", "notes", "note", "remarks"],
+            BankColumn::Amount => &["amount", "value", "transactionamount"],
+            BankColumn::Debit => &[
+                "debit",
+                "debits",
+                "withdrawal",
+                "withdrawals",
+                "moneyout",
+                "paidout",
+            ],
+            BankColumn::Credit => &[
+                "credit", "credits", "deposit", "deposits", "moneyin", "paidin",
+            ],
+            BankColumn::Id => &[
+                "id",
+                "transactionid",
+                "reference",
+                "referencenumber",
+                "ref",
+                "fitid",
+            ],
+        }
+    }
+}
+
+/// Which column of a bank's CSV holds each field.
+pub type BankMapping = BTreeMap<BankColumn, usize>;
+
+/// How to read the CSV export of one bank, saved by name so later
+/// statements from it import without setup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankProfile {
+    pub name: String,
+    pub columns: BankMapping,
+    pub date_format: DateFormat,
+    pub currency: Currency,
+    /// Whether the first row holds column names rather than a transaction.
+    pub has_header: bool,
+    /// Whether money going out is positive in the amount column.
+    #[serde(default)]
+    pub debits_positive: bool,
+}
+
+/// The start of a bank CSV, with the columns guessed from its header.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankCsvPreview {
+    /// The first row, whether or not it is a header.
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub row_count: usize,
+    pub mapping: BankMapping,
+    /// False when no column name was recognized, as some banks export no
+    /// header at all.
+    pub has_header: bool,
+    /// Formats that read every date in the guessed column, most likely
+    /// first.
+    pub date_formats: Vec<DateFormat>,
+}
+
+/// An overdue bill that the statement suggests may be paid after all.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PossiblyPaid {
+    pub bill_id: String,
+    pub bill_name: String,
+    pub due_date: NaiveDate,
+    pub balance: Money,
+    /// Unmatched debits that could be its payment, most likely first.
+    pub transactions: Vec<BankTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    #[serde(flatten)]
+    pub matches: MatchReport,
+    pub possibly_paid: Vec<PossiblyPaid>,
+    /// Rows that could not be read; the others are reconciled regardless.
+    pub errors: Vec<RowError>,
+}
+
+/// Reads a bank CSV's first rows and guesses its columns from the header.
+pub fn preview_bank_csv(text: &str) -> AppResult<BankCsvPreview> {
+    let mut records = read_records(text)?.into_iter();
+    let headers = records
+        .next()
+        .ok_or_else(|| AppError::validation("The file is empty"))?
+        .cells;
+    let mut mapping = BankMapping::new();
+    for field in BankColumn::ALL {
+        let unused = |i: &usize| !mapping.values().any(|m| m == i);
+        if let Some(i) = (0..headers.len())
+            .find(|i| unused(i) && field.aliases().contains(&normalize(&headers[*i]).as_str()))
+        {
+            mapping.insert(field, i);
+        }
+    }
+    let has_header = !mapping.is_empty();
+    let mut rows: Vec<Vec<String>> = records.map(|r| r.cells).collect();
+    if !has_header {
+        rows.insert(0, headers.clone());
+    }
+    let date_formats = match mapping.get(&BankColumn::Date) {
+        Some(&col) => {
+            DateFormat::detect(rows.iter().filter_map(|r| r.get(col)).map(String::as_str))
+        }
+        None => Vec::new(),
+    };
+    Ok(BankCsvPreview {
+        row_count: rows.len(),
+        rows: rows.into_iter().take(PREVIEW_ROWS).collect(),
+        headers,
+        mapping,
+        has_header,
+        date_formats,
+    })
+}
+
+impl BankProfile {
+    /// Trims the name and checks that the columns make a transaction.
+    pub fn clean(mut self) -> AppResult<Self> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err(AppError::validation("Bank profile name must not be empty"));
+        }
+        let has = |c| self.columns.contains_key(&c);
+        if !has(BankColumn::Date) {
+            return Err(AppError::validation("Pick the column holding the date"));
+        }
+        if !has(BankColumn::Payee) {
+            return Err(AppError::validation("Pick the column holding the payee"));
+        }
+        if !has(BankColumn::Amount) && !has(BankColumn::Debit) {
+            return Err(AppError::validation(
+                "Pick the column holding the amount, or the one holding debits",
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Reads one row as a transaction. `None` for rows that move no money,
+    /// such as balance lines.
+    fn transaction(&self, cells: &[String]) -> Result<Option<BankTransaction>, String> {
+        let cell = |column: BankColumn| {
+            self.columns
+                .get(&column)
+                .and_then(|&i| cells.get(i))
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+        };
+        let money = |text: &str| Money::parse(text, self.currency).map_err(|e| e.to_string());
+
+        let date_text = cell(BankColumn::Date).ok_or("Date is missing")?;
+        let date = self.date_format.parse(date_text).ok_or_else(|| {
+            format!(
+                "Date \"{date_text}\" is not in the format {}",
+                self.date_format.example()
+            )
+        })?;
+        let (amount, out) = if let Some(text) = cell(BankColumn::Amount) {
+            (money(text)?, self.debits_positive)
+        } else if let Some(text) = cell(BankColumn::Debit) {
+            let amount = money(text)?;
+            (amount, amount.is_positive())
+        } else if let Some(text) = cell(BankColumn::Credit) {
+            let amount = money(text)?;
+            (amount, !amount.is_positive())
+        } else {
+            return Ok(None);
+        };
+        if amount.minor == 0 {
+            return Ok(None);
+        }
+        let minor = if out {
+            amount.minor.checked_neg().ok_or("Amount is too large")?
+        } else {
+            amount.minor
+        };
+        let amount = Money::new(minor, amount.currency);
+        Ok(Some(BankTransaction {
+            id: cell(BankColumn::Id).unwrap_or_default().to_string(),
+            date,
+            amount,
+            payee: cell(BankColumn::Payee).unwrap_or_default().to_string(),
+            memo: cell(BankColumn::Memo).map(str::to_string),
+        }))
+    }
+
+    /// Reads the transactions of a statement, and the rows it could not
+    /// read. Rows without a reference of the bank's get one made of their
+    /// date, amount and payee, numbered when the same one repeats, so the
+    /// same rows get the same ids when an overlapping statement is read.
+    pub fn read_statement(&self, text: &str) -> AppResult<(Vec<BankTransaction>, Vec<RowError>)> {
+        let mut records = read_records(text)?.into_iter();
+        if self.has_header {
+            records.next();
+        }
+        let mut transactions = Vec::new();
+        let mut errors = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for record in records {
+            match self.transaction(&record.cells) {
+                Ok(Some(mut transaction)) => {
+                    transaction.id = if transaction.id.is_empty() {
+                        let key = format!(
+                            "{}:{}:{}",
+                            transaction.date,
+                            transaction.amount.minor,
+                            payee_key(&transaction.payee)
+                        );
+                        let n = seen.entry(key.clone()).or_default();
+                        *n += 1;
+                        format!("csv:{key}#{n}")
+                    } else {
+                        format!("csv:{}", transaction.id)
+                    };
+                    transactions.push(transaction);
+                }
+                Ok(None) => {}
+                Err(message) => errors.push(RowError {
+                    line: record.line,
+                    column: None,
+                    message,
+                }),
+            }
+        }
+        Ok((transactions, errors))
+    }
+}
+
+impl Data {
+    pub fn bank_profile(&self, name: &str) -> AppResult<&BankProfile> {
+        self.settings
+            .bank_profiles
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| AppError::not_found("Bank profile", name))
+    }
+
+    /// Adds a bank profile, or replaces the one of the same name.
+    pub fn save_bank_profile(&mut self, profile: BankProfile) -> AppResult<()> {
+        let profile = profile.clean()?;
+        let profiles = &mut self.settings.bank_profiles;
+        match profiles
+            .iter_mut()
+            .find(|p| p.name.eq_ignore_ascii_case(&profile.name))
+        {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
+        }
+        Ok(())
+    }
+
+    pub fn delete_bank_profile(&mut self, name: &str) -> AppResult<()> {
+        let before = self.settings.bank_profiles.len();
+        self.settings
+            .bank_profiles
+            .retain(|p| !p.name.eq_ignore_ascii_case(name.trim()));
+        if self.settings.bank_profiles.len() == before {
+            return Err(AppError::not_found("Bank profile", name));
+        }
+        Ok(())
+    }
+
+    /// Stops matching a payee to the bill it was confirmed for.
+    pub fn forget_payee(&mut self, payee: &str) -> AppResult<()> {
+        self.settings
+            .payee_bills
+            .remove(payee)
+            .map(drop)
+            .ok_or_else(|| AppError::not_found("Payee", payee))
+    }
+
+    /// Reads a bank CSV with the named profile, proposes payments for the
+    /// debits that match unpaid bills, and lists overdue bills the rest of
+    /// the statement suggests may be paid.
+    pub fn reconcile_bank_csv(
+        &self,
+        text: &str,
+        profile: &str,
+        options: &MatchOptions,
+        today: NaiveDate,
+    ) -> AppResult<Reconciliation> {
+        let (transactions, errors) = self.bank_profile(profile)?.read_statement(text)?;
+        let matches = self.match_transactions(&transactions, options)?;
+        let possibly_paid = self.possibly_paid(&matches, options, today)?;
+        Ok(Reconciliation {
+            matches,
+            possibly_paid,
+            errors,
+        })
+    }
+
+    /// Overdue bills without a proposed payment for which an unmatched debit
+    /// made on or after the start of their date window has a similar payee
+    /// or exactly the amount due.
+    fn possibly_paid(
+        &self,
+        report: &MatchReport,
+        options: &MatchOptions,
+        today: NaiveDate,
+    ) -> AppResult<Vec<PossiblyPaid>> {
+        let window = Days::new(u64::from(options.window_days));
+        let mut found = Vec::new();
+        for bill in &self.bills {
+            let overdue = !bill.paid && bill.due_date < today;
+            if !overdue || report.proposals.iter().any(|p| p.bill_id == bill.id) {
+                continue;
+            }
+            let balance = self.balance(bill, bill.due_date)?;
+            let from = bill
+                .due_date
+                .checked_sub_days(window)
+                .unwrap_or(bill.due_date);
+            let mut candidates: Vec<(f64, &BankTransaction)> = report
+                .unmatched
+                .iter()
+                .filter(|t| t.date >= from && t.amount.currency == balance.currency)
+                .filter_map(|t| {
+                    let paid = t.amount.minor.checked_neg()?;
+                    let score = self.payee_score(t, bill);
+                    let exact = paid == balance.minor || paid == bill.amount.minor;
+                    (score >= WEAK_PAYEE || exact)
+                        .then_some((score + f64::from(u8::from(exact)), t))
+                })
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+            found.push(PossiblyPaid {
+                bill_id: bill.id.clone(),
+                bill_name: bill.name.clone(),
+                due_date: bill.due_date,
+                balance,
+                transactions: candidates.into_iter().map(|(_, t)| t.clone()).collect(),
+            });
+        }
+        found.sort_by_key(|p| p.due_date);
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_bill, date as day, usd};
+
+    fn profile(columns: &[(BankColumn, usize)]) -> BankProfile {
+        BankProfile {
+            name: " My Bank ".to_string(),
+            columns: columns.iter().copied().collect(),
+            date_format: DateFormat::DayMonthYear,
+            currency: Currency::USD,
+            has_header: true,
+            debits_positive: false,
+        }
+    }
+
+    fn amounts(transactions: &[BankTransaction]) -> Vec<i64> {
+        transactions.iter().map(|t| t.amount.minor).collect()
+    }
+
+    #[test]
+    fn guesses_columns_from_the_header() {
+        let preview =
+            preview_bank_csv("Booking Date;Details;Money Out;Money In\n31/01/2024;Rent;1200;\n")
+                .unwrap();
+        let expected = [
+            (BankColumn::Date, 0),
+            (BankColumn::Payee, 1),
+            (BankColumn::Debit, 2),
+            (BankColumn::Credit, 3),
+        ];
+        assert_eq!(preview.mapping, BankMapping::from(expected));
+        assert!(preview.has_header);
+        assert_eq!(preview.date_formats, [DateFormat::DayMonthYear]);
+
+        // Without a header the first row is data
+        let preview = preview_bank_csv("31/01/2024,Rent,-1200\n01/02/2024,Gym,-30\n").unwrap();
+        assert!(!preview.has_header && preview.mapping.is_empty());
+        assert_eq!(preview.row_count, 2);
+    }
+
+    #[test]
+    fn checks_profiles() {
+        use BankColumn::*;
+        let clean = |columns: &[(BankColumn, usize)]| profile(columns).clean();
+        assert_eq!(
+            clean(&[(Date, 0), (Payee, 1), (Amount, 2)]).unwrap().name,
+            "My Bank"
+        );
+        assert_eq!(
+            clean(&[(Date, 0), (Payee, 1), (Debit, 2)]).unwrap().name,
+            "My Bank"
+        );
+        for columns in [
+            &[(Payee, 1), (Amount, 2)][..],
+            &[(Date, 0), (Amount, 2)],
+            &[(Date, 0), (Payee, 1), (Credit, 2)],
+        ] {
+            assert_eq!(clean(columns).unwrap_err().code(), "validation");
+        }
+        let blank = BankProfile {
+            name: "  ".to_string(),
+            ..profile(&[(Date, 0), (Payee, 1), (Amount, 2)])
+        };
+        assert!(blank.clean().is_err());
+    }
+
+    #[test]
+    fn gives_repeated_rows_their_own_ids() {
+        use BankColumn::*;
+        let text = "Date,Payee,Amount,Ref\n\
+                    05/01/2024,Coffee 1,-3.50,\n\
+                    05/01/2024,Coffee 2,-3.50,\n\
+                    06/01/2024,Coffee,-3.50,\n\
+                    06/01/2024,Rent,-1200.00,R7\n\
+                    07/01/2024,Balance,0,\n";
+        let profile = profile(&[(Date, 0), (Payee, 1), (Amount, 2), (Id, 3)]);
+        let (transactions, errors) = profile.read_statement(text).unwrap();
+        assert!(errors.is_empty());
+        let ids: Vec<&str> = transactions.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "csv:2024-01-05:-350:coffee#1",
+                "csv:2024-01-05:-350:coffee#2",
+                "csv:2024-01-06:-350:coffee#1",
+                "csv:R7",
+            ]
+        );
+        // The same rows read again get the same ids
+        assert_eq!(profile.read_statement(text).unwrap().0, transactions);
+    }
+
+    #[test]
+    fn signs_amounts_by_column() {
+        use BankColumn::*;
+        let text = "Date,Payee,Out,In\n\
+                    05/01/2024,Rent,1200.00,\n\
+                    05/01/2024,Refund,,25.00\n\
+                    05/01/2024,Fee,-2.00,\n\
+                    05/01/2024,Reversal,,-2.00\n";
+        let split = profile(&[(Date, 0), (Payee, 1), (Debit, 2), (Credit, 3)]);
+        let (transactions, _) = split.read_statement(text).unwrap();
+        assert_eq!(amounts(&transactions), [-120000, 2500, -200, 200]);
+
+        let text = "Date,Payee,Amount\n05/01/2024,Rent,1200.00\n05/01/2024,Salary,-3000\n";
+        let mut signed = profile(&[(Date, 0), (Payee, 1), (Amount, 2)]);
+        assert_eq!(
+            amounts(&signed.read_statement(text).unwrap().0),
+            [120000, -300000]
+        );
+        signed.debits_positive = true;
+        assert_eq!(
+            amounts(&signed.read_statement(text).unwrap().0),
+            [-120000, 300000]
+        );
+    }
+
+    #[test]
+    fn reports_unreadable_rows() {
+        use BankColumn::*;
+        let text = "Date,Payee,Amount\n\
+                    2024-01-05,Rent,-1\n\
+                    ,Rent,-1\n\
+                    05/01/2024,Rent,lots\n\
+                    05/01/2024,Rent,-1\n";
+        let (transactions, errors) = profile(&[(Date, 0), (Payee, 1), (Amount, 2)])
+            .read_statement(text)
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [2, 3, 4]);
+        assert_eq!(
+            errors[0].message,
+            "Date \"2024-01-05\" is not in the format 31/01/2024"
+        );
+        assert_eq!(errors[1].message, "Date is missing");
+    }
+
+    #[test]
+    fn rejects_amounts_that_cannot_change_sign() {
+        use BankColumn::*;
+        let text = format!(
+            "Date,Payee,Out\n05/01/2024,Rent,{}\n",
+            usd(i64::MIN).decimal()
+        );
+        let (transactions, errors) = profile(&[(Date, 0), (Payee, 1), (Debit, 2)])
+            .read_statement(&text)
+            .unwrap();
+        assert!(transactions.is_empty());
+        assert_eq!(errors[0].message, "Amount is too large");
+    }
+
+    #[test]
+    fn lists_overdue_bills_possibly_paid() {
+        use BankColumn::*;
+        let mut data = Data::fresh();
+        let gym = add_bill(&mut data, "Gym", 3000, "2024-01-05");
+        add_bill(&mut data, "Rent", 120000, "2024-03-01");
+        data.save_bank_profile(profile(&[(Date, 0), (Payee, 1), (Amount, 2)]))
+            .unwrap();
+        let text = "Date,Payee,Amount\n\
+                    20/12/2023,Fitness,-30.00\n\
+                    10/02/2024,Gymm,-10.00\n\
+                    15/02/2024,Fitness,-30.00\n\
+                    16/02/2024,Bakery,-4.00\n";
+        let reconciliation = data
+            .reconcile_bank_csv(text, "my bank", &MatchOptions::default(), day("2024-02-20"))
+            .unwrap();
+        assert!(reconciliation.matches.proposals.is_empty());
+        let [possibly] = &reconciliation.possibly_paid[..] else {
+            panic!("{:?}", reconciliation.possibly_paid);
+        };
+        assert_eq!(possibly.bill_id, gym.id);
+        assert_eq!(possibly.balance, usd(3000));
+        let payees: Vec<&str> = possibly
+            .transactions
+            .iter()
+            .map(|t| t.payee.as_str())
+            .collect();
+        assert_eq!(payees, ["Fitness", "Gymm"]);
+
+        assert!(data
+            .reconcile_bank_csv(text, "Other", &MatchOptions::default(), day("2024-02-20"))
+            .is_err());
+    }
+
+    #[test]
+    fn learned_payees_match_outright() {
+        use BankColumn::*;
+        let mut data = Data::fresh();
+        let gym = add_bill(&mut data, "Gym", 3000, "2024-01-05");
+        data.settings
+            .payee_bills
+            .insert("fitness".to_string(), gym.id.clone());
+        data.save_bank_profile(profile(&[(Date, 0), (Payee, 1), (Amount, 2)]))
+            .unwrap();
+        let text = "Date,Payee,Amount\n06/01/2024,POS FITNESS 1234,-30.00\n";
+        let reconciliation = data
+            .reconcile_bank_csv(text, "My Bank", &MatchOptions::default(), day("2024-01-10"))
+            .unwrap();
+        let proposal = &reconciliation.matches.proposals[0];
+        assert_eq!(proposal.bill_id, gym.id);
+        assert_eq!(proposal.score, 0.98);
+
+        data.forget_payee("fitness").unwrap();
+        assert!(data.forget_payee("fitness").is_err());
+        let reconciliation = data
+            .reconcile_bank_csv(text, "My Bank", &MatchOptions::default(), day("2024-01-10"))
+            .unwrap();
+        assert_eq!(reconciliation.matches.proposals[0].score, 0.63);
+    }
+
+    #[test]
+    fn saves_profiles_by_name() {
+        use BankColumn::*;
+        let mut data = Data::fresh();
+        let columns = [(Date, 0), (Payee, 1), (Amount, 2)];
+        data.save_bank_profile(profile(&columns)).unwrap();
+        let replaced = BankProfile {
+            name: "MY BANK".to_string(),
+            debits_positive: true,
+            ..profile(&columns)
+        };
+        data.save_bank_profile(replaced).unwrap();
+        assert_eq!(data.settings.bank_profiles.len(), 1);
+        assert!(data.bank_profile("my bank").unwrap().debits_positive);
+        data.delete_bank_profile(" my bank ").unwrap();
+        assert!(data.delete_bank_profile("my bank").is_err());
+    }
+}
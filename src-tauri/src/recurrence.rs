@@ -0,0 +1,412 @@
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+
+/// How often a bill repeats.
+///
+/// Simple rules serialize as plain strings (`"monthly"`), which keeps the
+/// values written by earlier versions readable; rules with parameters
+/// serialize as single-key objects (`{"everyNDays": {"days": 10}}`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Recurrence {
+    None,
+    Daily,
+    Weekly,
+    BiWeekly,
+    EveryNDays {
+        days: u32,
+    },
+    Monthly,
+    Quarterly,
+    SemiAnnual,
+    Yearly,
+    /// The `nth` given weekday of every month; `nth` is 1-4, or -1 for the
+    /// last one (e.g. "2nd Tuesday", "last Friday").
+    NthWeekday {
+        nth: i8,
+        weekday: Weekday,
+    },
+    LastDayOfMonth,
+    /// Last Monday-Friday of every month. Public holidays are not considered.
+    LastBusinessDay,
+}
+
+impl Recurrence {
+    pub fn validate(self) -> AppResult<()> {
+        match self {
+            Recurrence::EveryNDays { days: 0 } => {
+                Err(AppError::validation("Interval must be at least 1 day"))
+            }
+            Recurrence::NthWeekday { nth, .. } if !matches!(nth, 1..=4 | -1) => Err(
+                AppError::validation("Week of month must be 1-4, or -1 for the last"),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// All due dates of a series starting at `start`, in order.
+    ///
+    /// Interval rules count from `start` itself, and each date is computed
+    /// from `start` rather than from the previous date, so a monthly bill due
+    /// Jan 31 falls on Feb 29, Mar 31, Apr 30... Calendar rules such as
+    /// [`Recurrence::LastDayOfMonth`] yield their dates on or after `start`.
+    pub fn occurrences(self, start: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+        (0u32..)
+            .map(move |k| self.candidate(start, k))
+            .take_while(Option::is_some)
+            .flatten()
+            .filter(move |d| *d >= start)
+    }
+
+    /// The `k`-th date produced by the rule, before filtering out dates that
+    /// precede `start`. `None` once the calendar runs out.
+    fn candidate(self, start: NaiveDate, k: u32) -> Option<NaiveDate> {
+        let days = |n: u32| start.checked_add_days(Days::new(u64::from(n) * u64::from(k)));
+        let months = |n: u32| start.checked_add_months(Months::new(n.checked_mul(k)?));
+        let month = || first_of_month(start)?.checked_add_months(Months::new(k));
+        match self {
+            Recurrence::None => (k == 0).then_some(start),
+            Recurrence::Daily => days(1),
+            Recurrence::Weekly => days(7),
+            Recurrence::BiWeekly => days(14),
+            Recurrence::EveryNDays { days: n } => days(n),
+            Recurrence::Monthly => months(1),
+            Recurrence::Quarterly => months(3),
+            Recurrence::SemiAnnual => months(6),
+            Recurrence::Yearly => months(12),
+            Recurrence::NthWeekday { nth, weekday } => nth_weekday(month()?, nth, weekday),
+            Recurrence::LastDayOfMonth => last_of_month(month()?),
+            Recurrence::LastBusinessDay => {
+                let mut d = last_of_month(month()?)?;
+                while matches!(d.weekday(), Weekday::Sat | Weekday::Sun) {
+                    d = d.pred_opt()?;
+                }
+                Some(d)
+            }
+        }
+    }
+}
+
+/// Conditions that end or interrupt a recurring series.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurrenceLimits {
+    /// Last day an occurrence may fall on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_date: Option<NaiveDate>,
+    /// Number of occurrences in the series, e.g. 12 for a one-year contract.
+    /// Occurrences skipped by a pause do not count towards it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_occurrences: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pauses: Vec<Pause>,
+}
+
+/// Inclusive date range during which no occurrences are due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pause {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+}
+
+impl RecurrenceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self, start: NaiveDate) -> AppResult<()> {
+        if self.end_date.is_some_and(|end| end < start) {
+            return Err(AppError::validation(
+                "End date must not be before the due date",
+            ));
+        }
+        if self.max_occurrences == Some(0) {
+            return Err(AppError::validation(
+                "Number of occurrences must be at least 1",
+            ));
+        }
+        if self.pauses.iter().any(|p| p.until < p.from) {
+            return Err(AppError::validation(
+                "A pause must not end before it starts",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self, date: NaiveDate) -> bool {
+        self.pauses
+            .iter()
+            .any(|p| (p.from..=p.until).contains(&date))
+    }
+
+    /// Restricts a raw series to the dates that are actually due.
+    pub fn apply<'a>(
+        &'a self,
+        dates: impl Iterator<Item = NaiveDate> + 'a,
+    ) -> impl Iterator<Item = NaiveDate> + 'a {
+        let max = self
+            .max_occurrences
+            .map_or(usize::MAX, |n| usize::try_from(n).unwrap_or(usize::MAX));
+        dates
+            .take_while(move |d| self.end_date.is_none_or(|end| *d <= end))
+            .filter(move |d| !self.is_paused(*d))
+            .take(max)
+    }
+}
+
+fn first_of_month(d: NaiveDate) -> Option<NaiveDate> {
+    d.with_day(1)
+}
+
+fn last_of_month(d: NaiveDate) -> Option<NaiveDate> {
+    first_of_month(d)?
+        .checked_add_months(Months::new(1))?
+        .pred_opt()
+}
+
+/// The `nth` `weekday` in the month of `first`, counting from the end when
+/// `nth` is -1.
+fn nth_weekday(first: NaiveDate, nth: i8, weekday: Weekday) -> Option<NaiveDate> {
+    if nth < 0 {
+        let last = last_of_month(first)?;
+        let back = (7 + last.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+        return last.checked_sub_days(Days::new(u64::from(back)));
+    }
+    let ahead = (7 + weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    let weeks = u32::try_from(nth).ok()?.checked_sub(1)?;
+    first.checked_add_days(Days::new(u64::from(ahead + 7 * weeks)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    fn first(rule: Recurrence, start: NaiveDate, n: usize) -> Vec<NaiveDate> {
+        rule.occurrences(start).take(n).collect()
+    }
+
+    #[test]
+    fn monthly_clamps_to_month_end_without_drifting() {
+        assert_eq!(
+            first(Recurrence::Monthly, date("2024-01-31"), 4),
+            [
+                date("2024-01-31"),
+                date("2024-02-29"),
+                date("2024-03-31"),
+                date("2024-04-30")
+            ]
+        );
+        assert_eq!(
+            first(Recurrence::Quarterly, date("2023-11-30"), 2),
+            [date("2023-11-30"), date("2024-02-29")]
+        );
+        assert_eq!(
+            first(Recurrence::SemiAnnual, date("2024-08-31"), 2),
+            [date("2024-08-31"), date("2025-02-28")]
+        );
+    }
+
+    #[test]
+    fn yearly_from_leap_day_returns_to_leap_day() {
+        assert_eq!(
+            first(Recurrence::Yearly, date("2024-02-29"), 5),
+            [
+                date("2024-02-29"),
+                date("2025-02-28"),
+                date("2026-02-28"),
+                date("2027-02-28"),
+                date("2028-02-29")
+            ]
+        );
+    }
+
+    #[test]
+    fn interval_rules_count_from_start() {
+        let start = date("2024-02-27");
+        assert_eq!(
+            first(Recurrence::Daily, start, 4),
+            [
+                start,
+                date("2024-02-28"),
+                date("2024-02-29"),
+                date("2024-03-01")
+            ]
+        );
+        assert_eq!(
+            first(Recurrence::Weekly, start, 2),
+            [start, date("2024-03-05")]
+        );
+        assert_eq!(
+            first(Recurrence::BiWeekly, start, 2),
+            [start, date("2024-03-12")]
+        );
+        assert_eq!(
+            first(Recurrence::EveryNDays { days: 10 }, start, 2),
+            [start, date("2024-03-08")]
+        );
+        assert_eq!(first(Recurrence::None, start, 3), [start]);
+    }
+
+    #[test]
+    fn nth_weekday() {
+        let second_tuesday = Recurrence::NthWeekday {
+            nth: 2,
+            weekday: Weekday::Tue,
+        };
+        // The first candidate, Jan 9, precedes the start and is skipped
+        assert_eq!(
+            first(second_tuesday, date("2024-01-10"), 3),
+            [date("2024-02-13"), date("2024-03-12"), date("2024-04-09")]
+        );
+        let last_friday = Recurrence::NthWeekday {
+            nth: -1,
+            weekday: Weekday::Fri,
+        };
+        assert_eq!(
+            first(last_friday, date("2024-02-01"), 3),
+            [date("2024-02-23"), date("2024-03-29"), date("2024-04-26")]
+        );
+        let fourth_thursday = Recurrence::NthWeekday {
+            nth: 4,
+            weekday: Weekday::Thu,
+        };
+        assert_eq!(
+            first(fourth_thursday, date("2024-11-01"), 1),
+            [date("2024-11-28")]
+        );
+    }
+
+    #[test]
+    fn month_end_rules() {
+        assert_eq!(
+            first(Recurrence::LastDayOfMonth, date("2024-01-15"), 3),
+            [date("2024-01-31"), date("2024-02-29"), date("2024-03-31")]
+        );
+        // Aug 31 2024 is a Saturday and Nov 30 a Saturday too
+        assert_eq!(
+            first(Recurrence::LastBusinessDay, date("2024-08-01"), 4),
+            [
+                date("2024-08-30"),
+                date("2024-09-30"),
+                date("2024-10-31"),
+                date("2024-11-29")
+            ]
+        );
+    }
+
+    #[test]
+    fn series_stops_where_the_calendar_ends() {
+        let last = NaiveDate::MAX;
+        assert_eq!(first(Recurrence::Daily, last, 3), [last]);
+        assert_eq!(first(Recurrence::Yearly, last, 3), [last]);
+    }
+
+    #[test]
+    fn validate_rules() {
+        assert!(Recurrence::EveryNDays { days: 0 }.validate().is_err());
+        assert!(Recurrence::EveryNDays { days: 1 }.validate().is_ok());
+        for nth in [0, 5, -2] {
+            let rule = Recurrence::NthWeekday {
+                nth,
+                weekday: Weekday::Mon,
+            };
+            assert!(rule.validate().is_err(), "{nth}");
+        }
+        for nth in [1, 4, -1] {
+            let rule = Recurrence::NthWeekday {
+                nth,
+                weekday: Weekday::Mon,
+            };
+            assert!(rule.validate().is_ok(), "{nth}");
+        }
+    }
+
+    #[test]
+    fn serialized_forms() {
+        let json = |r: Recurrence| serde_json::to_string(&r).unwrap();
+        assert_eq!(json(Recurrence::Monthly), "\"monthly\"");
+        assert_eq!(json(Recurrence::BiWeekly), "\"biWeekly\"");
+        assert_eq!(
+            json(Recurrence::EveryNDays { days: 10 }),
+            r#"{"everyNDays":{"days":10}}"#
+        );
+        let rule: Recurrence =
+            serde_json::from_str(r#"{"nthWeekday":{"nth":-1,"weekday":"Fri"}}"#).unwrap();
+        assert_eq!(
+            rule,
+            Recurrence::NthWeekday {
+                nth: -1,
+                weekday: Weekday::Fri
+            }
+        );
+    }
+
+    #[test]
+    fn limits_apply_end_date_count_and_pauses() {
+        let start = date("2024-01-01");
+        let limits = RecurrenceLimits {
+            end_date: Some(date("2024-06-01")),
+            max_occurrences: None,
+            pauses: vec![Pause {
+                from: date("2024-02-01"),
+                until: date("2024-03-01"),
+            }],
+        };
+        assert_eq!(
+            limits
+                .apply(Recurrence::Monthly.occurrences(start))
+                .collect::<Vec<_>>(),
+            [
+                date("2024-01-01"),
+                date("2024-04-01"),
+                date("2024-05-01"),
+                date("2024-06-01")
+            ]
+        );
+        // Paused dates do not count towards the limit
+        let limits = RecurrenceLimits {
+            end_date: None,
+            max_occurrences: Some(2),
+            ..limits
+        };
+        assert_eq!(
+            limits
+                .apply(Recurrence::Monthly.occurrences(start))
+                .collect::<Vec<_>>(),
+            [date("2024-01-01"), date("2024-04-01")]
+        );
+        assert!(limits.is_paused(date("2024-03-01")));
+        assert!(!limits.is_paused(date("2024-03-02")));
+    }
+
+    #[test]
+    fn limits_validate() {
+        let start = date("2024-01-01");
+        assert!(RecurrenceLimits::default().validate(start).is_ok());
+        assert!(RecurrenceLimits::default().is_empty());
+        let bad = [
+            RecurrenceLimits {
+                end_date: Some(date("2023-12-31")),
+                ..Default::default()
+            },
+            RecurrenceLimits {
+                max_occurrences: Some(0),
+                ..Default::default()
+            },
+            RecurrenceLimits {
+                pauses: vec![Pause {
+                    from: date("2024-02-02"),
+                    until: date("2024-02-01"),
+                }],
+                ..Default::default()
+            },
+        ];
+        for limits in bad {
+            assert!(limits.validate(start).is_err(), "{limits:?}");
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ledger::LedgerAccounts;
+use crate::migrate::LEGACY_CURRENCY;
+use crate::money::Currency;
+use crate::reconcile::BankProfile;
+
+/// User preferences stored with the data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Currency totals and forecasts are converted into.
+    pub reporting_currency: Currency,
+    /// Minutes without use after which an encrypted vault locks itself;
+    /// 0 never locks.
+    pub auto_lock_minutes: u32,
+    /// Days a deleted bill stays in the trash before it is purged; 0 keeps
+    /// it until purged by hand.
+    pub trash_retention_days: u32,
+    /// Days before a due date to be reminded of a bill; 0 reminds on the
+    /// day itself.
+    pub reminder_days: u32,
+    /// Accounts of the plain-text accounting export.
+    pub ledger_accounts: LedgerAccounts,
+    /// Saved column layouts of bank CSV exports.
+    pub bank_profiles: Vec<BankProfile>,
+    /// Bills confirmed for bank payees, by [`crate::bank::payee_key`].
+    pub payee_bills: BTreeMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            reporting_currency: LEGACY_CURRENCY,
+            auto_lock_minutes: 15,
+            trash_retention_days: 30,
+            reminder_days: 0,
+            ledger_accounts: LedgerAccounts::default(),
+            bank_profiles: Vec::new(),
+            payee_bills: BTreeMap::new(),
+        }
+    }
+}
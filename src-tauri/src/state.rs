@@ -0,0 +1,738 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+
+use crate::backup::{self, Backup, Backups};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::history::ChangeSource;
+use crate::integrity::{IntegrityReport, Quarantine, QuarantinedRecord, RepairRequest};
+use crate::journal::JournalStatus;
+use crate::profile::{Profile, ProfileList, Profiles};
+use crate::store::{self, DataFiles, SqliteStore, Store};
+use crate::vault::{VaultStatus, VaultStore};
+
+struct Inner {
+    files: DataFiles,
+    store: Store,
+    /// `None` while the vault is locked.
+    data: Option<Data>,
+    backups: Backups,
+    last_used: Instant,
+}
+
+impl Inner {
+    /// Opens the data in `files`. An encrypted vault starts out locked.
+    fn open(files: DataFiles) -> AppResult<Self> {
+        let mut store = Store::open(&files)?;
+        let data = store.load()?;
+        let mut inner = Self {
+            backups: Backups::new(files.backups()),
+            files,
+            store,
+            data,
+            last_used: Instant::now(),
+        };
+        // Not fatal: the data is fine, and the purge is retried periodically
+        let _ = inner.purge_expired_trash(Utc::now());
+        Ok(inner)
+    }
+
+    fn data(&self) -> AppResult<&Data> {
+        self.data.as_ref().ok_or(AppError::Locked)
+    }
+
+    fn vault(&mut self) -> AppResult<&mut VaultStore> {
+        match &mut self.store {
+            Store::Vault(vault) => Ok(vault),
+            Store::Database(_) => Err(AppError::validation("Encryption is not turned on")),
+        }
+    }
+
+    fn lock_vault(&mut self) {
+        if let Store::Vault(vault) = &mut self.store {
+            vault.lock();
+            self.data = None;
+        }
+    }
+
+    /// Locks an unlocked vault that has not been used for the auto-lock
+    /// period.
+    fn expire(&mut self, now: Instant) {
+        let minutes = self
+            .data
+            .as_ref()
+            .map_or(0, |d| d.settings.auto_lock_minutes);
+        let idle = now.saturating_duration_since(self.last_used);
+        if minutes > 0 && idle >= Duration::from_secs(u64::from(minutes) * 60) {
+            self.lock_vault();
+        }
+    }
+
+    /// Purges trashed bills past their retention period. Not journaled, as
+    /// there is nothing to undo it to.
+    fn purge_expired_trash(&mut self, now: DateTime<Utc>) -> AppResult<()> {
+        let Some(data) = self.data.as_ref().filter(|d| d.has_expired_trash(now)) else {
+            return Ok(());
+        };
+        let mut draft = data.clone();
+        draft.purge_expired_trash(now);
+        self.store.save(data, &draft)?;
+        self.data = Some(draft);
+        Ok(())
+    }
+
+    fn quarantine(&self) -> Quarantine {
+        Quarantine::new(self.files.quarantine())
+    }
+
+    /// Counts a command as use of the data, unless the vault expired first.
+    fn touch(&mut self, now: Instant) {
+        self.expire(now);
+        self.last_used = now;
+    }
+}
+
+/// Why the data could not be opened when the app started, managed in place
+/// of the [`AppState`] so the UI can show it rather than the app crashing.
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl From<AppError> for StartupError {
+    fn from(err: AppError) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Managed Tauri state: the profiles, and the data of the open one plus
+/// the store it is saved to and the snapshots taken of it.
+pub struct AppState {
+    /// Locked before `inner` when both are needed.
+    profiles: Mutex<Profiles>,
+    inner: Mutex<Inner>,
+}
+
+impl AppState {
+    /// Opens the profile that was open last in the app data directory
+    /// `root`. An encrypted vault starts out locked.
+    pub fn open(root: impl Into<PathBuf>) -> AppResult<Self> {
+        let profiles = Profiles::open(root, Utc::now())?;
+        let inner = Inner::open(profiles.active_files())?;
+        Ok(Self {
+            profiles: Mutex::new(profiles),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// A panic inside a command cannot leave `data` half-updated (see
+    /// [`AppState::write`]), so a poisoned lock is safe to keep using.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Profiles only change once saved, so a poisoned lock is safe too.
+    fn profiles(&self) -> MutexGuard<'_, Profiles> {
+        self.profiles.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn list_profiles(&self) -> ProfileList {
+        self.profiles().list().clone()
+    }
+
+    pub fn create_profile(&self, name: &str) -> AppResult<Profile> {
+        self.profiles().create(name, Utc::now())
+    }
+
+    pub fn rename_profile(&self, id: &str, name: &str) -> AppResult<Profile> {
+        self.profiles().rename(id, name)
+    }
+
+    pub fn delete_profile(&self, id: &str) -> AppResult<Profile> {
+        self.profiles().delete(id)
+    }
+
+    /// Closes the open profile and opens `id` in its place, locked if its
+    /// data is encrypted. The open profile stays open if `id` fails to load.
+    pub fn switch_profile(&self, id: &str) -> AppResult<ProfileList> {
+        let mut profiles = self.profiles();
+        let opened = Inner::open(profiles.files(id)?)?;
+        profiles.set_active(id)?;
+        *self.lock() = opened;
+        Ok(profiles.list().clone())
+    }
+
+    pub fn read<T>(&self, f: impl FnOnce(&Data) -> AppResult<T>) -> AppResult<T> {
+        let mut inner = self.lock();
+        inner.touch(Instant::now());
+        f(inner.data()?)
+    }
+
+    /// Applies `f` to a copy of the data, journals the change under `label`
+    /// so it can be undone, and persists it. The in-memory data only changes
+    /// once the save succeeded, so a failed write leaves the app consistent
+    /// with what is on disk. The first change of a day snapshots the data
+    /// beforehand.
+    pub fn write<T>(&self, label: &str, f: impl FnOnce(&mut Data) -> AppResult<T>) -> AppResult<T> {
+        self.commit(Some(label), ChangeSource::Ui, |data, _| f(data))
+    }
+
+    /// [`AppState::write`] for changes that did not come from editing in
+    /// the app, so bill history shows where they came from.
+    pub fn write_as<T>(
+        &self,
+        source: ChangeSource,
+        label: &str,
+        f: impl FnOnce(&mut Data) -> AppResult<T>,
+    ) -> AppResult<T> {
+        self.commit(Some(label), source, |data, _| f(data))
+    }
+
+    pub fn journal_status(&self) -> AppResult<JournalStatus> {
+        self.read(|data| Ok(data.journal_status()))
+    }
+
+    /// Reverts the latest change and returns what it was.
+    pub fn undo(&self) -> AppResult<String> {
+        self.commit(None, ChangeSource::Undo, |data, _| data.undo())
+    }
+
+    /// Re-applies the latest undone change and returns what it was.
+    pub fn redo(&self) -> AppResult<String> {
+        self.commit(None, ChangeSource::Redo, |data, _| data.redo())
+    }
+
+    /// Permanently deletes trashed bills. Not recorded for undo, since that
+    /// would keep the purged bills around.
+    pub fn purge_trash(&self, id: Option<&str>) -> AppResult<usize> {
+        self.commit(None, ChangeSource::Ui, |data, _| data.purge_trash(id))
+    }
+
+    /// Applies the repairs picked from an integrity report, moving the
+    /// records they take out to the quarantine file, and checks again.
+    pub fn repair_integrity(&self, requests: &[RepairRequest]) -> AppResult<IntegrityReport> {
+        self.commit(Some("Repair data"), ChangeSource::Ui, |data, inner| {
+            let quarantined = data.repair(requests, Utc::now())?;
+            inner.quarantine().append(&quarantined, inner.store.key())?;
+            Ok(data.check_integrity())
+        })
+    }
+
+    /// [`AppState::write`], journaling the change only if it has a `label`.
+    /// `f` also gets the state, for files kept next to the data.
+    fn commit<T>(
+        &self,
+        label: Option<&str>,
+        source: ChangeSource,
+        f: impl FnOnce(&mut Data, &Inner) -> AppResult<T>,
+    ) -> AppResult<T> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        inner.touch(Instant::now());
+        let data = inner.data.as_ref().ok_or(AppError::Locked)?;
+        let mut draft = data.clone();
+        let out = f(&mut draft, inner)?;
+        let now = Utc::now();
+        draft.record_history(data, source, now);
+        if let Some(label) = label {
+            draft.record_operation(data, label, now)?;
+        }
+        inner
+            .backups
+            .snapshot_if_due(data, Local::now().date_naive(), inner.store.key())?;
+        inner.store.save(data, &draft)?;
+        inner.data = Some(draft);
+        Ok(out)
+    }
+
+    pub fn list_backups(&self) -> AppResult<Vec<Backup>> {
+        self.lock().backups.list()
+    }
+
+    /// Replaces all data with the backup called `name`, first saving the
+    /// current data as a backup of its own. Records of the backup that
+    /// cannot be read go to the quarantine file.
+    pub fn restore_backup(&self, name: &str, now: DateTime<Utc>) -> AppResult<()> {
+        self.restore(now, |inner| inner.backups.read(name, inner.store.key()))
+    }
+
+    /// Replaces all data with `restored`, e.g. from an archive, first saving
+    /// the current data as a backup.
+    pub fn restore_data(&self, restored: Data, now: DateTime<Utc>) -> AppResult<()> {
+        self.restore(now, |_| Ok((restored, Vec::new())))
+    }
+
+    fn restore(
+        &self,
+        now: DateTime<Utc>,
+        read: impl FnOnce(&Inner) -> AppResult<(Data, Vec<QuarantinedRecord>)>,
+    ) -> AppResult<()> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        inner.touch(Instant::now());
+        let data = inner.data.as_ref().ok_or(AppError::Locked)?;
+        let key = inner.store.key();
+        let (restored, quarantined) = read(inner)?;
+        inner.backups.snapshot_before_restore(data, now, key)?;
+        inner.quarantine().append(&quarantined, key)?;
+        inner.store.save(data, &restored)?;
+        inner.data = Some(restored);
+        Ok(())
+    }
+
+    /// Polled by the UI, so it does not count as use.
+    pub fn vault_status(&self) -> VaultStatus {
+        let mut inner = self.lock();
+        inner.expire(Instant::now());
+        VaultStatus {
+            enabled: matches!(inner.store, Store::Vault(_)),
+            locked: inner.data.is_none(),
+        }
+    }
+
+    pub fn unlock_vault(&self, passphrase: &str) -> AppResult<()> {
+        let mut inner = self.lock();
+        if inner.data.is_some() {
+            return Ok(());
+        }
+        let quarantine = inner.quarantine();
+        let data = inner.vault()?.unlock(passphrase, &quarantine)?;
+        inner.data = Some(data);
+        inner.last_used = Instant::now();
+        let _ = inner.purge_expired_trash(Utc::now());
+        Ok(())
+    }
+
+    pub fn lock_vault(&self) -> AppResult<()> {
+        let mut inner = self.lock();
+        inner.vault()?;
+        inner.lock_vault();
+        Ok(())
+    }
+
+    /// Locks the vault if it has been idle for the auto-lock period. Called
+    /// periodically so the data does not stay in memory until the next use.
+    pub fn lock_if_idle(&self) {
+        self.lock().expire(Instant::now());
+    }
+
+    /// Purges trashed bills past their retention period. Called
+    /// periodically, without counting as use of the data.
+    pub fn purge_expired_trash(&self, now: DateTime<Utc>) -> AppResult<()> {
+        self.lock().purge_expired_trash(now)
+    }
+
+    /// Moves the data from the database into a vault encrypted with
+    /// `passphrase`, encrypts the backups and deletes the plaintext files.
+    pub fn enable_vault(&self, passphrase: &str) -> AppResult<()> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        if matches!(inner.store, Store::Vault(_)) {
+            return Err(AppError::validation("Encryption is already turned on"));
+        }
+        let data = inner.data()?;
+        let vault = VaultStore::create(inner.files.vault(), passphrase, data)?;
+        // Dropping the database closes it, so its files can be removed
+        inner.store = Store::Vault(vault);
+        inner.last_used = Instant::now();
+        inner.backups.reseal_all(None, inner.store.key())?;
+        inner.quarantine().reseal(None, inner.store.key())?;
+        inner.files.remove_plaintext()
+    }
+
+    /// Moves the data from the vault back into a plain database.
+    pub fn disable_vault(&self, passphrase: &str) -> AppResult<()> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        inner.vault()?.verify(passphrase)?;
+        let data = inner.data()?;
+        let mut db = SqliteStore::open(&inner.files)?;
+        let initial = db.load(inner.store.key())?;
+        db.save(&initial, data)?;
+        inner.backups.reseal_all(inner.store.key(), None)?;
+        inner.quarantine().reseal(inner.store.key(), None)?;
+        let vault = std::mem::replace(&mut inner.store, Store::Database(db));
+        if let Store::Vault(vault) = vault {
+            store::remove_file(vault.path())?;
+        }
+        Ok(())
+    }
+
+    /// Re-encrypts the vault, its backups and the quarantine under
+    /// `new_passphrase`. Every file is written under the new key before any
+    /// is replaced, and if one cannot be replaced those that were are put
+    /// back, so a failure leaves everything readable with the old passphrase.
+    pub fn change_passphrase(&self, old_passphrase: &str, new_passphrase: &str) -> AppResult<()> {
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        inner.vault()?.verify(old_passphrase)?;
+        let data = inner.data.as_ref().ok_or(AppError::Locked)?;
+        let Store::Vault(vault) = &mut inner.store else {
+            return Err(AppError::validation("Encryption is not turned on"));
+        };
+        let (new_key, vault_file) = vault.stage_passphrase(new_passphrase, data)?;
+        let old_key = vault.key();
+        // The vault goes first, so after a crash midway the data itself
+        // opens with the new passphrase
+        let mut staged = vec![vault_file];
+        staged.extend(inner.backups.stage_reseal(old_key, Some(&new_key))?);
+        staged.extend(
+            Quarantine::new(inner.files.quarantine()).stage_reseal(old_key, Some(&new_key))?,
+        );
+        backup::commit_all(staged)?;
+        vault.rekey(new_key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use std::fs;
+
+    use super::*;
+    use crate::integrity::Repair;
+    use crate::testing::{add_bill, usd};
+    use crate::vault;
+
+    fn open(dir: &TempDir) -> AppState {
+        AppState::open(dir.path()).unwrap()
+    }
+
+    fn bill_names(state: &AppState) -> Vec<String> {
+        state
+            .read(|data| Ok(data.bills.iter().map(|b| b.name.clone()).collect()))
+            .unwrap()
+    }
+
+    #[test]
+    fn restoring_keeps_a_copy_of_the_replaced_data() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        // The first change of the day snapshots the data without this bill
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        let daily = state
+            .list_backups()
+            .unwrap()
+            .into_iter()
+            .find(|b| b.kind == crate::backup::BackupKind::Daily)
+            .unwrap();
+
+        let now = "2024-01-05T12:00:00Z".parse().unwrap();
+        state.restore_backup(&daily.name, now).unwrap();
+        assert!(bill_names(&state).is_empty());
+        let later = "2024-01-05T12:05:00Z".parse().unwrap();
+        state
+            .restore_backup("before-restore-20240105T120000.json", later)
+            .unwrap();
+        assert_eq!(bill_names(&state), ["Rent"]);
+
+        assert!(state
+            .restore_backup("daily-1999-01-01.json", later)
+            .is_err());
+        drop(state);
+        // The restored data is what the database holds
+        assert_eq!(bill_names(&open(&dir)), ["Rent"]);
+    }
+
+    #[test]
+    fn failed_changes_are_not_applied() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        let err = state
+            .write("Add bill", |data| {
+                add_bill(data, "Rent", 120_000, "2024-01-31");
+                Err::<(), _>(crate::error::AppError::validation("no"))
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no");
+        assert!(bill_names(&state).is_empty());
+    }
+
+    /// Data files of the open profile.
+    fn files(state: &AppState) -> DataFiles {
+        state.lock().files.clone()
+    }
+
+    fn backup_bytes(state: &AppState) -> Vec<Vec<u8>> {
+        let mut files: Vec<_> = fs::read_dir(files(state).backups())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        files.iter().map(|p| fs::read(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn vault_encrypts_the_data_and_its_backups() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        state.enable_vault("correct horse").unwrap();
+        assert!(!files(&state).database().exists());
+        assert!(backup_bytes(&state).iter().all(|b| vault::is_sealed(b)));
+        assert!(state.enable_vault("correct horse").is_err());
+
+        state.lock_vault().unwrap();
+        let status = state.vault_status();
+        assert!(status.enabled && status.locked);
+        assert_eq!(state.read(|_| Ok(())).unwrap_err().code(), "locked");
+        assert_eq!(
+            state.unlock_vault("battery staple").unwrap_err().code(),
+            "permission"
+        );
+        state.unlock_vault("correct horse").unwrap();
+        assert_eq!(bill_names(&state), ["Rent"]);
+        drop(state);
+
+        // A restart starts out locked
+        let state = open(&dir);
+        assert!(state.vault_status().locked);
+        state.unlock_vault("correct horse").unwrap();
+        assert_eq!(bill_names(&state), ["Rent"]);
+    }
+
+    #[test]
+    fn passphrase_changes_and_disabling_rewrite_the_backups() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        state.enable_vault("correct horse").unwrap();
+        assert_eq!(
+            state
+                .change_passphrase("wrong one", "battery staple")
+                .unwrap_err()
+                .code(),
+            "permission"
+        );
+        state
+            .change_passphrase("correct horse", "battery staple")
+            .unwrap();
+        state.lock_vault().unwrap();
+        assert!(state.unlock_vault("correct horse").is_err());
+        state.unlock_vault("battery staple").unwrap();
+        let daily = state.list_backups().unwrap()[0].name.clone();
+        state.restore_backup(&daily, Utc::now()).unwrap();
+
+        assert!(state.disable_vault("correct horse").is_err());
+        state.disable_vault("battery staple").unwrap();
+        assert!(!files(&state).vault().exists());
+        assert!(backup_bytes(&state).iter().all(|b| !vault::is_sealed(b)));
+        assert!(!state.vault_status().enabled);
+        assert_eq!(state.lock_vault().unwrap_err().code(), "validation");
+        drop(state);
+        assert!(bill_names(&open(&dir)).is_empty());
+    }
+
+    #[test]
+    fn idle_vaults_lock_themselves() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state.enable_vault("correct horse").unwrap();
+        let mut inner = state.lock();
+        let start = inner.last_used;
+        let minute = Duration::from_secs(60);
+        // Use within the period pushes the deadline back
+        inner.touch(start + 14 * minute);
+        inner.expire(start + 15 * minute);
+        assert!(inner.data.is_some());
+        inner.expire(start + 29 * minute);
+        assert!(inner.data.is_none());
+
+        // Zero minutes turns auto-lock off
+        drop(inner);
+        state.unlock_vault("correct horse").unwrap();
+        state
+            .write("Change settings", |data| {
+                data.settings.auto_lock_minutes = 0;
+                Ok(())
+            })
+            .unwrap();
+        let mut inner = state.lock();
+        let later = inner.last_used + 1000 * minute;
+        inner.expire(later);
+        assert!(inner.data.is_some());
+    }
+
+    #[test]
+    fn undo_and_redo_are_saved() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        let id = state.read(|data| Ok(data.bills[0].id.clone())).unwrap();
+        state
+            .write("Delete bill", |data| data.delete_bill(&id, Utc::now()))
+            .unwrap();
+        assert_eq!(state.undo().unwrap(), "Delete bill");
+        assert_eq!(bill_names(&state), ["Rent"]);
+        let status = state.journal_status().unwrap();
+        assert_eq!(
+            (status.undo.as_deref(), status.redo.as_deref()),
+            (Some("Add bill"), Some("Delete bill"))
+        );
+        drop(state);
+
+        let state = open(&dir);
+        assert_eq!(bill_names(&state), ["Rent"]);
+        assert_eq!(state.redo().unwrap(), "Delete bill");
+        assert!(bill_names(&state).is_empty());
+    }
+
+    #[test]
+    fn history_shows_where_changes_came_from() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        let rent = state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        state
+            .write_as(ChangeSource::Import, "Import bills", |data| {
+                data.bills[0].amount = usd(125_000);
+                Ok(())
+            })
+            .unwrap();
+        state.undo().unwrap();
+
+        let sources: Vec<ChangeSource> = state
+            .read(|data| data.bill_history(&rent.id))
+            .unwrap()
+            .iter()
+            .map(|e| e.source)
+            .collect();
+        assert_eq!(
+            sources,
+            [ChangeSource::Undo, ChangeSource::Import, ChangeSource::Ui]
+        );
+    }
+
+    #[test]
+    fn repairs_move_records_to_the_quarantine_file() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                let rent = add_bill(data, "Rent", 120_000, "2024-01-31");
+                data.bills.push(rent);
+                Ok(())
+            })
+            .unwrap();
+        let issue = state
+            .read(|data| Ok(data.check_integrity().issues))
+            .unwrap()
+            .remove(0);
+        let request = RepairRequest {
+            collection: issue.collection,
+            index: issue.index,
+            record_id: issue.record_id,
+            repair: Repair::Quarantine,
+        };
+        let report = state.repair_integrity(&[request]).unwrap();
+        assert!(report.issues.is_empty());
+        assert_eq!(bill_names(&state), ["Rent"]);
+
+        let path = files(&state).quarantine();
+        let quarantined = Quarantine::new(&path).read(None).unwrap();
+        assert_eq!(quarantined[0].collection, "bills");
+        state.enable_vault("correct horse").unwrap();
+        assert!(vault::is_sealed(&fs::read(&path).unwrap()));
+    }
+
+    #[test]
+    fn profiles_keep_their_data_apart() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        let rental = state.create_profile("Rental").unwrap();
+        let list = state.switch_profile(&rental.id).unwrap();
+        assert_eq!(list.active, rental.id);
+        assert!(bill_names(&state).is_empty());
+        assert_eq!(
+            state.delete_profile(&rental.id).unwrap_err().code(),
+            "validation"
+        );
+        drop(state);
+
+        // The last open profile opens on the next start
+        let state = open(&dir);
+        assert!(bill_names(&state).is_empty());
+        state.switch_profile("personal").unwrap();
+        assert_eq!(bill_names(&state), ["Rent"]);
+        assert_eq!(
+            state.switch_profile("gone").unwrap_err().code(),
+            "not_found"
+        );
+        assert_eq!(bill_names(&state), ["Rent"]);
+    }
+
+    #[test]
+    fn restored_archives_replace_the_data() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Rent", 120_000, "2024-01-31"))
+            })
+            .unwrap();
+        let archive = state.read(|data| data.export_archive(Utc::now())).unwrap();
+        state
+            .write("Add bill", |data| {
+                Ok(add_bill(data, "Water", 4_000, "2024-01-15"))
+            })
+            .unwrap();
+
+        let now = "2024-01-05T12:00:00Z".parse().unwrap();
+        let archive = crate::archive::read_archive(&archive).unwrap();
+        state.restore_data(archive.data, now).unwrap();
+        assert_eq!(bill_names(&state), ["Rent"]);
+        state
+            .restore_backup("before-restore-20240105T120000.json", now)
+            .unwrap();
+        assert_eq!(bill_names(&state), ["Rent", "Water"]);
+    }
+
+    #[test]
+    fn data_that_cannot_open_reports_why() {
+        let dir = TempDir::new().unwrap();
+        let state = open(&dir);
+        let database = files(&state).database();
+        drop(state);
+        let conn = rusqlite::Connection::open(database).unwrap();
+        conn.pragma_update(None, "user_version", 999).unwrap();
+        drop(conn);
+
+        let err = StartupError::from(AppState::open(dir.path()).err().unwrap());
+        assert_eq!(err.code, "newer_version");
+        assert!(err.message.contains("newer version"), "{}", err.message);
+    }
+}
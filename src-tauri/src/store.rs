@@ -0,0 +1,1332 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+use rusqlite::{params, Connection, Row, ToSql, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::bill::Bill;
+use crate::category::Category;
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::fx::ExchangeRate;
+use crate::history::HistoryEntry;
+use crate::integrity::{Quarantine, QuarantinedRecord};
+use crate::journal::Operation;
+use crate::migrate;
+use crate::money::{Currency, Money};
+use crate::payment::Payment;
+use crate::settings::Settings;
+use crate::trash::TrashedBill;
+use crate::vault::{VaultKey, VaultStore};
+
+/// File name of the bill database.
+const DATABASE_FILE: &str = "bills.db";
+/// Data file of earlier versions, imported when the database is created.
+const LEGACY_JSON_FILE: &str = "bills.json";
+/// Encrypted replacement of the database in vault mode.
+const VAULT_FILE: &str = "bills.vault";
+/// Directory of the automatic snapshots.
+const BACKUP_DIR: &str = "backups";
+/// Records set aside by the integrity check or a lenient import.
+const QUARANTINE_FILE: &str = "quarantine.json";
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have run, so only append to this list and never edit a released entry.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE bills (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        amount_minor INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        due_date TEXT NOT NULL,
+        recurrence TEXT NOT NULL,
+        start_date TEXT,
+        limits TEXT NOT NULL,
+        category_id TEXT,
+        tags TEXT NOT NULL,
+        notes TEXT,
+        paid INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX bills_due_date ON bills (due_date);
+    CREATE INDEX bills_category_id ON bills (category_id);
+
+    CREATE TABLE payments (
+        id TEXT PRIMARY KEY NOT NULL,
+        bill_id TEXT NOT NULL,
+        occurrence_date TEXT NOT NULL,
+        paid_date TEXT NOT NULL,
+        amount_minor INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        method TEXT,
+        confirmation TEXT,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX payments_bill_id ON payments (bill_id, occurrence_date);
+    CREATE INDEX payments_paid_date ON payments (paid_date);
+
+    CREATE TABLE categories (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL
+    );
+
+    CREATE TABLE exchange_rates (
+        date TEXT NOT NULL,
+        base TEXT NOT NULL,
+        quote TEXT NOT NULL,
+        rate REAL NOT NULL,
+        PRIMARY KEY (date, base, quote)
+    );
+
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY NOT NULL,
+        value TEXT NOT NULL
+    );",
+    // 2: undo journal
+    "CREATE TABLE journal (
+        seq INTEGER PRIMARY KEY NOT NULL,
+        label TEXT NOT NULL,
+        at TEXT NOT NULL,
+        changes TEXT NOT NULL,
+        undone INTEGER NOT NULL
+    );",
+    // 3: trash of deleted bills, each with its payments
+    "CREATE TABLE trash (
+        id TEXT PRIMARY KEY NOT NULL,
+        bill TEXT NOT NULL,
+        payments TEXT NOT NULL,
+        deleted_at TEXT NOT NULL
+    );",
+    // 4: field-level history of bills
+    "CREATE TABLE bill_history (
+        seq INTEGER PRIMARY KEY NOT NULL,
+        bill_id TEXT NOT NULL,
+        at TEXT NOT NULL,
+        source TEXT NOT NULL,
+        event TEXT NOT NULL,
+        changes TEXT NOT NULL
+    );
+    CREATE INDEX bill_history_bill_id ON bill_history (bill_id);",
+    // 5: bank transactions matched to payments
+    "ALTER TABLE payments ADD COLUMN transaction_id TEXT;
+    CREATE INDEX payments_transaction_id ON payments (transaction_id);",
+];
+
+/// Where one set of app data keeps its files.
+#[derive(Debug, Clone)]
+pub struct DataFiles {
+    dir: PathBuf,
+}
+
+impl DataFiles {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn database(&self) -> PathBuf {
+        self.dir.join(DATABASE_FILE)
+    }
+
+    pub fn legacy_json(&self) -> PathBuf {
+        self.dir.join(LEGACY_JSON_FILE)
+    }
+
+    pub fn vault(&self) -> PathBuf {
+        self.dir.join(VAULT_FILE)
+    }
+
+    pub fn backups(&self) -> PathBuf {
+        self.dir.join(BACKUP_DIR)
+    }
+
+    pub fn quarantine(&self) -> PathBuf {
+        self.dir.join(QUARANTINE_FILE)
+    }
+
+    /// Names of the files in the directory that `matches`.
+    fn names(&self, matches: impl Fn(&str) -> bool) -> AppResult<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(AppError::io(
+                    format!("Failed to list {}", self.dir.display()),
+                    e,
+                ))
+            }
+        };
+        Ok(entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|name| matches(name))
+            .collect())
+    }
+
+    /// Deletes the unencrypted copies of the data once a vault holds it: the
+    /// database with its log, the copies taken before schema upgrades and an
+    /// imported `bills.json`. Deleting does not scrub the disk blocks.
+    pub fn remove_plaintext(&self) -> AppResult<()> {
+        for name in self.names(|name| {
+            is_database_file(name) || name == format!("{LEGACY_JSON_FILE}.imported")
+        })? {
+            remove_file(&self.dir.join(name))?;
+        }
+        Ok(())
+    }
+
+    /// Moves every file of the data into `target`, which must not have data
+    /// of its own yet.
+    pub fn move_into(&self, target: &DataFiles) -> AppResult<()> {
+        let names = self.names(|name| {
+            is_database_file(name)
+                || name.starts_with(LEGACY_JSON_FILE)
+                || [VAULT_FILE, BACKUP_DIR, QUARANTINE_FILE].contains(&name)
+        })?;
+        if names.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&target.dir)
+            .map_err(|e| AppError::io(format!("Failed to create {}", target.dir.display()), e))?;
+        for name in names {
+            let from = self.dir.join(&name);
+            fs::rename(&from, target.dir.join(&name))
+                .map_err(|e| AppError::io(format!("Failed to move {}", from.display()), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The database, its log files and the copies taken before schema upgrades.
+fn is_database_file(name: &str) -> bool {
+    name == DATABASE_FILE
+        || name.strip_prefix(DATABASE_FILE).is_some_and(|rest| {
+            matches!(rest, "-wal" | "-shm" | "-journal")
+                || (rest.starts_with(".v") && rest.ends_with(".bak"))
+        })
+}
+
+/// Deletes `path`, which may already be gone.
+pub fn remove_file(path: &Path) -> AppResult<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::io(
+            format!("Failed to delete {}", path.display()),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The backend the data is saved to: the database, or an encrypted vault
+/// file when the user has set a passphrase.
+pub enum Store {
+    Database(SqliteStore),
+    Vault(VaultStore),
+}
+
+impl Store {
+    /// Opens the vault if there is one, locked, and the database otherwise.
+    pub fn open(files: &DataFiles) -> AppResult<Self> {
+        let vault = files.vault();
+        if vault.exists() {
+            return Ok(Store::Vault(VaultStore::locked(vault)));
+        }
+        SqliteStore::open(files).map(Store::Database)
+    }
+
+    /// `None` while the vault is locked.
+    pub fn load(&mut self) -> AppResult<Option<Data>> {
+        match self {
+            Store::Database(db) => db.load(None).map(Some),
+            Store::Vault(_) => Ok(None),
+        }
+    }
+
+    pub fn save(&mut self, before: &Data, after: &Data) -> AppResult<()> {
+        match self {
+            Store::Database(db) => db.save(before, after),
+            Store::Vault(vault) => vault.save(after),
+        }
+    }
+
+    /// Key of an unlocked vault, which backups are encrypted with too.
+    pub fn key(&self) -> Option<&VaultKey> {
+        match self {
+            Store::Database(_) => None,
+            Store::Vault(vault) => vault.key(),
+        }
+    }
+}
+
+/// Persists the app data in a SQLite database, one table per kind of record.
+pub struct SqliteStore {
+    conn: Connection,
+    quarantine: Quarantine,
+}
+
+impl SqliteStore {
+    /// Opens the database in `files` and brings its schema up to date.
+    ///
+    /// An existing database is first copied to `bills.db.v<N>.bak`, `N`
+    /// being its current schema version, and one written by a newer version
+    /// of the app is refused. When the database is created, the `bills.json`
+    /// written by earlier versions is imported in the same transaction and
+    /// then renamed to `bills.json.imported`; records of it that cannot be
+    /// read go to the quarantine file. Without one the new database gets the
+    /// built-in categories.
+    pub fn open(files: &DataFiles) -> AppResult<Self> {
+        let path = &files.database();
+        let legacy_json = &files.legacy_json();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| AppError::io(format!("Failed to create {}", dir.display()), e))?;
+        }
+        let failed = |e| AppError::db(format!("Failed to open {}", path.display()), e);
+        let mut conn = Connection::open(path).map_err(failed)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .map_err(failed)?;
+        // Sync the log on every commit, so a committed change survives a
+        // power loss and not just an app crash
+        conn.pragma_update(None, "synchronous", "FULL")
+            .map_err(failed)?;
+
+        let failed = |e| AppError::db("Failed to upgrade the database", e);
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(failed)?;
+        migrate::check_version(
+            &path.display().to_string(),
+            version as u64,
+            MIGRATIONS.len() as u64,
+        )?;
+        if version > 0 && version < MIGRATIONS.len() {
+            backup_before_upgrade(&conn, path, version)?;
+        }
+
+        let tx = conn.transaction().map_err(failed)?;
+        for sql in MIGRATIONS.iter().skip(version) {
+            tx.execute_batch(sql).map_err(failed)?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())
+            .map_err(failed)?;
+        let quarantine = Quarantine::new(files.quarantine());
+        let imported = if version == 0 {
+            let legacy = match read_json(legacy_json)? {
+                Some((data, quarantined)) => {
+                    quarantine.append(&quarantined, None)?;
+                    Some(data)
+                }
+                None => None,
+            };
+            let initial = legacy.clone().unwrap_or_else(Data::fresh);
+            write_changes(&tx, &Data::default(), &initial)
+                .map_err(|e| AppError::db("Failed to import bills", e))?;
+            legacy.is_some()
+        } else {
+            false
+        };
+        tx.commit().map_err(failed)?;
+
+        if imported {
+            // The database is authoritative from here on; a file that cannot
+            // be renamed is harmless because it is only read on creation
+            let _ = fs::rename(legacy_json, legacy_json.with_extension("json.imported"));
+        }
+        Ok(Self { conn, quarantine })
+    }
+
+    /// Reads all the data. Rows that cannot be read are moved to the
+    /// quarantine file, sealed with `key` while a vault is in use, and
+    /// deleted, so they do not keep the rest from loading.
+    pub fn load(&mut self, key: Option<&VaultKey>) -> AppResult<Data> {
+        let (data, unreadable) = read_all(&self.conn, Utc::now())
+            .map_err(|e| AppError::db("Failed to read bills", e))?;
+        if unreadable.is_empty() {
+            return Ok(data);
+        }
+        let (rows, records): (Vec<_>, Vec<_>) = unreadable
+            .into_iter()
+            .map(|row| ((row.table, row.rowid), row.record))
+            .unzip();
+        self.quarantine.append(&records, key)?;
+        let failed = |e| AppError::db("Failed to remove unreadable rows", e);
+        let tx = self.conn.transaction().map_err(failed)?;
+        for (table, rowid) in rows {
+            tx.execute(&format!("DELETE FROM {table} WHERE rowid = ?1"), [rowid])
+                .map_err(failed)?;
+        }
+        tx.commit().map_err(failed)?;
+        Ok(data)
+    }
+
+    /// Saves `after`, writing only the rows that differ from `before`, the
+    /// data as last loaded or saved.
+    pub fn save(&mut self, before: &Data, after: &Data) -> AppResult<()> {
+        let failed = |e| AppError::db("Failed to save bills", e);
+        let tx = self.conn.transaction().map_err(failed)?;
+        write_changes(&tx, before, after).map_err(failed)?;
+        tx.commit().map_err(failed)
+    }
+}
+
+/// Copies the database aside before a schema upgrade, replacing the copy left
+/// by an earlier attempt that failed (and so changed nothing).
+fn backup_before_upgrade(conn: &Connection, path: &Path, version: usize) -> AppResult<()> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{version}.bak"));
+    let backup = PathBuf::from(name);
+    remove_file(&backup)?;
+    let target = backup.to_str().ok_or_else(|| {
+        AppError::validation(format!("Unsupported file name {}", backup.display()))
+    })?;
+    conn.execute("VACUUM INTO ?1", [target])
+        .map_err(|e| AppError::db(format!("Failed to back up to {target}"), e))?;
+    Ok(())
+}
+
+/// Reads a `bills.json` document, upgrading older shapes on the way, with
+/// the records that could not be read. `None` when there is no such file.
+fn read_json(path: &Path) -> AppResult<Option<(Data, Vec<QuarantinedRecord>)>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(AppError::io(
+                format!("Failed to read {}", path.display()),
+                e,
+            ))
+        }
+    };
+    migrate::salvage_document(&path.display().to_string(), &bytes, Utc::now()).map(Some)
+}
+
+impl ToSql for Currency {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.code()))
+    }
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Currency::parse(value.as_str()?).map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// Stores a value that has no columns of its own, such as a recurrence rule,
+/// as JSON text.
+fn to_json<T: Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn json_column<T: DeserializeOwned>(row: &Row<'_>, idx: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+/// Settings as key/value rows, the value being JSON, so new settings need no
+/// schema change.
+fn settings_rows(data: &Data) -> rusqlite::Result<Vec<(String, String)>> {
+    match serde_json::to_value(&data.settings) {
+        Ok(Value::Object(map)) => Ok(map.into_iter().map(|(k, v)| (k, v.to_string())).collect()),
+        Ok(_) => Ok(Vec::new()),
+        Err(e) => Err(rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+    }
+}
+
+/// A row that could not be read, to be moved to the quarantine file.
+struct UnreadableRow {
+    table: &'static str,
+    rowid: i64,
+    record: QuarantinedRecord,
+}
+
+/// Reads tables row by row, setting aside the rows that cannot be read.
+struct RowReader<'a> {
+    conn: &'a Connection,
+    now: DateTime<Utc>,
+    unreadable: Vec<UnreadableRow>,
+}
+
+impl RowReader<'_> {
+    /// Reads the rows `sql` selects from `table`, whose last column must be
+    /// the rowid. Rows `map` fails on are set aside as records of
+    /// `collection`, the field of [`Data`] they belong to.
+    fn rows<T>(
+        &mut self,
+        table: &'static str,
+        collection: &str,
+        sql: &str,
+        map: impl Fn(&Row<'_>) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<Vec<T>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            match map(row) {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    let (rowid, record) = raw_row(row)?;
+                    self.unreadable.push(UnreadableRow {
+                        table,
+                        rowid,
+                        record: QuarantinedRecord {
+                            collection: collection.to_string(),
+                            reason: e.to_string(),
+                            quarantined_at: self.now,
+                            record,
+                        },
+                    });
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// The rowid, the last column, and the other columns of `row` as a JSON
+/// object by column name.
+fn raw_row(row: &Row<'_>) -> rusqlite::Result<(i64, Value)> {
+    let names = row.as_ref().column_names();
+    let columns = &names[..names.len().saturating_sub(1)];
+    let mut record = Map::new();
+    for (idx, name) in columns.iter().enumerate() {
+        let value = match row.get_ref(idx)? {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(i) => i.into(),
+            ValueRef::Real(f) => f.into(),
+            ValueRef::Text(t) | ValueRef::Blob(t) => String::from_utf8_lossy(t).into(),
+        };
+        record.insert(name.to_string(), value);
+    }
+    Ok((row.get(columns.len())?, Value::Object(record)))
+}
+
+fn read_all(conn: &Connection, now: DateTime<Utc>) -> rusqlite::Result<(Data, Vec<UnreadableRow>)> {
+    let mut reader = RowReader {
+        conn,
+        now,
+        unreadable: Vec::new(),
+    };
+    let bills = reader.rows(
+        "bills",
+        "bills",
+        "SELECT id, name, amount_minor, currency, due_date, recurrence, start_date, limits,
+                category_id, tags, notes, paid, created_at, updated_at, rowid
+         FROM bills ORDER BY rowid",
+        |row| {
+            Ok(Bill {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                amount: Money::new(row.get(2)?, row.get(3)?),
+                due_date: row.get(4)?,
+                recurrence: json_column(row, 5)?,
+                start_date: row.get(6)?,
+                limits: json_column(row, 7)?,
+                category_id: row.get(8)?,
+                tags: json_column(row, 9)?,
+                notes: row.get(10)?,
+                paid: row.get(11)?,
+                created_at: row.get(12)?,
+                updated_at: row.get(13)?,
+            })
+        },
+    )?;
+
+    let payments = reader.rows(
+        "payments",
+        "payments",
+        "SELECT id, bill_id, occurrence_date, paid_date, amount_minor, currency, method,
+                confirmation, created_at, transaction_id, rowid
+         FROM payments ORDER BY rowid",
+        |row| {
+            Ok(Payment {
+                id: row.get(0)?,
+                bill_id: row.get(1)?,
+                occurrence_date: row.get(2)?,
+                paid_date: row.get(3)?,
+                amount: Money::new(row.get(4)?, row.get(5)?),
+                method: row.get(6)?,
+                confirmation: row.get(7)?,
+                transaction_id: row.get(9)?,
+                created_at: row.get(8)?,
+            })
+        },
+    )?;
+
+    let categories = reader.rows(
+        "categories",
+        "categories",
+        "SELECT id, name, rowid FROM categories ORDER BY rowid",
+        |row| {
+            Ok(Category {
+                id: row.get(0)?,
+                name: row.get(1)?,
+            })
+        },
+    )?;
+
+    let rates = reader.rows(
+        "exchange_rates",
+        "rates",
+        "SELECT date, base, quote, rate, rowid FROM exchange_rates ORDER BY date, base, quote",
+        |row| {
+            Ok(ExchangeRate {
+                date: row.get(0)?,
+                base: row.get(1)?,
+                quote: row.get(2)?,
+                rate: row.get(3)?,
+            })
+        },
+    )?;
+
+    // Each setting is checked on its own, so one bad value only loses that
+    // setting
+    let settings: Map<String, Value> = reader
+        .rows(
+            "settings",
+            "settings",
+            "SELECT key, value, rowid FROM settings ORDER BY rowid",
+            |row| {
+                let key: String = row.get(0)?;
+                let value: Value = json_column(row, 1)?;
+                let single = Value::Object(Map::from_iter([(key.clone(), value.clone())]));
+                serde_json::from_value::<Settings>(single).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e))
+                })?;
+                Ok((key, value))
+            },
+        )?
+        .into_iter()
+        .collect();
+    let settings = serde_json::from_value(Value::Object(settings))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))?;
+
+    let trash = reader.rows(
+        "trash",
+        "trash",
+        "SELECT bill, payments, deleted_at, rowid FROM trash ORDER BY rowid",
+        |row| {
+            Ok(TrashedBill {
+                bill: json_column(row, 0)?,
+                payments: json_column(row, 1)?,
+                deleted_at: row.get(2)?,
+            })
+        },
+    )?;
+
+    let history = reader.rows(
+        "bill_history",
+        "history",
+        "SELECT seq, bill_id, at, source, event, changes, rowid FROM bill_history ORDER BY seq",
+        |row| {
+            Ok(HistoryEntry {
+                seq: row.get(0)?,
+                bill_id: row.get(1)?,
+                at: row.get(2)?,
+                source: json_column(row, 3)?,
+                event: json_column(row, 4)?,
+                changes: json_column(row, 5)?,
+            })
+        },
+    )?;
+
+    let journal = reader.rows(
+        "journal",
+        "journal",
+        "SELECT seq, label, at, changes, undone, rowid FROM journal ORDER BY seq",
+        |row| {
+            Ok(Operation {
+                seq: row.get(0)?,
+                label: row.get(1)?,
+                at: row.get(2)?,
+                changes: json_column(row, 3)?,
+                undone: row.get(4)?,
+            })
+        },
+    )?;
+
+    let data = Data {
+        bills,
+        payments,
+        categories,
+        rates,
+        settings,
+        trash,
+        history,
+        journal,
+    };
+    Ok((data, reader.unreadable))
+}
+
+/// Deletes the records of `before` missing from `after` and upserts those
+/// that are new or changed, matching records by `key`. New records get the
+/// next rowids, so when one is put back between existing ones (by undo) the
+/// whole table is rewritten to keep the order.
+fn sync<T: PartialEq, K: Eq + Hash>(
+    before: &[T],
+    after: &[T],
+    key: impl Fn(&T) -> K,
+    mut delete: impl FnMut(&K) -> rusqlite::Result<()>,
+    mut upsert: impl FnMut(&T) -> rusqlite::Result<()>,
+) -> rusqlite::Result<()> {
+    let mut old: HashMap<K, &T> = before.iter().map(|r| (key(r), r)).collect();
+    let appended = after
+        .iter()
+        .position(|r| !old.contains_key(&key(r)))
+        .is_none_or(|first_new| {
+            after[first_new..]
+                .iter()
+                .all(|r| !old.contains_key(&key(r)))
+        });
+    let kept: HashSet<K> = after.iter().map(&key).collect();
+    for k in old.keys().filter(|k| !appended || !kept.contains(*k)) {
+        delete(k)?;
+    }
+    if !appended {
+        old.clear();
+    }
+    for record in after {
+        if old.get(&key(record)) != Some(&record) {
+            upsert(record)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_changes(tx: &Transaction<'_>, before: &Data, after: &Data) -> rusqlite::Result<()> {
+    sync(
+        &before.bills,
+        &after.bills,
+        |b| b.id.clone(),
+        |id| {
+            tx.prepare_cached("DELETE FROM bills WHERE id = ?1")?
+                .execute([id])
+                .map(drop)
+        },
+        |b| {
+            // An upsert rather than a replace keeps the rowid, and with it
+            // the list order
+            tx.prepare_cached(
+                "INSERT INTO bills (id, name, amount_minor, currency, due_date, recurrence,
+                                    start_date, limits, category_id, tags, notes, paid,
+                                    created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT (id) DO UPDATE SET
+                    name = excluded.name, amount_minor = excluded.amount_minor,
+                    currency = excluded.currency, due_date = excluded.due_date,
+                    recurrence = excluded.recurrence, start_date = excluded.start_date,
+                    limits = excluded.limits, category_id = excluded.category_id,
+                    tags = excluded.tags, notes = excluded.notes, paid = excluded.paid,
+                    created_at = excluded.created_at, updated_at = excluded.updated_at",
+            )?
+            .execute(params![
+                b.id,
+                b.name,
+                b.amount.minor,
+                b.amount.currency,
+                b.due_date,
+                to_json(&b.recurrence)?,
+                b.start_date,
+                to_json(&b.limits)?,
+                b.category_id,
+                to_json(&b.tags)?,
+                b.notes,
+                b.paid,
+                b.created_at,
+                b.updated_at,
+            ])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.payments,
+        &after.payments,
+        |p| p.id.clone(),
+        |id| {
+            tx.prepare_cached("DELETE FROM payments WHERE id = ?1")?
+                .execute([id])
+                .map(drop)
+        },
+        |p| {
+            tx.prepare_cached(
+                "INSERT INTO payments (id, bill_id, occurrence_date, paid_date, amount_minor,
+                                       currency, method, confirmation, created_at, transaction_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT (id) DO UPDATE SET
+                    bill_id = excluded.bill_id, occurrence_date = excluded.occurrence_date,
+                    paid_date = excluded.paid_date, amount_minor = excluded.amount_minor,
+                    currency = excluded.currency, method = excluded.method,
+                    confirmation = excluded.confirmation, created_at = excluded.created_at,
+                    transaction_id = excluded.transaction_id",
+            )?
+            .execute(params![
+                p.id,
+                p.bill_id,
+                p.occurrence_date,
+                p.paid_date,
+                p.amount.minor,
+                p.amount.currency,
+                p.method,
+                p.confirmation,
+                p.created_at,
+                p.transaction_id,
+            ])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.categories,
+        &after.categories,
+        |c| c.id.clone(),
+        |id| {
+            tx.prepare_cached("DELETE FROM categories WHERE id = ?1")?
+                .execute([id])
+                .map(drop)
+        },
+        |c| {
+            tx.prepare_cached(
+                "INSERT INTO categories (id, name) VALUES (?1, ?2)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+            )?
+            .execute(params![c.id, c.name])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.rates,
+        &after.rates,
+        |r| (r.date, r.base, r.quote),
+        |(date, base, quote)| {
+            tx.prepare_cached(
+                "DELETE FROM exchange_rates WHERE date = ?1 AND base = ?2 AND quote = ?3",
+            )?
+            .execute(params![date, base, quote])
+            .map(drop)
+        },
+        |r| {
+            tx.prepare_cached(
+                "INSERT INTO exchange_rates (date, base, quote, rate) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (date, base, quote) DO UPDATE SET rate = excluded.rate",
+            )?
+            .execute(params![r.date, r.base, r.quote, r.rate])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &settings_rows(before)?,
+        &settings_rows(after)?,
+        |(key, _)| key.clone(),
+        |key| {
+            tx.prepare_cached("DELETE FROM settings WHERE key = ?1")?
+                .execute([key])
+                .map(drop)
+        },
+        |(key, value)| {
+            tx.prepare_cached(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            )?
+            .execute(params![key, value])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.trash,
+        &after.trash,
+        |t| t.id().to_string(),
+        |id| {
+            tx.prepare_cached("DELETE FROM trash WHERE id = ?1")?
+                .execute([id])
+                .map(drop)
+        },
+        |t| {
+            tx.prepare_cached(
+                "INSERT INTO trash (id, bill, payments, deleted_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    bill = excluded.bill, payments = excluded.payments,
+                    deleted_at = excluded.deleted_at",
+            )?
+            .execute(params![
+                t.id(),
+                to_json(&t.bill)?,
+                to_json(&t.payments)?,
+                t.deleted_at
+            ])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.history,
+        &after.history,
+        |e| e.seq,
+        |seq| {
+            tx.prepare_cached("DELETE FROM bill_history WHERE seq = ?1")?
+                .execute([seq])
+                .map(drop)
+        },
+        |e| {
+            // Entries never change once recorded
+            tx.prepare_cached(
+                "INSERT OR REPLACE INTO bill_history (seq, bill_id, at, source, event, changes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                e.seq,
+                e.bill_id,
+                e.at,
+                to_json(&e.source)?,
+                to_json(&e.event)?,
+                to_json(&e.changes)?
+            ])
+            .map(drop)
+        },
+    )?;
+
+    sync(
+        &before.journal,
+        &after.journal,
+        |op| op.seq,
+        |seq| {
+            tx.prepare_cached("DELETE FROM journal WHERE seq = ?1")?
+                .execute([seq])
+                .map(drop)
+        },
+        |op| {
+            tx.prepare_cached(
+                "INSERT INTO journal (seq, label, at, changes, undone) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (seq) DO UPDATE SET
+                    label = excluded.label, at = excluded.at, changes = excluded.changes,
+                    undone = excluded.undone",
+            )?
+            .execute(params![
+                op.seq,
+                op.label,
+                op.at,
+                to_json(&op.changes)?,
+                op.undone
+            ])
+            .map(drop)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::history::ChangeSource;
+    use crate::payment::PaymentInput;
+    use crate::testing::{add_bill, date};
+
+    fn json(data: &Data) -> Value {
+        serde_json::to_value(data).unwrap()
+    }
+
+    fn user_version(store: &SqliteStore) -> usize {
+        store
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn new_databases_start_with_the_default_categories() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        let names = |data: Data| {
+            data.categories
+                .into_iter()
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+        };
+        let first = names(store.load(None).unwrap());
+        assert_eq!(first.len(), 6);
+        drop(store);
+        // Reopening neither re-runs the schema nor adds them again
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(names(store.load(None).unwrap()), first);
+    }
+
+    #[test]
+    fn imports_bills_json_once() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let doc = serde_json::json!({
+            "bills": [{
+                "id": "b1", "name": "Rent", "amount": 1200.5, "dueDate": "2024-01-31",
+                "recurrence": "monthly", "paid": false,
+                "createdAt": "2024-01-01T00:00:00Z", "updatedAt": "2024-01-01T00:00:00Z"
+            }],
+            "payments": [{
+                "id": "p1", "billId": "b1", "occurrenceDate": "2023-12-31",
+                "paidDate": "2023-12-30", "amount": 1200.5,
+                "createdAt": "2023-12-30T00:00:00Z"
+            }]
+        });
+        fs::write(files.legacy_json(), doc.to_string()).unwrap();
+
+        let data = SqliteStore::open(&files).unwrap().load(None).unwrap();
+        assert_eq!(data.bills[0].amount, Money::new(120_050, Currency::USD));
+        assert_eq!(data.payments[0].occurrence_date, date("2023-12-31"));
+        assert!(data.categories.is_empty());
+        assert!(!files.legacy_json().exists());
+        assert!(dir.path().join("bills.json.imported").exists());
+
+        // A file that reappears later is ignored
+        fs::write(files.legacy_json(), r#"{"bills": []}"#).unwrap();
+        let data = SqliteStore::open(&files).unwrap().load(None).unwrap();
+        assert_eq!(data.bills.len(), 1);
+    }
+
+    #[test]
+    fn unreadable_records_of_bills_json_are_quarantined() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let doc = serde_json::json!({
+            "bills": [
+                {
+                    "id": "b1", "name": "Rent", "amount": 1200.5, "dueDate": "2024-01-31",
+                    "recurrence": "monthly", "paid": false,
+                    "createdAt": "2024-01-01T00:00:00Z", "updatedAt": "2024-01-01T00:00:00Z"
+                },
+                { "id": "b2", "name": "Water", "dueDate": "someday" }
+            ]
+        });
+        fs::write(files.legacy_json(), doc.to_string()).unwrap();
+
+        let data = SqliteStore::open(&files).unwrap().load(None).unwrap();
+        let ids: Vec<&str> = data.bills.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["b1"]);
+        let quarantined = Quarantine::new(files.quarantine()).read(None).unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].collection, "bills");
+        assert_eq!(quarantined[0].record["id"], "b2");
+    }
+
+    #[test]
+    fn unreadable_bills_json_leaves_no_database_behind() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        fs::write(files.legacy_json(), "{ not json").unwrap();
+        let err = SqliteStore::open(&files).err().unwrap();
+        assert_eq!(err.code(), "corrupt_data");
+
+        // The import is retried on the next launch
+        fs::write(files.legacy_json(), r#"{"bills": []}"#).unwrap();
+        let store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        assert!(!files.legacy_json().exists());
+    }
+
+    #[test]
+    fn saved_data_loads_back_unchanged() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let mut store = SqliteStore::open(&files).unwrap();
+        let before = store.load(None).unwrap();
+        let mut after = before.clone();
+        let rent = add_bill(&mut after, "Rent", 120_000, "2024-01-31");
+        after.bills[0].tags = vec!["home".to_string()];
+        after.bills[0].category_id = Some(after.categories[1].id.clone());
+        add_bill(&mut after, "Water", 4000, "2024-01-10");
+        let input = PaymentInput {
+            bill_id: rent.id.clone(),
+            occurrence_date: None,
+            paid_date: None,
+            amount: None,
+            method: Some("card".to_string()),
+            confirmation: None,
+            transaction_id: None,
+        };
+        after
+            .record_payment(input, date("2024-01-30"), Utc::now())
+            .unwrap();
+        after.rates.push(ExchangeRate {
+            date: date("2024-01-05"),
+            base: Currency::parse("EUR").unwrap(),
+            quote: Currency::USD,
+            rate: 1.0921,
+        });
+        after.settings.reporting_currency = Currency::parse("EUR").unwrap();
+        let water = after.bills[1].id.clone();
+        after.delete_bill(&water, Utc::now()).unwrap();
+        store.save(&before, &after).unwrap();
+        drop(store);
+
+        let loaded = SqliteStore::open(&files).unwrap().load(None).unwrap();
+        assert_eq!(json(&loaded), json(&after));
+    }
+
+    #[test]
+    fn saves_keep_the_list_order() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let mut store = SqliteStore::open(&files).unwrap();
+        let mut before = store.load(None).unwrap();
+        let mut after = before.clone();
+        for name in ["A", "B", "C"] {
+            add_bill(&mut after, name, 1000, "2024-01-10");
+        }
+        store.save(&before, &after).unwrap();
+        before = after.clone();
+        after.bills.remove(0);
+        after.bills[0].name = "B2".to_string();
+        add_bill(&mut after, "D", 1000, "2024-01-10");
+        store.save(&before, &after).unwrap();
+
+        let names: Vec<String> = store
+            .load(None)
+            .unwrap()
+            .bills
+            .into_iter()
+            .map(|b| b.name)
+            .collect();
+        assert_eq!(names, ["B2", "C", "D"]);
+    }
+
+    #[test]
+    fn sync_writes_only_what_changed() {
+        let before = [(1, "a"), (2, "b"), (3, "c")];
+        let after = [(2, "B"), (3, "c"), (4, "d")];
+        let mut deleted = Vec::new();
+        let mut upserted = Vec::new();
+        sync(
+            &before,
+            &after,
+            |r| r.0,
+            |k| {
+                deleted.push(*k);
+                Ok(())
+            },
+            |r| {
+                upserted.push(*r);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(deleted, [1]);
+        assert_eq!(upserted, [(2, "B"), (4, "d")]);
+    }
+
+    #[test]
+    fn damaged_rows_are_quarantined() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let mut store = SqliteStore::open(&files).unwrap();
+        let before = store.load(None).unwrap();
+        let mut after = before.clone();
+        add_bill(&mut after, "Rent", 120_000, "2024-01-31");
+        add_bill(&mut after, "Power", 6_000, "2024-01-15");
+        store.save(&before, &after).unwrap();
+        let power = after.bills[1].id.clone();
+        store
+            .conn
+            .execute_batch(&format!(
+                "UPDATE bills SET recurrence = 'nope' WHERE id = '{power}';
+                 INSERT INTO settings (key, value) VALUES ('x', 'not json');
+                 INSERT INTO settings (key, value) VALUES ('autoLockMinutes', '\"soon\"');"
+            ))
+            .unwrap();
+
+        let data = store.load(None).unwrap();
+        assert_eq!(data.bills, after.bills[..1]);
+        assert_eq!(data.settings, after.settings);
+        let quarantined = Quarantine::new(files.quarantine()).read(None).unwrap();
+        assert_eq!(quarantined.len(), 3);
+        assert_eq!(quarantined[0].collection, "bills");
+        assert_eq!(quarantined[0].record["id"], power.as_str());
+        assert_eq!(quarantined[0].record["recurrence"], "nope");
+        let settings: Vec<(&str, &str)> = quarantined[1..]
+            .iter()
+            .map(|q| (q.collection.as_str(), q.record["key"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            settings,
+            [("settings", "x"), ("settings", "autoLockMinutes")]
+        );
+        // The rows are gone, so the next load sets nothing aside
+        assert_eq!(store.load(None).unwrap().bills, after.bills[..1]);
+        assert_eq!(
+            Quarantine::new(files.quarantine())
+                .read(None)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let store = SqliteStore::open(&files).unwrap();
+        store
+            .conn
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(store);
+        let err = SqliteStore::open(&files).err().unwrap();
+        assert_eq!(err.code(), "newer_version");
+        // Nothing was downgraded on the way
+        let conn = Connection::open(files.database()).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() + 1);
+    }
+
+    #[test]
+    fn upgrade_backups_replace_stale_copies() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        let store = SqliteStore::open(&files).unwrap();
+        let backup = dir.path().join("bills.db.v1.bak");
+        fs::write(&backup, "left over").unwrap();
+        backup_before_upgrade(&store.conn, &files.database(), 1).unwrap();
+
+        let copy = Connection::open(&backup).unwrap();
+        let categories: usize = copy
+            .query_row("SELECT count(*) FROM categories", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(categories, 6);
+    }
+
+    /// A database left by a version that knew only the first `version`
+    /// migrations.
+    fn database_at(path: &Path, version: usize) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        for sql in &MIGRATIONS[..version] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+        conn
+    }
+
+    #[test]
+    fn upgrades_from_schema_1() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        database_at(&files.database(), 1)
+            .execute(
+                "INSERT INTO categories (id, name) VALUES ('c1', 'Pets')",
+                [],
+            )
+            .unwrap();
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        let data = store.load(None).unwrap();
+        assert_eq!(data.categories.len(), 1);
+        assert!(data.journal.is_empty());
+
+        let backup = Connection::open(dir.path().join("bills.db.v1.bak")).unwrap();
+        let version: usize = backup
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 1);
+    }
+
+    #[test]
+    fn sync_rewrites_the_table_when_a_record_is_put_back_in_between() {
+        let before = [(1, "a"), (2, "b")];
+        let after = [(1, "a"), (3, "c"), (2, "b")];
+        let mut deleted = Vec::new();
+        let mut upserted = Vec::new();
+        sync(
+            &before,
+            &after,
+            |r| r.0,
+            |k| {
+                deleted.push(*k);
+                Ok(())
+            },
+            |r| {
+                upserted.push(*r);
+                Ok(())
+            },
+        )
+        .unwrap();
+        deleted.sort();
+        assert_eq!(deleted, [1, 2]);
+        assert_eq!(upserted, after);
+    }
+
+    #[test]
+    fn upgrades_from_schema_2() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        database_at(&files.database(), 2);
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        assert!(dir.path().join("bills.db.v2.bak").exists());
+
+        let before = store.load(None).unwrap();
+        let mut after = before.clone();
+        let rent = add_bill(&mut after, "Rent", 120_000, "2024-01-31");
+        after.delete_bill(&rent.id, Utc::now()).unwrap();
+        store.save(&before, &after).unwrap();
+        assert_eq!(store.load(None).unwrap().trash, after.trash);
+    }
+
+    #[test]
+    fn upgrades_from_schema_3() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        database_at(&files.database(), 3);
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        assert!(dir.path().join("bills.db.v3.bak").exists());
+
+        let before = store.load(None).unwrap();
+        let mut after = before.clone();
+        add_bill(&mut after, "Rent", 120_000, "2024-01-31");
+        after.record_history(&before, ChangeSource::Import, Utc::now());
+        store.save(&before, &after).unwrap();
+        assert_eq!(store.load(None).unwrap().history, after.history);
+    }
+
+    #[test]
+    fn upgrades_from_schema_4() {
+        let dir = TempDir::new().unwrap();
+        let files = DataFiles::new(dir.path());
+        database_at(&files.database(), 4);
+        let mut store = SqliteStore::open(&files).unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        assert!(dir.path().join("bills.db.v4.bak").exists());
+
+        let before = store.load(None).unwrap();
+        let mut after = before.clone();
+        let rent = add_bill(&mut after, "Rent", 120_000, "2024-01-31");
+        let input = PaymentInput {
+            bill_id: rent.id,
+            occurrence_date: None,
+            paid_date: None,
+            amount: None,
+            method: None,
+            confirmation: None,
+            transaction_id: Some("123:A1".to_string()),
+        };
+        after
+            .record_payment(input, date("2024-01-30"), Utc::now())
+            .unwrap();
+        store.save(&before, &after).unwrap();
+        assert_eq!(store.load(None).unwrap().payments, after.payments);
+    }
+}
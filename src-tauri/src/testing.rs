@@ -0,0 +1,49 @@
+//! Fixtures shared by the unit tests.
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::bill::{Bill, BillInput};
+use crate::data::Data;
+use crate::money::{Currency, Money};
+use crate::recurrence::Recurrence;
+
+/// Parses an ISO date such as `2024-01-31`.
+pub fn date(text: &str) -> NaiveDate {
+    text.parse().unwrap()
+}
+
+/// US dollars from a count of cents.
+pub fn usd(minor: i64) -> Money {
+    Money::new(minor, Currency::USD)
+}
+
+/// Form input for a monthly bill of `amount` cents.
+pub fn bill_input(name: &str, amount: i64, due_date: &str) -> BillInput {
+    BillInput {
+        id: None,
+        name: name.to_string(),
+        amount: usd(amount),
+        due_date: due_date.to_string(),
+        recurrence: Recurrence::Monthly,
+        limits: Default::default(),
+        category_id: None,
+        tags: Vec::new(),
+        notes: None,
+        updated_at: None,
+    }
+}
+
+/// Adds a monthly bill of `amount` cents to `data`.
+pub fn add_bill(data: &mut Data, name: &str, amount: i64, due_date: &str) -> Bill {
+    data.upsert_bill(bill_input(name, amount, due_date), Utc::now())
+        .unwrap()
+}
+
+/// Applies `f` and journals it under `label`, as a command does.
+pub fn change<T>(data: &mut Data, label: &str, f: impl FnOnce(&mut Data) -> T) -> T {
+    let before = data.clone();
+    let out = f(data);
+    data.record_operation(&before, label, DateTime::UNIX_EPOCH)
+        .unwrap();
+    out
+}
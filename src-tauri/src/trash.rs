@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::Bill;
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::payment::Payment;
+
+/// A deleted bill with the payments recorded for it, kept until it is
+/// restored or purged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedBill {
+    pub bill: Bill,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub payments: Vec<Payment>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl TrashedBill {
+    /// Bill id, which also identifies the trash entry.
+    pub fn id(&self) -> &str {
+        &self.bill.id
+    }
+}
+
+impl Data {
+    fn trash_index(&self, id: &str) -> AppResult<usize> {
+        self.trash
+            .iter()
+            .position(|t| t.id() == id)
+            .ok_or_else(|| AppError::not_found("Deleted bill", id))
+    }
+
+    /// Trashed bills, most recently deleted first.
+    pub fn list_trash(&self) -> Vec<TrashedBill> {
+        let mut trash = self.trash.clone();
+        trash.sort_by_key(|t| std::cmp::Reverse(t.deleted_at));
+        trash
+    }
+
+    /// Moves a bill and its payment history to the trash.
+    pub fn delete_bill(&mut self, id: &str, now: DateTime<Utc>) -> AppResult<Bill> {
+        let idx = self.bill_index(id)?;
+        let (payments, kept) = std::mem::take(&mut self.payments)
+            .into_iter()
+            .partition(|p| p.bill_id == id);
+        self.payments = kept;
+        let bill = self.bills.remove(idx);
+        self.trash.push(TrashedBill {
+            bill: bill.clone(),
+            payments,
+            deleted_at: now,
+        });
+        Ok(bill)
+    }
+
+    /// Puts a trashed bill and its payments back. A category that no longer
+    /// exists is dropped from the bill.
+    pub fn restore_bill(&mut self, id: &str) -> AppResult<Bill> {
+        let idx = self.trash_index(id)?;
+        if self.bill(id).is_some() {
+            return Err(AppError::Conflict(format!(
+                "A bill with id {id} already exists"
+            )));
+        }
+        let TrashedBill {
+            mut bill, payments, ..
+        } = self.trash.remove(idx);
+        if let Some(category_id) = &bill.category_id {
+            if self.category(category_id).is_err() {
+                bill.category_id = None;
+            }
+        }
+        self.bills.push(bill.clone());
+        self.payments.extend(payments);
+        Ok(bill)
+    }
+
+    /// Permanently deletes one trashed bill, or the whole trash when `id` is
+    /// `None`. Returns the number of bills purged.
+    pub fn purge_trash(&mut self, id: Option<&str>) -> AppResult<usize> {
+        let purged = match id {
+            Some(id) => {
+                let idx = self.trash_index(id)?;
+                vec![self.trash.remove(idx)]
+            }
+            None => std::mem::take(&mut self.trash),
+        };
+        self.forget_purged(&purged);
+        Ok(purged.len())
+    }
+
+    /// Leaves no trace of purged bills in the undo journal or the history.
+    fn forget_purged(&mut self, purged: &[TrashedBill]) {
+        let ids: HashSet<String> = purged.iter().map(|t| t.id().to_string()).collect();
+        self.forget_in_journal(&ids);
+        self.prune_history();
+    }
+
+    /// Bills deleted before this have outlived the retention period.
+    fn trash_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.settings.trash_retention_days {
+            0 => None,
+            days => Some(now - Duration::days(i64::from(days))),
+        }
+    }
+
+    pub fn has_expired_trash(&self, now: DateTime<Utc>) -> bool {
+        self.trash_cutoff(now)
+            .is_some_and(|cutoff| self.trash.iter().any(|t| t.deleted_at <= cutoff))
+    }
+
+    /// Purges bills that have been in the trash longer than the retention
+    /// period.
+    pub fn purge_expired_trash(&mut self, now: DateTime<Utc>) {
+        if let Some(cutoff) = self.trash_cutoff(now) {
+            let (purged, kept) = std::mem::take(&mut self.trash)
+                .into_iter()
+                .partition::<Vec<_>, _>(|t| t.deleted_at <= cutoff);
+            self.trash = kept;
+            self.forget_purged(&purged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_bill, change, date};
+
+    fn at(day: &str) -> DateTime<Utc> {
+        date(day).and_hms_opt(12, 0, 0).unwrap().and_utc()
+    }
+
+    fn labels(data: &Data) -> Vec<&str> {
+        data.journal.iter().map(|op| op.label.as_str()).collect()
+    }
+
+    fn trashed_ids(data: &Data) -> Vec<String> {
+        data.list_trash()
+            .iter()
+            .map(|t| t.id().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn deleted_bills_keep_their_payments() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let water = add_bill(&mut data, "Water", 4_000, "2024-01-15");
+        for id in [&rent.id, &water.id] {
+            data.set_paid(id, true, date("2024-01-10"), Utc::now())
+                .unwrap();
+        }
+        let paid_rent = data.bill(&rent.id).unwrap().clone();
+
+        data.delete_bill(&rent.id, at("2024-02-01")).unwrap();
+        assert!(data.bill(&rent.id).is_none());
+        assert!(data.payments.iter().all(|p| p.bill_id == water.id));
+        assert_eq!(data.trash[0].payments.len(), 1);
+
+        let restored = data.restore_bill(&rent.id).unwrap();
+        assert_eq!(restored, paid_rent);
+        assert_eq!(data.payments.len(), 2);
+        assert!(data.trash.is_empty());
+        assert_eq!(data.restore_bill(&rent.id).unwrap_err().code(), "not_found");
+    }
+
+    #[test]
+    fn trash_lists_the_latest_deletion_first() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let water = add_bill(&mut data, "Water", 4_000, "2024-01-15");
+        data.delete_bill(&water.id, at("2024-02-02")).unwrap();
+        data.delete_bill(&rent.id, at("2024-02-01")).unwrap();
+        assert_eq!(trashed_ids(&data), [water.id.as_str(), rent.id.as_str()]);
+    }
+
+    #[test]
+    fn restoring_drops_a_missing_category() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        data.bills[0].category_id = Some("gone".to_string());
+        data.delete_bill(&rent.id, at("2024-02-01")).unwrap();
+        assert_eq!(data.restore_bill(&rent.id).unwrap().category_id, None);
+    }
+
+    #[test]
+    fn restoring_over_an_existing_bill_conflicts() {
+        let mut data = Data::fresh();
+        let rent = add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        data.delete_bill(&rent.id, at("2024-02-01")).unwrap();
+        data.bills.push(rent.clone());
+        assert_eq!(data.restore_bill(&rent.id).unwrap_err().code(), "conflict");
+        assert_eq!(data.trash.len(), 1);
+    }
+
+    #[test]
+    fn purges_one_bill_or_the_whole_trash() {
+        let mut data = Data::fresh();
+        for name in ["Rent", "Water", "Power"] {
+            let bill = add_bill(&mut data, name, 1_000, "2024-01-01");
+            data.delete_bill(&bill.id, at("2024-02-01")).unwrap();
+        }
+        let first = data.trash[0].id().to_string();
+        assert_eq!(data.purge_trash(Some(&first)).unwrap(), 1);
+        assert_eq!(
+            data.purge_trash(Some(&first)).unwrap_err().code(),
+            "not_found"
+        );
+        assert_eq!(data.purge_trash(None).unwrap(), 2);
+        assert!(data.trash.is_empty());
+    }
+
+    #[test]
+    fn purge_cannot_be_undone() {
+        let mut data = Data::fresh();
+        let now = at("2024-03-01");
+        let rent = add_bill(&mut data, "Rent", 1_000, "2024-01-01");
+        let water = add_bill(&mut data, "Water", 1_000, "2024-01-01");
+        change(&mut data, "Delete bill", |d| {
+            d.delete_bill(&rent.id, now).unwrap()
+        });
+        change(&mut data, "Delete bill", |d| {
+            d.delete_bill(&water.id, now).unwrap()
+        });
+
+        assert_eq!(data.purge_trash(Some(&rent.id)).unwrap(), 1);
+        // Every operation up to the last one holding the bill is gone, so
+        // undo cannot bring it back
+        assert_eq!(labels(&data), ["Delete bill"]);
+        data.undo().unwrap();
+        assert!(data.bill(&water.id).is_some());
+        assert!(data.bill(&rent.id).is_none());
+        assert!(data.undo().is_err());
+        assert!(data.purge_trash(Some(&rent.id)).is_err());
+    }
+
+    #[test]
+    fn purges_expired_bills_only() {
+        let mut data = Data::fresh();
+        data.settings.trash_retention_days = 30;
+        let now = at("2024-03-01");
+        let old = change(&mut data, "Add bill", |d| {
+            add_bill(d, "Old", 1_000, "2024-01-01")
+        });
+        let new = change(&mut data, "Add bill", |d| {
+            add_bill(d, "New", 1_000, "2024-01-01")
+        });
+        data.delete_bill(&old.id, now - Duration::days(31)).unwrap();
+        data.delete_bill(&new.id, now).unwrap();
+        assert!(data.has_expired_trash(now));
+
+        data.purge_expired_trash(now);
+        assert_eq!(trashed_ids(&data), [new.id.as_str()]);
+        assert_eq!(labels(&data), ["Add bill"]);
+        assert!(!data.has_expired_trash(now));
+
+        data.settings.trash_retention_days = 0;
+        assert!(!data.has_expired_trash(now + Duration::days(365)));
+    }
+}
@@ -0,0 +1,546 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chrono::Utc;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::backup::{self, write_atomic, Staged};
+use crate::data::Data;
+use crate::error::{AppError, AppResult};
+use crate::integrity::Quarantine;
+use crate::migrate;
+
+/// Marks a file as sealed by [`seal`].
+const MAGIC: &[u8; 8] = b"ABCVAULT";
+const FORMAT: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Magic, format, three KDF parameters, salt and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LEN + NONCE_LEN;
+
+/// Argon2id cost for new keys: 64 MiB of memory and three passes, which
+/// takes well under a second on a desktop but makes guessing expensive.
+#[cfg(not(test))]
+const MEMORY_KIB: u32 = 64 * 1024;
+/// Files name the cost they were sealed with, so the tests can use the
+/// cheapest one without changing anything else.
+#[cfg(test)]
+const MEMORY_KIB: u32 = 8;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 1;
+
+/// KDF costs accepted from a file header. They are read before the file is
+/// authenticated, so a damaged or crafted header must not be able to make
+/// unlocking exhaust memory or run for hours.
+#[cfg(not(test))]
+const MEMORY_KIB_RANGE: RangeInclusive<u32> = 8 * 1024..=1024 * 1024;
+#[cfg(test)]
+const MEMORY_KIB_RANGE: RangeInclusive<u32> = MEMORY_KIB..=1024 * 1024;
+const ITERATIONS_RANGE: RangeInclusive<u32> = 1..=16;
+const PARALLELISM_RANGE: RangeInclusive<u32> = 1..=16;
+
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Key derived from the passphrase, with the salt and cost it was derived
+/// with so files sealed by it can name them.
+pub struct VaultKey {
+    key: Zeroizing<[u8; 32]>,
+    salt: [u8; SALT_LEN],
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl VaultKey {
+    /// Derives a key for a new passphrase, with a fresh random salt.
+    pub fn create(passphrase: &str) -> AppResult<Self> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(AppError::validation(format!(
+                "Passphrase must be at least {MIN_PASSPHRASE_LEN} characters"
+            )));
+        }
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt, MEMORY_KIB, ITERATIONS, PARALLELISM)
+    }
+
+    fn derive(
+        passphrase: &str,
+        salt: [u8; SALT_LEN],
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    ) -> AppResult<Self> {
+        let params = Params::new(memory_kib, iterations, parallelism, Some(32))
+            .map_err(|e| AppError::CorruptData(format!("Invalid vault parameters: {e}")))?;
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| AppError::CorruptData(format!("Failed to derive the vault key: {e}")))?;
+        Ok(Self {
+            key,
+            salt,
+            memory_kib,
+            iterations,
+            parallelism,
+        })
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.key.as_ref().into())
+    }
+}
+
+/// Whether `bytes` were produced by [`seal`].
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypts `plaintext` with XChaCha20-Poly1305 under a random nonce. The
+/// header, which names the salt and KDF cost, is authenticated too.
+pub fn seal(key: &VaultKey, plaintext: &[u8]) -> AppResult<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT);
+    for n in [key.memory_kib, key.iterations, key.parallelism] {
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+    out.extend_from_slice(&key.salt);
+    out.extend_from_slice(&nonce);
+    let sealed = key
+        .cipher()
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &out,
+            },
+        )
+        .map_err(|_| AppError::CorruptData("Failed to encrypt bills".to_string()))?;
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+struct Header<'a> {
+    salt: [u8; SALT_LEN],
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    nonce: &'a [u8],
+    aad: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+fn parse<'a>(what: &str, bytes: &'a [u8]) -> AppResult<Header<'a>> {
+    let corrupt = || AppError::CorruptData(format!("{what} is not a valid encrypted file"));
+    if !is_sealed(bytes) || bytes.len() < HEADER_LEN {
+        return Err(corrupt());
+    }
+    if bytes[MAGIC.len()] != FORMAT {
+        return Err(AppError::NewerVersion {
+            what: what.to_string(),
+            found: u64::from(bytes[MAGIC.len()]),
+            supported: u64::from(FORMAT),
+        });
+    }
+    let (aad, ciphertext) = bytes.split_at(HEADER_LEN);
+    let mut rest = &aad[MAGIC.len() + 1..];
+    let mut take = |n: usize| {
+        let (head, tail) = rest.split_at(n);
+        rest = tail;
+        head
+    };
+    let mut u32_at = || u32::from_le_bytes(take(4).try_into().unwrap_or_default());
+    let (memory_kib, iterations, parallelism) = (u32_at(), u32_at(), u32_at());
+    if !MEMORY_KIB_RANGE.contains(&memory_kib)
+        || !ITERATIONS_RANGE.contains(&iterations)
+        || !PARALLELISM_RANGE.contains(&parallelism)
+    {
+        return Err(AppError::CorruptData(format!(
+            "{what} names an unsupported key derivation cost"
+        )));
+    }
+    let salt = take(SALT_LEN).try_into().map_err(|_| corrupt())?;
+    let nonce = take(NONCE_LEN);
+    Ok(Header {
+        salt,
+        memory_kib,
+        iterations,
+        parallelism,
+        nonce,
+        aad,
+        ciphertext,
+    })
+}
+
+fn decrypt(what: &str, key: &VaultKey, header: &Header<'_>) -> AppResult<Zeroizing<Vec<u8>>> {
+    key.cipher()
+        .decrypt(
+            XNonce::from_slice(header.nonce),
+            Payload {
+                msg: header.ciphertext,
+                aad: header.aad,
+            },
+        )
+        .map(Zeroizing::new)
+        // Wrong key and tampering are indistinguishable by design
+        .map_err(|_| AppError::Permission(format!("Wrong passphrase, or {what} was tampered with")))
+}
+
+/// Decrypts a sealed file with the key it was sealed with.
+pub fn unseal(what: &str, key: &VaultKey, bytes: &[u8]) -> AppResult<Zeroizing<Vec<u8>>> {
+    let header = parse(what, bytes)?;
+    if header.salt != key.salt {
+        return Err(AppError::Permission(format!(
+            "{what} was encrypted with a different passphrase"
+        )));
+    }
+    decrypt(what, key, &header)
+}
+
+/// Derives the key a sealed file names from `passphrase` and decrypts the
+/// file with it.
+fn unseal_with_passphrase(
+    what: &str,
+    passphrase: &str,
+    bytes: &[u8],
+) -> AppResult<(VaultKey, Zeroizing<Vec<u8>>)> {
+    let header = parse(what, bytes)?;
+    let key = VaultKey::derive(
+        passphrase,
+        header.salt,
+        header.memory_kib,
+        header.iterations,
+        header.parallelism,
+    )?;
+    let plaintext = decrypt(what, &key, &header)?;
+    Ok((key, plaintext))
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    /// The data is kept in an encrypted vault.
+    pub enabled: bool,
+    /// No data is available until the passphrase is entered.
+    pub locked: bool,
+}
+
+/// Keeps the app data in a single encrypted file. Only the key is held while
+/// unlocked; every save re-encrypts the whole document.
+pub struct VaultStore {
+    path: PathBuf,
+    key: Option<VaultKey>,
+}
+
+impl VaultStore {
+    /// A vault at `path`, locked until [`VaultStore::unlock`].
+    pub fn locked(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            key: None,
+        }
+    }
+
+    /// Encrypts `data` into a new vault at `path` under `passphrase`.
+    pub fn create(path: impl Into<PathBuf>, passphrase: &str, data: &Data) -> AppResult<Self> {
+        let store = Self {
+            path: path.into(),
+            key: Some(VaultKey::create(passphrase)?),
+        };
+        store.save(data)?;
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn key(&self) -> Option<&VaultKey> {
+        self.key.as_ref()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// Checks `passphrase` and returns the decrypted data, keeping the key
+    /// for later saves. Records that cannot be read are moved to
+    /// `quarantine` and the vault is saved without them.
+    pub fn unlock(&mut self, passphrase: &str, quarantine: &Quarantine) -> AppResult<Data> {
+        let bytes = fs::read(&self.path)
+            .map_err(|e| AppError::io(format!("Failed to read {}", self.path.display()), e))?;
+        let what = self.path.display().to_string();
+        let (key, plaintext) = unseal_with_passphrase(&what, passphrase, &bytes)?;
+        let (data, quarantined) = migrate::salvage_document(&what, &plaintext, Utc::now())?;
+        if !quarantined.is_empty() {
+            quarantine.append(&quarantined, Some(&key))?;
+            write_atomic(&self.path, &seal_data(&key, &data)?)?;
+        }
+        self.key = Some(key);
+        Ok(data)
+    }
+
+    /// Forgets the key; the data has to be dropped by the caller.
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    pub fn save(&self, data: &Data) -> AppResult<()> {
+        let key = self.key.as_ref().ok_or(AppError::Locked)?;
+        write_atomic(&self.path, &seal_data(key, data)?)
+    }
+
+    /// Encrypts `data` under a key derived from `new`, with a new salt,
+    /// into a file staged next to the vault. The vault keeps its current key
+    /// until [`VaultStore::rekey`], once the file is committed.
+    pub fn stage_passphrase(&self, new: &str, data: &Data) -> AppResult<(VaultKey, Staged)> {
+        if self.is_locked() {
+            return Err(AppError::Locked);
+        }
+        let key = VaultKey::create(new)?;
+        let staged = backup::stage(&self.path, &seal_data(&key, data)?)?;
+        Ok((key, staged))
+    }
+
+    /// Switches to the key of the file staged by
+    /// [`VaultStore::stage_passphrase`], once that file is in place.
+    pub fn rekey(&mut self, key: VaultKey) {
+        self.key = Some(key);
+    }
+
+    /// Checks that `passphrase` opens the vault file.
+    pub fn verify(&self, passphrase: &str) -> AppResult<()> {
+        let bytes = fs::read(&self.path)
+            .map_err(|e| AppError::io(format!("Failed to read {}", self.path.display()), e))?;
+        unseal_with_passphrase(&self.path.display().to_string(), passphrase, &bytes).map(drop)
+    }
+}
+
+/// Encrypts `data` as a vault document.
+fn seal_data(key: &VaultKey, data: &Data) -> AppResult<Vec<u8>> {
+    let plaintext = Zeroizing::new(migrate::encode_document(data)?);
+    seal(key, &plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::add_bill;
+
+    /// A key with the cheapest Argon2 cost, so the tests stay fast.
+    fn key(passphrase: &str, salt: u8) -> VaultKey {
+        VaultKey::derive(passphrase, [salt; SALT_LEN], 8, 1, 1).unwrap()
+    }
+
+    fn error(result: AppResult<impl Sized>) -> (&'static str, String) {
+        let err = result.err().unwrap();
+        (err.code(), err.to_string())
+    }
+
+    #[test]
+    fn sealed_data_unseals_with_the_same_key() {
+        let key = key("correct horse", 1);
+        let sealed = seal(&key, b"bills").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(sealed.len(), HEADER_LEN + 5 + 16);
+        assert_eq!(unseal("vault", &key, &sealed).unwrap().as_slice(), b"bills");
+        // Every seal uses a fresh nonce
+        assert_ne!(seal(&key, b"bills").unwrap(), sealed);
+
+        let (derived, plaintext) =
+            unseal_with_passphrase("vault", "correct horse", &sealed).unwrap();
+        assert_eq!(plaintext.as_slice(), b"bills");
+        assert_eq!(
+            (derived.memory_kib, derived.iterations, derived.parallelism),
+            (8, 1, 1)
+        );
+    }
+
+    #[test]
+    fn wrong_passphrases_are_refused() {
+        let sealed = seal(&key("correct horse", 1), b"bills").unwrap();
+        assert_eq!(
+            error(unseal_with_passphrase("vault", "battery staple", &sealed)),
+            (
+                "permission",
+                "Wrong passphrase, or vault was tampered with".to_string()
+            )
+        );
+        assert_eq!(
+            error(unseal("vault", &key("correct horse", 2), &sealed)),
+            (
+                "permission",
+                "vault was encrypted with a different passphrase".to_string()
+            )
+        );
+        // Same salt, different passphrase
+        assert_eq!(
+            error(unseal("vault", &key("battery staple", 1), &sealed)).0,
+            "permission"
+        );
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let key = key("correct horse", 1);
+        let sealed = seal(&key, b"bills").unwrap();
+        // The last ciphertext byte, a nonce byte and a KDF parameter byte
+        for at in [sealed.len() - 1, HEADER_LEN - 1, MAGIC.len() + 1] {
+            let mut tampered = sealed.clone();
+            tampered[at] ^= 1;
+            assert_eq!(
+                error(unseal("vault", &key, &tampered)),
+                (
+                    "permission",
+                    "Wrong passphrase, or vault was tampered with".to_string()
+                ),
+                "byte {at}"
+            );
+        }
+        let mut appended = sealed.clone();
+        appended.push(0);
+        assert_eq!(error(unseal("vault", &key, &appended)).0, "permission");
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let key = key("correct horse", 1);
+        let sealed = seal(&key, b"bills").unwrap();
+        for bytes in [&b"{\"bills\": []}"[..], &sealed[..HEADER_LEN - 1]] {
+            assert_eq!(
+                error(unseal("vault", &key, bytes)),
+                (
+                    "corrupt_data",
+                    "vault is not a valid encrypted file".to_string()
+                )
+            );
+        }
+        let mut newer = sealed;
+        newer[MAGIC.len()] = FORMAT + 1;
+        assert_eq!(error(unseal("vault", &key, &newer)).0, "newer_version");
+    }
+
+    #[test]
+    fn rejects_key_derivation_costs_out_of_range() {
+        let sealed = seal(&key("correct horse", 1), b"bills").unwrap();
+        let costs = MAGIC.len() + 1;
+        // Memory, iterations and parallelism, each just past its limit
+        for (offset, value) in [
+            (0, 1024 * 1024 + 1),
+            (0, 7),
+            (4, 0),
+            (4, 17),
+            (8, 0),
+            (8, 17),
+        ] {
+            let mut crafted = sealed.clone();
+            crafted[costs + offset..costs + offset + 4].copy_from_slice(&u32::to_le_bytes(value));
+            assert_eq!(
+                error(unseal_with_passphrase("vault", "correct horse", &crafted)),
+                (
+                    "corrupt_data",
+                    "vault names an unsupported key derivation cost".to_string()
+                ),
+                "{offset}: {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn passphrases_need_a_minimum_length() {
+        assert_eq!(
+            error(VaultKey::create("short")),
+            (
+                "validation",
+                "Passphrase must be at least 8 characters".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn vault_files_unlock_lock_and_rekey() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bills.vault");
+        let mut data = Data::default();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let quarantine = Quarantine::new(dir.path().join("quarantine.json"));
+        let mut vault = VaultStore::create(&path, "correct horse", &data).unwrap();
+        assert!(!vault.is_locked());
+        assert!(!fs::read(&path).unwrap().windows(4).any(|w| w == b"Rent"));
+
+        vault.lock();
+        assert_eq!(error(vault.save(&data)).0, "locked");
+        assert_eq!(
+            error(vault.unlock("battery staple", &quarantine)).0,
+            "permission"
+        );
+        assert!(vault.is_locked());
+        assert_eq!(
+            vault.unlock("correct horse", &quarantine).unwrap().bills,
+            data.bills
+        );
+
+        let (key, staged) = vault.stage_passphrase("battery staple", &data).unwrap();
+        // Nothing changes until the staged file is put in place
+        vault.verify("correct horse").unwrap();
+        let old_salt = vault.key().unwrap().salt;
+        staged.commit().unwrap();
+        vault.rekey(key);
+        assert_ne!(old_salt, vault.key().unwrap().salt);
+        assert!(vault.verify("correct horse").is_err());
+        vault.verify("battery staple").unwrap();
+        let mut reopened = VaultStore::locked(&path);
+        assert_eq!(
+            reopened
+                .unlock("battery staple", &quarantine)
+                .unwrap()
+                .bills,
+            data.bills
+        );
+        reopened.lock();
+        assert_eq!(
+            error(reopened.stage_passphrase("battery staple", &data)).0,
+            "locked"
+        );
+    }
+
+    #[test]
+    fn unreadable_records_are_quarantined_on_unlock() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bills.vault");
+        let quarantine = Quarantine::new(dir.path().join("quarantine.json"));
+        let mut data = Data::default();
+        add_bill(&mut data, "Rent", 120_000, "2024-01-31");
+        let mut doc: serde_json::Value =
+            serde_json::from_slice(&migrate::encode_document(&data).unwrap()).unwrap();
+        doc["bills"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({ "id": "b2", "name": 42 }));
+        let key = key("correct horse", 1);
+        let plaintext = serde_json::to_vec(&doc).unwrap();
+        fs::write(&path, seal(&key, &plaintext).unwrap()).unwrap();
+
+        let mut vault = VaultStore::locked(&path);
+        let unlocked = vault.unlock("correct horse", &quarantine).unwrap();
+        assert_eq!(unlocked.bills, data.bills);
+        let quarantined = quarantine.read(vault.key()).unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].record["id"], "b2");
+        // The vault was saved without the record, so it is set aside once
+        vault.lock();
+        vault.unlock("correct horse", &quarantine).unwrap();
+        assert_eq!(quarantine.read(vault.key()).unwrap().len(), 1);
+    }
+}
@@ -1,60 +1,417 @@
-// For Tauri v2 (recommended):
-import { Store } from "@tauri-apps/plugin-store";
+import { invoke } from "@tauri-apps/api/core";
 import {
   sendNotification,
   isPermissionGranted,
   requestPermission,
 } from "@tauri-apps/plugin-notification";
 
-// For Tauri v1 (if you're using v1, uncomment these and comment out the v2 imports above):
-// import { Store } from "tauri-plugin-store-api";
-// import { sendNotification, isPermissionGranted, requestPermission } from "@tauri-apps/api/notification";
+type Weekday = "Mon" | "Tue" | "Wed" | "Thu" | "Fri" | "Sat" | "Sun";
 
-type Recurrence = "none" | "monthly" | "yearly";
+type Recurrence =
+  | "none"
+  | "daily"
+  | "weekly"
+  | "biWeekly"
+  | "monthly"
+  | "quarterly"
+  | "semiAnnual"
+  | "yearly"
+  | "lastDayOfMonth"
+  | "lastBusinessDay"
+  | { everyNDays: { days: number } }
+  | { nthWeekday: { nth: number; weekday: Weekday } };
+
+// Shape of the `AppError` returned by every backend command
+type AppError = {
+  code:
+    | "validation"
+    | "not_found"
+    | "storage"
+    | "corrupt_data"
+    | "conflict"
+    | "permission";
+  message: string;
+};
+
+// Exact amount in the currency's minor unit (cents, yen, fils...)
+type Money = {
+  minor: number;
+  currency: string; // ISO 4217
+};
+
+// Total converted into the reporting currency
+type ConvertedTotal = {
+  total: Money;
+  rateDate?: string; // oldest exchange rate used
+  missingRates: string[];
+};
+
+type Category = {
+  id: string;
+  name: string;
+};
+
+// Matches the backend `BillFilter`; omitted fields match every bill
+type BillFilter = {
+  categoryId?: string; // "" selects uncategorized bills
+  tags?: string[];
+  text?: string;
+};
+
+type Settings = {
+  reportingCurrency: string;
+  autoLockMinutes: number;
+  trashRetentionDays: number;
+  reminderDays: number;
+  ledgerAccounts: LedgerAccounts;
+  bankProfiles: BankProfile[];
+  payeeBills: Record<string, string>; // bill id by payee
+};
+
+type LedgerAccounts = {
+  expense: string;
+  payment: string;
+  categories?: Record<string, string>;
+  methods?: Record<string, string>;
+};
+
+type JournalStatus = {
+  undo: string | null;
+  redo: string | null;
+};
+
+type VaultStatus = {
+  enabled: boolean;
+  locked: boolean;
+};
+
+type RestoreReport = {
+  createdAt: string;
+  appVersion: string;
+  bills: number;
+  payments: number;
+  categories: number;
+  rates: number;
+  skipped: number;
+  issues: number;
+};
+
+type Backup = {
+  name: string;
+  kind: "daily" | "weekly" | "beforeRestore";
+  createdAt: string;
+  size: number;
+};
+
+type HistoryEntry = {
+  seq: number;
+  billId: string;
+  at: string; // ISO
+  source: "ui" | "import" | "rule" | "undo" | "redo";
+  event: "created" | "updated" | "deleted" | "restored";
+  changes?: { field: string; old: unknown; new: unknown }[];
+};
+
+type Repair = "newId" | "clearCategory" | "resetStartDate" | "quarantine";
+
+type Issue = {
+  collection: string;
+  index: number;
+  recordId: string;
+  problem: string;
+  message: string;
+  repairs: Repair[];
+};
+
+type IntegrityReport = {
+  records: number;
+  issues: Issue[];
+};
+
+type TrashedBill = {
+  bill: Omit<Bill, "paidAmount" | "balance">;
+  payments?: unknown[];
+  deletedAt: string; // ISO
+};
+
+type BillColumn =
+  | "name"
+  | "amount"
+  | "currency"
+  | "dueDate"
+  | "recurrence"
+  | "category"
+  | "tags"
+  | "notes"
+  | "endDate"
+  | "maxOccurrences";
+
+type DateFormat =
+  | "iso"
+  | "yearMonthDay"
+  | "dayMonthYear"
+  | "monthDayYear"
+  | "dayMonthYearDots"
+  | "dayMonthYearDashes";
+
+type CsvPreview = {
+  headers: string[];
+  rows: string[][];
+  rowCount: number;
+  mapping: Partial<Record<BillColumn, number>>;
+  dateFormats: DateFormat[];
+};
+
+type CsvImportReport = {
+  bills: Bill[];
+  newCategories: string[];
+  errors: { line: number; column?: BillColumn; message: string }[];
+};
+
+type IcsImportReport = {
+  bills: Bill[];
+  uids: string[];
+  newCategories: string[];
+  skipped: { uid: string; summary: string; message: string; needsAmount: boolean }[];
+};
+
+type BankTransaction = {
+  id: string;
+  date: string;
+  amount: Money;
+  payee: string;
+  memo?: string;
+};
+
+type PaymentProposal = {
+  transaction: BankTransaction;
+  billId: string;
+  billName: string;
+  occurrenceDate: string;
+  balance: Money;
+  score: number;
+};
+
+type MatchReport = {
+  proposals: PaymentProposal[];
+  unmatched: BankTransaction[];
+  alreadyRecorded: number;
+  credits: number;
+};
+
+type BankColumn = "date" | "payee" | "memo" | "amount" | "debit" | "credit" | "id";
+
+type BankProfile = {
+  name: string;
+  columns: Partial<Record<BankColumn, number>>;
+  dateFormat: DateFormat;
+  currency: string;
+  hasHeader: boolean;
+  debitsPositive: boolean;
+};
+
+type BankCsvPreview = {
+  headers: string[]; // the first row, header or not
+  rows: string[][];
+  rowCount: number;
+  mapping: Partial<Record<BankColumn, number>>;
+  hasHeader: boolean;
+  dateFormats: DateFormat[];
+};
+
+type PossiblyPaid = {
+  billId: string;
+  billName: string;
+  dueDate: string;
+  balance: Money;
+  transactions: BankTransaction[];
+};
+
+type Reconciliation = MatchReport & {
+  possiblyPaid: PossiblyPaid[];
+  errors: { line: number; message: string }[];
+};
+
+type Profile = {
+  id: string;
+  name: string;
+  createdAt: string; // ISO
+};
+
+type ProfileList = {
+  active: string;
+  profiles: Profile[];
+};
+
+type RecurrenceLimits = {
+  endDate?: string; // ISO yyyy-mm-dd
+  maxOccurrences?: number;
+  pauses?: { from: string; until: string }[];
+};
+
+type BillInput = {
+  id?: string;
+  name: string;
+  amount: Money;
+  dueDate: string;
+  recurrence: Recurrence;
+  limits?: RecurrenceLimits;
+  categoryId?: string;
+  tags: string[];
+  notes?: string;
+  updatedAt?: string;
+};
 
 type Bill = {
   id: string;
   name: string;
-  amount: number;
+  amount: Money;
   dueDate: string; // ISO yyyy-mm-dd
   recurrence: Recurrence;
+  startDate?: string; // ISO yyyy-mm-dd
+  limits?: RecurrenceLimits;
+  categoryId?: string;
+  tags?: string[];
   notes?: string;
   paid: boolean;
   createdAt: string; // ISO
   updatedAt: string; // ISO
+  paidAmount: Money; // paid towards the current occurrence
+  balance: Money; // still owed for the current occurrence
 };
 
-// For Tauri v2 store plugin - use Store.load() instead of constructor
-let store: Store;
-
 // Elements
 let billForm: HTMLFormElement;
 let idEl: HTMLInputElement;
 let nameEl: HTMLInputElement;
 let amountEl: HTMLInputElement;
+let currencyEl: HTMLInputElement;
 let dueEl: HTMLInputElement;
 let recurrenceEl: HTMLSelectElement;
+let endDateEl: HTMLInputElement;
+let maxOccurrencesEl: HTMLInputElement;
+let categoryEl: HTMLSelectElement;
+let tagsEl: HTMLInputElement;
 let notesEl: HTMLTextAreaElement;
 let resetBtn: HTMLButtonElement;
 let billList: HTMLUListElement;
 let searchEl: HTMLInputElement;
 let filterEl: HTMLSelectElement;
+let categoryFilterEl: HTMLSelectElement;
 let totalCountEl: HTMLElement;
 let totalAmountEl: HTMLElement;
+let reportingCurrencyEl: HTMLInputElement;
+let ratesFileEl: HTMLInputElement;
+let backupListEl: HTMLSelectElement;
+let archiveModeEl: HTMLSelectElement;
+let ledgerAccounts: LedgerAccounts | undefined;
+let bankProposals: PaymentProposal[] = [];
+let bankProfiles: BankProfile[] = [];
+let bankCsvText = "";
+let restoreBackupBtn: HTMLButtonElement;
+let vaultStatusEl: HTMLElement;
+let passphraseEl: HTMLInputElement;
+let newPassphraseEl: HTMLInputElement;
+let unlockBtn: HTMLButtonElement;
+let lockBtn: HTMLButtonElement;
+let enableVaultBtn: HTMLButtonElement;
+let disableVaultBtn: HTMLButtonElement;
+let changePassphraseBtn: HTMLButtonElement;
+let autoLockEl: HTMLInputElement;
+let checkIntegrityBtn: HTMLButtonElement;
+let integritySummaryEl: HTMLElement;
+let integrityIssuesEl: HTMLUListElement;
+let integrityIssues: Issue[] = [];
+let trashListEl: HTMLSelectElement;
+let restoreTrashedBtn: HTMLButtonElement;
+let purgeTrashedBtn: HTMLButtonElement;
+let emptyTrashBtn: HTMLButtonElement;
+let trashRetentionEl: HTMLInputElement;
+let undoBtn: HTMLButtonElement;
+let redoBtn: HTMLButtonElement;
+let profileSelectEl: HTMLSelectElement;
+let profileNameEl: HTMLInputElement;
+let createProfileBtn: HTMLButtonElement;
+let renameProfileBtn: HTMLButtonElement;
+let deleteProfileBtn: HTMLButtonElement;
+let profiles: ProfileList = { active: "", profiles: [] };
+let importFileEl: HTMLInputElement;
+let importPreviewEl: HTMLElement;
+let importMappingEl: HTMLElement;
+let importDateFormatEl: HTMLSelectElement;
+let importSampleEl: HTMLTableElement;
+let importSummaryEl: HTMLElement;
+let importErrorsEl: HTMLUListElement;
+let importText = "";
+let icsFileEl: HTMLInputElement;
+let icsPreviewEl: HTMLElement;
+let icsEventsEl: HTMLUListElement;
+let icsText = "";
+// Preview lines by event UID, kept so that events left out stay listed
+const icsLabels = new Map<string, string>();
+let reminderDaysEl: HTMLInputElement;
+let reminderDays = 0;
+let vaultLocked = false;
 
 let bills: Bill[] = [];
+let categories: Category[] = [];
+// `updatedAt` of the bill loaded into the form, used to detect stale edits
+let editingUpdatedAt: string | undefined;
+// Recurrence of the bill loaded into the form when the select can't express it
+let editingRecurrence: Recurrence | undefined;
+// Pause windows have no form fields; keep them when editing a bill
+let editingPauses: RecurrenceLimits["pauses"];
+
+// Currency for new bills; existing bills keep their own
+const DEFAULT_CURRENCY = "USD";
 
-function uid(): string {
-  return crypto.randomUUID();
+// Decimal places of currencies without two, from the backend so amounts
+// are read and shown with the precision they are stored in
+let currencyDigits: Record<string, number> = {};
+
+function minorDigits(currency: string): number {
+  return currencyDigits[currency] ?? 2;
+}
+
+// Parses the decimal text without going through floats; throws with a
+// message to show when the text is not a plain amount
+function parseMoney(v: string, currency: string): Money {
+  const digits = minorDigits(currency);
+  const text = v.trim();
+  if (!text) throw new Error("Enter an amount");
+  if (text.includes(",")) {
+    throw new Error("Write the amount with a decimal point and no separators, e.g. 1234.50");
+  }
+  const m = /^(\d*)(?:\.(\d*))?$/.exec(text);
+  if (!m || !(m[1] || m[2])) throw new Error(`"${text}" is not an amount`);
+  const frac = m[2] ?? "";
+  if (frac.length > digits) {
+    throw new Error(`${currency} amounts have at most ${digits} decimal places`);
+  }
+  const minor = Number((m[1] || "0") + frac.padEnd(digits, "0"));
+  if (!Number.isSafeInteger(minor)) throw new Error("Amount is too large");
+  return { minor, currency };
+}
+
+function moneyToInput(m: Money): string {
+  return (m.minor / 10 ** minorDigits(m.currency)).toFixed(minorDigits(m.currency));
 }
 
-function parseMoney(v: string): number {
-  const n = Number(v);
-  return Number.isFinite(n) ? Math.max(0, n) : 0;
+function fmtMoney(m: Money): string {
+  const digits = minorDigits(m.currency);
+  return (m.minor / 10 ** digits).toLocaleString(undefined, {
+    style: "currency",
+    currency: m.currency,
+    minimumFractionDigits: digits,
+    maximumFractionDigits: digits,
+  });
 }
 
-function fmtMoney(n: number): string {
-  return n.toLocaleString(undefined, { style: "currency", currency: "USD" });
+function recurrenceLabel(r: Recurrence): string {
+  if (typeof r === "string") return r;
+  if ("everyNDays" in r) return `every ${r.everyNDays.days}d`;
+  const { nth, weekday } = r.nthWeekday;
+  return `${nth === -1 ? "last" : `#${nth}`} ${weekday}`;
 }
 
 function daysUntil(dateStr: string): number {
@@ -66,57 +423,74 @@ function daysUntil(dateStr: string): number {
   return Math.round(diff / (1000 * 60 * 60 * 24));
 }
 
-function nextRecurringDate(dateStr: string, recurrence: Recurrence): string {
-  if (recurrence === "none") return dateStr;
-  const d = new Date(dateStr);
-  if (recurrence === "monthly") {
-    d.setMonth(d.getMonth() + 1);
-  } else if (recurrence === "yearly") {
-    d.setFullYear(d.getFullYear() + 1);
-  }
-  return d.toISOString().slice(0, 10);
+// Search words starting with "#" filter by tag, the rest by name and notes
+function currentFilter(): BillFilter {
+  const words = searchEl.value.trim().split(/\s+/).filter(Boolean);
+  const tags = words.filter((w) => w.startsWith("#")).map((w) => w.slice(1));
+  const text = words.filter((w) => !w.startsWith("#")).join(" ");
+  return {
+    categoryId: categoryFilterEl.value === "all" ? undefined : categoryFilterEl.value,
+    tags,
+    text: text || undefined,
+  };
 }
 
-async function initializeStore(): Promise<void> {
+async function load(): Promise<void> {
   try {
-    // Initialize store - use Store.load() for v2
-    store = await Store.load("bills.json");
-    console.log("Store initialized successfully");
+    bills = await invoke<Bill[]>("list_bills", { filter: currentFilter() });
+    console.log(`Loaded ${bills.length} bills`);
   } catch (error) {
-    console.error("Failed to initialize store:", error);
-    throw new Error("Could not initialize storage");
+    console.error("Failed to load bills:", error);
+    bills = [];
+    showError(`Failed to load bills: ${errorMessage(error)}. "Check data" can find the cause.`);
   }
+  await loadJournal();
 }
 
-async function load(): Promise<void> {
+// Every change the backend makes is journaled, so the buttons only need the
+// labels of what would be undone or redone
+async function loadJournal(): Promise<void> {
   try {
-    if (!store) {
-      throw new Error("Store not initialized");
-    }
-    const storedBills = await store.get<Bill[]>("bills");
-    bills = storedBills ?? [];
-    console.log(`Loaded ${bills.length} bills from storage`);
+    const status = await invoke<JournalStatus>("journal_status");
+    undoBtn.disabled = !status.undo;
+    undoBtn.title = status.undo ? `Undo: ${status.undo}` : "Nothing to undo";
+    redoBtn.disabled = !status.redo;
+    redoBtn.title = status.redo ? `Redo: ${status.redo}` : "Nothing to redo";
   } catch (error) {
-    console.error("Failed to load bills from storage:", error);
-    bills = [];
-    showError("Failed to load bills. Starting with empty list.");
+    console.error("Failed to load undo history:", error);
+    undoBtn.disabled = redoBtn.disabled = true;
   }
 }
 
-async function save(): Promise<boolean> {
+async function undoRedo(command: "undo" | "redo"): Promise<void> {
   try {
-    if (!store) {
-      throw new Error("Store not initialized");
-    }
-    await store.set("bills", bills);
-    await store.save();
-    console.log(`Saved ${bills.length} bills to storage`);
-    return true;
+    await invoke<string>(command);
   } catch (error) {
-    console.error("Failed to save bills to storage:", error);
-    showError("Failed to save bills. Changes may be lost.");
-    return false;
+    showError(`Failed to ${command}: ${errorMessage(error)}`);
+  }
+  await reloadAll();
+}
+
+function errorMessage(error: unknown): string {
+  if (error && typeof error === "object" && "message" in error) {
+    return (error as AppError).message;
   }
+  return String(error);
+}
+
+// Nothing works without the data, so the error takes the place of the app
+function showStartupError(error: AppError): void {
+  const main = document.querySelector("main")!;
+  const heading = document.createElement("h1");
+  heading.textContent = "Your bills could not be opened";
+  const message = document.createElement("p");
+  message.textContent = error.message;
+  const hint = document.createElement("p");
+  hint.textContent =
+    error.code === "newer_version"
+      ? "Install the latest version of AutoBillChecker to open them."
+      : "Fix the problem described above, then restart the app.";
+  main.replaceChildren(heading, message, hint);
 }
 
 function showError(message: string): void {
@@ -146,14 +520,10 @@ function showError(message: string): void {
 
 function render(): void {
   try {
-    const q = searchEl.value.toLowerCase();
     const status = filterEl.value;
 
+    // Text, tag and category filters are applied by `list_bills`
     const filtered = bills.filter((b) => {
-      const matches =
-        b.name.toLowerCase().includes(q) ||
-        (b.notes ?? "").toLowerCase().includes(q);
-      if (!matches) return false;
       const d = daysUntil(b.dueDate);
       if (status === "due") return !b.paid && d >= 0 && d <= 7;
       if (status === "overdue") return !b.paid && d < 0;
@@ -163,9 +533,7 @@ function render(): void {
     });
 
     billList.innerHTML = "";
-    let total = 0;
     for (const b of filtered) {
-      if (!b.paid) total += b.amount;
       const li = document.createElement("li");
       li.className = "bill-item";
       const d = daysUntil(b.dueDate);
@@ -188,12 +556,16 @@ function render(): void {
         </div>
         <div class="meta">
           <span>Due: ${b.dueDate} (${d === 0 ? "today" : d > 0 ? `${d}d` : `${-d}d ago`})</span>
-          ${b.recurrence !== "none" ? `<span class="badge">${b.recurrence}</span>` : ""}
+          ${b.recurrence !== "none" ? `<span class="badge">${recurrenceLabel(b.recurrence)}</span>` : ""}
+          ${b.categoryId ? `<span class="badge">${escapeHtml(categories.find((c) => c.id === b.categoryId)?.name ?? "")}</span>` : ""}
+          ${(b.tags ?? []).map((t) => `<span class="badge">#${escapeHtml(t)}</span>`).join("")}
           ${b.paid ? `<span class="badge paid">paid</span>` : ""}
+          ${!b.paid && b.paidAmount.minor > 0 ? `<span class="badge">${fmtMoney(b.balance)} left</span>` : ""}
         </div>
         <div class="item-actions">
           <button data-action="toggle-paid" data-id="${b.id}">${b.paid ? "Mark Unpaid" : "Mark Paid"}</button>
           <button data-action="edit" data-id="${b.id}" class="secondary">Edit</button>
+          <button data-action="history" data-id="${b.id}" class="secondary">History</button>
           <button data-action="delete" data-id="${b.id}" class="danger">Delete</button>
         </div>
         ${escapedNotes ? `<p class="notes">${escapedNotes}</p>` : ""}
@@ -202,13 +574,814 @@ function render(): void {
     }
 
     totalCountEl.textContent = `${filtered.length} bill${filtered.length !== 1 ? "s" : ""}`;
-    totalAmountEl.textContent = `Total: ${fmtMoney(total)}`;
+    void renderTotals(filtered.map((b) => b.id));
   } catch (error) {
     console.error("Failed to render bills:", error);
     showError("Failed to display bills");
   }
 }
 
+// Totals mix currencies, so the backend converts them with its rate table
+async function renderTotals(ids: string[]): Promise<void> {
+  try {
+    const t = await invoke<ConvertedTotal>("bill_totals", { ids });
+    let text = `Outstanding: ${fmtMoney(t.total)}`;
+    if (t.rateDate) text += ` (rates of ${t.rateDate})`;
+    if (t.missingRates.length > 0) {
+      text += `, excluding ${t.missingRates.join(", ")} (no rate)`;
+    }
+    totalAmountEl.textContent = text;
+  } catch (error) {
+    console.error("Failed to compute totals:", error);
+    totalAmountEl.textContent = "Outstanding: ?";
+  }
+}
+
+function categoryOptions(): string {
+  return categories
+    .map((c) => `<option value="${c.id}">${escapeHtml(c.name)}</option>`)
+    .join("");
+}
+
+async function loadCategories(): Promise<void> {
+  try {
+    categories = await invoke<Category[]>("list_categories");
+    const selected = categoryFilterEl.value;
+    categoryFilterEl.innerHTML =
+      `<option value="all">All categories</option>` +
+      `<option value="">Uncategorized</option>` +
+      categoryOptions();
+    categoryFilterEl.value = selected || "all";
+    const formSelected = categoryEl.value;
+    categoryEl.innerHTML =
+      `<option value="">None</option>` +
+      categoryOptions() +
+      `<option value="__new__">New category...</option>`;
+    categoryEl.value = formSelected === "__new__" ? "" : formSelected;
+    renderLedgerAccounts();
+  } catch (error) {
+    console.error("Failed to load categories:", error);
+  }
+}
+
+async function createCategoryFromForm(): Promise<void> {
+  const name = prompt("Category name");
+  if (!name) {
+    categoryEl.value = "";
+    return;
+  }
+  try {
+    const category = await invoke<Category>("create_category", { name });
+    await loadCategories();
+    categoryEl.value = category.id;
+  } catch (error) {
+    showError(errorMessage(error));
+    categoryEl.value = "";
+  }
+}
+
+async function loadSettings(): Promise<void> {
+  try {
+    const settings = await invoke<Settings>("get_settings");
+    reportingCurrencyEl.value = settings.reportingCurrency;
+    autoLockEl.value = String(settings.autoLockMinutes);
+    trashRetentionEl.value = String(settings.trashRetentionDays);
+    reminderDays = settings.reminderDays;
+    reminderDaysEl.value = String(reminderDays);
+    ledgerAccounts = settings.ledgerAccounts;
+    renderLedgerAccounts();
+    renderBankSettings(settings);
+  } catch (error) {
+    console.error("Failed to load settings:", error);
+  }
+}
+
+async function loadBackups(): Promise<void> {
+  try {
+    const backups = await invoke<Backup[]>("list_backups");
+    const labels = { daily: "Daily", weekly: "Weekly", beforeRestore: "Before restore" };
+    backupListEl.innerHTML = backups.length
+      ? backups
+          .map(
+            (b) =>
+              `<option value="${escapeHtml(b.name)}">${labels[b.kind]} – ${new Date(
+                b.createdAt
+              ).toLocaleString()}</option>`
+          )
+          .join("")
+      : `<option value="">No backups yet</option>`;
+    restoreBackupBtn.disabled = backups.length === 0;
+  } catch (error) {
+    console.error("Failed to list backups:", error);
+  }
+}
+
+async function restoreBackup(): Promise<void> {
+  const name = backupListEl.value;
+  if (!name) return;
+  const label = backupListEl.selectedOptions[0]?.textContent ?? name;
+  if (!confirm(`Replace all current data with the backup "${label}"?`)) return;
+  try {
+    await invoke("restore_backup", { name });
+    await reloadAll();
+    resetForm();
+  } catch (error) {
+    console.error("Failed to restore backup:", error);
+    showError(`Failed to restore backup: ${errorMessage(error)}`);
+  }
+  await loadBackups();
+}
+
+async function exportArchive(): Promise<void> {
+  try {
+    const bytes = await invoke<number[]>("export_archive");
+    const date = new Date().toISOString().slice(0, 10);
+    downloadFile(`autobillchecker-${date}.zip`, new Uint8Array(bytes), "application/zip");
+  } catch (error) {
+    showError(`Failed to export the archive: ${errorMessage(error)}`);
+  }
+}
+
+// Verifies the archive with a dry run and asks before restoring it
+async function restoreArchive(file: File): Promise<void> {
+  const archive = Array.from(new Uint8Array(await file.arrayBuffer()));
+  const mode = archiveModeEl.value;
+  try {
+    const report = await invoke<RestoreReport>("import_archive", { archive, mode, dryRun: true });
+    const counts =
+      `${report.bills} bills, ${report.payments} payments, ${report.categories} categories ` +
+      `and ${report.rates} exchange rates`;
+    const lines = [
+      `Archive from ${new Date(report.createdAt).toLocaleString()} (version ${report.appVersion}).`,
+      mode === "replace"
+        ? `Replace all current data with ${counts}?`
+        : `Add ${counts}?${report.skipped ? ` ${report.skipped} records are already here and will be skipped.` : ""}`,
+    ];
+    if (report.issues) lines.push(`The data check finds ${report.issues} problems in the archive.`);
+    if (!confirm(lines.join("\n\n"))) return;
+    await invoke("import_archive", { archive, mode, dryRun: false });
+    await reloadAll();
+    resetForm();
+  } catch (error) {
+    showError(`Failed to restore the archive: ${errorMessage(error)}`);
+  }
+  await loadBackups();
+}
+
+// One account field per category, and one per payment method plus a blank
+// pair for adding another method
+function renderLedgerAccounts(): void {
+  if (!ledgerAccounts) return;
+  const accounts = ledgerAccounts;
+  (document.querySelector("#ledger-expense") as HTMLInputElement).value = accounts.expense;
+  (document.querySelector("#ledger-payment") as HTMLInputElement).value = accounts.payment;
+  document.querySelector("#ledger-categories")!.innerHTML = categories
+    .map(
+      (c) => `<label>
+        ${escapeHtml(c.name)}
+        <input data-category="${escapeHtml(c.id)}" value="${escapeHtml(accounts.categories?.[c.id] ?? "")}"
+          placeholder="${escapeHtml(`${accounts.expense}:${c.name}`)}" />
+      </label>`
+    )
+    .join("");
+  const methods: [string, string][] = [...Object.entries(accounts.methods ?? {}), ["", ""]];
+  document.querySelector("#ledger-methods")!.innerHTML = methods
+    .map(
+      ([method, account]) => `<div class="filters">
+        <input data-method value="${escapeHtml(method)}" placeholder="Payment method, e.g. Card" />
+        <input data-method-account value="${escapeHtml(account)}" placeholder="${escapeHtml(accounts.payment)}" />
+      </div>`
+    )
+    .join("");
+}
+
+async function saveLedgerAccounts(event: Event): Promise<void> {
+  event.preventDefault();
+  const categoryAccounts: Record<string, string> = {};
+  document.querySelectorAll<HTMLInputElement>("#ledger-categories input").forEach((input) => {
+    categoryAccounts[input.dataset.category!] = input.value;
+  });
+  const methodAccounts: Record<string, string> = {};
+  document.querySelectorAll<HTMLElement>("#ledger-methods > div").forEach((row) => {
+    const method = row.querySelector<HTMLInputElement>("[data-method]")!.value;
+    methodAccounts[method] = row.querySelector<HTMLInputElement>("[data-method-account]")!.value;
+  });
+  const accounts: LedgerAccounts = {
+    expense: (document.querySelector("#ledger-expense") as HTMLInputElement).value,
+    payment: (document.querySelector("#ledger-payment") as HTMLInputElement).value,
+    categories: categoryAccounts,
+    methods: methodAccounts,
+  };
+  try {
+    await invoke("set_ledger_accounts", { accounts });
+  } catch (error) {
+    showError(errorMessage(error));
+  }
+  await loadSettings();
+}
+
+async function exportLedger(): Promise<void> {
+  const format = (document.querySelector("#ledger-format") as HTMLSelectElement).value;
+  const from = (document.querySelector("#ledger-from") as HTMLInputElement).value || null;
+  const to = (document.querySelector("#ledger-to") as HTMLInputElement).value || null;
+  try {
+    const journal = await invoke<string>("export_ledger", { options: { format, from, to } });
+    const extension = format === "beancount" ? "beancount" : "journal";
+    downloadFile(`payments-${new Date().toISOString().slice(0, 10)}.${extension}`, journal, "text/plain");
+  } catch (error) {
+    showError(`Failed to export payments: ${errorMessage(error)}`);
+  }
+}
+
+// Lists the proposed payments, all ticked, for the user to confirm
+function showBankMatches(report: MatchReport): void {
+  bankProposals = report.proposals;
+  const parts = [`${report.proposals.length} matched`, `${report.unmatched.length} unmatched`];
+  if (report.alreadyRecorded) parts.push(`${report.alreadyRecorded} already recorded`);
+  document.querySelector("#bank-summary")!.textContent = parts.join(", ");
+  document.querySelector("#bank-proposals")!.innerHTML = report.proposals
+    .map((p, i) => {
+      const paid = { ...p.transaction.amount, minor: -p.transaction.amount.minor };
+      return `<li><label><input type="checkbox" data-proposal="${i}" checked />
+        ${p.transaction.date} ${escapeHtml(p.transaction.payee)} ${fmtMoney(paid)} →
+        ${escapeHtml(p.billName)} due ${p.occurrenceDate} (${Math.round(p.score * 100)}%)</label></li>`;
+    })
+    .join("");
+  (document.querySelector("#bank-review") as HTMLElement).hidden = report.proposals.length === 0;
+  (document.querySelector("#bank-possibly-paid") as HTMLElement).hidden = true;
+  document.querySelector("#bank-errors")!.innerHTML = "";
+}
+
+// Saved profiles, with the one picked before kept, and the learned payees
+function renderBankSettings(settings: Settings): void {
+  bankProfiles = settings.bankProfiles;
+  const select = document.querySelector<HTMLSelectElement>("#bank-profile")!;
+  const selected = select.value;
+  select.innerHTML =
+    bankProfiles.map((p) => `<option value="${escapeHtml(p.name)}">${escapeHtml(p.name)}</option>`).join("") +
+    `<option value="">New bank profile...</option>`;
+  select.value = bankProfiles.some((p) => p.name === selected) ? selected : (bankProfiles[0]?.name ?? "");
+  const payees = Object.entries(settings.payeeBills);
+  document.querySelector("#bank-payees")!.innerHTML = payees
+    .map(([payee, billId]) => {
+      const bill = bills.find((b) => b.id === billId);
+      return `<li>${escapeHtml(payee)} → ${escapeHtml(bill?.name ?? billId)}
+        <button type="button" class="secondary" data-forget="${escapeHtml(payee)}">Forget</button></li>`;
+    })
+    .join("");
+  (document.querySelector("#bank-learned") as HTMLElement).hidden = payees.length === 0;
+}
+
+// Shows the statement's first rows with a column picker per field, for a
+// new profile
+async function previewBankCsv(): Promise<void> {
+  try {
+    const preview = await invoke<BankCsvPreview>("preview_bank_csv", { csv: bankCsvText });
+    const columns = preview.headers
+      .map((h, i) => {
+        const label = preview.hasHeader && h ? h : `Column ${i + 1}`;
+        return `<option value="${i}">${escapeHtml(label)}</option>`;
+      })
+      .join("");
+    const columnsEl = document.querySelector("#bank-profile-columns")!;
+    columnsEl.innerHTML = (Object.keys(BANK_COLUMNS) as BankColumn[])
+      .map(
+        (field) =>
+          `<label>${BANK_COLUMNS[field]}<select data-field="${field}"><option value="">None</option>${columns}</select></label>`
+      )
+      .join("");
+    columnsEl.querySelectorAll<HTMLSelectElement>("select").forEach((select) => {
+      select.value = String(preview.mapping[select.dataset.field as BankColumn] ?? "");
+    });
+    const formats = preview.dateFormats.length
+      ? preview.dateFormats
+      : (Object.keys(DATE_FORMATS) as DateFormat[]);
+    document.querySelector("#bank-date-format")!.innerHTML = formats
+      .map((f) => `<option value="${f}">${DATE_FORMATS[f]}</option>`)
+      .join("");
+    (document.querySelector("#bank-currency") as HTMLInputElement).value = reportingCurrencyEl.value;
+    (document.querySelector("#bank-has-header") as HTMLInputElement).checked = preview.hasHeader;
+    (document.querySelector("#bank-debits-positive") as HTMLInputElement).checked = false;
+    const sample = preview.hasHeader ? [preview.headers, ...preview.rows] : preview.rows;
+    document.querySelector("#bank-sample")!.innerHTML = sample
+      .map((row) => `<tr>${row.map((c) => `<td>${escapeHtml(c)}</td>`).join("")}</tr>`)
+      .join("");
+    (document.querySelector("#bank-profile-editor") as HTMLElement).hidden = false;
+  } catch (error) {
+    showError(`Failed to read the statement: ${errorMessage(error)}`);
+  }
+}
+
+async function importBankCsv(file: File): Promise<void> {
+  bankCsvText = await file.text();
+  const profile = document.querySelector<HTMLSelectElement>("#bank-profile")!.value;
+  if (profile) {
+    await reconcileBankCsv(profile);
+  } else {
+    await previewBankCsv();
+  }
+}
+
+async function saveBankProfile(): Promise<void> {
+  const columns: Partial<Record<BankColumn, number>> = {};
+  document.querySelectorAll<HTMLSelectElement>("#bank-profile-columns select").forEach((select) => {
+    if (select.value !== "") columns[select.dataset.field as BankColumn] = Number(select.value);
+  });
+  const profile: BankProfile = {
+    name: (document.querySelector("#bank-profile-name") as HTMLInputElement).value,
+    columns,
+    dateFormat: (document.querySelector("#bank-date-format") as HTMLSelectElement).value as DateFormat,
+    currency: (document.querySelector("#bank-currency") as HTMLInputElement).value.trim().toUpperCase(),
+    hasHeader: (document.querySelector("#bank-has-header") as HTMLInputElement).checked,
+    debitsPositive: (document.querySelector("#bank-debits-positive") as HTMLInputElement).checked,
+  };
+  try {
+    const settings = await invoke<Settings>("save_bank_profile", { profile });
+    const select = document.querySelector<HTMLSelectElement>("#bank-profile")!;
+    select.value = "";
+    renderBankSettings(settings);
+    select.value = profile.name.trim();
+    (document.querySelector("#bank-profile-editor") as HTMLElement).hidden = true;
+    await reconcileBankCsv(select.value);
+  } catch (error) {
+    showError(errorMessage(error));
+  }
+}
+
+async function deleteBankProfile(): Promise<void> {
+  const name = document.querySelector<HTMLSelectElement>("#bank-profile")!.value;
+  if (!name || !confirm(`Delete the bank profile "${name}"?`)) return;
+  try {
+    renderBankSettings(await invoke<Settings>("delete_bank_profile", { name }));
+  } catch (error) {
+    showError(errorMessage(error));
+  }
+}
+
+// Proposals as for OFX statements, plus the overdue bills the rest of the
+// statement suggests are paid and the rows that could not be read
+async function reconcileBankCsv(profile: string): Promise<void> {
+  try {
+    const report = await invoke<Reconciliation>("reconcile_bank_csv", {
+      csv: bankCsvText,
+      profile,
+      options: {},
+    });
+    showBankMatches(report);
+    if (report.possiblyPaid.length) {
+      const summary = document.querySelector("#bank-summary")!;
+      summary.textContent += `, ${report.possiblyPaid.length} overdue possibly paid`;
+    }
+    document.querySelector("#bank-possibly-paid-list")!.innerHTML = report.possiblyPaid
+      .map((p) => {
+        const candidates = p.transactions
+          .map((t) => {
+            const paid = { ...t.amount, minor: -t.amount.minor };
+            return `${t.date} ${escapeHtml(t.payee)} ${fmtMoney(paid)}`;
+          })
+          .join("; ");
+        return `<li>${escapeHtml(p.billName)} due ${p.dueDate}, ${fmtMoney(p.balance)} owed: ${candidates}</li>`;
+      })
+      .join("");
+    (document.querySelector("#bank-possibly-paid") as HTMLElement).hidden = report.possiblyPaid.length === 0;
+    document.querySelector("#bank-errors")!.innerHTML = report.errors
+      .map((e) => `<li>Line ${e.line}: ${escapeHtml(e.message)}</li>`)
+      .join("");
+  } catch (error) {
+    showError(`Failed to reconcile the statement: ${errorMessage(error)}`);
+  }
+}
+
+async function importOfx(file: File): Promise<void> {
+  try {
+    const report = await invoke<MatchReport>("import_ofx", { ofx: await file.text(), options: {} });
+    showBankMatches(report);
+  } catch (error) {
+    showError(`Failed to read the statement: ${errorMessage(error)}`);
+  }
+}
+
+async function recordBankPayments(): Promise<void> {
+  const matches = [...document.querySelectorAll<HTMLInputElement>("#bank-proposals input:checked")].map(
+    (input) => {
+      const p = bankProposals[Number(input.dataset.proposal)];
+      return {
+        transactionId: p.transaction.id,
+        payee: p.transaction.payee,
+        billId: p.billId,
+        occurrenceDate: p.occurrenceDate,
+        paidDate: p.transaction.date,
+        amount: { ...p.transaction.amount, minor: -p.transaction.amount.minor },
+      };
+    }
+  );
+  try {
+    await invoke("record_bank_payments", { matches });
+    (document.querySelector("#bank-review") as HTMLElement).hidden = true;
+    document.querySelector("#bank-summary")!.textContent = `Recorded ${matches.length} payments`;
+    await reloadAll();
+  } catch (error) {
+    showError(`Failed to record payments: ${errorMessage(error)}`);
+  }
+}
+
+const REPAIR_LABELS: Record<Repair, string> = {
+  newId: "Give new id",
+  clearCategory: "Clear category",
+  resetStartDate: "Restart schedule",
+  quarantine: "Move to quarantine",
+};
+
+function renderIntegrity(report: IntegrityReport): void {
+  integrityIssues = report.issues;
+  integritySummaryEl.textContent = report.issues.length
+    ? `${report.issues.length} issue${report.issues.length === 1 ? "" : "s"} in ${report.records} records`
+    : `All ${report.records} records look fine.`;
+  integrityIssuesEl.innerHTML = report.issues
+    .map(
+      (issue, i) =>
+        `<li>${escapeHtml(issue.message)} ${issue.repairs
+          .map(
+            (r) =>
+              `<button type="button" class="secondary" data-issue="${i}" data-repair="${r}">${REPAIR_LABELS[r]}</button>`
+          )
+          .join(" ")}</li>`
+    )
+    .join("");
+}
+
+async function checkIntegrity(): Promise<void> {
+  try {
+    renderIntegrity(await invoke<IntegrityReport>("check_integrity"));
+  } catch (error) {
+    showError(`Failed to check data: ${errorMessage(error)}`);
+  }
+}
+
+async function repairIssue(issue: Issue, repair: Repair): Promise<void> {
+  try {
+    const { collection, index, recordId } = issue;
+    const report = await invoke<IntegrityReport>("repair_integrity", {
+      repairs: [{ collection, index, recordId, repair }],
+    });
+    renderIntegrity(report);
+    await reloadAll();
+  } catch (error) {
+    showError(`Failed to repair: ${errorMessage(error)}`);
+    await checkIntegrity();
+  }
+}
+
+function historyValue(value: unknown): string {
+  if (value === null || value === undefined) return "–";
+  if (typeof value === "object" && "minor" in value && "currency" in value) {
+    return fmtMoney(value as Money);
+  }
+  return typeof value === "string" ? value : JSON.stringify(value);
+}
+
+// Shows the change history under a bill, or hides it when already shown
+async function toggleHistory(item: HTMLElement, id: string): Promise<void> {
+  const shown = item.querySelector(".history");
+  if (shown) {
+    shown.remove();
+    return;
+  }
+  const entries = await invoke<HistoryEntry[]>("bill_history", { id });
+  const list = document.createElement("ol");
+  list.className = "history";
+  list.innerHTML = entries
+    .map((e) => {
+      const changes = (e.changes ?? [])
+        .map(
+          (c) =>
+            `${escapeHtml(c.field)}: ${escapeHtml(historyValue(c.old))} → ${escapeHtml(
+              historyValue(c.new)
+            )}`
+        )
+        .join("; ");
+      return `<li>${new Date(e.at).toLocaleString()} – ${e.event} (${e.source})${
+        changes ? `: ${changes}` : ""
+      }</li>`;
+    })
+    .join("");
+  item.appendChild(list);
+}
+
+async function loadTrash(): Promise<void> {
+  try {
+    const trash = await invoke<TrashedBill[]>("list_trash");
+    trashListEl.innerHTML = trash.length
+      ? trash
+          .map((t) => {
+            const payments = t.payments?.length ?? 0;
+            const extra = payments ? `, ${payments} payment${payments === 1 ? "" : "s"}` : "";
+            return `<option value="${escapeHtml(t.bill.id)}">${escapeHtml(t.bill.name)} – deleted ${new Date(
+              t.deletedAt
+            ).toLocaleString()}${extra}</option>`;
+          })
+          .join("")
+      : `<option value="">Trash is empty</option>`;
+    restoreTrashedBtn.disabled = purgeTrashedBtn.disabled = emptyTrashBtn.disabled =
+      trash.length === 0;
+  } catch (error) {
+    console.error("Failed to list trash:", error);
+  }
+}
+
+async function trashAction(command: "restore_bill" | "purge_trash", id?: string): Promise<void> {
+  try {
+    await invoke(command, { id });
+    await load();
+    render();
+  } catch (error) {
+    console.error(`Failed to ${command}:`, error);
+    showError(errorMessage(error));
+  }
+  await loadTrash();
+}
+
+async function reloadAll(): Promise<void> {
+  await loadCategories();
+  await load();
+  await loadSettings();
+  await loadBackups();
+  await loadTrash();
+  render();
+}
+
+const BILL_COLUMNS: Record<BillColumn, string> = {
+  name: "Name",
+  amount: "Amount",
+  currency: "Currency",
+  dueDate: "Due date",
+  recurrence: "Recurs",
+  category: "Category",
+  tags: "Tags",
+  notes: "Notes",
+  endDate: "Ends on",
+  maxOccurrences: "Occurrences",
+};
+
+const BANK_COLUMNS: Record<BankColumn, string> = {
+  date: "Date",
+  payee: "Payee",
+  memo: "Memo",
+  amount: "Amount",
+  debit: "Debit",
+  credit: "Credit",
+  id: "Reference",
+};
+
+const DATE_FORMATS: Record<DateFormat, string> = {
+  iso: "2024-01-31",
+  yearMonthDay: "2024/01/31",
+  dayMonthYear: "31/01/2024",
+  monthDayYear: "01/31/2024",
+  dayMonthYearDots: "31.01.2024",
+  dayMonthYearDashes: "31-01-2024",
+};
+
+function downloadFile(filename: string, content: BlobPart, type: string): void {
+  const url = URL.createObjectURL(new Blob([content], { type }));
+  const link = document.createElement("a");
+  link.href = url;
+  link.download = filename;
+  link.click();
+  URL.revokeObjectURL(url);
+}
+
+async function exportCsv(command: "export_bills_csv" | "export_payments_csv", name: string): Promise<void> {
+  try {
+    const csv = await invoke<string>(command);
+    downloadFile(`${name}-${new Date().toISOString().slice(0, 10)}.csv`, csv, "text/csv");
+  } catch (error) {
+    showError(`Failed to export ${name}: ${errorMessage(error)}`);
+  }
+}
+
+// Shows the file's first rows with a column picker per field
+async function previewImport(file: File): Promise<void> {
+  try {
+    importText = await file.text();
+    const preview = await invoke<CsvPreview>("preview_bills_csv", { csv: importText });
+    const columns = preview.headers
+      .map((h, i) => `<option value="${i}">${escapeHtml(h || `Column ${i + 1}`)}</option>`)
+      .join("");
+    importMappingEl.innerHTML = (Object.keys(BILL_COLUMNS) as BillColumn[])
+      .map(
+        (field) =>
+          `<label>${BILL_COLUMNS[field]}<select data-field="${field}"><option value="">Not imported</option>${columns}</select></label>`
+      )
+      .join("");
+    importMappingEl.querySelectorAll<HTMLSelectElement>("select").forEach((select) => {
+      select.value = String(preview.mapping[select.dataset.field as BillColumn] ?? "");
+    });
+    const formats = preview.dateFormats.length
+      ? preview.dateFormats
+      : (Object.keys(DATE_FORMATS) as DateFormat[]);
+    importDateFormatEl.innerHTML = formats
+      .map((f) => `<option value="${f}">${DATE_FORMATS[f]}</option>`)
+      .join("");
+    importSampleEl.innerHTML = [preview.headers, ...preview.rows]
+      .map((row) => `<tr>${row.map((c) => `<td>${escapeHtml(c)}</td>`).join("")}</tr>`)
+      .join("");
+    importSummaryEl.textContent = `${preview.rowCount} row${preview.rowCount === 1 ? "" : "s"} in the file.`;
+    importErrorsEl.innerHTML = "";
+    importPreviewEl.hidden = false;
+  } catch (error) {
+    showError(`Failed to read the file: ${errorMessage(error)}`);
+  }
+  importFileEl.value = "";
+}
+
+async function runImport(dryRun: boolean): Promise<void> {
+  const mapping: Partial<Record<BillColumn, number>> = {};
+  importMappingEl.querySelectorAll<HTMLSelectElement>("select").forEach((select) => {
+    if (select.value !== "") mapping[select.dataset.field as BillColumn] = Number(select.value);
+  });
+  try {
+    const report = await invoke<CsvImportReport>("import_bills_csv", {
+      csv: importText,
+      options: { mapping, dateFormat: importDateFormatEl.value },
+      dryRun,
+    });
+    const count = `${report.bills.length} bill${report.bills.length === 1 ? "" : "s"}`;
+    const categories = report.newCategories.length
+      ? `, adding categories ${report.newCategories.join(", ")}`
+      : "";
+    const skipped = report.errors.length ? `; rows with errors are skipped` : "";
+    importSummaryEl.textContent = dryRun
+      ? `Would import ${count}${categories}${skipped}.`
+      : `Imported ${count}${categories}.`;
+    importErrorsEl.innerHTML = report.errors
+      .map(
+        (e) =>
+          `<li>Line ${e.line}${e.column ? ` (${BILL_COLUMNS[e.column]})` : ""}: ${escapeHtml(e.message)}</li>`
+      )
+      .join("");
+    if (!dryRun) {
+      importPreviewEl.hidden = report.errors.length === 0;
+      await reloadAll();
+    }
+  } catch (error) {
+    showError(`Failed to import: ${errorMessage(error)}`);
+  }
+}
+
+// Options from the preview: unticked events are left out, and typed
+// amounts fill in for events without one
+function icsOptions(): Record<string, unknown> {
+  const exclude: string[] = [];
+  const amounts: Record<string, Money> = {};
+  icsEventsEl.querySelectorAll<HTMLInputElement>("input[data-uid]").forEach((input) => {
+    const uid = input.dataset.uid!;
+    if (input.type === "checkbox" && !input.checked) exclude.push(uid);
+    if (input.type === "text" && input.value) {
+      amounts[uid] = parseMoney(input.value, reportingCurrencyEl.value || DEFAULT_CURRENCY);
+    }
+  });
+  return { exclude, amounts };
+}
+
+async function runIcsImport(dryRun: boolean): Promise<void> {
+  try {
+    const options = icsOptions();
+    const report = await invoke<IcsImportReport>("import_ics", { ics: icsText, options, dryRun });
+    if (!dryRun) {
+      icsPreviewEl.hidden = true;
+      const count = report.bills.length;
+      alert(`Imported ${count} bill${count === 1 ? "" : "s"} from the calendar.`);
+      await reloadAll();
+      return;
+    }
+    const excluded = options.exclude as string[];
+    report.bills.forEach((b, i) => {
+      icsLabels.set(
+        report.uids[i],
+        `${escapeHtml(b.name)} – ${fmtMoney(b.amount)}, ${recurrenceLabel(b.recurrence)}, next due ${b.dueDate}`
+      );
+    });
+    const line = (uid: string, checked: boolean) =>
+      `<li><label><input type="checkbox" data-uid="${escapeHtml(uid)}"${checked ? " checked" : ""} /> ${
+        icsLabels.get(uid) ?? escapeHtml(uid)
+      }</label></li>`;
+    const bills = report.uids.map((uid) => line(uid, true));
+    const left = excluded.map((uid) => line(uid, false));
+    const skipped = report.skipped.map(
+      (s) =>
+        `<li>${escapeHtml(s.summary || s.uid)}: ${escapeHtml(s.message)}${
+          s.needsAmount
+            ? ` <input type="text" inputmode="decimal" placeholder="Amount" data-uid="${escapeHtml(s.uid)}" />`
+            : ""
+        }</li>`
+    );
+    icsEventsEl.innerHTML = [...bills, ...left, ...skipped].join("");
+    icsPreviewEl.hidden = false;
+  } catch (error) {
+    showError(`Failed to import the calendar: ${errorMessage(error)}`);
+  }
+}
+
+function renderProfiles(list: ProfileList): void {
+  profiles = list;
+  profileSelectEl.innerHTML = list.profiles
+    .map(
+      (p) =>
+        `<option value="${escapeHtml(p.id)}"${p.id === list.active ? " selected" : ""}>${escapeHtml(
+          p.name
+        )}</option>`
+    )
+    .join("");
+  deleteProfileBtn.disabled = list.profiles.length < 2;
+}
+
+async function loadProfiles(): Promise<void> {
+  try {
+    renderProfiles(await invoke<ProfileList>("list_profiles"));
+  } catch (error) {
+    console.error("Failed to list profiles:", error);
+  }
+}
+
+// Everything on screen belongs to the open profile, so all of it reloads
+async function switchProfile(id: string): Promise<void> {
+  try {
+    renderProfiles(await invoke<ProfileList>("switch_profile", { id }));
+    resetForm();
+    integrityIssuesEl.innerHTML = "";
+    integritySummaryEl.textContent = "";
+    vaultLocked = false;
+    if ((await refreshVault()).locked) {
+      passphraseEl.focus();
+    } else {
+      await reloadAll();
+    }
+  } catch (error) {
+    console.error("Failed to switch profile:", error);
+    showError(errorMessage(error));
+    await loadProfiles();
+  }
+}
+
+async function profileAction(command: string, args: Record<string, unknown>): Promise<void> {
+  try {
+    await invoke<Profile>(command, args);
+    profileNameEl.value = "";
+  } catch (error) {
+    console.error(`Failed to ${command}:`, error);
+    showError(errorMessage(error));
+  }
+  await loadProfiles();
+}
+
+// Shows the vault controls that apply; clears the list once it locks
+async function refreshVault(): Promise<VaultStatus> {
+  const status = await invoke<VaultStatus>("vault_status");
+  vaultStatusEl.textContent = !status.enabled
+    ? "Data is stored unencrypted."
+    : status.locked
+      ? "Data is encrypted and locked."
+      : "Data is encrypted and unlocked.";
+  unlockBtn.hidden = !status.locked;
+  lockBtn.hidden = !status.enabled || status.locked;
+  enableVaultBtn.hidden = status.enabled;
+  disableVaultBtn.hidden = !status.enabled || status.locked;
+  changePassphraseBtn.hidden = !status.enabled || status.locked;
+  passphraseEl.hidden = !status.enabled;
+  newPassphraseEl.hidden = status.locked;
+  if (status.locked && !vaultLocked) {
+    bills = [];
+    render();
+  }
+  vaultLocked = status.locked;
+  return status;
+}
+
+async function vaultAction(command: string, args: Record<string, unknown> = {}): Promise<void> {
+  try {
+    await invoke(command, args);
+    passphraseEl.value = "";
+    newPassphraseEl.value = "";
+  } catch (error) {
+    console.error(`Failed to ${command}:`, error);
+    showError(errorMessage(error));
+  }
+  const status = await refreshVault();
+  if (!status.locked) await reloadAll();
+}
+
+async function importRates(file: File): Promise<void> {
+  try {
+    const count = await invoke<number>("import_rates", { csv: await file.text() });
+    console.log(`Imported ${count} exchange rates`);
+    render();
+  } catch (error) {
+    console.error("Failed to import rates:", error);
+    showError(`Failed to import rates: ${errorMessage(error)}`);
+  }
+}
+
 function escapeHtml(unsafe: string): string {
   return unsafe
     .replace(/&/g, "&amp;")
@@ -220,19 +1393,42 @@ function escapeHtml(unsafe: string): string {
 
 function resetForm(): void {
   idEl.value = "";
+  editingUpdatedAt = undefined;
   nameEl.value = "";
   amountEl.value = "";
+  currencyEl.value = DEFAULT_CURRENCY;
   dueEl.value = new Date().toISOString().slice(0, 10);
   recurrenceEl.value = "monthly";
+  editingRecurrence = undefined;
+  endDateEl.value = "";
+  maxOccurrencesEl.value = "";
+  editingPauses = undefined;
+  categoryEl.value = "";
+  tagsEl.value = "";
   notesEl.value = "";
 }
 
 function fillForm(b: Bill): void {
   idEl.value = b.id;
+  editingUpdatedAt = b.updatedAt;
   nameEl.value = b.name;
-  amountEl.value = String(b.amount);
+  amountEl.value = moneyToInput(b.amount);
+  currencyEl.value = b.amount.currency;
   dueEl.value = b.dueDate;
-  recurrenceEl.value = b.recurrence;
+  if (typeof b.recurrence === "string") {
+    recurrenceEl.value = b.recurrence;
+    editingRecurrence = undefined;
+  } else {
+    recurrenceEl.value = "custom";
+    editingRecurrence = b.recurrence;
+  }
+  endDateEl.value = b.limits?.endDate ?? "";
+  maxOccurrencesEl.value = b.limits?.maxOccurrences
+    ? String(b.limits.maxOccurrences)
+    : "";
+  editingPauses = b.limits?.pauses;
+  categoryEl.value = b.categoryId ?? "";
+  tagsEl.value = (b.tags ?? []).join(", ");
   notesEl.value = b.notes ?? "";
 }
 
@@ -258,11 +1454,12 @@ async function maybeNotifyDue(): Promise<void> {
       return;
     }
 
-    // Notify for items due today or overdue (up to 3 days) and unpaid
+    // Notify for unpaid items due within the reminder period or overdue
+    // (up to 3 days)
     const dueNow = bills.filter((b) => {
       if (b.paid) return false;
       const days = daysUntil(b.dueDate);
-      return days <= 0 && days >= -3;
+      return days <= reminderDays && days >= -3;
     });
 
     if (dueNow.length === 0) return;
@@ -271,7 +1468,8 @@ async function maybeNotifyDue(): Promise<void> {
       .slice(0, 4) // Limit to 4 bills to keep notification readable
       .map((b) => {
         const days = daysUntil(b.dueDate);
-        const dueText = days === 0 ? "today" : `${-days}d overdue`;
+        const dueText =
+          days === 0 ? "today" : days > 0 ? `in ${days}d` : `${-days}d overdue`;
         return `${b.name} (${fmtMoney(b.amount)}) ${dueText}`;
       })
       .join("\n");
@@ -287,56 +1485,47 @@ async function maybeNotifyDue(): Promise<void> {
   }
 }
 
-function validateBillForm(): string | null {
-  const name = nameEl.value.trim();
-  const amount = parseMoney(amountEl.value);
-  const dueDate = dueEl.value;
-
-  if (!name) return "Bill name is required";
-  if (amount <= 0) return "Amount must be greater than 0";
-  if (!dueDate) return "Due date is required";
-
-  // Validate date format
-  const dateRegex = /^\d{4}-\d{2}-\d{2}$/;
-  if (!dateRegex.test(dueDate)) return "Invalid due date format";
-
-  return null;
-}
-
-function upsertBillFromForm(): boolean {
-  const validationError = validateBillForm();
-  if (validationError) {
-    showError(validationError);
+async function upsertBillFromForm(): Promise<boolean> {
+  const currency = currencyEl.value.trim().toUpperCase();
+  if (!/^[A-Z]{3}$/.test(currency)) {
+    showError("Currency must be a three-letter code such as USD");
     return false;
   }
-
+  let amount: Money;
   try {
-    const id = idEl.value || uid();
-    const now = new Date().toISOString();
-    const existingIdx = bills.findIndex((b) => b.id === id);
-
-    const record: Bill = {
-      id,
-      name: nameEl.value.trim(),
-      amount: parseMoney(amountEl.value),
-      dueDate: dueEl.value,
-      recurrence: recurrenceEl.value as Recurrence,
-      notes: notesEl.value.trim() || undefined,
-      paid: existingIdx >= 0 ? bills[existingIdx].paid : false,
-      createdAt: existingIdx >= 0 ? bills[existingIdx].createdAt : now,
-      updatedAt: now,
-    };
-
-    if (existingIdx >= 0) {
-      bills[existingIdx] = record;
-    } else {
-      bills.push(record);
-    }
+    amount = parseMoney(amountEl.value, currency);
+  } catch (error) {
+    showError(errorMessage(error));
+    return false;
+  }
+  const input: BillInput = {
+    id: idEl.value || undefined,
+    name: nameEl.value,
+    amount,
+    dueDate: dueEl.value,
+    recurrence:
+      recurrenceEl.value === "custom" && editingRecurrence
+        ? editingRecurrence
+        : (recurrenceEl.value as Recurrence),
+    limits: {
+      endDate: endDateEl.value || undefined,
+      maxOccurrences: maxOccurrencesEl.value
+        ? Number(maxOccurrencesEl.value)
+        : undefined,
+      pauses: editingPauses,
+    },
+    categoryId: categoryEl.value || undefined,
+    tags: tagsEl.value.split(","),
+    notes: notesEl.value || undefined,
+    updatedAt: editingUpdatedAt,
+  };
 
+  try {
+    await invoke<Bill>("upsert_bill", { input });
     return true;
   } catch (error) {
     console.error("Failed to create/update bill:", error);
-    showError("Failed to save bill");
+    showError(errorMessage(error));
     return false;
   }
 }
@@ -349,42 +1538,96 @@ window.addEventListener("DOMContentLoaded", async () => {
     idEl = document.querySelector("#bill-id")!;
     nameEl = document.querySelector("#bill-name")!;
     amountEl = document.querySelector("#bill-amount")!;
+    currencyEl = document.querySelector("#bill-currency")!;
     dueEl = document.querySelector("#bill-due")!;
     recurrenceEl = document.querySelector("#bill-recurrence")!;
+    endDateEl = document.querySelector("#bill-end-date")!;
+    maxOccurrencesEl = document.querySelector("#bill-max-occurrences")!;
+    categoryEl = document.querySelector("#bill-category")!;
+    tagsEl = document.querySelector("#bill-tags")!;
     notesEl = document.querySelector("#bill-notes")!;
     resetBtn = document.querySelector("#reset-form")!;
     billList = document.querySelector("#bill-list")!;
     searchEl = document.querySelector("#search")!;
     filterEl = document.querySelector("#filter-status")!;
+    categoryFilterEl = document.querySelector("#filter-category")!;
     totalCountEl = document.querySelector("#total-count")!;
     totalAmountEl = document.querySelector("#total-amount")!;
+    reportingCurrencyEl = document.querySelector("#reporting-currency")!;
+    ratesFileEl = document.querySelector("#rates-file")!;
+    backupListEl = document.querySelector("#backup-list")!;
+    archiveModeEl = document.querySelector("#archive-mode")!;
+    restoreBackupBtn = document.querySelector("#restore-backup")!;
+    vaultStatusEl = document.querySelector("#vault-status")!;
+    passphraseEl = document.querySelector("#vault-passphrase")!;
+    newPassphraseEl = document.querySelector("#vault-new-passphrase")!;
+    unlockBtn = document.querySelector("#vault-unlock")!;
+    lockBtn = document.querySelector("#vault-lock")!;
+    enableVaultBtn = document.querySelector("#vault-enable")!;
+    disableVaultBtn = document.querySelector("#vault-disable")!;
+    changePassphraseBtn = document.querySelector("#vault-change")!;
+    autoLockEl = document.querySelector("#auto-lock")!;
+    checkIntegrityBtn = document.querySelector("#check-integrity")!;
+    integritySummaryEl = document.querySelector("#integrity-summary")!;
+    integrityIssuesEl = document.querySelector("#integrity-issues")!;
+    trashListEl = document.querySelector("#trash-list")!;
+    restoreTrashedBtn = document.querySelector("#restore-trashed")!;
+    purgeTrashedBtn = document.querySelector("#purge-trashed")!;
+    emptyTrashBtn = document.querySelector("#empty-trash")!;
+    trashRetentionEl = document.querySelector("#trash-retention")!;
+    undoBtn = document.querySelector("#undo")!;
+    redoBtn = document.querySelector("#redo")!;
+    reminderDaysEl = document.querySelector("#reminder-days")!;
+    icsFileEl = document.querySelector("#ics-file")!;
+    icsPreviewEl = document.querySelector("#ics-preview")!;
+    icsEventsEl = document.querySelector("#ics-events")!;
+    importFileEl = document.querySelector("#import-file")!;
+    importPreviewEl = document.querySelector("#import-preview")!;
+    importMappingEl = document.querySelector("#import-mapping")!;
+    importDateFormatEl = document.querySelector("#import-date-format")!;
+    importSampleEl = document.querySelector("#import-sample")!;
+    importSummaryEl = document.querySelector("#import-summary")!;
+    importErrorsEl = document.querySelector("#import-errors")!;
+    profileSelectEl = document.querySelector("#profile-select")!;
+    profileNameEl = document.querySelector("#profile-name")!;
+    createProfileBtn = document.querySelector("#create-profile")!;
+    renameProfileBtn = document.querySelector("#rename-profile")!;
+    deleteProfileBtn = document.querySelector("#delete-profile")!;
 
     // Check if all required elements exist
     if (!billForm || !nameEl || !amountEl || !dueEl || !billList) {
       throw new Error("Required DOM elements not found");
     }
 
-    // Initialize store first
-    await initializeStore();
-    await load();
-
     // Set default due date if not set
     if (!dueEl.value) {
       dueEl.value = new Date().toISOString().slice(0, 10);
     }
 
-    render();
-    await maybeNotifyDue();
+    const startupError = await invoke<AppError | null>("startup_error");
+    if (startupError) {
+      showStartupError(startupError);
+      return;
+    }
+    currencyDigits = await invoke<Record<string, number>>("currency_digits");
+    await loadProfiles();
+
+    // An encrypted vault has to be unlocked before anything can load
+    if ((await refreshVault()).locked) {
+      passphraseEl.focus();
+    } else {
+      await reloadAll();
+      await maybeNotifyDue();
+    }
+    setInterval(refreshVault, 30_000);
 
     // Form submission
     billForm.addEventListener("submit", async (e) => {
       e.preventDefault();
-      if (upsertBillFromForm()) {
-        const saved = await save();
-        if (saved) {
-          render();
-          resetForm();
-        }
+      if (await upsertBillFromForm()) {
+        await load();
+        render();
+        resetForm();
       }
     });
 
@@ -392,8 +1635,222 @@ window.addEventListener("DOMContentLoaded", async () => {
     resetBtn.addEventListener("click", () => resetForm());
 
     // Search and filter
-    searchEl.addEventListener("input", render);
+    const reload = async () => {
+      await load();
+      render();
+    };
+    searchEl.addEventListener("input", reload);
+    categoryFilterEl.addEventListener("change", reload);
     filterEl.addEventListener("change", render);
+    categoryEl.addEventListener("change", async () => {
+      if (categoryEl.value === "__new__") await createCategoryFromForm();
+    });
+
+    // Currency conversion
+    reportingCurrencyEl.addEventListener("change", async () => {
+      try {
+        const settings = await invoke<Settings>("set_reporting_currency", {
+          currency: reportingCurrencyEl.value,
+        });
+        reportingCurrencyEl.value = settings.reportingCurrency;
+        render();
+      } catch (error) {
+        showError(errorMessage(error));
+        await loadSettings();
+      }
+    });
+    ratesFileEl.addEventListener("change", async () => {
+      const file = ratesFileEl.files?.[0];
+      if (file) await importRates(file);
+      ratesFileEl.value = "";
+    });
+
+    // Encryption
+    unlockBtn.addEventListener("click", () =>
+      vaultAction("unlock_vault", { passphrase: passphraseEl.value })
+    );
+    passphraseEl.addEventListener("keydown", (e) => {
+      if (e.key === "Enter" && vaultLocked) unlockBtn.click();
+    });
+    lockBtn.addEventListener("click", () => vaultAction("lock_vault"));
+    enableVaultBtn.addEventListener("click", () => {
+      if (confirm("Encrypt all data? It cannot be recovered without the passphrase.")) {
+        return vaultAction("enable_vault", { passphrase: newPassphraseEl.value });
+      }
+    });
+    disableVaultBtn.addEventListener("click", () => {
+      if (confirm("Store all data unencrypted again?")) {
+        return vaultAction("disable_vault", { passphrase: passphraseEl.value });
+      }
+    });
+    changePassphraseBtn.addEventListener("click", () =>
+      vaultAction("change_passphrase", {
+        oldPassphrase: passphraseEl.value,
+        newPassphrase: newPassphraseEl.value,
+      })
+    );
+    autoLockEl.addEventListener("change", async () => {
+      try {
+        await invoke("set_auto_lock_minutes", { minutes: Number(autoLockEl.value) || 0 });
+      } catch (error) {
+        showError(errorMessage(error));
+      }
+      await loadSettings();
+    });
+
+    // Undo / redo, also on Ctrl+Z and Ctrl+Shift+Z outside text fields
+    undoBtn.addEventListener("click", () => undoRedo("undo"));
+    redoBtn.addEventListener("click", () => undoRedo("redo"));
+    document.addEventListener("keydown", (e) => {
+      const target = e.target as HTMLElement;
+      if (!(e.ctrlKey || e.metaKey) || e.key.toLowerCase() !== "z") return;
+      if (target.closest("input, textarea, select")) return;
+      e.preventDefault();
+      (e.shiftKey ? redoBtn : undoBtn).click();
+    });
+
+    // Data check
+    checkIntegrityBtn.addEventListener("click", checkIntegrity);
+    integrityIssuesEl.addEventListener("click", (e) => {
+      const btn = (e.target as HTMLElement).closest("button");
+      const issue = integrityIssues[Number(btn?.dataset.issue)];
+      const repair = btn?.dataset.repair as Repair | undefined;
+      if (issue && repair) return repairIssue(issue, repair);
+    });
+
+    // Trash
+    trashListEl.addEventListener("focus", loadTrash);
+    restoreTrashedBtn.addEventListener("click", () => {
+      if (trashListEl.value) return trashAction("restore_bill", trashListEl.value);
+    });
+    purgeTrashedBtn.addEventListener("click", () => {
+      const label = trashListEl.selectedOptions[0]?.textContent ?? "";
+      if (trashListEl.value && confirm(`Permanently delete "${label}"?`)) {
+        return trashAction("purge_trash", trashListEl.value);
+      }
+    });
+    emptyTrashBtn.addEventListener("click", () => {
+      if (confirm("Permanently delete everything in the trash?")) return trashAction("purge_trash");
+    });
+    trashRetentionEl.addEventListener("change", async () => {
+      try {
+        await invoke("set_trash_retention_days", { days: Number(trashRetentionEl.value) || 0 });
+      } catch (error) {
+        showError(errorMessage(error));
+      }
+      await loadSettings();
+    });
+
+    // Profiles
+    profileSelectEl.addEventListener("change", () => switchProfile(profileSelectEl.value));
+    createProfileBtn.addEventListener("click", () =>
+      profileAction("create_profile", { name: profileNameEl.value })
+    );
+    renameProfileBtn.addEventListener("click", () =>
+      profileAction("rename_profile", { id: profiles.active, name: profileNameEl.value })
+    );
+    deleteProfileBtn.addEventListener("click", () => {
+      const others = profiles.profiles.filter((p) => p.id !== profiles.active);
+      const name = prompt(
+        `Profile to delete, with all of its bills (${others.map((p) => p.name).join(", ")}):`
+      );
+      const target = others.find((p) => p.name === name?.trim());
+      if (name && !target) showError(`No other profile is named "${name}"`);
+      if (target) return profileAction("delete_profile", { id: target.id });
+    });
+
+    // Import / export
+    document
+      .querySelector("#export-bills")!
+      .addEventListener("click", () => exportCsv("export_bills_csv", "bills"));
+    document
+      .querySelector("#export-payments")!
+      .addEventListener("click", () => exportCsv("export_payments_csv", "payments"));
+    document.querySelector("#export-calendar")!.addEventListener("click", async () => {
+      try {
+        downloadFile("bills.ics", await invoke<string>("export_ics"), "text/calendar");
+      } catch (error) {
+        showError(`Failed to export calendar: ${errorMessage(error)}`);
+      }
+    });
+    reminderDaysEl.addEventListener("change", async () => {
+      try {
+        await invoke("set_reminder_days", { days: Number(reminderDaysEl.value) || 0 });
+      } catch (error) {
+        showError(errorMessage(error));
+      }
+      await loadSettings();
+    });
+    icsFileEl.addEventListener("change", async () => {
+      const file = icsFileEl.files?.[0];
+      icsFileEl.value = "";
+      if (!file) return;
+      icsText = await file.text();
+      icsEventsEl.innerHTML = "";
+      icsLabels.clear();
+      await runIcsImport(true);
+    });
+    document.querySelector("#ics-check")!.addEventListener("click", () => runIcsImport(true));
+    document.querySelector("#ics-run")!.addEventListener("click", () => runIcsImport(false));
+    document.querySelector("#ics-cancel")!.addEventListener("click", () => {
+      icsPreviewEl.hidden = true;
+      icsText = "";
+    });
+    importFileEl.addEventListener("change", () => {
+      const file = importFileEl.files?.[0];
+      if (file) return previewImport(file);
+    });
+    document.querySelector("#import-check")!.addEventListener("click", () => runImport(true));
+    document.querySelector("#import-run")!.addEventListener("click", () => runImport(false));
+    document.querySelector("#import-cancel")!.addEventListener("click", () => {
+      importPreviewEl.hidden = true;
+      importText = "";
+    });
+
+    // Backups
+    backupListEl.addEventListener("focus", loadBackups);
+    restoreBackupBtn.addEventListener("click", restoreBackup);
+    document.querySelector("#export-archive")!.addEventListener("click", exportArchive);
+    document.querySelector("#export-ledger")!.addEventListener("click", exportLedger);
+    const ofxFileEl = document.querySelector<HTMLInputElement>("#ofx-file")!;
+    ofxFileEl.addEventListener("change", () => {
+      const file = ofxFileEl.files?.[0];
+      ofxFileEl.value = "";
+      if (file) importOfx(file);
+    });
+    document.querySelector("#bank-record")!.addEventListener("click", recordBankPayments);
+    document.querySelector("#bank-cancel")!.addEventListener("click", () => {
+      (document.querySelector("#bank-review") as HTMLElement).hidden = true;
+      document.querySelector("#bank-summary")!.textContent = "";
+    });
+    const bankCsvFileEl = document.querySelector<HTMLInputElement>("#bank-csv-file")!;
+    bankCsvFileEl.addEventListener("change", () => {
+      const file = bankCsvFileEl.files?.[0];
+      bankCsvFileEl.value = "";
+      if (file) importBankCsv(file);
+    });
+    document.querySelector("#bank-profile-save")!.addEventListener("click", saveBankProfile);
+    document.querySelector("#bank-profile-cancel")!.addEventListener("click", () => {
+      (document.querySelector("#bank-profile-editor") as HTMLElement).hidden = true;
+      bankCsvText = "";
+    });
+    document.querySelector("#bank-profile-delete")!.addEventListener("click", deleteBankProfile);
+    document.querySelector("#bank-payees")!.addEventListener("click", async (e) => {
+      const payee = (e.target as HTMLElement).closest<HTMLElement>("[data-forget]")?.dataset.forget;
+      if (payee === undefined) return;
+      try {
+        renderBankSettings(await invoke<Settings>("forget_payee", { payee }));
+      } catch (error) {
+        showError(errorMessage(error));
+      }
+    });
+    document.querySelector("#ledger-accounts")!.addEventListener("submit", saveLedgerAccounts);
+    const archiveFileEl = document.querySelector<HTMLInputElement>("#archive-file")!;
+    archiveFileEl.addEventListener("change", () => {
+      const file = archiveFileEl.files?.[0];
+      archiveFileEl.value = "";
+      if (file) restoreArchive(file);
+    });
 
     // Bill list actions
     billList.addEventListener("click", async (e) => {
@@ -404,41 +1861,31 @@ window.addEventListener("DOMContentLoaded", async () => {
       const action = btn.getAttribute("data-action");
       if (!id || !action) return;
 
-      const idx = bills.findIndex((b) => b.id === id);
-      if (idx < 0) return;
-
-      const b = bills[idx];
+      const b = bills.find((b) => b.id === id);
+      if (!b) return;
 
       try {
         if (action === "edit") {
           fillForm(b);
           window.scrollTo({ top: 0, behavior: "smooth" });
         } else if (action === "delete") {
-          if (confirm(`Are you sure you want to delete "${b.name}"?`)) {
-            bills.splice(idx, 1);
-            const saved = await save();
-            if (saved) {
-              render();
-            }
-          }
-        } else if (action === "toggle-paid") {
-          b.paid = !b.paid;
-          b.updatedAt = new Date().toISOString();
-
-          if (b.paid && b.recurrence !== "none") {
-            // If paid and recurring, schedule the next occurrence
-            b.dueDate = nextRecurringDate(b.dueDate, b.recurrence);
-            b.paid = false; // next cycle is unpaid
-          }
-
-          const saved = await save();
-          if (saved) {
+          if (confirm(`Move "${b.name}" to the trash?`)) {
+            await invoke("delete_bill", { id });
+            await load();
+            await loadTrash();
             render();
           }
+        } else if (action === "history") {
+          await toggleHistory(btn.closest("li")!, id);
+        } else if (action === "toggle-paid") {
+          // Recurring bills roll over to their next cycle in the backend
+          await invoke("set_paid", { id, paid: !b.paid });
+          await load();
+          render();
         }
       } catch (error) {
         console.error(`Failed to ${action} bill:`, error);
-        showError(`Failed to ${action} bill`);
+        showError(`Failed to ${action} bill: ${errorMessage(error)}`);
       }
     });
   } catch (error) {